@@ -0,0 +1,65 @@
+use evdev_rs::enums::EV_KEY;
+use serde::{Deserialize, Serialize};
+
+use ftd_device::report::BUTTON_COUNT;
+
+use crate::keys;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Keys held down together while the button is pressed, by the names
+    /// of `keys::KEY_NAMES`.
+    Keys(#[serde(deserialize_with = "keys::deserialize_keys")] Vec<EV_KEY>),
+    /// Wheel clicks sent once per press.
+    Scroll(i32),
+    /// Program started on press, see `command::CommandPolicy`.
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Switches the mapped area to the next of `mapping.areas` on press.
+    CycleArea,
+    /// Maps the tablet onto the next output of the desktop on press.
+    CycleOutput,
+    /// Turns all output off or back on, unless `Panic` locked it.
+    ToggleOutput,
+    /// Turns all output off at once and locks it off: `ToggleOutput` no
+    /// longer turns it on, only `Unlock` or the `enable` control command do.
+    Panic,
+    /// Lifts a `Panic` lock and turns the output back on.
+    Unlock,
+    /// Moves the pen over to the `[mouse]` device or back to the tablet.
+    ToggleMouse,
+}
+
+/// What the pen tip does by default, touching down like a left click.
+pub fn default_tip() -> Action {
+    Action::Keys(vec![EV_KEY::BTN_TOUCH])
+}
+
+/// The bindings printed on the tablet, indexed like `report::BUTTON_BITS`.
+pub fn default_button_map() -> Vec<Action> {
+    use EV_KEY::*;
+
+    let map = vec![
+        Action::Keys(vec![KEY_LEFTCTRL, KEY_MINUS]),
+        Action::Keys(vec![KEY_LEFTBRACE]),
+        Action::Scroll(1),
+        Action::Scroll(-1),
+        Action::Keys(vec![KEY_LEFTCTRL]),
+        Action::Keys(vec![KEY_LEFTALT]),
+        Action::Keys(vec![KEY_SPACE]),
+        Action::Keys(vec![KEY_TAB]),
+        Action::Keys(vec![BTN_STYLUS]),
+        Action::Keys(vec![BTN_STYLUS2]),
+        Action::Keys(vec![KEY_RIGHTBRACE]),
+        Action::Keys(vec![KEY_B]),
+        Action::Keys(vec![KEY_E]),
+        Action::Keys(vec![KEY_LEFTCTRL, KEY_EQUAL]),
+    ];
+    debug_assert_eq!(map.len(), BUTTON_COUNT);
+
+    map
+}
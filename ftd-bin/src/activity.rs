@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    /// How long reads may keep timing out before the tablet counts as idle.
+    /// Timeouts between reports are normal, only a gap this long is not.
+    pub grace_ms: u64,
+    /// Take the pen out of proximity once idle, for when it left without
+    /// an out of range report.
+    pub release_pen: bool,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        IdleConfig {
+            grace_ms: 1000,
+            release_pen: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    Active,
+    Idle,
+}
+
+/// The one definition of idle: no successful read for longer than the
+/// grace period. Everything that reacts to idleness asks this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivityTracker {
+    grace: Duration,
+    last_read: Option<Instant>,
+    idle: bool,
+}
+
+impl ActivityTracker {
+    /// Starts out idle, until the first read.
+    pub fn new(grace: Duration) -> ActivityTracker {
+        ActivityTracker {
+            grace,
+            last_read: None,
+            idle: true,
+        }
+    }
+
+    /// A report was read at `at`. Returns `Active` when that ends idleness.
+    pub fn read(&mut self, at: Instant) -> Option<Activity> {
+        self.last_read = Some(at);
+        std::mem::take(&mut self.idle).then_some(Activity::Active)
+    }
+
+    /// Checks the gap at `now`, after a timeout. Returns `Idle` once, when
+    /// the gap first exceeds the grace period.
+    pub fn tick(&mut self, now: Instant) -> Option<Activity> {
+        let last_read = self.last_read?;
+        if self.idle || now.saturating_duration_since(last_read) <= self.grace {
+            return None;
+        }
+        self.idle = true;
+        Some(Activity::Idle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE: Duration = Duration::from_millis(500);
+
+    fn ms(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn stray_timeouts_between_reads_stay_active() {
+        let start = Instant::now();
+        let mut tracker = ActivityTracker::new(GRACE);
+        assert_eq!(tracker.tick(start), None);
+
+        assert_eq!(tracker.read(start), Some(Activity::Active));
+        for gap in [10, 100, 400, 500] {
+            assert_eq!(tracker.tick(ms(start, gap)), None);
+        }
+        assert_eq!(tracker.read(ms(start, 520)), None);
+        assert_eq!(tracker.tick(ms(start, 1000)), None);
+        assert_eq!(tracker.tick(ms(start, 1021)), Some(Activity::Idle));
+    }
+
+    #[test]
+    fn a_long_gap_goes_idle_once_and_a_read_ends_it() {
+        let start = Instant::now();
+        let mut tracker = ActivityTracker::new(GRACE);
+        tracker.read(start);
+
+        assert_eq!(tracker.tick(ms(start, 501)), Some(Activity::Idle));
+        assert_eq!(tracker.tick(ms(start, 900)), None);
+
+        assert_eq!(tracker.read(ms(start, 950)), Some(Activity::Active));
+        assert_eq!(tracker.tick(ms(start, 1200)), None);
+        assert_eq!(tracker.tick(ms(start, 1451)), Some(Activity::Idle));
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+/// Longest wait between two packets of one report. A longer one means the
+/// rest of the report was lost, the next packet starts the next report.
+const PACKET_GAP: Duration = Duration::from_millis(50);
+
+/// Joins the packets of reports longer than one interrupt transfer into
+/// whole reports of `length` bytes, see `device.tablet_report_length`.
+///
+/// A report starts on a packet. When a packet goes missing, the partial
+/// report is dropped once the next packet would run past `length` or comes
+/// after `PACKET_GAP`, and that packet starts over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportAssembler {
+    interface: u8,
+    length: Option<usize>,
+    partial: Vec<u8>,
+    last_packet: Option<Instant>,
+}
+
+impl ReportAssembler {
+    /// Without a `length` every packet is a report.
+    pub fn new(interface: u8, length: Option<usize>) -> ReportAssembler {
+        ReportAssembler {
+            interface,
+            length,
+            partial: vec![],
+            last_packet: None,
+        }
+    }
+
+    /// The report `packet` read at `at` completes, if any.
+    pub fn push(&mut self, packet: Vec<u8>, at: Instant) -> Option<Vec<u8>> {
+        let Some(length) = self.length else {
+            return Some(packet);
+        };
+
+        let lost = self
+            .last_packet
+            .is_some_and(|last| at.saturating_duration_since(last) > PACKET_GAP)
+            || self.partial.len() + packet.len() > length;
+        if !self.partial.is_empty() && lost {
+            debug!(
+                "Dropped {} bytes of an incomplete report on interface {}",
+                self.partial.len(),
+                self.interface
+            );
+            self.partial.clear();
+        }
+        if self.partial.is_empty() && packet.len() >= length {
+            return Some(packet);
+        }
+
+        self.partial.extend(packet);
+        self.last_packet = Some(at);
+        if self.partial.len() < length {
+            return None;
+        }
+        self.last_packet = None;
+        Some(std::mem::take(&mut self.partial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftd_device::report::{Contact, TOUCH_REPORT_ID, TouchReport};
+
+    /// A touch report with all five contacts, 32 bytes.
+    fn touch_report() -> Vec<u8> {
+        let mut report = vec![TOUCH_REPORT_ID, 5];
+        for id in 0..5u8 {
+            report.extend([0x01, id, 0x00, 0x10 + id, 0x00, 0x20]);
+        }
+        report
+    }
+
+    #[test]
+    fn joins_a_two_packet_report_before_decoding() {
+        let mut assembler = ReportAssembler::new(2, Some(32));
+        let report = touch_report();
+        let now = Instant::now();
+
+        assert_eq!(assembler.push(report[..16].to_vec(), now), None);
+        let joined = assembler.push(report[16..].to_vec(), now).unwrap();
+        let touch = TouchReport::parse(&joined).unwrap();
+        assert_eq!(touch.contacts.len(), 5);
+        assert_eq!(
+            touch.contacts[4],
+            Contact {
+                id: 4,
+                down: true,
+                x: 0x1400,
+                y: 0x2000,
+            }
+        );
+
+        let mut single = ReportAssembler::new(2, None);
+        assert_eq!(
+            single.push(report[..16].to_vec(), now),
+            Some(report[..16].to_vec())
+        );
+    }
+
+    #[test]
+    fn a_lost_packet_drops_the_partial_report() {
+        let mut assembler = ReportAssembler::new(2, Some(32));
+        let report = touch_report();
+        let now = Instant::now();
+
+        // The second half of the first report never arrives.
+        assembler.push(report[..16].to_vec(), now);
+        assembler.push(report[..20].to_vec(), now);
+        assert_eq!(
+            assembler.push(report[20..].to_vec(), now),
+            Some(report.clone())
+        );
+
+        // Nor does it here, and the next report comes well after.
+        assembler.push(report[..16].to_vec(), now);
+        let later = now + PACKET_GAP * 2;
+        assert_eq!(assembler.push(report[..16].to_vec(), later), None);
+        assert_eq!(assembler.push(report[16..].to_vec(), later), Some(report));
+    }
+}
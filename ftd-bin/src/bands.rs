@@ -0,0 +1,170 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use ftd_device::report::DEVICE_MAX_PRESSURE;
+
+use crate::action::Action;
+
+/// Pressures from `min` to `max`, after the pressure pipeline, that fire
+/// `action` once when the pen presses into them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PressureBand {
+    pub min: u16,
+    pub max: u16,
+    pub action: Action,
+}
+
+/// Tracks which band the pressure is in and reports each band entered.
+/// Leaving a band takes `hysteresis` past its edge, so pressure hovering
+/// on a boundary does not fire the neighbours over and over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PressureBands {
+    bands: Vec<PressureBand>,
+    hysteresis: u16,
+    current: Option<usize>,
+}
+
+impl PressureBands {
+    pub fn new(mut bands: Vec<PressureBand>, hysteresis: u16) -> Result<PressureBands> {
+        bands.sort_by_key(|band| band.min);
+        for band in &bands {
+            if band.min >= band.max || band.max > DEVICE_MAX_PRESSURE as u16 {
+                bail!(
+                    "pressure band {}..{} must have min < max <= {DEVICE_MAX_PRESSURE}",
+                    band.min,
+                    band.max
+                );
+            }
+        }
+        if let Some(pair) = bands.windows(2).find(|pair| pair[0].max >= pair[1].min) {
+            bail!(
+                "pressure bands {}..{} and {}..{} overlap",
+                pair[0].min,
+                pair[0].max,
+                pair[1].min,
+                pair[1].max
+            );
+        }
+
+        Ok(PressureBands {
+            bands,
+            hysteresis,
+            current: None,
+        })
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        self.bands.iter().map(|band| &band.action)
+    }
+
+    /// Takes the processed pressure of every pen report and returns the
+    /// action of a band just entered. Lifting the tip leaves any band.
+    pub fn feed(&mut self, pressure: u16, tip: bool) -> Option<&Action> {
+        if !tip {
+            self.current = None;
+            return None;
+        }
+
+        if let Some(current) = self.current {
+            let band = &self.bands[current];
+            let low = band.min.saturating_sub(self.hysteresis);
+            let high = band.max.saturating_add(self.hysteresis);
+            if (low..=high).contains(&pressure) {
+                return None;
+            }
+        }
+
+        let entered = self
+            .bands
+            .iter()
+            .position(|band| (band.min..=band.max).contains(&pressure));
+        let changed = entered.is_some() && entered != self.current;
+        self.current = entered;
+
+        match entered {
+            Some(index) if changed => Some(&self.bands[index].action),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev_rs::enums::EV_KEY;
+
+    fn bands() -> PressureBands {
+        PressureBands::new(
+            vec![
+                PressureBand {
+                    min: 4000,
+                    max: 8191,
+                    action: Action::Keys(vec![EV_KEY::KEY_RIGHTBRACE]),
+                },
+                PressureBand {
+                    min: 100,
+                    max: 3999,
+                    action: Action::Keys(vec![EV_KEY::KEY_LEFTBRACE]),
+                },
+            ],
+            200,
+        )
+        .unwrap()
+    }
+
+    /// The actions fired along `ramp`, as the bands' first key.
+    fn fired(bands: &mut PressureBands, ramp: &[u16]) -> Vec<EV_KEY> {
+        ramp.iter()
+            .filter_map(|&pressure| match bands.feed(pressure, true) {
+                Some(Action::Keys(keys)) => Some(keys[0]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_once_per_band_on_a_rising_and_falling_ramp() {
+        let mut bands = bands();
+        let ramp: Vec<u16> = (0..=8000).step_by(50).collect();
+        assert_eq!(
+            fired(&mut bands, &ramp),
+            vec![EV_KEY::KEY_LEFTBRACE, EV_KEY::KEY_RIGHTBRACE]
+        );
+
+        let falling: Vec<u16> = ramp.iter().rev().copied().collect();
+        // Back into the light band once 200 below the hard one's edge.
+        assert_eq!(fired(&mut bands, &falling), vec![EV_KEY::KEY_LEFTBRACE]);
+        assert_eq!(bands.current, Some(0));
+    }
+
+    #[test]
+    fn jitter_on_a_boundary_does_not_chatter() {
+        let mut bands = bands();
+        let jitter = [3900, 4050, 3950, 4100, 3850, 4150, 3990];
+
+        assert_eq!(fired(&mut bands, &jitter), vec![EV_KEY::KEY_LEFTBRACE]);
+        assert_eq!(fired(&mut bands, &[4300]), vec![EV_KEY::KEY_RIGHTBRACE]);
+        assert_eq!(fired(&mut bands, &[3850, 3950]), vec![]);
+
+        bands.feed(0, false);
+        assert_eq!(fired(&mut bands, &[4300]), vec![EV_KEY::KEY_RIGHTBRACE]);
+        assert!(
+            PressureBands::new(
+                vec![
+                    PressureBand {
+                        min: 100,
+                        max: 500,
+                        action: Action::Scroll(1),
+                    },
+                    PressureBand {
+                        min: 500,
+                        max: 900,
+                        action: Action::Scroll(-1),
+                    },
+                ],
+                0,
+            )
+            .is_err()
+        );
+    }
+}
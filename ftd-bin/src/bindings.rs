@@ -0,0 +1,179 @@
+use std::io;
+
+use ftd_device::report::{BUTTON_COUNT, BUTTON_NAMES};
+
+use crate::{
+    action::Action,
+    driver::{Driver, Settings},
+    keys,
+};
+
+/// A binding of the active profile, as `test-bindings` lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// Index into the button map, past `BUTTON_COUNT` on the layer.
+    pub index: u8,
+    pub button: String,
+    pub action: Action,
+    /// What the driver keeps the button for instead, its action never runs.
+    pub role: Option<&'static str>,
+}
+
+impl Binding {
+    /// Why firing it is skipped: the action would turn the output off and
+    /// swallow the ones after it.
+    pub fn skipped(&self) -> Option<&'static str> {
+        match (&self.role, &self.action) {
+            (Some(role), _) => Some(role),
+            (None, Action::ToggleOutput | Action::Panic) => Some("turns the output off"),
+            _ => None,
+        }
+    }
+}
+
+/// The bound buttons of the active profile, the main ones first, then the
+/// layer's. Unbound buttons are left out.
+pub fn list(settings: &Settings) -> Vec<Binding> {
+    let role = |index| {
+        let index = Some(index);
+        if index == settings.layer.button {
+            Some("holds the layer")
+        } else if index == settings.precision.button {
+            Some("holds precision mode")
+        } else if index == settings.pressure_scroll.button {
+            Some("holds pressure scroll")
+        } else {
+            None
+        }
+    };
+
+    let button_map = &settings.profiles.active().button_map;
+    button_map
+        .iter()
+        .enumerate()
+        .filter_map(|(index, action)| {
+            let (button, role) = match BUTTON_NAMES.get(index) {
+                Some(name) => (name.to_string(), role(index as u8)),
+                None => (
+                    format!("layer {}", BUTTON_NAMES[index - BUTTON_COUNT]),
+                    None,
+                ),
+            };
+            let unbound = matches!(action, Action::Keys(keys) if keys.is_empty());
+            (role.is_some() || !unbound).then(|| Binding {
+                index: index as u8,
+                button,
+                action: action.clone(),
+                role,
+            })
+        })
+        .collect()
+}
+
+pub fn describe(action: &Action) -> String {
+    match action {
+        Action::Keys(pressed) => pressed
+            .iter()
+            .map(|&key| keys::key_name(key).map_or_else(|| format!("{key:?}"), str::to_string))
+            .collect::<Vec<_>>()
+            .join("+"),
+        Action::Scroll(clicks) => format!("scroll {clicks}"),
+        Action::Command { program, args } => {
+            format!(
+                "run {}",
+                [program]
+                    .into_iter()
+                    .chain(args)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )
+        }
+        Action::CycleArea => "cycle area".into(),
+        Action::CycleOutput => "cycle output".into(),
+        Action::ToggleOutput => "toggle output".into(),
+        Action::Panic => "panic".into(),
+        Action::Unlock => "unlock".into(),
+        Action::ToggleMouse => "toggle mouse".into(),
+    }
+}
+
+/// `index  button  action`, one binding per line under a header.
+pub fn table(bindings: &[Binding]) -> String {
+    let mut table = format!("{:<5}  {:<12}  action\n", "index", "button");
+    for binding in bindings {
+        let action = match binding.role {
+            Some(role) => role.to_string(),
+            None => describe(&binding.action),
+        };
+        table += &format!("{:<5}  {:<12}  {action}\n", binding.index, binding.button);
+    }
+
+    table
+}
+
+/// Fires each binding once through `driver`, calling `before` ahead of
+/// each so there is time to see what the previous one did.
+pub fn fire_all(
+    driver: &mut Driver,
+    bindings: &[Binding],
+    mut before: impl FnMut(&Binding),
+) -> io::Result<()> {
+    for binding in bindings {
+        before(binding);
+        if binding.skipped().is_none() {
+            driver.fire(binding.index)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use evdev_rs::enums::{EV_KEY, EventCode};
+
+    use super::*;
+    use crate::{
+        config::Config, control::ControlTarget, driver::recording_driver_with,
+        emitter::EmitterState,
+    };
+
+    #[test]
+    fn lists_and_fires_every_binding() {
+        let mut config = Config::default();
+        config.button_map.truncate(2);
+        config.button_map[1] = Action::Panic;
+        config.layer.button = Some(0);
+        config.layer.button_map = vec![Action::Keys(vec![]), Action::Keys(vec![EV_KEY::KEY_B])];
+        let settings = Settings::from_config(&config).unwrap();
+
+        let bindings = list(&settings);
+        assert_eq!(
+            table(&bindings),
+            "index  button        action\n\
+             0      CTRL-         holds the layer\n\
+             1      [             panic\n\
+             15     layer [       b\n"
+        );
+
+        let button_map = config.layer.combined_map(&config.button_map);
+        let state = EmitterState::new(button_map, true, (0x7FFF, 0x7FFF));
+        let (mut driver, sink) = recording_driver_with(settings, state);
+        let mut announced = vec![];
+        fire_all(&mut driver, &bindings, |binding| {
+            announced.push(binding.index)
+        })
+        .unwrap();
+
+        assert_eq!(announced, vec![0, 1, 15]);
+        let key_b: Vec<_> = sink
+            .take()
+            .into_iter()
+            .filter(|(code, _)| *code == EventCode::EV_KEY(EV_KEY::KEY_B))
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(key_b, vec![1, 0]);
+        assert!(driver.status().starts_with("enabled"));
+    }
+}
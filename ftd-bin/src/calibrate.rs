@@ -0,0 +1,170 @@
+use anyhow::{Result, bail};
+
+use ftd_device::report::{DEVICE_MAX_X, DEVICE_MAX_Y, PenReport};
+
+use crate::mapper::{Affine, Area};
+
+/// Corners touched in order during `calibrate`.
+pub const CORNERS: [&str; 4] = ["top left", "top right", "bottom right", "bottom left"];
+
+/// Points touched in order during `calibrate --affine`, with where they
+/// should end up in device units.
+pub const AFFINE_POINTS: [(&str, (f64, f64)); 3] = [
+    ("top left", (0.0, 0.0)),
+    ("top right", (DEVICE_MAX_X as f64, 0.0)),
+    ("bottom left", (0.0, DEVICE_MAX_Y as f64)),
+];
+
+/// Collects the raw position of a tap on each target point: the extents of
+/// the four `CORNERS` become the mapped area, the three `AFFINE_POINTS`
+/// give the matrix.
+#[derive(Debug)]
+pub struct Calibration {
+    targets: Vec<&'static str>,
+    touched: Vec<(u16, u16)>,
+    touching: Option<(u16, u16)>,
+}
+
+impl Calibration {
+    pub fn corners() -> Calibration {
+        Calibration::new(CORNERS.to_vec())
+    }
+
+    pub fn affine() -> Calibration {
+        Calibration::new(AFFINE_POINTS.iter().map(|(name, _)| *name).collect())
+    }
+
+    fn new(targets: Vec<&'static str>) -> Calibration {
+        Calibration {
+            targets,
+            touched: vec![],
+            touching: None,
+        }
+    }
+
+    /// Returns whether the report completed a corner, which happens when
+    /// the tip lifts. The last position while down is the one kept.
+    pub fn feed(&mut self, report: &PenReport) -> bool {
+        if self.next_corner().is_none() {
+            return false;
+        }
+
+        if report.in_range && report.tip {
+            self.touching = Some((report.x, report.y));
+            return false;
+        }
+        match self.touching.take() {
+            Some(position) => {
+                self.touched.push(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn next_corner(&self) -> Option<&'static str> {
+        self.targets.get(self.touched.len()).copied()
+    }
+
+    /// The extents once every corner was touched, `None` before that or if
+    /// the taps do not span an area.
+    pub fn area(&self) -> Option<Area> {
+        if self.targets != CORNERS || self.next_corner().is_some() {
+            return None;
+        }
+
+        let xs = self.touched.iter().map(|(x, _)| *x);
+        let ys = self.touched.iter().map(|(_, y)| *y);
+        let area = Area {
+            x_min: xs.clone().min()?,
+            y_min: ys.clone().min()?,
+            x_max: xs.max()?,
+            y_max: ys.max()?,
+        };
+
+        area.validate().ok().map(|()| area)
+    }
+
+    /// The matrix taking the three taps of `affine` onto `AFFINE_POINTS`.
+    pub fn matrix(&self) -> Result<Affine> {
+        let [a, b, c] = self.touched.as_slice() else {
+            bail!("The affine calibration needs one tap on each of the three points");
+        };
+        let point = |(x, y): (u16, u16)| (x as f64, y as f64);
+
+        Affine::from_points(
+            [point(*a), point(*b), point(*c)],
+            AFFINE_POINTS.map(|(_, target)| target),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pen(x: u16, y: u16, tip: bool) -> PenReport {
+        PenReport {
+            x,
+            y,
+            pressure: if tip { 500 } else { 0 },
+            in_range: true,
+            tip,
+        }
+    }
+
+    fn tap(calibration: &mut Calibration, x: u16, y: u16) -> bool {
+        calibration.feed(&pen(x, y, false));
+        calibration.feed(&pen(x + 5, y + 5, true));
+        calibration.feed(&pen(x, y, true));
+        calibration.feed(&pen(x, y, false))
+    }
+
+    #[test]
+    fn four_corner_taps_give_their_extents() {
+        let mut calibration = Calibration::corners();
+
+        assert_eq!(calibration.next_corner(), Some("top left"));
+        assert!(tap(&mut calibration, 120, 90));
+        assert!(!calibration.feed(&pen(4000, 4000, false)));
+        assert!(tap(&mut calibration, 32600, 110));
+        assert!(tap(&mut calibration, 32610, 32500));
+        assert_eq!(calibration.area(), None);
+        assert!(tap(&mut calibration, 100, 32480));
+
+        assert_eq!(calibration.next_corner(), None);
+        assert_eq!(
+            calibration.area(),
+            Some(Area {
+                x_min: 100,
+                y_min: 90,
+                x_max: 32610,
+                y_max: 32500,
+            })
+        );
+    }
+
+    #[test]
+    fn three_taps_give_the_matrix_onto_the_targets() {
+        let mut calibration = Calibration::affine();
+        assert!(tap(&mut calibration, 1000, 1200));
+        assert!(tap(&mut calibration, 31000, 1200));
+        assert!(calibration.matrix().is_err());
+        assert!(tap(&mut calibration, 1000, 31200));
+
+        let matrix = calibration.matrix().unwrap();
+        assert_eq!(matrix.apply(1000, 1200), (0, 0));
+        assert_eq!(matrix.apply(31000, 31200), (32767, 32767));
+        assert_eq!(calibration.area(), None);
+    }
+
+    #[test]
+    fn taps_on_one_spot_are_no_area() {
+        let mut calibration = Calibration::corners();
+        for _ in CORNERS {
+            tap(&mut calibration, 500, 500);
+        }
+
+        assert_eq!(calibration.area(), None);
+    }
+}
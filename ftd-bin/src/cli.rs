@@ -0,0 +1,459 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use log::info;
+
+use crate::{
+    action::Action,
+    config::{self, Config, ReadMode},
+    format::OutputFormat,
+    simulate::Pattern,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    /// The `monitor` subcommand: a live terminal view instead of uinput.
+    pub monitor: bool,
+    /// The `calibrate` subcommand: tap the corners to set `mapping.area`.
+    pub calibrate: bool,
+    /// The `pressure-test` subcommand: show the pressure as it is read.
+    pub pressure_test: bool,
+    /// `calibrate --affine`: three taps solved into `mapping.matrix`.
+    pub affine: bool,
+    /// The `watch` subcommand: decode and print the events, emit nothing.
+    pub watch: bool,
+    /// The `dump-config` subcommand: print the config in effect and exit.
+    pub dump_config: bool,
+    /// The `version` subcommand, or `--version`: print the build and exit.
+    pub version: bool,
+    /// The `simulate [circle|sine]` subcommand: synthetic pen motion
+    /// through the driver, at `--rate` reports a second (default 100).
+    pub simulate: Option<Pattern>,
+    pub rate: Option<u32>,
+    /// The `list-keys` subcommand: print the key names and exit.
+    pub list_keys: bool,
+    /// The `test-bindings` subcommand: print the bindings and exit.
+    pub test_bindings: bool,
+    /// `test-bindings --emit`: also fire each binding once.
+    pub emit: bool,
+    /// The `raw-send` subcommand: one control request, then exit.
+    pub raw_send: Option<RawControl>,
+    /// The `diagnostics [file]` subcommand: where to write the bundle.
+    pub diagnostics: Option<PathBuf>,
+    /// The `import-bindings <file>` subcommand: print the `xsetwacom`
+    /// bindings in `file` as a config and exit.
+    pub import_bindings: Option<PathBuf>,
+    /// Keep the serial number in the diagnostics bundle.
+    pub include_serial: bool,
+    pub config: Option<PathBuf>,
+    pub pressure_in_min: Option<u16>,
+    pub pressure_in_max: Option<u16>,
+    pub pressure_smoothing: Option<f32>,
+    pub ignore_touch: bool,
+    pub tap_time_ms: Option<u64>,
+    pub tap_distance: Option<u16>,
+    pub edge_scroll: Option<u16>,
+    pub allow_commands: bool,
+    pub allow_commands_as_root: bool,
+    pub profile: Option<String>,
+    pub output: Option<String>,
+    /// `--fit <output>`: map onto `output` with `mapping.fit`.
+    pub fit: bool,
+    /// `--full-resolution`: `mapping.full_resolution`.
+    pub full_resolution: bool,
+    pub screen: Option<(i32, i32)>,
+    pub force_init: bool,
+    /// `--force`: turns `device.identity.check` off.
+    pub force: bool,
+    /// `--no-set-idle`: turns `init.set_idle` off.
+    pub no_set_idle: bool,
+    /// `--grab`: `device.grab_event_nodes`.
+    pub grab: bool,
+    /// `--dwell`: `dwell.enabled`.
+    pub dwell: bool,
+    /// Log the events instead of creating the uinput device.
+    pub dry_run: bool,
+    /// Also log every event sent to uinput, without slowing it down.
+    pub log_events: bool,
+    /// What the read loop prints, `--format raw|human|binary`, see
+    /// `output_format`.
+    pub format: Option<OutputFormat>,
+    /// `--reader sync|async`: `device.reader`, async is experimental.
+    pub reader: Option<ReadMode>,
+    /// Also write the log to this file, rotated at `--log-file-max-kb`
+    /// (default 1024) keeping `--log-file-keep` (default 3) older ones.
+    pub log_file: Option<PathBuf>,
+    pub log_file_max_kb: Option<u64>,
+    pub log_file_keep: Option<usize>,
+    /// Also write the reports read to this file, for `FTD_REPLAY`. A
+    /// `.gz` or `.zst` one is piped through `gzip` or `zstd`, which has to
+    /// be installed to record and to replay it.
+    pub record: Option<PathBuf>,
+    /// Log a line whenever the pen, touch or button state changes.
+    pub log_transitions: bool,
+    pub control_socket: Option<PathBuf>,
+    pub no_control_socket: bool,
+    /// Serve the control commands over HTTP on this port, on
+    /// `--http-bind` (default 127.0.0.1).
+    pub http_port: Option<u16>,
+    pub http_bind: Option<std::net::IpAddr>,
+    pub init_window_secs: Option<u64>,
+    /// `(button index, command line)` pairs from `--button-command`.
+    pub button_commands: Vec<(u8, String)>,
+}
+
+/// A control request given on the command line as
+/// `raw-send <request_type> <request> <value> <index> [payload]`, all in hex.
+/// `request_type` with bit 7 set reads up to `length` bytes back instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawControl {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub payload: Vec<u8>,
+    pub length: usize,
+}
+
+impl RawControl {
+    pub fn is_read(&self) -> bool {
+        self.request_type & 0x80 != 0
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Args> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Args> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "monitor" => parsed.monitor = true,
+                "calibrate" => parsed.calibrate = true,
+                "pressure-test" => parsed.pressure_test = true,
+                "watch" => parsed.watch = true,
+                "version" | "--version" => parsed.version = true,
+                "list-keys" => parsed.list_keys = true,
+                "simulate" => {
+                    let pattern = args.next_if(|next| !next.starts_with("--"));
+                    parsed.simulate = Some(match pattern {
+                        Some(name) => Pattern::parse(&name)?,
+                        None => Pattern::default(),
+                    });
+                }
+                "--rate" => parsed.rate = Some(value(&arg, args.next())?),
+                "dump-config" => parsed.dump_config = true,
+                "test-bindings" => parsed.test_bindings = true,
+                "--emit" => parsed.emit = true,
+                "--affine" => parsed.affine = true,
+                "raw-send" => {
+                    let mut raw = RawControl {
+                        request_type: hex(&arg, args.next())?,
+                        request: hex(&arg, args.next())?,
+                        value: hex(&arg, args.next())?,
+                        index: hex(&arg, args.next())?,
+                        payload: vec![],
+                        length: 64,
+                    };
+                    if let Some(payload) = args.next_if(|next| !next.starts_with("--")) {
+                        raw.payload = hex_bytes(&payload)?;
+                    }
+                    parsed.raw_send = Some(raw);
+                }
+                "diagnostics" => {
+                    let path = args.next_if(|next| !next.starts_with("--"));
+                    parsed.diagnostics = Some(
+                        path.unwrap_or_else(|| "freetomate-diagnostics.txt".to_string())
+                            .into(),
+                    );
+                }
+                "import-bindings" => parsed.import_bindings = Some(value(&arg, args.next())?),
+                "--include-serial" => parsed.include_serial = true,
+                "--length" => {
+                    let length = value(&arg, args.next())?;
+                    parsed
+                        .raw_send
+                        .as_mut()
+                        .context("--length only applies to raw-send")?
+                        .length = length;
+                }
+                "--config" => parsed.config = Some(value(&arg, args.next())?),
+                "--pressure-in-min" => parsed.pressure_in_min = Some(value(&arg, args.next())?),
+                "--pressure-in-max" => parsed.pressure_in_max = Some(value(&arg, args.next())?),
+                "--pressure-smoothing" => {
+                    parsed.pressure_smoothing = Some(value(&arg, args.next())?)
+                }
+                "--ignore-touch" => parsed.ignore_touch = true,
+                "--tap-time-ms" => parsed.tap_time_ms = Some(value(&arg, args.next())?),
+                "--tap-distance" => parsed.tap_distance = Some(value(&arg, args.next())?),
+                "--edge-scroll" => parsed.edge_scroll = Some(value(&arg, args.next())?),
+                "--allow-commands" => parsed.allow_commands = true,
+                "--allow-commands-as-root" => parsed.allow_commands_as_root = true,
+                "--force-init" => parsed.force_init = true,
+                "--force" => parsed.force = true,
+                "--no-set-idle" => parsed.no_set_idle = true,
+                "--grab" => parsed.grab = true,
+                "--dwell" => parsed.dwell = true,
+                "--dry-run" => parsed.dry_run = true,
+                "--log-events" => parsed.log_events = true,
+                "--log-transitions" => parsed.log_transitions = true,
+                "--log-file" => parsed.log_file = Some(value(&arg, args.next())?),
+                "--log-file-max-kb" => parsed.log_file_max_kb = Some(value(&arg, args.next())?),
+                "--record" => parsed.record = Some(value(&arg, args.next())?),
+                "--log-file-keep" => parsed.log_file_keep = Some(value(&arg, args.next())?),
+                "--format" => {
+                    parsed.format = Some(OutputFormat::parse(&value::<String>(&arg, args.next())?)?)
+                }
+                "--reader" => {
+                    parsed.reader = Some(ReadMode::parse(&value::<String>(&arg, args.next())?)?)
+                }
+                "--control-socket" => parsed.control_socket = Some(value(&arg, args.next())?),
+                "--no-control-socket" => parsed.no_control_socket = true,
+                "--http-port" => parsed.http_port = Some(value(&arg, args.next())?),
+                "--http-bind" => parsed.http_bind = Some(value(&arg, args.next())?),
+                "--init-window-secs" => parsed.init_window_secs = Some(value(&arg, args.next())?),
+                "--output" => parsed.output = Some(value(&arg, args.next())?),
+                "--fit" => {
+                    parsed.output = Some(value(&arg, args.next())?);
+                    parsed.fit = true;
+                }
+                "--full-resolution" => parsed.full_resolution = true,
+                "--screen" => {
+                    let size: String = value(&arg, args.next())?;
+                    parsed.screen = Some(
+                        size.split_once('x')
+                            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                            .with_context(|| format!("Invalid value for --screen: {size}"))?,
+                    );
+                }
+                "--profile" => parsed.profile = Some(value(&arg, args.next())?),
+                "--button-command" => {
+                    let index = value(&arg, args.next())?;
+                    let command = value(&arg, args.next())?;
+                    parsed.button_commands.push((index, command));
+                }
+                _ => bail!("Unknown argument: {arg}"),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// `--format`, by default the bytes of each report, or the decoded
+    /// events for `watch`.
+    pub fn output_format(&self) -> OutputFormat {
+        self.format.unwrap_or(if self.watch {
+            OutputFormat::Human
+        } else {
+            OutputFormat::Raw
+        })
+    }
+
+    /// The `--config` file, or the first one found on `config::search_paths`,
+    /// or the defaults, with the flags applied on top.
+    pub fn load_config(&self) -> Result<Config> {
+        let mut config = match config::locate(self.config.as_deref(), &config::search_paths()) {
+            Some(path) => {
+                info!("Using config {}", path.display());
+                Config::load(&path)?
+            }
+            None => {
+                info!("No config file found, using the defaults");
+                Config::default()
+            }
+        };
+        self.apply(&mut config)?;
+
+        Ok(config)
+    }
+
+    /// Overrides the config with the flags that were given.
+    pub fn apply(&self, config: &mut Config) -> Result<()> {
+        if let Some(min) = self.pressure_in_min {
+            config.pressure.in_min = min;
+        }
+        if let Some(max) = self.pressure_in_max {
+            config.pressure.in_max = max;
+        }
+        if self.pressure_smoothing.is_some() {
+            config.pressure.smoothing = self.pressure_smoothing;
+        }
+        if self.ignore_touch {
+            config.touch.ignore = true;
+        }
+        if let Some(ms) = self.tap_time_ms {
+            config.touch.tap_time_ms = ms;
+        }
+        if let Some(distance) = self.tap_distance {
+            config.touch.tap_distance = distance;
+        }
+        if let Some(width) = self.edge_scroll {
+            config.touch.edge_scroll = width;
+        }
+        if self.profile.is_some() {
+            config.profile = self.profile.clone();
+        }
+        if self.output.is_some() {
+            config.mapping.output = self.output.clone();
+        }
+        if self.fit {
+            config.mapping.fit = true;
+        }
+        if self.grab {
+            config.device.grab_event_nodes = true;
+        }
+        if self.no_set_idle {
+            config.init.set_idle = false;
+        }
+        if self.force {
+            config.device.identity.check = false;
+        }
+        if let Some(reader) = self.reader {
+            config.device.reader = reader;
+        }
+        if self.dwell {
+            config.dwell.enabled = true;
+        }
+        if self.full_resolution {
+            config.mapping.full_resolution = true;
+        }
+        if self.screen.is_some() {
+            config.mapping.screen = self.screen;
+        }
+        if let Some(secs) = self.init_window_secs {
+            config.init.window_secs = secs;
+        }
+
+        for (index, command) in &self.button_commands {
+            let mut words = command.split_whitespace().map(str::to_string);
+            let slot = config
+                .button_map
+                .get_mut(*index as usize)
+                .with_context(|| format!("No button with index {index}"))?;
+            *slot = Action::Command {
+                program: words.next().context("Empty --button-command")?,
+                args: words.collect(),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+fn value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value = value.with_context(|| format!("Missing value for {flag}"))?;
+    value
+        .parse()
+        .with_context(|| format!("Invalid value for {flag}: {value}"))
+}
+
+/// A hex number, with or without `0x`.
+fn hex<T: TryFrom<u64>>(flag: &str, value: Option<String>) -> Result<T> {
+    let value = value.with_context(|| format!("Missing value for {flag}"))?;
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u64::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|number| T::try_from(number).ok())
+        .with_context(|| format!("Invalid hex value for {flag}: {value}"))
+}
+
+/// Hex bytes like `0201` or `02:01`.
+fn hex_bytes(value: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = value
+        .chars()
+        .filter(|c| !matches!(c, ':' | ' ' | ','))
+        .map(|c| c.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<_>>()
+        .with_context(|| format!("Invalid payload {value}"))?;
+    if !digits.len().is_multiple_of(2) {
+        bail!("Invalid payload {value}: odd number of hex digits");
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse_from(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_a_raw_control_request() {
+        let args = parse(&["raw-send", "0x21", "09", "0202", "2", "02:01"]).unwrap();
+        assert_eq!(
+            args.raw_send,
+            Some(RawControl {
+                request_type: 0x21,
+                request: 0x09,
+                value: 0x0202,
+                index: 2,
+                payload: vec![0x02, 0x01],
+                length: 64,
+            })
+        );
+
+        let args = parse(&["raw-send", "A1", "01", "0305", "2", "--length", "16"]).unwrap();
+        let raw = args.raw_send.unwrap();
+        assert!(raw.is_read());
+        assert_eq!((raw.payload.len(), raw.length), (0, 16));
+
+        assert!(parse(&["raw-send", "100", "01", "0", "0"]).is_err());
+        assert!(parse(&["raw-send", "21", "09", "0202", "2", "021"]).is_err());
+        assert!(parse(&["--length", "8"]).is_err());
+    }
+
+    #[test]
+    fn watch_prints_decoded_events_unless_told_otherwise() {
+        assert_eq!(parse(&[]).unwrap().output_format(), OutputFormat::Raw);
+        assert_eq!(
+            parse(&["watch"]).unwrap().output_format(),
+            OutputFormat::Human
+        );
+        assert_eq!(
+            parse(&["watch", "--format", "raw"])
+                .unwrap()
+                .output_format(),
+            OutputFormat::Raw
+        );
+    }
+
+    #[test]
+    fn the_dumped_config_reloads_with_the_overrides() {
+        let args = parse(&[
+            "dump-config",
+            "--pressure-in-min",
+            "40",
+            "--screen",
+            "1920x1080",
+            "--button-command",
+            "3",
+            "krita --nosplash",
+            "--reader",
+            "async",
+        ])
+        .unwrap();
+        assert!(args.dump_config);
+
+        let mut config = Config::default();
+        args.apply(&mut config).unwrap();
+        let reloaded = Config::parse(&config.to_text().unwrap()).unwrap();
+        assert_eq!(reloaded, config);
+        assert_eq!(reloaded.pressure.in_min, 40);
+        assert_eq!(reloaded.mapping.screen, Some((1920, 1080)));
+        assert_eq!(reloaded.device.reader, ReadMode::Async);
+        assert!(parse(&["--reader", "usbfs"]).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+use log::{info, warn};
+
+pub trait Spawner {
+    fn spawn(&self, program: &str, args: &[String]) -> io::Result<()>;
+}
+
+/// Starts the program detached and reaps it from a background thread.
+pub struct ProcessSpawner;
+
+impl Spawner for ProcessSpawner {
+    fn spawn(&self, program: &str, args: &[String]) -> io::Result<()> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandPolicy {
+    /// Commands are never run unless this is set.
+    pub allow: bool,
+    /// Also run them when the driver itself runs as root.
+    pub allow_root: bool,
+}
+
+pub struct CommandRunner<S: Spawner = ProcessSpawner> {
+    spawner: S,
+    policy: CommandPolicy,
+    is_root: bool,
+}
+
+impl CommandRunner {
+    pub fn new(policy: CommandPolicy) -> CommandRunner {
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        let is_root = unsafe { libc::geteuid() } == 0;
+        CommandRunner::with_spawner(ProcessSpawner, policy, is_root)
+    }
+}
+
+impl<S: Spawner> CommandRunner<S> {
+    pub fn with_spawner(spawner: S, policy: CommandPolicy, is_root: bool) -> CommandRunner<S> {
+        CommandRunner {
+            spawner,
+            policy,
+            is_root,
+        }
+    }
+
+    pub fn run(&self, program: &str, args: &[String]) {
+        if !self.policy.allow {
+            warn!("Ignoring command `{program}`, commands are disabled (--allow-commands)");
+            return;
+        }
+
+        if self.is_root && !self.policy.allow_root {
+            warn!("Ignoring command `{program}` while running as root (--allow-commands-as-root)");
+            return;
+        }
+
+        match self.spawner.spawn(program, args) {
+            Ok(()) => info!("Started `{program}`"),
+            Err(e) => warn!("Failed to start `{program}`: {e}"),
+        }
+    }
+}
+
+/// Records the programs instead of starting them, for the tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordingSpawner(pub std::cell::RefCell<Vec<(String, Vec<String>)>>);
+
+#[cfg(test)]
+impl Spawner for &RecordingSpawner {
+    fn spawn(&self, program: &str, args: &[String]) -> io::Result<()> {
+        self.0
+            .borrow_mut()
+            .push((program.to_string(), args.to_vec()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed() -> CommandPolicy {
+        CommandPolicy {
+            allow: true,
+            allow_root: false,
+        }
+    }
+
+    #[test]
+    fn spawns_with_the_configured_args() {
+        let recorder = RecordingSpawner::default();
+        let runner = CommandRunner::with_spawner(&recorder, allowed(), false);
+
+        runner.run("feh", &["ref.png".to_string()]);
+
+        assert_eq!(
+            *recorder.0.borrow(),
+            vec![("feh".to_string(), vec!["ref.png".to_string()])]
+        );
+    }
+
+    #[test]
+    fn respects_the_policy() {
+        let recorder = RecordingSpawner::default();
+
+        CommandRunner::with_spawner(&recorder, CommandPolicy::default(), false).run("feh", &[]);
+        CommandRunner::with_spawner(&recorder, allowed(), true).run("feh", &[]);
+
+        assert!(recorder.0.borrow().is_empty());
+    }
+}
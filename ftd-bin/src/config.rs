@@ -0,0 +1,1497 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use ftd_device::{
+    device::{
+        BUTTONS_INTERAFCE, MASS_STORAGE, MessageDevice, PRODUCT_ID, TABLET_INTERFACE, VENDOR_ID,
+    },
+    error::FtdError,
+    report::Capabilities,
+};
+
+use crate::{
+    action::{Action, default_button_map, default_tip},
+    activity::IdleConfig,
+    bands::PressureBand,
+    dispatch::{ReportRoute, UnknownReports, default_routes},
+    distance::HoverDistance,
+    dwell::DwellSettings,
+    edge::EdgeSettings,
+    emitter::DEVICE_NAME,
+    gesture::TapSettings,
+    hooks::HooksConfig,
+    identity::DeviceIdentity,
+    keep_alive::KeepAliveConfig,
+    layer::LayerConfig,
+    mapper::{Affine, Area, Padding, Rotation},
+    merge::TabletSinks,
+    mouse::MouseConfig,
+    precision::PrecisionSettings,
+    pressure::{PressureCurve, PressurePipeline, PressureRange},
+    pressure_scroll::PressureScrollSettings,
+    profile::{DEFAULT_PROFILE, ProfileConfig},
+    proximity::ProximitySettings,
+    queue::QueueConfig,
+    reconnect::ReconnectConfig,
+    recovery::RecoveryConfig,
+    screen::Rect,
+    smoothing::SmoothingSettings,
+    tilt::TiltConfig,
+};
+
+/// `bRequest` of the HID class SET_REPORT request.
+const SET_REPORT: u8 = 0x09;
+/// `bRequest` of the HID class SET_IDLE request.
+const SET_IDLE: u8 = 0x0A;
+
+/// Everything the driver can be told from a TOML file.
+///
+/// Every field has a default, so a config only needs the keys it changes.
+/// Command line flags are applied on top, see `Args::apply`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub device: DeviceConfig,
+    pub init: InitConfig,
+    pub pressure: PressureConfig,
+    pub touch: TouchConfig,
+    pub mapping: MappingConfig,
+    /// Smoothing of the pen position.
+    pub smoothing: SmoothingSettings,
+    /// A held button that slows the pen down for fine adjustments.
+    pub precision: PrecisionSettings,
+    /// A held button that turns pen pressure into wheel scrolling.
+    pub pressure_scroll: PressureScrollSettings,
+    /// What happens when the tablet disconnects.
+    pub reconnect: ReconnectConfig,
+    /// When the tablet counts as idle and what is done then.
+    pub idle: IdleConfig,
+    /// How the pen leaving proximity is detected.
+    pub proximity: ProximitySettings,
+    /// Clicking by hovering the pen still.
+    pub dwell: DwellSettings,
+    /// What is tried when reads keep failing.
+    pub recovery: RecoveryConfig,
+    /// The reports between the USB readers and the report loop.
+    pub queue: QueueConfig,
+    /// A packet re-sent periodically while the tablet is read.
+    pub keep_alive: KeepAliveConfig,
+    /// Profile selected at startup, and again when the focused window
+    /// matches no `window_class` or `process_name` rule.
+    pub profile: Option<String>,
+    /// How often the focused window is read when a profile has a rule.
+    pub focus_poll_ms: u64,
+    /// Name of the virtual uinput device. Some applications keep their
+    /// tablet settings per device name, so it should stay the same.
+    pub device_name: String,
+    /// With several tablets, `merged` writes them all to one virtual device
+    /// and `per-device` gives each its own.
+    pub tablet_sinks: TabletSinks,
+    /// Events read from different interfaces within this many ms are
+    /// written as one frame, e.g. a button press and the pen move next to
+    /// it. 0 writes each event as soon as it is decoded.
+    pub coalesce_ms: u64,
+    /// A second Ctrl+C within this many ms of the first exits at once
+    /// instead of waiting for the graceful stop, 0 never does.
+    pub force_quit_ms: u64,
+    /// Bindings of the default profile, indexed like `report::BUTTON_BITS`.
+    pub button_map: Vec<Action>,
+    /// What the pen tip does, pressed on tip-down and released on tip-up.
+    /// By default a touch, `{ keys = [] }` leaves a cursor that only
+    /// hovers. Pressure is only sent with the default.
+    pub tip: Action,
+    /// A second set of bindings while a button is held, like an Fn key.
+    pub layer: LayerConfig,
+    /// How a pen lifted high above the surface maps, for models that report
+    /// the hover distance.
+    pub hover_distance: HoverDistance,
+    /// Actions fired by leaning the pen, for models that report tilt.
+    pub tilt: TiltConfig,
+    /// What to do when the tablet connects and disconnects.
+    pub hooks: HooksConfig,
+    /// A relative mouse the pen can be switched to.
+    pub mouse: MouseConfig,
+    /// Profiles besides the default one, see `ProfileConfig`.
+    pub profiles: Vec<ProfileConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            device: DeviceConfig::default(),
+            init: InitConfig::default(),
+            pressure: PressureConfig::default(),
+            touch: TouchConfig::default(),
+            mapping: MappingConfig::default(),
+            smoothing: SmoothingSettings::default(),
+            precision: PrecisionSettings::default(),
+            pressure_scroll: PressureScrollSettings::default(),
+            reconnect: ReconnectConfig::default(),
+            idle: IdleConfig::default(),
+            proximity: ProximitySettings::default(),
+            dwell: DwellSettings::default(),
+            recovery: RecoveryConfig::default(),
+            queue: QueueConfig::default(),
+            keep_alive: KeepAliveConfig::default(),
+            profile: None,
+            focus_poll_ms: 500,
+            device_name: DEVICE_NAME.to_string(),
+            tablet_sinks: TabletSinks::default(),
+            coalesce_ms: 0,
+            force_quit_ms: 3000,
+            button_map: default_button_map(),
+            tip: default_tip(),
+            layer: LayerConfig::default(),
+            hover_distance: HoverDistance::default(),
+            tilt: TiltConfig::default(),
+            hooks: HooksConfig::default(),
+            mouse: MouseConfig::default(),
+            profiles: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// `bConfigurationValue` to switch to before looking for interfaces,
+    /// unset keeps the configuration the device is on.
+    pub configuration: Option<u8>,
+    /// Interfaces detached from the kernel and claimed.
+    pub interfaces: Vec<u8>,
+    /// Claim only the buttons and tablet interfaces, which are the ones
+    /// read, and leave the rest of `interfaces` (e.g. mass storage) to the
+    /// kernel.
+    pub claim_read_only: bool,
+    /// Leave the kernel's usb-storage driver on interface `MASS_STORAGE`
+    /// instead of detaching it, so the desktop shows no "device removed"
+    /// notice at startup. The tablet does not need it, but its volume then
+    /// stays mounted while the driver runs and has to be ejected by hand.
+    pub keep_mass_storage: bool,
+    /// Interfaces that must be claimed. Failing to claim any other one is
+    /// logged and it is not read, so a busy buttons interface still leaves
+    /// the pen working. Unset makes every claimed interface essential.
+    pub essential_interfaces: Option<Vec<u8>>,
+    pub buttons_interface: u8,
+    pub tablet_interface: u8,
+    /// Tell the buttons and tablet interfaces apart by their HID report
+    /// descriptors, a keyboard and a digitizer, and read those instead of
+    /// the two above. These still apply when that is inconclusive.
+    pub detect_interfaces: bool,
+    /// Units per mm along X and Y, reported to apps that size brushes
+    /// physically. Unset means the tablet's capabilities, then
+    /// `DEVICE_RESOLUTION`.
+    pub resolution: Option<(i32, i32)>,
+    /// Read the capabilities feature report at startup.
+    pub probe_capabilities: bool,
+    /// Grab the tablet's input nodes the kernel still handles, so programs
+    /// reading them see nothing and only the virtual device gets through.
+    pub grab_event_nodes: bool,
+    /// How long each interface's reader thread blocks in libusb per read,
+    /// raised by `read_timeouts = "long"`.
+    pub buttons_timeout_ms: u64,
+    pub tablet_timeout_ms: u64,
+    /// Whether idle readers wake up often or block, see `ReadTimeouts`.
+    pub read_timeouts: ReadTimeouts,
+    /// How the reader threads read, `--reader` overrides it.
+    pub reader: ReadMode,
+    /// How long the startup status request of each IN endpoint to be read
+    /// may wait, 0 skips it. A halted endpoint or an error fails it.
+    pub health_check_ms: u64,
+    /// IN endpoint address to read on each interface. Unset tries every
+    /// IN endpoint in turn, each costing a timeout when it has nothing.
+    pub buttons_endpoint: Option<u8>,
+    pub tablet_endpoint: Option<u8>,
+    /// IN endpoint addresses never read, for clones that declare one that
+    /// only errors. An interface keeps at least one.
+    pub skip_endpoints: Vec<u8>,
+    /// Stop reading an IN endpoint after this many errors in a row other
+    /// than timeouts, while the interface has another. 0 keeps reading it.
+    pub skip_failing_endpoint_after: u32,
+    /// Bytes read per report on each interface, for clones whose descriptor
+    /// gives a smaller packet size than they send. Unset uses the descriptor.
+    pub buttons_read_size: Option<usize>,
+    pub tablet_read_size: Option<usize>,
+    /// Bytes of one report on each interface, for models whose reports span
+    /// more than one interrupt transfer. Packets are joined until this many
+    /// are in, see `assemble::ReportAssembler`. Unset takes every packet as
+    /// a report.
+    pub buttons_report_length: Option<usize>,
+    pub tablet_report_length: Option<usize>,
+    /// Logical index of each decoded button, for units whose bits do not
+    /// match the labels. Empty keeps the order of `report::BUTTON_BITS`.
+    pub button_remap: Vec<u8>,
+    /// Decoded button indices, as in `report::BUTTON_BITS`, that are dropped
+    /// entirely, e.g. a bit that repeats the pen tip and would double click.
+    pub ignore_buttons: Vec<u8>,
+    /// Exchange X and Y as soon as a pen report is parsed, for clones that
+    /// send each axis in the other's bytes. This fixes the data before
+    /// anything else sees it, while `mapping.rotation` turns correct data
+    /// to match how the tablet is held.
+    pub swap_xy: bool,
+    /// Trust the tip bit only while the pen is in range, for units that
+    /// report phantom tip presses as the pen enters or leaves proximity.
+    pub tip_needs_in_range: bool,
+    /// Which parser handles each report ID on each interface.
+    pub reports: Vec<ReportRoute>,
+    /// What happens to reports none of `reports` matches: `log`, `drop` or
+    /// `passthrough-raw`.
+    pub unknown_reports: UnknownReports,
+    /// `bcdDevice` the settings above were checked against, e.g. `0x0102`
+    /// for firmware 1.02. Another revision only logs a warning, unset skips
+    /// the check.
+    pub revision: Option<u16>,
+    /// Overrides applied when the device reports a given revision.
+    pub revision_quirks: Vec<RevisionQuirks>,
+    /// Checked before the init packets are sent.
+    pub identity: DeviceIdentity,
+}
+
+/// The shortest read timeout with `ReadTimeouts::Long`.
+pub const LONG_READ_TIMEOUT_MS: u64 = 1000;
+
+/// How long the reader threads block per read. A report ends a read as it
+/// arrives either way, this only decides how often an idle reader wakes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadTimeouts {
+    /// `buttons_timeout_ms` and `tablet_timeout_ms` as set, so Ctrl+C, a
+    /// pause or an unplugged tablet is noticed within 10 ms by default.
+    #[default]
+    Short,
+    /// At least `LONG_READ_TIMEOUT_MS`, so an idle reader sleeps in libusb
+    /// instead of waking a hundred times a second. For laptops on battery,
+    /// at the cost of Ctrl+C, a pause or a reconnect taking up to a second
+    /// to be noticed.
+    Long,
+}
+
+/// How the reader threads get the reports off the USB interfaces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadMode {
+    /// One blocking read at a time, each waiting up to its timeout.
+    #[default]
+    Sync,
+    /// Reads kept queued with libusb, so a report is taken as soon as it
+    /// arrives instead of once a read is started for it, see
+    /// `transfer::AsyncReader`. Experimental: how much latency it saves on
+    /// a real tablet has not been measured yet.
+    Async,
+}
+
+impl ReadMode {
+    pub fn parse(name: &str) -> Result<ReadMode> {
+        match name {
+            "sync" => Ok(ReadMode::Sync),
+            "async" => Ok(ReadMode::Async),
+            _ => bail!("Unknown reader {name}, expected sync or async"),
+        }
+    }
+}
+
+/// Device settings that differ on one firmware revision, each unset field
+/// keeping the `device` value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RevisionQuirks {
+    pub revision: u16,
+    pub swap_xy: Option<bool>,
+    pub tip_needs_in_range: Option<bool>,
+    pub resolution: Option<(i32, i32)>,
+    pub ignore_buttons: Option<Vec<u8>>,
+    pub reports: Option<Vec<ReportRoute>>,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        DeviceConfig {
+            vendor_id: VENDOR_ID,
+            product_id: PRODUCT_ID,
+            configuration: None,
+            interfaces: vec![MASS_STORAGE, BUTTONS_INTERAFCE, TABLET_INTERFACE],
+            claim_read_only: false,
+            keep_mass_storage: false,
+            essential_interfaces: None,
+            buttons_interface: BUTTONS_INTERAFCE,
+            tablet_interface: TABLET_INTERFACE,
+            detect_interfaces: true,
+            resolution: None,
+            probe_capabilities: true,
+            grab_event_nodes: false,
+            buttons_timeout_ms: 10,
+            tablet_timeout_ms: 10,
+            read_timeouts: ReadTimeouts::default(),
+            reader: ReadMode::default(),
+            health_check_ms: 100,
+            buttons_endpoint: None,
+            tablet_endpoint: None,
+            skip_endpoints: vec![],
+            skip_failing_endpoint_after: 0,
+            buttons_read_size: None,
+            tablet_read_size: None,
+            buttons_report_length: None,
+            tablet_report_length: None,
+            button_remap: vec![],
+            ignore_buttons: vec![],
+            swap_xy: false,
+            tip_needs_in_range: false,
+            reports: default_routes(),
+            unknown_reports: UnknownReports::default(),
+            revision: None,
+            revision_quirks: vec![],
+            identity: DeviceIdentity::default(),
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// The buttons and the tablet interface, once when they are the same.
+    pub fn report_interfaces(&self) -> Vec<u8> {
+        let mut read = vec![self.buttons_interface, self.tablet_interface];
+        read.dedup();
+        read
+    }
+
+    /// `buttons_report_length` or `tablet_report_length` for `interface`,
+    /// the tablet one when both are the same interface.
+    pub fn report_length(&self, interface: u8) -> Option<usize> {
+        if interface == self.tablet_interface {
+            self.tablet_report_length
+        } else if interface == self.buttons_interface {
+            self.buttons_report_length
+        } else {
+            None
+        }
+    }
+
+    /// The interfaces to detach and claim.
+    pub fn claimed_interfaces(&self) -> Vec<u8> {
+        let mut claimed = if self.claim_read_only {
+            self.report_interfaces()
+        } else {
+            self.interfaces.clone()
+        };
+        if self.keep_mass_storage {
+            claimed.retain(|&number| {
+                number != MASS_STORAGE
+                    || number == self.buttons_interface
+                    || number == self.tablet_interface
+            });
+        }
+
+        claimed
+    }
+
+    /// The interfaces whose claim may not fail.
+    pub fn essential_interfaces(&self) -> Vec<u8> {
+        self.essential_interfaces
+            .clone()
+            .unwrap_or_else(|| self.claimed_interfaces())
+    }
+
+    /// Reads the buttons from `buttons` and the pen from `tablet`, taking the
+    /// report routes of the old numbers along.
+    pub fn use_interfaces(&mut self, buttons: u8, tablet: u8) {
+        let (old_buttons, old_tablet) = (self.buttons_interface, self.tablet_interface);
+        for route in &mut self.reports {
+            if route.interface == old_buttons {
+                route.interface = buttons;
+            } else if route.interface == old_tablet {
+                route.interface = tablet;
+            }
+        }
+        (self.buttons_interface, self.tablet_interface) = (buttons, tablet);
+    }
+
+    /// Applies the quirks of `revision`, if any, and tells whether there were.
+    pub fn use_revision(&mut self, revision: u16) -> bool {
+        let Some(quirks) = self
+            .revision_quirks
+            .iter()
+            .find(|quirks| quirks.revision == revision)
+            .cloned()
+        else {
+            return false;
+        };
+        if let Some(swap_xy) = quirks.swap_xy {
+            self.swap_xy = swap_xy;
+        }
+        if let Some(tip_needs_in_range) = quirks.tip_needs_in_range {
+            self.tip_needs_in_range = tip_needs_in_range;
+        }
+        if let Some(resolution) = quirks.resolution {
+            self.resolution = Some(resolution);
+        }
+        if let Some(ignore_buttons) = quirks.ignore_buttons {
+            self.ignore_buttons = ignore_buttons;
+        }
+        if let Some(reports) = quirks.reports {
+            self.reports = reports;
+        }
+
+        true
+    }
+
+    /// The read timeouts in ms of the buttons then the tablet interface.
+    pub fn read_timeouts_ms(&self) -> [u64; 2] {
+        let timeouts = [self.buttons_timeout_ms, self.tablet_timeout_ms];
+        match self.read_timeouts {
+            ReadTimeouts::Short => timeouts,
+            ReadTimeouts::Long => timeouts.map(|ms| ms.max(LONG_READ_TIMEOUT_MS)),
+        }
+    }
+
+    /// The interfaces a reader thread is started for, buttons then tablet.
+    pub fn read_interfaces(&self) -> [u8; 2] {
+        [self.buttons_interface, self.tablet_interface]
+    }
+
+    /// Fails for a route, in `reports` or the quirks of a revision, on an
+    /// interface that is not read, whose reports would never arrive.
+    pub fn check_routes(&self) -> Result<()> {
+        let read = self.read_interfaces();
+        let quirks = self
+            .revision_quirks
+            .iter()
+            .filter_map(|quirks| quirks.reports.as_ref());
+        for route in std::iter::once(&self.reports).chain(quirks).flatten() {
+            if !read.contains(&route.interface) {
+                bail!(
+                    "A report route is on interface {}, which is not read: only \
+                     device.buttons_interface {} and device.tablet_interface {} are, \
+                     set one of them to {}",
+                    route.interface,
+                    read[0],
+                    read[1],
+                    route.interface
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The interfaces the device must have: the claimed ones and the two read.
+    pub fn expected_interfaces(&self) -> Vec<u8> {
+        let mut expected = self.interfaces.clone();
+        expected.extend(self.read_interfaces());
+        expected.sort_unstable();
+        expected.dedup();
+        expected
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InitConfig {
+    /// Control transfers or interrupt writes sent in order to switch the
+    /// tablet to its full mode.
+    pub packets: Vec<InitPacket>,
+    pub delay_before_ms: u64,
+    pub delay_after_ms: u64,
+    /// A restart within this many seconds skips the packets.
+    pub window_secs: u64,
+    /// Extra attempts at a packet that failed before giving up.
+    pub retries: u32,
+    pub retry_delay_ms: u64,
+    /// Events decoded this long after the packets were sent are dropped,
+    /// for tablets that send noise while they settle.
+    pub warm_up_ms: u64,
+    /// Send a HID SET_IDLE to the interfaces that are read before the
+    /// packets, some tablets send no interrupt reports without one.
+    pub set_idle: bool,
+    /// The SET_IDLE duration in 4 ms steps, 0 reports only on a change.
+    pub idle_duration: u8,
+    /// Read back a feature report after the packets instead of waiting
+    /// `delay_after_ms`, for tablets that can tell they switched modes.
+    pub verify: Option<InitVerify>,
+}
+
+impl Default for InitConfig {
+    fn default() -> Self {
+        InitConfig {
+            packets: vec![InitPacket::default()],
+            delay_before_ms: 500,
+            delay_after_ms: 500,
+            window_secs: 30,
+            retries: 2,
+            retry_delay_ms: 200,
+            warm_up_ms: 0,
+            set_idle: true,
+            idle_duration: 0,
+            verify: None,
+        }
+    }
+}
+
+impl InitConfig {
+    /// Fails for a SET_REPORT whose `value` is not a report type, 1 for
+    /// input, 2 for output or 3 for feature, in the high byte followed by
+    /// the report ID. Other requests take any `value`.
+    pub fn validate(&self) -> Result<()> {
+        for (index, packet) in self.packets.iter().enumerate() {
+            let set_report = packet.kind == InitKind::Control
+                && packet.request_type & 0x60 == 0x20
+                && packet.request == SET_REPORT;
+            let report_type = packet.value >> 8;
+            if set_report && !(1..=3).contains(&report_type) {
+                bail!(
+                    "Init packet {index} is a SET_REPORT with value {:#06x}, whose high byte \
+                     must be the report type: 1 input, 2 output or 3 feature",
+                    packet.value
+                );
+            }
+        }
+        if let Some(verify) = &self.verify {
+            if verify.attempts == 0 {
+                bail!("init.verify.attempts must be at least 1");
+            }
+            if verify.expect.len() > verify.length {
+                bail!(
+                    "init.verify.expect has {} bytes but only {} are read, raise init.verify.length",
+                    verify.expect.len(),
+                    verify.length
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails for a control packet addressed to an interface, recipient 1 in
+    /// the low bits of `request_type`, whose `interface` (wIndex) is not one
+    /// of the device's, and for an interrupt packet without an OUT endpoint.
+    pub fn check_interfaces(&self, has_interface: impl Fn(u8) -> bool) -> Result<()> {
+        for (index, packet) in self.packets.iter().enumerate() {
+            if packet.kind == InitKind::Interrupt {
+                match packet.endpoint {
+                    Some(address) if address & 0x80 == 0 => continue,
+                    Some(address) => {
+                        bail!("Init packet {index} is written to {address:#04x}, an IN endpoint")
+                    }
+                    None => bail!("Init packet {index} is an interrupt write without an endpoint"),
+                }
+            }
+            let number = (packet.interface & 0xFF) as u8;
+            if packet.request_type & 0x1F == 0x01 && !has_interface(number) {
+                bail!(
+                    "Init packet {index} is addressed to interface {number}, which the device does not have"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends SET_IDLE for all reports to each of `interfaces` through
+    /// `send`, unless `set_idle` is off. A tablet without the request
+    /// stalls it, which is only logged.
+    pub fn send_set_idle(
+        &self,
+        interfaces: &[u8],
+        mut send: impl FnMut(&InitStep) -> Result<(), FtdError>,
+    ) {
+        if !self.set_idle {
+            return;
+        }
+        for &interface in interfaces {
+            let step = InitStep::Control(MessageDevice {
+                request_type: 0x21,
+                request: SET_IDLE,
+                value: (self.idle_duration as u16) << 8,
+                interface: interface as u16,
+                payload: vec![],
+                timeout: Duration::from_millis(1000),
+            });
+            if let Err(e) = send(&step) {
+                warn!("SET_IDLE on interface {interface} failed ({e}), carrying on without it");
+            }
+        }
+    }
+
+    /// Sends the packets in order through `send`, retrying each one that
+    /// fails. Stops at the first packet that fails every attempt.
+    pub fn send_packets(
+        &self,
+        mut send: impl FnMut(&InitStep) -> Result<(), FtdError>,
+    ) -> Result<(), FtdError> {
+        for (index, packet) in self.packets.iter().enumerate() {
+            let step = packet.step();
+            let mut attempt = 0;
+            while let Err(e) = send(&step) {
+                attempt += 1;
+                if attempt > self.retries {
+                    return Err(e);
+                }
+                warn!(
+                    "Init packet {index} failed ({e}), retrying ({attempt}/{})",
+                    self.retries
+                );
+                thread::sleep(Duration::from_millis(self.retry_delay_ms));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the packets, then waits `delay_after_ms`. With `verify` it
+    /// instead reads the report through `read` and sends the packets again
+    /// until it starts with `expect`, at most `attempts` times.
+    pub fn send_confirmed(
+        &self,
+        mut send: impl FnMut(&InitStep) -> Result<(), FtdError>,
+        mut read: impl FnMut(&InitVerify) -> Result<Vec<u8>, FtdError>,
+    ) -> Result<(), FtdError> {
+        let Some(verify) = &self.verify else {
+            let result = self.send_packets(send);
+            thread::sleep(Duration::from_millis(self.delay_after_ms));
+            return result;
+        };
+
+        let mut failure = FtdError::InitNotConfirmed(vec![]);
+        for attempt in 1..=verify.attempts {
+            self.send_packets(&mut send)?;
+            thread::sleep(Duration::from_millis(verify.delay_ms));
+            match read(verify) {
+                Ok(report) if report.starts_with(&verify.expect) => return Ok(()),
+                Ok(report) => {
+                    warn!(
+                        "The tablet answered {report:02x?} to the init packets, expected {:02x?} \
+                         ({attempt}/{})",
+                        verify.expect, verify.attempts
+                    );
+                    failure = FtdError::InitNotConfirmed(report);
+                }
+                Err(e) => {
+                    warn!(
+                        "Reading back the init state failed ({e}) ({attempt}/{})",
+                        verify.attempts
+                    );
+                    failure = e;
+                }
+            }
+        }
+
+        Err(failure)
+    }
+}
+
+/// The feature report that tells the init packets took, see
+/// `InitConfig::send_confirmed`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InitVerify {
+    pub interface: u16,
+    pub report_id: u8,
+    /// Bytes asked for, the tablet may send fewer.
+    pub length: usize,
+    /// What the report starts with once the tablet is in its full mode.
+    pub expect: Vec<u8>,
+    /// Rounds of packets and a read before giving up.
+    pub attempts: u32,
+    /// Between the packets and the read.
+    pub delay_ms: u64,
+}
+
+impl Default for InitVerify {
+    fn default() -> Self {
+        InitVerify {
+            interface: TABLET_INTERFACE as u16,
+            report_id: 0x02,
+            length: 16,
+            expect: vec![],
+            attempts: 3,
+            delay_ms: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitKind {
+    /// A control request made of `request_type` to `interface`.
+    #[default]
+    Control,
+    /// `payload` written to the interrupt OUT `endpoint`.
+    Interrupt,
+}
+
+/// An init packet as it goes out, see `InitPacket::step`.
+pub enum InitStep {
+    Control(MessageDevice),
+    Interrupt {
+        endpoint: u8,
+        payload: Vec<u8>,
+        timeout: Duration,
+    },
+}
+
+/// A class request, the default one is the magic packet from MTM-1106.md,
+/// or with `kind = "interrupt"` a write to `endpoint`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InitPacket {
+    pub kind: InitKind,
+    /// OUT endpoint address of an `interrupt` packet.
+    pub endpoint: Option<u8>,
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub interface: u16,
+    pub payload: Vec<u8>,
+    pub timeout_ms: u64,
+}
+
+impl Default for InitPacket {
+    fn default() -> Self {
+        InitPacket {
+            kind: InitKind::Control,
+            endpoint: None,
+            request_type: 0x21,
+            request: SET_REPORT,
+            value: 0x0202,
+            interface: TABLET_INTERFACE as u16,
+            payload: vec![0x02, 0x01],
+            timeout_ms: 1000,
+        }
+    }
+}
+
+impl InitPacket {
+    /// `check_interfaces` makes sure an interrupt packet has its endpoint.
+    pub fn step(&self) -> InitStep {
+        match (self.kind, self.endpoint) {
+            (InitKind::Interrupt, Some(endpoint)) => InitStep::Interrupt {
+                endpoint,
+                payload: self.payload.clone(),
+                timeout: Duration::from_millis(self.timeout_ms),
+            },
+            _ => InitStep::Control(self.message()),
+        }
+    }
+
+    pub fn message(&self) -> MessageDevice {
+        MessageDevice {
+            request_type: self.request_type,
+            request: self.request,
+            value: self.value,
+            interface: self.interface,
+            payload: self.payload.clone(),
+            timeout: Duration::from_millis(self.timeout_ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PressureConfig {
+    pub in_min: u16,
+    pub in_max: u16,
+    /// Exponent of `PressureCurve`, 1.0 is linear.
+    pub curve: f32,
+    pub smoothing: Option<f32>,
+    /// Pressure ranges that fire a `keys` or `scroll` action when pressed into.
+    pub bands: Vec<PressureBand>,
+    /// How far past a band's edge the pressure must go to leave it.
+    pub band_hysteresis: u16,
+}
+
+impl Default for PressureConfig {
+    fn default() -> Self {
+        let range = PressureRange::default();
+        PressureConfig {
+            in_min: range.in_min,
+            in_max: range.in_max,
+            curve: 1.0,
+            smoothing: None,
+            bands: vec![],
+            band_hysteresis: 200,
+        }
+    }
+}
+
+impl PressureConfig {
+    /// Range, curve and smoothing, without the bands.
+    pub fn pipeline(&self) -> Result<PressurePipeline> {
+        PressurePipeline::new(
+            PressureRange::new(self.in_min, self.in_max)?,
+            PressureCurve::new(self.curve)?,
+            self.smoothing,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TouchConfig {
+    pub ignore: bool,
+    pub tap_time_ms: u64,
+    pub tap_distance: u16,
+    /// Width of the scroll strip on the right edge, 0 disables it.
+    pub edge_scroll: u16,
+}
+
+impl Default for TouchConfig {
+    fn default() -> Self {
+        let tap = TapSettings::default();
+        TouchConfig {
+            ignore: false,
+            tap_time_ms: tap.max_duration.as_millis() as u64,
+            tap_distance: tap.max_distance,
+            edge_scroll: EdgeSettings::default().width,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingConfig {
+    /// xrandr output the pen is mapped to.
+    pub output: Option<String>,
+    /// Fixed `(width, height)` screen, takes precedence over `output`.
+    pub screen: Option<(i32, i32)>,
+    /// Affine correction of the raw coordinates, see `mapper::Affine`.
+    pub matrix: Option<Affine>,
+    pub area: Area,
+    /// More areas a `cycle_area` button steps through after `area`.
+    pub areas: Vec<Area>,
+    pub rotation: Rotation,
+    /// Trims the edges the pen cannot reach off every area, see
+    /// `mapper::Padding`.
+    pub padding: Padding,
+    /// Pick `rotation` and `area` so the whole width or height of the
+    /// tablet maps onto the output without stretching, see `mapper::fit`.
+    /// The two settings above are ignored then.
+    pub fit: bool,
+    /// Keeps the mapped cursor inside this part of the desktop, in pixels,
+    /// e.g. `{ x = 200, y = 100, width = 800, height = 600 }` to lock the
+    /// pen to a window. Applied after everything else, `area` still picks
+    /// what of the tablet is mapped.
+    pub clamp: Option<Rect>,
+    /// Move the cursor with the hovering pen, off sends the position only
+    /// while the tip is down.
+    pub hover_moves_cursor: bool,
+    /// Put the cursor in the middle of the output as soon as the virtual
+    /// device exists, for compositors that hide it until a tablet has
+    /// reported a position. Off leaves it where it is until the pen hovers.
+    pub center_on_start: bool,
+    /// Give the pen axes the tablet's own range across the desktop, for
+    /// the compositor to map without rounding to whole pixels. Off sends
+    /// desktop pixels.
+    pub full_resolution: bool,
+}
+
+impl Default for MappingConfig {
+    fn default() -> Self {
+        MappingConfig {
+            output: None,
+            screen: None,
+            matrix: None,
+            area: Area::default(),
+            areas: vec![],
+            rotation: Rotation::default(),
+            padding: Padding::default(),
+            fit: false,
+            clamp: None,
+            hover_moves_cursor: true,
+            center_on_start: false,
+            full_resolution: false,
+        }
+    }
+}
+
+/// Where a config is looked for without `--config`, most specific first:
+/// `$XDG_CONFIG_HOME/freetomate/config.toml` (`~/.config` when unset), then
+/// `/etc/freetomate/config.toml`.
+pub fn search_paths() -> Vec<PathBuf> {
+    let user = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    user.map(|dir| dir.join("freetomate").join("config.toml"))
+        .into_iter()
+        .chain([PathBuf::from("/etc/freetomate/config.toml")])
+        .collect()
+}
+
+/// `explicit` when given, even if missing so that loading it fails loudly,
+/// otherwise the first of `candidates` that exists.
+pub fn locate(explicit: Option<&Path>, candidates: &[PathBuf]) -> Option<PathBuf> {
+    match explicit {
+        Some(path) => Some(path.to_path_buf()),
+        None => candidates.iter().find(|path| path.is_file()).cloned(),
+    }
+}
+
+/// Profiles and bindings from one file of the directory next to a config,
+/// see `Config::load`. Other sections are an error rather than ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fragment {
+    /// Replaces the bindings of the default profile, as do the ones of the
+    /// files after it.
+    pub button_map: Option<Vec<Action>>,
+    /// Added after the profiles of the config and the files before it. A
+    /// name that is already taken is an error, not an override.
+    pub profiles: Vec<ProfileConfig>,
+}
+
+/// The `.toml` files in `dir`, sorted by name, none when it is missing.
+pub fn fragment_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).context(format!("Failed to list {}", dir.display())),
+    };
+    let mut paths = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Writes `profile` as the fragment `profile-<name>.toml` next to the
+/// config at `path`, where `Config::load` picks it up, and returns where.
+/// Saving a profile again replaces its file, but a name the config or
+/// another fragment already uses is refused.
+pub fn save_profile(path: &Path, config: &Config, profile: &ProfileConfig) -> Result<PathBuf> {
+    if profile.name == DEFAULT_PROFILE {
+        bail!("The default profile is the config itself, pick another name");
+    }
+    let file_name: String = profile
+        .name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let dir = path.with_extension("d");
+    let fragment = dir.join(format!("profile-{file_name}.toml"));
+    if !fragment.exists() && config.profiles.iter().any(|p| p.name == profile.name) {
+        bail!(
+            "Profile `{}` is already defined, pick another name",
+            profile.name
+        );
+    }
+
+    let text = toml::to_string(&Fragment {
+        button_map: None,
+        profiles: vec![profile.clone()],
+    })?;
+    fs::create_dir_all(&dir)?;
+    fs::write(&fragment, text)
+        .with_context(|| format!("Failed to write {}", fragment.display()))?;
+
+    Ok(fragment)
+}
+
+/// Writes `value` as `mapping.<key>` into the config at `path`, creating
+/// it if needed and keeping every other key as it was.
+pub fn save_mapping(path: &Path, key: &str, value: &impl Serialize) -> Result<()> {
+    let mut document: toml::Table = match fs::read_to_string(path) {
+        Ok(text) => text
+            .parse()
+            .with_context(|| format!("Invalid config {}", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+        Err(e) => return Err(e).context(format!("Failed to read config {}", path.display())),
+    };
+
+    if !document.contains_key("mapping") {
+        document.insert(
+            "mapping".to_string(),
+            toml::Value::Table(toml::Table::new()),
+        );
+    }
+    let Some(mapping) = document
+        .get_mut("mapping")
+        .and_then(toml::Value::as_table_mut)
+    else {
+        bail!("`mapping` in {} is not a table", path.display());
+    };
+    mapping.insert(key.to_string(), toml::Value::try_from(value)?);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, document.to_string())
+        .with_context(|| format!("Failed to write config {}", path.display()))
+}
+
+impl Config {
+    /// The config at `path` with the fragments of the directory next to
+    /// it merged in, in the order of their names: `config.d/*.toml` for
+    /// `config.toml`.
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        let mut config =
+            Config::parse(&text).with_context(|| format!("Invalid config {}", path.display()))?;
+
+        for fragment in fragment_paths(&path.with_extension("d"))? {
+            info!("Merging config fragment {}", fragment.display());
+            let text = fs::read_to_string(&fragment)
+                .with_context(|| format!("Failed to read {}", fragment.display()))?;
+            toml::from_str(&text)
+                .map_err(anyhow::Error::from)
+                .and_then(|parsed| config.merge(parsed))
+                .with_context(|| format!("Invalid config fragment {}", fragment.display()))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Adds the profiles of `fragment` and takes its bindings, see
+    /// `Fragment`.
+    pub fn merge(&mut self, fragment: Fragment) -> Result<()> {
+        for profile in &fragment.profiles {
+            if self.profiles.iter().any(|known| known.name == profile.name) {
+                bail!("Profile `{}` is already defined", profile.name);
+            }
+        }
+        if let Some(button_map) = fragment.button_map {
+            self.button_map = button_map;
+        }
+        self.profiles.extend(fragment.profiles);
+
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<Config> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// The whole config as TOML, which `parse` reads back unchanged.
+    pub fn to_text(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Fills in what the tablet reported, never overriding explicit values.
+    pub fn seed(&mut self, capabilities: &Capabilities) {
+        self.device
+            .resolution
+            .get_or_insert(capabilities.resolution);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smoothing::SmoothingMode;
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut config = Config::default();
+        config.pressure.smoothing = Some(0.25);
+        config.mapping.rotation = Rotation::Cw90;
+        config.mapping.matrix = Some(Affine([[0.0, -0.5, 20000.0], [0.5, 0.0, 0.0]]));
+        config.smoothing.mode = SmoothingMode::Adaptive;
+        config.device.resolution = Some((100, 120));
+        config.button_map[0] = Action::Command {
+            program: "krita".to_string(),
+            args: vec!["--nosplash".to_string()],
+        };
+
+        let text = toml::to_string(&config).unwrap();
+        assert_eq!(Config::parse(&text).unwrap(), config);
+    }
+
+    #[test]
+    fn minimal_config_keeps_the_defaults() {
+        let config = Config::parse(
+            "[device]\n\
+             product_id = 0x6812\n\
+             [mapping]\n\
+             rotation = \"half\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.device.product_id, 0x6812);
+        assert_eq!(config.device.vendor_id, VENDOR_ID);
+        assert_eq!(config.mapping.rotation, Rotation::Half);
+        assert_eq!(config.init, InitConfig::default());
+        assert_eq!(config.button_map, default_button_map());
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn lazy_claiming_takes_only_the_read_interfaces() {
+        let mut device = DeviceConfig::default();
+        assert_eq!(
+            device.claimed_interfaces(),
+            vec![MASS_STORAGE, BUTTONS_INTERAFCE, TABLET_INTERFACE]
+        );
+
+        device.claim_read_only = true;
+        assert_eq!(
+            device.claimed_interfaces(),
+            vec![BUTTONS_INTERAFCE, TABLET_INTERFACE]
+        );
+
+        device.buttons_interface = TABLET_INTERFACE;
+        assert_eq!(device.claimed_interfaces(), vec![TABLET_INTERFACE]);
+    }
+
+    #[test]
+    fn kept_mass_storage_is_neither_detached_nor_claimed() {
+        let device = DeviceConfig {
+            keep_mass_storage: true,
+            ..DeviceConfig::default()
+        };
+
+        assert_eq!(
+            device.claimed_interfaces(),
+            vec![BUTTONS_INTERAFCE, TABLET_INTERFACE]
+        );
+        assert!(device.expected_interfaces().contains(&MASS_STORAGE));
+    }
+
+    #[test]
+    fn long_read_timeouts_only_raise_the_short_ones() {
+        let mut device =
+            Config::parse("[device]\nread_timeouts = \"long\"\ntablet_timeout_ms = 5000\n")
+                .unwrap()
+                .device;
+        assert_eq!(device.read_timeouts_ms(), [LONG_READ_TIMEOUT_MS, 5000]);
+
+        device.read_timeouts = ReadTimeouts::Short;
+        assert_eq!(device.read_timeouts_ms(), [10, 5000]);
+    }
+
+    #[test]
+    fn revision_quirks_apply_only_to_their_revision() {
+        let mut device = Config::parse(
+            "[[device.revision_quirks]]\n\
+             revision = 0x0201\n\
+             swap_xy = true\n\
+             ignore_buttons = [3]\n",
+        )
+        .unwrap()
+        .device;
+
+        assert!(!device.clone().use_revision(0x0102));
+        assert!(device.use_revision(0x0201));
+        assert!(device.swap_xy);
+        assert_eq!(device.ignore_buttons, vec![3]);
+        assert!(!device.tip_needs_in_range);
+        assert_eq!(device.reports, default_routes());
+    }
+
+    #[test]
+    fn config_search_prefers_the_flag_then_the_user_then_the_system() {
+        let dir = env::temp_dir().join(format!("ftd-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.toml");
+        let system = dir.join("system.toml");
+        let candidates = [user.clone(), system.clone()];
+
+        assert_eq!(locate(None, &candidates), None);
+
+        fs::write(&system, "").unwrap();
+        assert_eq!(locate(None, &candidates), Some(system.clone()));
+
+        fs::write(&user, "").unwrap();
+        assert_eq!(locate(None, &candidates), Some(user.clone()));
+
+        let flag = dir.join("missing.toml");
+        assert_eq!(locate(Some(&flag), &candidates), Some(flag));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fragments_merge_in_name_order_and_reject_a_taken_profile() {
+        let dir = env::temp_dir().join(format!("ftd-fragments-{}", std::process::id()));
+        let fragments = dir.join("config.d");
+        fs::create_dir_all(&fragments).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "[[profiles]]\nname = \"krita\"\n").unwrap();
+        fs::write(
+            fragments.join("20-inkscape.toml"),
+            "button_map = [{ scroll = 2 }]\n[[profiles]]\nname = \"inkscape\"\n",
+        )
+        .unwrap();
+        fs::write(
+            fragments.join("10-gimp.toml"),
+            "button_map = [{ scroll = 1 }]\n[[profiles]]\nname = \"gimp\"\n",
+        )
+        .unwrap();
+        fs::write(fragments.join("notes.txt"), "not a fragment").unwrap();
+
+        let config = Config::load(&path).unwrap();
+        let names: Vec<&str> = config.profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["krita", "gimp", "inkscape"]);
+        assert_eq!(config.button_map, vec![Action::Scroll(2)]);
+
+        fs::write(
+            fragments.join("30-krita.toml"),
+            "[[profiles]]\nname = \"krita\"\n",
+        )
+        .unwrap();
+        let error = format!("{:#}", Config::load(&path).unwrap_err());
+        assert!(error.contains("30-krita.toml"), "{error}");
+        assert!(
+            error.contains("Profile `krita` is already defined"),
+            "{error}"
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_saved_profile_loads_back_with_the_config() {
+        let dir = env::temp_dir().join(format!("ftd-save-profile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "[[profiles]]\nname = \"krita\"\n").unwrap();
+        let config = Config::load(&path).unwrap();
+        let profile = ProfileConfig {
+            name: "My Sketch".to_string(),
+            button_map: Some(vec![Action::Scroll(3), Action::CycleArea]),
+            pressure_curve: Some(0.7),
+            area: Some(Area {
+                x_min: 100,
+                y_min: 200,
+                x_max: 20000,
+                y_max: 15000,
+            }),
+            rotation: Some(Rotation::Cw90),
+            pen: None,
+            window_class: None,
+            process_name: None,
+        };
+
+        let saved = save_profile(&path, &config, &profile).unwrap();
+        assert_eq!(saved, dir.join("config.d").join("profile-My_Sketch.toml"));
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.profiles.len(), 2);
+        assert_eq!(config.profiles[1], profile);
+
+        let again = ProfileConfig {
+            pressure_curve: Some(1.5),
+            ..profile
+        };
+        save_profile(&path, &config, &again).unwrap();
+        assert_eq!(Config::load(&path).unwrap().profiles[1], again);
+
+        let krita = ProfileConfig {
+            name: "krita".to_string(),
+            ..again
+        };
+        assert!(save_profile(&path, &config, &krita).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn saving_a_mapping_key_keeps_the_other_keys() {
+        let path = env::temp_dir().join(format!("ftd-area-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "profile = \"krita\"\n[mapping]\nrotation = \"half\"\n",
+        )
+        .unwrap();
+        let area = Area {
+            x_min: 100,
+            y_min: 90,
+            x_max: 32610,
+            y_max: 32500,
+        };
+
+        save_mapping(&path, "area", &area).unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.mapping.area, area);
+        assert_eq!(config.mapping.rotation, Rotation::Half);
+        assert_eq!(config.profile.as_deref(), Some("krita"));
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn init_retries_a_failing_packet_then_gives_up() {
+        let init = InitConfig {
+            packets: vec![InitPacket::default(); 2],
+            retries: 2,
+            retry_delay_ms: 0,
+            ..InitConfig::default()
+        };
+
+        let mut failures = 2;
+        let mut sent = 0;
+        let result = init.send_packets(|_| {
+            if failures > 0 {
+                failures -= 1;
+                return Err(FtdError::Usb(rusb::Error::Pipe));
+            }
+            sent += 1;
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(sent, 2);
+
+        let mut attempts = 0;
+        let result = init.send_packets(|_| {
+            attempts += 1;
+            Err(FtdError::Usb(rusb::Error::Io))
+        });
+        assert!(matches!(result, Err(FtdError::Usb(rusb::Error::Io))));
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn init_is_sent_again_until_the_tablet_confirms_it() {
+        let init: InitConfig =
+            toml::from_str("[verify]\nreport_id = 0x05\nexpect = [0x05, 0x01]\ndelay_ms = 0\n")
+                .unwrap();
+        init.validate().unwrap();
+
+        // The tablet only switches on the second round of packets.
+        let sent = std::cell::Cell::new(0);
+        let mut reads = 0;
+        let result = init.send_confirmed(
+            |_| {
+                sent.set(sent.get() + 1);
+                Ok(())
+            },
+            |verify| {
+                assert_eq!((verify.report_id, verify.length), (0x05, 16));
+                reads += 1;
+                Ok(match sent.get() {
+                    2 => vec![0x05, 0x01, 0x7f],
+                    _ => vec![0x05, 0x00],
+                })
+            },
+        );
+        assert!(result.is_ok());
+        assert_eq!((sent.get(), reads), (2, 2));
+
+        let result = init.send_confirmed(|_| Ok(()), |_| Ok(vec![0x05, 0x00]));
+        assert!(
+            matches!(result, Err(FtdError::InitNotConfirmed(report)) if report == [0x05, 0x00])
+        );
+    }
+
+    #[test]
+    fn set_idle_goes_to_each_read_interface_and_a_stall_is_not_fatal() {
+        let init: InitConfig = toml::from_str("idle_duration = 5").unwrap();
+        let device = DeviceConfig::default();
+
+        let mut sent = vec![];
+        init.send_set_idle(&device.report_interfaces(), |step| {
+            if let InitStep::Control(message) = step {
+                sent.push((
+                    message.request_type,
+                    message.request,
+                    message.value,
+                    message.interface,
+                    message.payload.len(),
+                ));
+            }
+            Err(FtdError::Usb(rusb::Error::Pipe))
+        });
+        assert_eq!(
+            sent,
+            vec![
+                (0x21, 0x0A, 0x0500, BUTTONS_INTERAFCE as u16, 0),
+                (0x21, 0x0A, 0x0500, TABLET_INTERFACE as u16, 0),
+            ]
+        );
+
+        let skipped = InitConfig {
+            set_idle: false,
+            ..init
+        };
+        skipped.send_set_idle(&[1], |_| panic!("SET_IDLE was sent"));
+    }
+
+    #[test]
+    fn init_packets_keep_their_configured_index() {
+        let init: InitConfig = toml::from_str(
+            "retry_delay_ms = 0\n[[packets]]\ninterface = 1\n[[packets]]\nrequest_type = 0x40\ninterface = 0x0304\n",
+        )
+        .unwrap();
+
+        let mut indices = vec![];
+        init.send_packets(|step| {
+            if let InitStep::Control(message) = step {
+                indices.push(message.interface);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(indices, vec![1, 0x0304]);
+
+        // The vendor request is not checked, the interface request is.
+        assert!(init.check_interfaces(|number| number == 1).is_ok());
+        assert!(init.check_interfaces(|number| number == 2).is_err());
+    }
+
+    #[test]
+    fn the_configured_value_is_sent_unchanged() {
+        let init: InitConfig = toml::from_str("[[packets]]\nvalue = 0x0305\n").unwrap();
+
+        let mut values = vec![];
+        init.send_packets(|step| {
+            if let InitStep::Control(message) = step {
+                values.push(message.value);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(values, vec![0x0305]);
+        assert!(init.validate().is_ok());
+
+        let not_a_report: InitConfig = toml::from_str("[[packets]]\nvalue = 0x0402\n").unwrap();
+        assert!(not_a_report.validate().is_err());
+        let vendor: InitConfig =
+            toml::from_str("[[packets]]\nrequest_type = 0x40\nvalue = 0x0402\n").unwrap();
+        assert!(vendor.validate().is_ok());
+    }
+
+    #[test]
+    fn interrupt_packets_are_written_to_their_endpoint() {
+        let init: InitConfig = toml::from_str(
+            "[[packets]]\nkind = \"interrupt\"\nendpoint = 0x03\npayload = [0x02, 0x01]\n",
+        )
+        .unwrap();
+
+        let mut writes = vec![];
+        init.send_packets(|step| match step {
+            InitStep::Interrupt {
+                endpoint, payload, ..
+            } => {
+                writes.push((*endpoint, payload.clone()));
+                Ok(())
+            }
+            InitStep::Control(_) => panic!("sent as a control request"),
+        })
+        .unwrap();
+        assert_eq!(writes, vec![(0x03, vec![0x02, 0x01])]);
+        assert!(init.check_interfaces(|_| false).is_ok());
+
+        let mut to_in = init.clone();
+        to_in.packets[0].endpoint = Some(0x83);
+        assert!(to_in.check_interfaces(|_| true).is_err());
+        to_in.packets[0].endpoint = None;
+        assert!(to_in.check_interfaces(|_| true).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    thread,
+};
+
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender};
+use log::{debug, warn};
+
+/// A line received on the control socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    Enable,
+    Disable,
+    SetProfile(String),
+    /// Writes what is in effect as a new profile, see
+    /// `ControlTarget::save_profile`.
+    SaveProfile(String),
+    Reload,
+    Reinit,
+    Status,
+    /// The config in effect, as JSON.
+    GetConfig,
+    /// What the driver is doing, as JSON, see `ControlTarget::state`.
+    GetState,
+    /// Logs the raw packets of one interface, or stops, see `RawDump`.
+    Dump {
+        interface: u8,
+        on: bool,
+    },
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> Result<ControlCommand, String> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        let command = match name {
+            "enable" => ControlCommand::Enable,
+            "disable" => ControlCommand::Disable,
+            "set-profile" if rest.is_empty() => return Err("usage: set-profile <name>".into()),
+            "set-profile" => return Ok(ControlCommand::SetProfile(rest.to_string())),
+            "save-profile" if rest.is_empty() => return Err("usage: save-profile <name>".into()),
+            "save-profile" => return Ok(ControlCommand::SaveProfile(rest.to_string())),
+            "reload" => ControlCommand::Reload,
+            "reinit" => ControlCommand::Reinit,
+            "status" => ControlCommand::Status,
+            "get-config" => ControlCommand::GetConfig,
+            "get-state" => ControlCommand::GetState,
+            "dump" => return parse_dump(rest),
+            _ => return Err(format!("unknown command `{name}`")),
+        };
+
+        if !rest.is_empty() {
+            return Err(format!("`{name}` takes no arguments"));
+        }
+
+        Ok(command)
+    }
+}
+
+/// `on 2` or `off 2` of `dump on 2`.
+fn parse_dump(rest: &str) -> Result<ControlCommand, String> {
+    let usage = || "usage: dump on|off <interface>".to_string();
+    let (switch, interface) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+    let on = match switch {
+        "on" => true,
+        "off" => false,
+        _ => return Err(usage()),
+    };
+    let interface = interface.trim().parse().map_err(|_| usage())?;
+
+    Ok(ControlCommand::Dump { interface, on })
+}
+
+/// What the control commands act on.
+pub trait ControlTarget {
+    fn set_enabled(&mut self, enabled: bool) -> Result<()>;
+    fn set_profile(&mut self, name: &str) -> Result<()>;
+    /// Saves the bindings, pressure curve, area and rotation in effect as
+    /// the profile `name`, loaded from the next `reload` on.
+    fn save_profile(&mut self, name: &str) -> Result<()>;
+    fn reload(&mut self) -> Result<()>;
+    fn reinit(&mut self) -> Result<()>;
+    /// Starts or stops logging the raw packets read on `interface`.
+    fn set_dump(&mut self, interface: u8, on: bool) -> Result<()>;
+    fn status(&self) -> String;
+    /// The effective config as one line of JSON.
+    fn config(&self) -> Result<String>;
+    /// Enabled or not, the active profile, the mapped output and how long
+    /// ago the last event went out, as one line of JSON.
+    fn state(&self) -> String;
+}
+
+/// Runs `command` and returns the reply line, `ok ...` or `error: ...`.
+/// The JSON of `get-config` and `get-state` follows the `ok `.
+pub fn dispatch(command: &ControlCommand, target: &mut dyn ControlTarget) -> String {
+    let result = match command {
+        ControlCommand::GetConfig => {
+            return match target.config() {
+                Ok(json) => format!("ok {json}"),
+                Err(e) => format!("error: {e:#}"),
+            };
+        }
+        ControlCommand::GetState => return format!("ok {}", target.state()),
+        ControlCommand::Enable => target.set_enabled(true),
+        ControlCommand::Disable => target.set_enabled(false),
+        ControlCommand::SetProfile(name) => target.set_profile(name),
+        ControlCommand::SaveProfile(name) => target.save_profile(name),
+        ControlCommand::Reload => target.reload(),
+        ControlCommand::Reinit => target.reinit(),
+        ControlCommand::Dump { interface, on } => target.set_dump(*interface, *on),
+        ControlCommand::Status => Ok(()),
+    };
+
+    match result {
+        Ok(()) => format!("ok {}", target.status()),
+        Err(e) => format!("error: {e:#}"),
+    }
+}
+
+/// A parsed command handed from a connection thread to `run_requests`,
+/// with where the reply line goes.
+pub struct Request {
+    pub command: ControlCommand,
+    pub reply: Sender<String>,
+}
+
+/// Runs the pending `requests` against `target`.
+pub fn run_requests(requests: &Receiver<Request>, target: &mut dyn ControlTarget) {
+    while let Ok(request) = requests.try_recv() {
+        debug!("Control command {:?}", request.command);
+        let _ = request.reply.send(dispatch(&request.command, target));
+    }
+}
+
+/// Unix socket taking one command per line, e.g.
+/// `echo disable | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/freetomate.sock`.
+///
+/// Connections are served on background threads, the commands themselves
+/// run on the caller's thread in `poll`.
+pub struct ControlServer {
+    path: PathBuf,
+    requests: Receiver<Request>,
+}
+
+impl ControlServer {
+    pub fn default_path() -> PathBuf {
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join("freetomate.sock")
+    }
+
+    pub fn bind(path: &Path) -> io::Result<ControlServer> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another instance", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+        let (sender, requests) = crossbeam_channel::unbounded();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || serve(stream, sender));
+                    }
+                    Err(e) => warn!("Control socket accept failed: {e}"),
+                }
+            }
+        });
+
+        Ok(ControlServer {
+            path: path.to_path_buf(),
+            requests,
+        })
+    }
+
+    /// Runs the pending commands against `target`.
+    pub fn poll(&self, target: &mut dyn ControlTarget) {
+        run_requests(&self.requests, target);
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn serve(stream: UnixStream, requests: Sender<Request>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match ControlCommand::parse(&line) {
+            Ok(command) => {
+                let (reply, answer) = crossbeam_channel::bounded(1);
+                if requests.send(Request { command, reply }).is_err() {
+                    return;
+                }
+                answer
+                    .recv()
+                    .unwrap_or_else(|_| "error: driver stopped".into())
+            }
+            Err(e) => format!("error: {e}"),
+        };
+
+        if writeln!(writer, "{reply}").is_err() {
+            return;
+        }
+    }
+}
+
+/// Records what the commands did, for the tests of the control servers.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeTarget {
+    pub enabled: bool,
+    pub profile: String,
+    pub reinits: u32,
+    pub dumped: Vec<u8>,
+}
+
+#[cfg(test)]
+impl ControlTarget for FakeTarget {
+    fn set_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.enabled = enabled;
+        Ok(())
+    }
+
+    fn set_profile(&mut self, name: &str) -> Result<()> {
+        if name != "krita" {
+            anyhow::bail!("Unknown profile `{name}`");
+        }
+        self.profile = name.to_string();
+        Ok(())
+    }
+
+    fn save_profile(&mut self, _name: &str) -> Result<()> {
+        anyhow::bail!("Nothing to save to")
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        anyhow::bail!("Nothing to reload from")
+    }
+
+    fn reinit(&mut self) -> Result<()> {
+        self.reinits += 1;
+        Ok(())
+    }
+
+    fn set_dump(&mut self, interface: u8, on: bool) -> Result<()> {
+        self.dumped.retain(|&dumped| dumped != interface);
+        if on {
+            self.dumped.push(interface);
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> String {
+        format!("enabled={} profile={}", self.enabled, self.profile)
+    }
+
+    fn config(&self) -> Result<String> {
+        crate::json::to_json(&crate::config::Config::default())
+    }
+
+    fn state(&self) -> String {
+        format!(
+            r#"{{"enabled":{},"profile":{}}}"#,
+            self.enabled,
+            crate::json::quote(&self.profile)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_rejects_bad_lines() {
+        assert_eq!(
+            ControlCommand::parse(" enable \n"),
+            Ok(ControlCommand::Enable)
+        );
+        assert_eq!(
+            ControlCommand::parse("set-profile  My Sketch "),
+            Ok(ControlCommand::SetProfile("My Sketch".into()))
+        );
+        assert!(ControlCommand::parse("set-profile").is_err());
+        assert_eq!(
+            ControlCommand::parse("save-profile inking"),
+            Ok(ControlCommand::SaveProfile("inking".into()))
+        );
+        assert!(ControlCommand::parse("save-profile ").is_err());
+        assert!(ControlCommand::parse("reinit now").is_err());
+        assert_eq!(
+            ControlCommand::parse("dump on 2"),
+            Ok(ControlCommand::Dump {
+                interface: 2,
+                on: true
+            })
+        );
+        assert!(ControlCommand::parse("dump 2").is_err());
+        assert!(ControlCommand::parse("dump off tablet").is_err());
+        assert!(ControlCommand::parse("explode").is_err());
+    }
+
+    #[test]
+    fn dispatch_replies_with_the_outcome() {
+        let mut target = FakeTarget::default();
+
+        assert_eq!(
+            dispatch(&ControlCommand::Enable, &mut target),
+            "ok enabled=true profile="
+        );
+        assert_eq!(
+            dispatch(&ControlCommand::SetProfile("krita".into()), &mut target),
+            "ok enabled=true profile=krita"
+        );
+        assert_eq!(
+            dispatch(&ControlCommand::SetProfile("gimp".into()), &mut target),
+            "error: Unknown profile `gimp`"
+        );
+        assert_eq!(
+            dispatch(&ControlCommand::Reload, &mut target),
+            "error: Nothing to reload from"
+        );
+        dispatch(&ControlCommand::Reinit, &mut target);
+        assert_eq!(target.reinits, 1);
+    }
+
+    #[test]
+    fn get_state_and_get_config_reply_with_json() {
+        let mut target = FakeTarget {
+            enabled: true,
+            profile: "My \"Sketch\"".into(),
+            reinits: 0,
+            dumped: vec![],
+        };
+
+        assert_eq!(
+            dispatch(&ControlCommand::parse("get-state").unwrap(), &mut target),
+            r#"ok {"enabled":true,"profile":"My \"Sketch\""}"#
+        );
+
+        let reply = dispatch(&ControlCommand::parse("get-config").unwrap(), &mut target);
+        let json = reply.strip_prefix("ok ").unwrap();
+        assert!(json.starts_with('{') && json.ends_with('}'));
+        assert!(json.contains(r#""device_name":"FreeTomate Tablet""#));
+        assert!(json.contains(r#""vendor_id":"#));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+        assert_eq!(json.matches('[').count(), json.matches(']').count());
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    fmt::Write,
+    fs,
+    time::{Duration, Instant},
+};
+
+use rusb::{Device, DeviceHandle, UsbContext};
+
+use ftd_device::device::{InterfaceInfo, read_device};
+
+use crate::version;
+
+/// How long `capture` records reports for.
+pub const CAPTURE: Duration = Duration::from_secs(5);
+const REDACTED: &str = "<redacted, pass --include-serial to keep it>";
+
+/// The text file `diagnostics` writes, one titled section after the other.
+#[derive(Debug, Default)]
+pub struct Bundle {
+    text: String,
+}
+
+impl Bundle {
+    pub fn section(&mut self, title: &str, body: &str) {
+        let _ = writeln!(self.text, "== {title} ==");
+        let _ = writeln!(self.text, "{}\n", body.trim_end());
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+}
+
+/// The build as `version` prints it, and the kernel version.
+pub fn environment() -> String {
+    let kernel = fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|release| release.trim().to_string())
+        .unwrap_or_else(|e| format!("unknown ({e})"));
+
+    format!("{}\nkernel {kernel}", version::about())
+}
+
+/// The serial as read, or a placeholder so the bundle can be posted publicly.
+pub fn serial_line(serial: rusb::Result<String>, include_serial: bool) -> String {
+    match serial {
+        Ok(_) if !include_serial => format!("Serial: {REDACTED}"),
+        Ok(serial) => format!("Serial: {serial}"),
+        Err(e) => format!("Serial: unreadable ({e})"),
+    }
+}
+
+/// Every descriptor of `device`, like `lsusb -v` but limited to what the
+/// driver looks at. The strings need the device to open, they are noted as
+/// unreadable otherwise.
+pub fn describe_device<T: UsbContext>(device: &Device<T>, include_serial: bool) -> String {
+    let mut out = String::new();
+    let descriptor = match device.device_descriptor() {
+        Ok(descriptor) => descriptor,
+        Err(e) => return format!("Failed to read the device descriptor: {e}"),
+    };
+
+    let _ = writeln!(
+        out,
+        "Bus {:03} Device {:03}: ID {:04x}:{:04x}, USB {}, class {:#04x}",
+        device.bus_number(),
+        device.address(),
+        descriptor.vendor_id(),
+        descriptor.product_id(),
+        descriptor.usb_version(),
+        descriptor.class_code()
+    );
+    match device.open() {
+        Ok(handle) => write_strings(&mut out, &handle, &descriptor, include_serial),
+        Err(e) => {
+            let _ = writeln!(out, "Strings unreadable, the device did not open ({e})");
+        }
+    }
+
+    let active = device
+        .active_config_descriptor()
+        .map(|config| config.number());
+    for index in 0..descriptor.num_configurations() {
+        let config = match device.config_descriptor(index) {
+            Ok(config) => config,
+            Err(e) => {
+                let _ = writeln!(out, "Configuration #{index}: unreadable ({e})");
+                continue;
+            }
+        };
+        let marker = if active.as_ref() == Ok(&config.number()) {
+            " (active)"
+        } else {
+            ""
+        };
+        let _ = writeln!(out, "Configuration {}{marker}", config.number());
+        for interface in config.interfaces() {
+            for setting in interface.descriptors() {
+                let _ = writeln!(
+                    out,
+                    "  Interface {} alt {}: class {:#04x} subclass {:#04x} protocol {:#04x}",
+                    setting.interface_number(),
+                    setting.setting_number(),
+                    setting.class_code(),
+                    setting.sub_class_code(),
+                    setting.protocol_code()
+                );
+                for endpoint in setting.endpoint_descriptors() {
+                    let _ = writeln!(
+                        out,
+                        "    Endpoint {:#04x} {:?} {:?}, max packet {}, interval {}",
+                        endpoint.address(),
+                        endpoint.direction(),
+                        endpoint.transfer_type(),
+                        endpoint.max_packet_size(),
+                        endpoint.interval()
+                    );
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn write_strings<T: UsbContext>(
+    out: &mut String,
+    handle: &DeviceHandle<T>,
+    descriptor: &rusb::DeviceDescriptor,
+    include_serial: bool,
+) {
+    let unreadable = |e: rusb::Error| format!("unreadable ({e})");
+    let manufacturer = handle
+        .read_manufacturer_string_ascii(descriptor)
+        .unwrap_or_else(unreadable);
+    let product = handle
+        .read_product_string_ascii(descriptor)
+        .unwrap_or_else(unreadable);
+    let serial = handle.read_serial_number_string_ascii(descriptor);
+
+    let _ = writeln!(out, "Manufacturer: {manufacturer}");
+    let _ = writeln!(out, "Product: {product}");
+    let _ = writeln!(out, "{}", serial_line(serial, include_serial));
+}
+
+/// Reads `interfaces` in turn for `duration` and keeps every report in the
+/// lines the driver prints, so the capture can go straight to `FTD_REPLAY`.
+pub fn capture<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interfaces: &[InterfaceInfo],
+    duration: Duration,
+) -> String {
+    let mut out = String::new();
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        for interface in interfaces {
+            match read_device(handle, interface, 10) {
+                Ok((id, bytes)) => {
+                    let _ = writeln!(out, "Interface: {id} || Bytes: {bytes:02X?}");
+                }
+                Err(rusb::Error::Timeout) => {}
+                Err(e) => {
+                    let _ = writeln!(out, "Interface {}: read failed ({e})", interface.number);
+                    return out;
+                }
+            }
+        }
+    }
+
+    if out.is_empty() {
+        out.push_str("No reports, was the tablet touched during the capture?");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay;
+
+    #[test]
+    fn redacts_the_serial_unless_asked() {
+        let serial = || Ok("MTM1106-00042".to_string());
+
+        assert!(!serial_line(serial(), false).contains("00042"));
+        assert_eq!(serial_line(serial(), true), "Serial: MTM1106-00042");
+        assert!(serial_line(Err(rusb::Error::Pipe), false).contains("unreadable"));
+    }
+
+    #[test]
+    fn sections_follow_each_other_and_reports_replay() {
+        let mut bundle = Bundle::default();
+        bundle.section("Environment", "kernel 6.1\n");
+        bundle.section("Reports", "Interface: 2 || Bytes: [09, 40]");
+        let text = bundle.into_text();
+
+        assert_eq!(
+            text,
+            "== Environment ==\nkernel 6.1\n\n== Reports ==\nInterface: 2 || Bytes: [09, 40]\n\n"
+        );
+        assert_eq!(text.lines().filter_map(replay::parse_line).count(), 1);
+    }
+}
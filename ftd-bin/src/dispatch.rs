@@ -0,0 +1,197 @@
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use ftd_device::{
+    device::{BUTTONS_INTERAFCE, TABLET_INTERFACE},
+    report::{BUTTON_REPORT_ID, PEN_REPORT_ID, TOUCH_REPORT_ID},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportKind {
+    Pen,
+    Buttons,
+    Touch,
+    /// Passed on undecoded, see `UnknownReports::PassthroughRaw`.
+    Raw,
+    Ignore,
+}
+
+/// What happens to a report that matches no route.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownReports {
+    /// Each one is logged with its bytes, for working out a new model.
+    Log,
+    /// Dropped, only logged at debug level.
+    #[default]
+    Drop,
+    /// Handed to the observers as `Event::Raw`, e.g. `--format human`.
+    PassthroughRaw,
+}
+
+/// Decode reports starting with `id` on `interface` as `kind`. Without an
+/// `id` it covers every report on the interface that no route with an
+/// `id` matches, e.g. `{ interface = 2, kind = "pen" }`. Only the buttons
+/// and tablet interfaces are read, a route on another one is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportRoute {
+    pub interface: u8,
+    pub id: Option<u8>,
+    pub kind: ReportKind,
+}
+
+/// The routes of the MTM-1106, see `MTM-1106.md`.
+pub fn default_routes() -> Vec<ReportRoute> {
+    vec![
+        ReportRoute {
+            interface: BUTTONS_INTERAFCE,
+            id: Some(BUTTON_REPORT_ID),
+            kind: ReportKind::Buttons,
+        },
+        ReportRoute {
+            interface: TABLET_INTERFACE,
+            id: Some(PEN_REPORT_ID),
+            kind: ReportKind::Pen,
+        },
+        ReportRoute {
+            interface: TABLET_INTERFACE,
+            id: Some(TOUCH_REPORT_ID),
+            kind: ReportKind::Touch,
+        },
+    ]
+}
+
+/// Picks the parser for a report by its interface and leading ID byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportRouter {
+    routes: Vec<ReportRoute>,
+    unknown: UnknownReports,
+}
+
+impl ReportRouter {
+    pub fn new(routes: Vec<ReportRoute>) -> ReportRouter {
+        ReportRouter {
+            routes,
+            unknown: UnknownReports::default(),
+        }
+    }
+
+    pub fn with_unknown(mut self, unknown: UnknownReports) -> ReportRouter {
+        self.unknown = unknown;
+        self
+    }
+
+    /// Reports matching no route are never guessed at, `unknown` says
+    /// whether they are ignored or passed on raw.
+    pub fn kind(&self, interface: u8, bytes: &[u8]) -> ReportKind {
+        let Some(&id) = bytes.first() else {
+            return ReportKind::Ignore;
+        };
+
+        let on_interface = || {
+            self.routes
+                .iter()
+                .filter(|route| route.interface == interface)
+        };
+        match on_interface()
+            .find(|route| route.id == Some(id))
+            .or_else(|| on_interface().find(|route| route.id.is_none()))
+        {
+            Some(route) => route.kind,
+            None => match self.unknown {
+                UnknownReports::Log => {
+                    info!("Unknown report on interface {interface}: {bytes:02X?}");
+                    ReportKind::Ignore
+                }
+                UnknownReports::Drop => {
+                    debug!("No route for report {id:02X} on interface {interface}");
+                    ReportKind::Ignore
+                }
+                UnknownReports::PassthroughRaw => ReportKind::Raw,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatches_by_interface_and_report_id() {
+        let mut routes = default_routes();
+        routes.push(ReportRoute {
+            interface: TABLET_INTERFACE,
+            id: Some(BUTTON_REPORT_ID),
+            kind: ReportKind::Buttons,
+        });
+        let router = ReportRouter::new(routes);
+
+        assert_eq!(
+            router.kind(TABLET_INTERFACE, &[0x09, 0xC0]),
+            ReportKind::Pen
+        );
+        assert_eq!(
+            router.kind(TABLET_INTERFACE, &[0x03, 0x01]),
+            ReportKind::Touch
+        );
+        assert_eq!(
+            router.kind(TABLET_INTERFACE, &[0x01, 0x80]),
+            ReportKind::Buttons
+        );
+        assert_eq!(router.kind(BUTTONS_INTERAFCE, &[0x09]), ReportKind::Ignore);
+        assert_eq!(router.kind(TABLET_INTERFACE, &[0x04]), ReportKind::Ignore);
+        assert_eq!(router.kind(TABLET_INTERFACE, &[]), ReportKind::Ignore);
+    }
+
+    #[test]
+    fn a_route_without_an_id_takes_the_rest_of_its_interface() {
+        let mut routes = default_routes();
+        routes.extend([
+            ReportRoute {
+                interface: BUTTONS_INTERAFCE,
+                id: None,
+                kind: ReportKind::Pen,
+            },
+            ReportRoute {
+                interface: TABLET_INTERFACE,
+                id: None,
+                kind: ReportKind::Buttons,
+            },
+        ]);
+        let router = ReportRouter::new(routes);
+
+        assert_eq!(
+            router.kind(BUTTONS_INTERAFCE, &[0x09, 0xC0]),
+            ReportKind::Pen
+        );
+        assert_eq!(router.kind(BUTTONS_INTERAFCE, &[0x01]), ReportKind::Buttons);
+        assert_eq!(router.kind(TABLET_INTERFACE, &[0x03]), ReportKind::Touch);
+        assert_eq!(router.kind(TABLET_INTERFACE, &[0x42]), ReportKind::Buttons);
+        assert_eq!(router.kind(4, &[0x09]), ReportKind::Ignore);
+    }
+
+    #[test]
+    fn only_passthrough_hands_on_an_unknown_report() {
+        let kinds = [
+            (UnknownReports::Log, ReportKind::Ignore),
+            (UnknownReports::Drop, ReportKind::Ignore),
+            (UnknownReports::PassthroughRaw, ReportKind::Raw),
+        ];
+        for (unknown, kind) in kinds {
+            let router = ReportRouter::new(default_routes()).with_unknown(unknown);
+            assert_eq!(router.kind(TABLET_INTERFACE, &[0x42, 0x01]), kind);
+            // Known reports and empty ones are unaffected.
+            assert_eq!(router.kind(TABLET_INTERFACE, &[0x09]), ReportKind::Pen);
+            assert_eq!(router.kind(TABLET_INTERFACE, &[]), ReportKind::Ignore);
+        }
+
+        let config: crate::config::Config =
+            toml::from_str("[device]\nunknown_reports = \"passthrough-raw\"\n").unwrap();
+        assert_eq!(
+            config.device.unknown_reports,
+            UnknownReports::PassthroughRaw
+        );
+    }
+}
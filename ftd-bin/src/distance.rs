@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+/// Whether the hovering pen is close enough to map as usual.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reach {
+    Near,
+    Far,
+}
+
+/// A second behavior for a pen lifted high but still in range, so it does
+/// not drag the cursor around on its way to or from the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HoverDistance {
+    /// Byte of the hover distance in pen reports, larger when farther. The
+    /// MTM-1106 sends none, this is for models that do. Unset reads none
+    /// and the pen is always near.
+    pub offset: Option<usize>,
+    /// Distances above this are far.
+    pub far_above: u8,
+    /// Deadband of the far pen, in place of `smoothing.deadband`.
+    pub far_deadband: u16,
+    /// Send nothing of the far pen, the cursor stays where it was.
+    pub far_still: bool,
+}
+
+impl Default for HoverDistance {
+    fn default() -> Self {
+        HoverDistance {
+            offset: None,
+            far_above: 128,
+            far_deadband: 200,
+            far_still: false,
+        }
+    }
+}
+
+impl HoverDistance {
+    pub fn read(&self, bytes: &[u8]) -> Option<u8> {
+        bytes.get(self.offset?).copied()
+    }
+
+    /// Only a hovering pen is ever far, one touching the surface is near
+    /// whatever the distance says.
+    pub fn reach(&self, distance: Option<u8>, in_range: bool, tip: bool) -> Reach {
+        match distance {
+            Some(distance) if in_range && !tip && distance > self.far_above => Reach::Far,
+            _ => Reach::Near,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_threshold_picks_the_far_mapping_while_hovering() {
+        let settings = HoverDistance {
+            offset: Some(9),
+            far_above: 100,
+            ..HoverDistance::default()
+        };
+        let report = |distance| [0x09, 0x40, 0, 0, 0, 0, 0, 0, 0, distance];
+
+        let reach = |distance, tip| settings.reach(settings.read(&report(distance)), true, tip);
+        assert_eq!(reach(40, false), Reach::Near);
+        assert_eq!(reach(100, false), Reach::Near);
+        assert_eq!(reach(101, false), Reach::Far);
+        assert_eq!(reach(200, true), Reach::Near);
+        assert_eq!(settings.reach(Some(200), false, false), Reach::Near);
+        assert_eq!(settings.read(&report(200)[..9]), None);
+        assert_eq!(
+            HoverDistance::default().reach(
+                HoverDistance::default().read(&report(255)),
+                true,
+                false
+            ),
+            Reach::Near
+        );
+    }
+}
@@ -0,0 +1,1448 @@
+use std::{
+    io,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use log::{debug, info, warn};
+
+use ftd_device::{
+    device::BUTTONS_INTERAFCE,
+    report::{
+        BUTTON_COUNT, ButtonDecoder, ButtonEvent, DEVICE_RESOLUTION, Event, PEN_SEQUENCE_OFFSET,
+        PenReport, RawReport, Timestamped, TouchReport,
+    },
+    sequence::SequenceTracker,
+};
+
+use crate::{
+    action::Action,
+    bands::PressureBands,
+    command::CommandRunner,
+    config::{self, Config},
+    control::ControlTarget,
+    dispatch::{ReportKind, ReportRouter},
+    distance::{HoverDistance, Reach},
+    dump::RawDump,
+    dwell::{DwellClick, DwellSettings},
+    edge::{EdgeOutput, EdgeScroll, EdgeSettings},
+    emitter::Emitter,
+    focus::{self, Focused},
+    gesture::{TapRecognizer, TapSettings},
+    hooks::{ConnectionHooks, HooksConfig},
+    json::{quote, to_json},
+    layer::{Layer, LayerConfig},
+    mapper::{AreaCycle, CoordinateMapper, OutputCycle, fit},
+    mouse::{MouseEmitter, PenTarget},
+    pause::Pause,
+    precision::{PrecisionMode, PrecisionSettings},
+    pressure::PressurePipeline,
+    pressure_scroll::{PressureScroll, PressureScrollSettings},
+    profile::{DEFAULT_PROFILE, Profile, ProfileConfig, ProfileSet},
+    proximity::{ProximityFilter, ProximitySettings},
+    read_stats::ReadStats,
+    remap::ButtonRemap,
+    screen::{FixedScreen, Rect, ScreenProvider, X11Screen},
+    smoothing::{Deadband, PositionSmoother},
+    tilt::TiltZones,
+    tip_delay::TipDelay,
+};
+
+/// The parts of the pipeline built from the config, and rebuilt on `reload`.
+pub struct Settings {
+    pub pressure: PressurePipeline,
+    pub bands: PressureBands,
+    pub tilt: TiltZones,
+    pub hooks: HooksConfig,
+    pub smoother: PositionSmoother,
+    pub deadband: Deadband,
+    pub hover_distance: HoverDistance,
+    /// In place of `deadband` while the pen is far, see `HoverDistance`.
+    pub far_deadband: Deadband,
+    pub tap: TapSettings,
+    pub edge: EdgeSettings,
+    pub mapper: CoordinateMapper,
+    pub areas: AreaCycle,
+    pub outputs: OutputCycle,
+    pub precision: PrecisionSettings,
+    pub pressure_scroll: PressureScrollSettings,
+    pub profiles: ProfileSet,
+    pub remap: ButtonRemap,
+    pub layer: LayerConfig,
+    pub hover_moves_cursor: bool,
+    pub tip: Action,
+    pub ignored_buttons: Vec<u8>,
+    pub swap_xy: bool,
+    pub tip_needs_in_range: bool,
+    pub ignore_touch: bool,
+    pub router: ReportRouter,
+    pub coalesce: Option<Duration>,
+    pub warm_up: Duration,
+    pub proximity: ProximitySettings,
+    pub dwell: DwellSettings,
+    /// What these were built from, for `get-config`.
+    pub config: Config,
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Result<Settings> {
+        let mut pressure = config.pressure.pipeline()?;
+
+        let tap = TapSettings {
+            max_duration: std::time::Duration::from_millis(config.touch.tap_time_ms),
+            max_distance: config.touch.tap_distance,
+            ..TapSettings::default()
+        };
+        let edge = EdgeSettings {
+            width: config.touch.edge_scroll,
+            ..EdgeSettings::default()
+        };
+
+        config.layer.validate()?;
+        if config.layer.button.is_some() && config.layer.button == config.precision.button {
+            bail!("layer.button and precision.button are the same button");
+        }
+        let held_buttons = [
+            ("layer.button", config.layer.button),
+            ("precision.button", config.precision.button),
+        ];
+        for (name, button) in held_buttons {
+            if button.is_some() && button == config.pressure_scroll.button {
+                bail!("{name} and pressure_scroll.button are the same button");
+            }
+        }
+        let (mapper, outputs) = match (config.mapping.screen, &config.mapping.output) {
+            (Some((width, height)), _) => {
+                let screen = FixedScreen {
+                    rect: Rect::new(0, 0, width, height),
+                };
+                (CoordinateMapper::new(&screen, None), screen.outputs())
+            }
+            (None, Some(name)) => {
+                let screen = X11Screen::query().context("Failed to query outputs with xrandr")?;
+                if screen.output_by_name(name).is_none() {
+                    bail!("Output `{name}` not found");
+                }
+                (CoordinateMapper::new(&screen, Some(name)), screen.outputs())
+            }
+            (None, None) => (CoordinateMapper::device(), vec![]),
+        };
+        // `fit` replaces the configured rotation and area.
+        let (rotation, area) = if config.mapping.fit {
+            let resolution = config.device.resolution.unwrap_or(DEVICE_RESOLUTION);
+            fit(mapper.target, resolution)
+        } else {
+            (config.mapping.rotation, config.mapping.area)
+        };
+        let default = Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            button_map: config.layer.combined_map(&config.button_map),
+            curve: pressure.curve,
+            area,
+            rotation,
+            pen: config.mouse.pen,
+        };
+        let configs: Vec<ProfileConfig> = config
+            .profiles
+            .iter()
+            .map(|profile| ProfileConfig {
+                button_map: profile
+                    .button_map
+                    .as_ref()
+                    .map(|map| config.layer.combined_map(map)),
+                ..profile.clone()
+            })
+            .collect();
+        let mut profiles = ProfileSet::with_configs(default, &configs)?;
+        if let Some(name) = &config.profile {
+            profiles.select(name)?;
+        }
+        pressure.curve = profiles.active().curve;
+
+        let areas = AreaCycle::new(area, &config.mapping.areas)?;
+        config.hooks.validate(profiles.profiles())?;
+        config.mouse.validate()?;
+        if !config.mouse.enabled
+            && let Some(profile) = profiles
+                .profiles()
+                .iter()
+                .find(|profile| profile.pen == PenTarget::Mouse)
+        {
+            bail!(
+                "Profile `{}` moves the mouse but mouse.enabled is off",
+                profile.name
+            );
+        }
+        for area in [area].iter().chain(&config.mapping.areas) {
+            config.mapping.padding.validate(area)?;
+        }
+        if let Some(clamp) = config.mapping.clamp
+            && (clamp.width < 1 || clamp.height < 1)
+        {
+            bail!("mapping.clamp must be at least one pixel wide and high");
+        }
+        config.precision.validate()?;
+        config.pressure_scroll.validate()?;
+        config.dwell.validate()?;
+        config.queue.validate()?;
+        config.keep_alive.validate()?;
+        config.init.validate()?;
+        config.device.check_routes()?;
+        if let Some(index) = config
+            .device
+            .ignore_buttons
+            .iter()
+            .find(|&&index| index as usize >= BUTTON_COUNT)
+        {
+            bail!("ignore_buttons entry {index} is not a button index");
+        }
+        let outputs = OutputCycle::new(outputs, mapper.target);
+        let mapper = CoordinateMapper {
+            matrix: config.mapping.matrix.unwrap_or_default(),
+            area: profiles.active().area,
+            padding: config.mapping.padding,
+            rotation: profiles.active().rotation,
+            full_resolution: config.mapping.full_resolution,
+            clamp: config.mapping.clamp,
+            ..mapper
+        };
+
+        Ok(Settings {
+            pressure,
+            bands: PressureBands::new(
+                config.pressure.bands.clone(),
+                config.pressure.band_hysteresis,
+            )?,
+            tilt: TiltZones::new(&config.tilt)?,
+            hooks: config.hooks.clone(),
+            smoother: PositionSmoother::new(config.smoothing)?,
+            deadband: Deadband::new(config.smoothing.deadband),
+            hover_distance: config.hover_distance,
+            far_deadband: Deadband::new(config.hover_distance.far_deadband),
+            tap,
+            edge,
+            mapper,
+            areas,
+            outputs,
+            precision: config.precision,
+            pressure_scroll: config.pressure_scroll,
+            profiles,
+            remap: ButtonRemap::new(config.device.button_remap.clone())?,
+            layer: config.layer.clone(),
+            hover_moves_cursor: config.mapping.hover_moves_cursor,
+            tip: config.tip.clone(),
+            ignored_buttons: config.device.ignore_buttons.clone(),
+            swap_xy: config.device.swap_xy,
+            tip_needs_in_range: config.device.tip_needs_in_range,
+            ignore_touch: config.touch.ignore,
+            router: ReportRouter::new(config.device.reports.clone())
+                .with_unknown(config.device.unknown_reports),
+            coalesce: (config.coalesce_ms > 0).then(|| Duration::from_millis(config.coalesce_ms)),
+            warm_up: Duration::from_millis(config.init.warm_up_ms),
+            proximity: config.proximity,
+            dwell: config.dwell.clone(),
+            config: config.clone(),
+        })
+    }
+}
+
+pub type Reload = Box<dyn FnMut() -> Result<Settings>>;
+pub type Observer = Box<dyn FnMut(&Timestamped)>;
+
+/// Turns raw reports into output, between the USB reads and the emitter.
+pub struct Driver {
+    settings: Settings,
+    emitter: Emitter,
+    /// The `[mouse]` device, `pen_target` picks which of the two the pen
+    /// moves. Buttons and touch always go to `emitter`.
+    mouse: Option<MouseEmitter>,
+    pen_target: PenTarget,
+    commands: CommandRunner,
+    reload: Option<Reload>,
+    /// The config `save_profile` writes next to.
+    config_path: Option<PathBuf>,
+    observers: Vec<Observer>,
+    /// Shown by `status`.
+    read_stats: Option<Arc<ReadStats>>,
+    /// Set while the output is off, see `Pause`.
+    pause: Option<Arc<Pause>>,
+    /// Switched by the `dump` command.
+    raw_dump: Option<Arc<RawDump>>,
+    buttons: ButtonDecoder,
+    sequence: SequenceTracker,
+    taps: TapRecognizer,
+    precision: PrecisionMode,
+    pressure_scroll: PressureScroll,
+    proximity: ProximityFilter,
+    tip_delay: TipDelay,
+    dwell: DwellClick,
+    connection: ConnectionHooks,
+    layer: Layer,
+    pen_edge: EdgeScroll,
+    touch_edge: EdgeScroll,
+    /// Of the last pen report, see `HoverDistance`.
+    distance: Option<u8>,
+    /// Whether the tip was down in the last pen event, for the press of
+    /// `Settings::tip`.
+    tip_down: bool,
+    enabled: bool,
+    /// Set by `Action::Panic`, keeps `Action::ToggleOutput` from enabling.
+    locked: bool,
+    reinit_requested: bool,
+    /// When the last event was handed to the emitter.
+    last_event: Option<Instant>,
+    /// Events of reports read before this are dropped, see `warm_up`.
+    warm_up_until: Option<Instant>,
+    /// When the report being handled was read, and from which interface.
+    read_at: Instant,
+    interface: u8,
+}
+
+impl Driver {
+    pub fn new(settings: Settings, emitter: Emitter, commands: CommandRunner) -> Driver {
+        Driver {
+            taps: TapRecognizer::new(settings.tap.clone()),
+            precision: PrecisionMode::new(settings.precision),
+            pressure_scroll: PressureScroll::new(settings.pressure_scroll),
+            proximity: ProximityFilter::new(settings.proximity),
+            tip_delay: TipDelay::new(&settings.proximity),
+            dwell: DwellClick::new(settings.dwell.clone()),
+            connection: ConnectionHooks::default(),
+            layer: Layer::new(&settings.layer),
+            pen_edge: EdgeScroll::new(settings.edge),
+            touch_edge: EdgeScroll::new(settings.edge),
+            distance: None,
+            tip_down: false,
+            buttons: ButtonDecoder::ignoring(&settings.ignored_buttons),
+            emitter: emitter.with_coalescing(settings.coalesce),
+            mouse: None,
+            pen_target: settings.profiles.active().pen,
+            settings,
+            commands,
+            reload: None,
+            config_path: None,
+            observers: vec![],
+            read_stats: None,
+            pause: None,
+            raw_dump: None,
+            sequence: SequenceTracker::new(PEN_SEQUENCE_OFFSET),
+            enabled: true,
+            locked: false,
+            reinit_requested: false,
+            last_event: None,
+            warm_up_until: None,
+            read_at: Instant::now(),
+            interface: 0,
+        }
+    }
+
+    /// How `reload` rebuilds the settings, without it `reload` fails.
+    pub fn with_reload(mut self, reload: Reload) -> Driver {
+        self.reload = Some(reload);
+        self
+    }
+
+    /// Where the config was loaded from, without it `save_profile` fails.
+    pub fn with_config_path(mut self, path: PathBuf) -> Driver {
+        self.config_path = Some(path);
+        self
+    }
+
+    /// The relative mouse the pen can be switched to, none keeps it on the
+    /// tablet.
+    pub fn with_mouse(mut self, mouse: Option<MouseEmitter>) -> Driver {
+        self.mouse = mouse;
+        self
+    }
+
+    /// The read counts `status` includes.
+    pub fn with_read_stats(mut self, stats: Arc<ReadStats>) -> Driver {
+        self.read_stats = Some(stats);
+        self
+    }
+
+    /// Pauses the readers while the output is off.
+    pub fn with_pause(mut self, pause: Arc<Pause>) -> Driver {
+        self.pause = Some(pause);
+        self
+    }
+
+    /// Lets the `dump` command log the packets the readers get.
+    pub fn with_raw_dump(mut self, dump: Arc<RawDump>) -> Driver {
+        self.raw_dump = Some(dump);
+        self
+    }
+
+    /// Sees every event as it is handed to the emitter, stamped with when
+    /// its report was read.
+    pub fn with_observer(mut self, observer: Observer) -> Driver {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Runs `hooks.on_connect` or `on_disconnect` when the tablet connects or
+    /// disconnects.
+    pub fn set_connected(&mut self, connected: bool) -> Result<()> {
+        let hooks = self.settings.hooks.clone();
+        if let Some(profile) = self
+            .connection
+            .transition(&hooks, connected, &self.commands)
+        {
+            self.set_profile(profile)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes a report read from `interface` at `read_at` and emits what
+    /// it yields.
+    pub fn handle_report(
+        &mut self,
+        interface: u8,
+        bytes: &[u8],
+        read_at: Instant,
+    ) -> io::Result<()> {
+        self.read_at = read_at;
+        self.interface = interface;
+        match self.settings.router.kind(interface, bytes) {
+            ReportKind::Buttons => self.buttons(bytes),
+            ReportKind::Pen => self.pen(bytes),
+            ReportKind::Touch => self.touch(bytes),
+            ReportKind::Raw if self.enabled => self.emit(Event::Raw(RawReport {
+                interface,
+                bytes: bytes.to_vec(),
+            })),
+            ReportKind::Raw | ReportKind::Ignore => Ok(()),
+        }
+    }
+
+    fn buttons(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let events = self.buttons.decode(bytes);
+
+        for button in events {
+            let button = self.settings.remap.apply(button);
+            if !self.enabled {
+                // Only the bindings that turn the output back on still act.
+                let button_map = &self.settings.profiles.active().button_map;
+                match button_map.get(button.index as usize) {
+                    Some(Action::ToggleOutput) if button.pressed => self.toggle_output()?,
+                    Some(Action::Unlock) if button.pressed => self.unlock()?,
+                    _ => {}
+                }
+                continue;
+            }
+            if Some(button.index) == self.precision.button() {
+                self.precision.set_held(button.pressed);
+                continue;
+            }
+            if Some(button.index) == self.pressure_scroll.button() {
+                if button.pressed && !self.pressure_scroll.held() {
+                    // Lift the tip so the scroll does not draw.
+                    self.release_pen()?;
+                }
+                self.pressure_scroll.set_held(button.pressed);
+                continue;
+            }
+            let Some(button) = self.layer.apply(button) else {
+                continue;
+            };
+            self.act(button)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the binding of `button`, indexed into the active button map
+    /// with the layer already applied.
+    fn act(&mut self, button: ButtonEvent) -> io::Result<()> {
+        let button_map = &self.settings.profiles.active().button_map;
+        if let Some(action) = button_map.get(button.index as usize).cloned()
+            && button.pressed
+            && !self.press(&action)?
+        {
+            return Ok(());
+        }
+        self.emit(Event::Button(button))
+    }
+
+    /// Does the part of a press of `action` that is not an event, the
+    /// emitter sends those. False when the output was turned off by it and
+    /// nothing is to be sent.
+    fn press(&mut self, action: &Action) -> io::Result<bool> {
+        match action {
+            Action::Command { program, args } => self.commands.run(program, args),
+            Action::CycleArea => self.cycle_area(),
+            Action::CycleOutput => self.cycle_output(),
+            Action::ToggleOutput => {
+                self.toggle_output()?;
+                return Ok(false);
+            }
+            Action::Panic => {
+                self.panic()?;
+                return Ok(false);
+            }
+            Action::Unlock => self.unlock()?,
+            Action::ToggleMouse => self.set_pen_target(match self.pen_target {
+                PenTarget::Tablet => PenTarget::Mouse,
+                PenTarget::Mouse => PenTarget::Tablet,
+            })?,
+            Action::Keys(_) | Action::Scroll(_) => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Presses and releases binding `index` of the active button map, past
+    /// `BUTTON_COUNT` on the layer, as if its button were pressed. For
+    /// `test-bindings`, which has no buttons to press.
+    pub fn fire(&mut self, index: u8) -> io::Result<()> {
+        self.read_at = Instant::now();
+        self.interface = BUTTONS_INTERAFCE;
+        for pressed in [true, false] {
+            self.act(ButtonEvent { index, pressed })?;
+        }
+        self.emitter.flush()
+    }
+
+    /// Switches to the profile whose rule matches `focused`, or back to
+    /// the one selected at startup when none does. A window nothing could
+    /// be read about changes nothing.
+    pub fn follow_focus(&mut self, focused: &Focused) -> Result<()> {
+        if focused.is_unknown() {
+            return Ok(());
+        }
+        let config = &self.settings.config;
+        let name = focus::matching(&config.profiles, focused)
+            .or(config.profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+            .to_string();
+        if name == self.settings.profiles.active().name {
+            return Ok(());
+        }
+        self.set_profile(&name)
+    }
+
+    fn toggle_output(&mut self) -> io::Result<()> {
+        if self.locked {
+            info!("Output is locked by the panic button, unlock it to enable");
+            return Ok(());
+        }
+        self.switch_output(!self.enabled)
+    }
+
+    fn panic(&mut self) -> io::Result<()> {
+        self.locked = true;
+        self.switch_output(false)?;
+        warn!("Panic button pressed, output is off until unlocked");
+
+        Ok(())
+    }
+
+    fn unlock(&mut self) -> io::Result<()> {
+        self.locked = false;
+        self.switch_output(true)
+    }
+
+    /// Releases everything held when turning off, and forgets the pen as
+    /// it was when turning back on: it may have left or moved meanwhile.
+    fn switch_output(&mut self, enabled: bool) -> io::Result<()> {
+        if self.enabled && !enabled {
+            self.teardown()?;
+        }
+        if !self.enabled && enabled {
+            self.proximity = ProximityFilter::new(self.settings.proximity);
+            self.tip_delay = TipDelay::new(&self.settings.proximity);
+            self.dwell = DwellClick::new(self.settings.dwell.clone());
+            self.taps = TapRecognizer::new(self.settings.tap.clone());
+            self.settings.smoother.reset();
+            self.settings.deadband.reset();
+            self.settings.far_deadband.reset();
+            self.precision.reset();
+            self.tip_down = false;
+        }
+        if let Some(pause) = &self.pause {
+            pause.set(!enabled);
+        }
+        self.enabled = enabled;
+        info!("Output {}", if enabled { "enabled" } else { "disabled" });
+
+        Ok(())
+    }
+
+    /// Takes the pen off the device it was on before `target` gets it.
+    fn set_pen_target(&mut self, target: PenTarget) -> io::Result<()> {
+        if target == self.pen_target {
+            return Ok(());
+        }
+        match (target, &mut self.mouse) {
+            (PenTarget::Mouse, None) => {
+                info!("There is no mouse to move the pen to, set mouse.enabled");
+                return Ok(());
+            }
+            (PenTarget::Mouse, Some(_)) => self.emitter.emit(&Event::Pen(PenReport {
+                x: 0,
+                y: 0,
+                pressure: 0,
+                in_range: false,
+                tip: false,
+            }))?,
+            (PenTarget::Tablet, Some(mouse)) => mouse.teardown()?,
+            (PenTarget::Tablet, None) => {}
+        }
+        self.pen_target = target;
+        info!(
+            "The pen moves the {}",
+            match target {
+                PenTarget::Tablet => "tablet",
+                PenTarget::Mouse => "mouse",
+            }
+        );
+
+        Ok(())
+    }
+
+    fn cycle_area(&mut self) {
+        let (index, area) = self.settings.areas.advance();
+        self.settings.mapper.area = area;
+        info!(
+            "Active area {} of {}: {area:?}",
+            index + 1,
+            self.settings.areas.len()
+        );
+    }
+
+    fn cycle_output(&mut self) {
+        if self.settings.outputs.len() < 2 {
+            info!("There is no other output to map the tablet onto");
+            return;
+        }
+        let (name, target) = self.settings.outputs.advance();
+        info!(
+            "Mapped onto output {name}: {}x{}+{}+{}",
+            target.width, target.height, target.x, target.y
+        );
+        self.settings.mapper.target = target;
+        self.precision.reset();
+    }
+
+    fn pen(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(mut report) = PenReport::parse(bytes) else {
+            return Ok(());
+        };
+        if self.settings.swap_xy {
+            report = report.swap_xy();
+        }
+        if self.settings.tip_needs_in_range && !report.in_range {
+            report.tip = false;
+        }
+        if let Some(bit) = self.settings.layer.pen_bit {
+            self.layer
+                .set_pen(report.in_range && bytes[1] & bit != 0, report.tip);
+        }
+        self.sequence.observe(bytes);
+        if !self.enabled {
+            return Ok(());
+        }
+        self.distance = self.settings.hover_distance.read(bytes);
+        let tilt = self.settings.tilt.read(bytes);
+        for action in self.settings.tilt.feed(tilt, report.in_range) {
+            self.emitter.trigger(&action)?;
+        }
+        match self.proximity.feed(report, self.read_at) {
+            Some(report) => self.pen_in_proximity(report),
+            None => Ok(()),
+        }
+    }
+
+    /// The rest of `pen`, for a report `ProximityFilter` let through.
+    fn pen_in_proximity(&mut self, mut report: PenReport) -> io::Result<()> {
+        let hovering = report.in_range && !report.tip && !self.pressure_scroll.held();
+        if let Some(action) = self
+            .dwell
+            .feed(report.x, report.y, hovering, self.read_at)
+            .cloned()
+            && self.press(&action)?
+        {
+            self.emitter.trigger(&action)?;
+        }
+        report.tip = self
+            .tip_delay
+            .feed(report.in_range && report.tip, self.read_at);
+
+        report.pressure = self
+            .settings
+            .pressure
+            .process(report.pressure, report.in_range);
+        if let Some(clicks) = self
+            .pressure_scroll
+            .feed(report.pressure, report.in_range && report.tip)
+        {
+            return match clicks {
+                0 => Ok(()),
+                clicks => self.emitter.trigger(&Action::Scroll(clicks)),
+            };
+        }
+        if let Some(action) = self
+            .settings
+            .bands
+            .feed(report.pressure, report.in_range && report.tip)
+        {
+            self.emitter.trigger(action)?;
+        }
+        match self.pen_edge.feed(report.x, report.y, report.tip) {
+            EdgeOutput::Pass => {
+                let reach =
+                    self.settings
+                        .hover_distance
+                        .reach(self.distance, report.in_range, report.tip);
+                if reach == Reach::Far && self.settings.hover_distance.far_still {
+                    return Ok(());
+                }
+                let (x, y) = if report.in_range {
+                    let smoother = &mut self.settings.smoother;
+                    let (x, y) = smoother.quantize(report.x, report.y);
+                    let (x, y) = smoother.apply(x, y);
+                    let (used, unused) = match reach {
+                        Reach::Near => {
+                            (&mut self.settings.deadband, &mut self.settings.far_deadband)
+                        }
+                        Reach::Far => {
+                            (&mut self.settings.far_deadband, &mut self.settings.deadband)
+                        }
+                    };
+                    unused.reset();
+                    used.apply(x, y)
+                } else {
+                    self.settings.smoother.reset();
+                    self.settings.deadband.reset();
+                    self.settings.far_deadband.reset();
+                    self.precision.reset();
+                    (report.x, report.y)
+                };
+                let mapper = &self.settings.mapper;
+                let (max_x, max_y) = mapper.bounds();
+                let (x, y) = self
+                    .precision
+                    .apply(mapper.map(x, y), (max_x + 1, max_y + 1));
+                let (x, y) = mapper.clamp((x, y));
+                (report.x, report.y) = (x as u16, y as u16);
+                let tip = report.in_range && report.tip;
+                if std::mem::replace(&mut self.tip_down, tip) != tip && tip {
+                    let action = self.settings.tip.clone();
+                    if !self.press(&action)? {
+                        return Ok(());
+                    }
+                }
+                self.emit(Event::Pen(report))
+            }
+            EdgeOutput::Scroll(0) => Ok(()),
+            EdgeOutput::Scroll(clicks) => self.emitter.trigger(&Action::Scroll(clicks)),
+        }
+    }
+
+    fn touch(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(touch) = TouchReport::parse(bytes) else {
+            return Ok(());
+        };
+        if !self.enabled || self.settings.ignore_touch {
+            return Ok(());
+        }
+
+        if let Some(action) = self.taps.feed(&touch, self.read_at) {
+            self.emitter.trigger(action)?;
+        }
+        let edge = touch
+            .contacts
+            .first()
+            .map(|c| self.touch_edge.feed(c.x, c.y, c.down))
+            .unwrap_or(EdgeOutput::Pass);
+        match edge {
+            EdgeOutput::Pass => self.emit(Event::Touch(touch)),
+            EdgeOutput::Scroll(0) => Ok(()),
+            EdgeOutput::Scroll(clicks) => self.emitter.trigger(&Action::Scroll(clicks)),
+        }
+    }
+
+    fn emit(&mut self, event: Event) -> io::Result<()> {
+        if self.warm_up_until.is_some_and(|until| self.read_at < until) {
+            debug!("Dropped while warming up: {event:?}");
+            return Ok(());
+        }
+        let stamped = Timestamped {
+            at: self.read_at,
+            event,
+        };
+        for observer in &mut self.observers {
+            observer(&stamped);
+        }
+        self.last_event = Some(stamped.at);
+        if let (Event::Pen(report), PenTarget::Mouse, Some(mouse)) =
+            (&stamped.event, self.pen_target, &mut self.mouse)
+        {
+            return mouse.emit(report);
+        }
+        self.emitter
+            .coalesce(self.interface, self.read_at, &stamped.event)
+    }
+
+    /// When the frame of events read together must be written, see
+    /// `flush_due`.
+    pub fn frame_deadline(&self) -> Option<Instant> {
+        self.emitter.deadline()
+    }
+
+    /// Writes the frame of events read together once it is due at `now`.
+    /// Also takes the pen out of range once `proximity.debounce_ms` passed
+    /// since its last report had it leaving.
+    pub fn flush_due(&mut self, now: Instant) -> io::Result<()> {
+        if let Some(report) = self.proximity.expire(now)
+            && self.enabled
+        {
+            self.read_at = now;
+            self.pen_in_proximity(report)?;
+        }
+        self.emitter.flush_due(now)
+    }
+
+    /// Takes the pen out of proximity, for when its reports stopped without
+    /// an out of range one.
+    pub fn release_pen(&mut self) -> io::Result<()> {
+        self.settings.smoother.reset();
+        self.settings.deadband.reset();
+        self.precision.reset();
+        self.emit(Event::Pen(PenReport {
+            x: 0,
+            y: 0,
+            pressure: 0,
+            in_range: false,
+            tip: false,
+        }))
+    }
+
+    /// Drops the events of what is read within `init.warm_up_ms` of `now`,
+    /// for right after the init packets were sent.
+    pub fn warm_up(&mut self, now: Instant) {
+        self.warm_up_until =
+            (!self.settings.warm_up.is_zero()).then(|| now + self.settings.warm_up);
+    }
+
+    /// Returns whether a re-init was requested since the last call.
+    pub fn take_reinit_request(&mut self) -> bool {
+        std::mem::take(&mut self.reinit_requested)
+    }
+
+    pub fn teardown(&mut self) -> io::Result<()> {
+        if let Some(mouse) = &mut self.mouse {
+            mouse.teardown()?;
+        }
+        self.emitter.teardown()
+    }
+
+    pub fn sequence(&self) -> &SequenceTracker {
+        &self.sequence
+    }
+
+    /// The USB device, init packets and the virtual device's axes stay as
+    /// they were, those need a restart.
+    fn apply(&mut self, settings: Settings) -> io::Result<()> {
+        if settings.mapper.bounds() != self.settings.mapper.bounds() {
+            warn!("The desktop size changed, restart the driver to resize the pen axes");
+        }
+        self.taps = TapRecognizer::new(settings.tap.clone());
+        self.precision = PrecisionMode::new(settings.precision);
+        self.pressure_scroll = PressureScroll::new(settings.pressure_scroll);
+        self.proximity = ProximityFilter::new(settings.proximity);
+        self.tip_delay = TipDelay::new(&settings.proximity);
+        self.dwell = DwellClick::new(settings.dwell.clone());
+        self.layer = Layer::new(&settings.layer);
+        if settings.ignored_buttons != self.settings.ignored_buttons {
+            self.buttons = ButtonDecoder::ignoring(&settings.ignored_buttons);
+        }
+        self.pen_edge = EdgeScroll::new(settings.edge);
+        self.touch_edge = EdgeScroll::new(settings.edge);
+        self.emitter
+            .set_button_map(settings.profiles.active().button_map.clone())?;
+        self.emitter.set_hover(settings.hover_moves_cursor);
+        self.emitter.set_tip(settings.tip.clone())?;
+        self.emitter.set_coalescing(settings.coalesce)?;
+        self.set_pen_target(settings.profiles.active().pen)?;
+        self.settings = settings;
+
+        Ok(())
+    }
+}
+
+impl ControlTarget for Driver {
+    /// An explicit `enable` also lifts a panic lock.
+    fn set_enabled(&mut self, enabled: bool) -> Result<()> {
+        if enabled {
+            self.locked = false;
+        }
+        self.switch_output(enabled)?;
+
+        Ok(())
+    }
+
+    /// The bindings, pressure curve and area all switch in this one call,
+    /// no report is handled with some of them switched.
+    fn set_profile(&mut self, name: &str) -> Result<()> {
+        self.settings.profiles.select(name)?;
+        let profile = self.settings.profiles.active();
+        let pen = profile.pen;
+        self.emitter.set_button_map(profile.button_map.clone())?;
+        self.settings.pressure.curve = profile.curve;
+        self.settings.mapper.area = profile.area;
+        self.settings.mapper.rotation = profile.rotation;
+        self.set_pen_target(pen)?;
+        info!("Switched to profile `{name}`");
+
+        Ok(())
+    }
+
+    /// Written as a fragment next to the config rather than into it, which
+    /// keeps the comments of the config as they are.
+    fn save_profile(&mut self, name: &str) -> Result<()> {
+        let path = self
+            .config_path
+            .as_ref()
+            .context("No config file to save next to, pass --config")?;
+        let profile = ProfileConfig {
+            name: name.to_string(),
+            button_map: Some(self.settings.profiles.active().button_map.clone()),
+            pressure_curve: Some(self.settings.pressure.curve.gamma()),
+            area: Some(self.settings.mapper.area),
+            rotation: Some(self.settings.mapper.rotation),
+            pen: Some(self.pen_target),
+            window_class: None,
+            process_name: None,
+        };
+        let saved = config::save_profile(path, &self.settings.config, &profile)?;
+        info!("Saved profile `{name}` to {}", saved.display());
+
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let reload = self.reload.as_mut().context("Nothing to reload from")?;
+        let settings = reload()?;
+        self.apply(settings)?;
+        info!("Config reloaded");
+
+        Ok(())
+    }
+
+    fn reinit(&mut self) -> Result<()> {
+        self.reinit_requested = true;
+        Ok(())
+    }
+
+    fn set_dump(&mut self, interface: u8, on: bool) -> Result<()> {
+        let dump = self
+            .raw_dump
+            .as_ref()
+            .context("No USB readers to dump the packets of")?;
+        dump.set(interface, on);
+        info!(
+            "{} the raw packets of interface {interface}",
+            if on { "Dumping" } else { "No longer dumping" }
+        );
+
+        Ok(())
+    }
+
+    fn status(&self) -> String {
+        let mut status = format!(
+            "{} profile={}",
+            match (self.enabled, self.locked) {
+                (true, _) => "enabled",
+                (false, false) => "disabled",
+                (false, true) => "locked",
+            },
+            self.settings.profiles.active().name
+        );
+        if self.sequence.is_active() {
+            status.push_str(&format!(" dropped={}", self.sequence.dropped()));
+        }
+        if let Some(reads) = self.read_stats.as_ref().map(|stats| stats.summary())
+            && !reads.is_empty()
+        {
+            status.push(' ');
+            status.push_str(&reads);
+        }
+
+        status
+    }
+
+    fn config(&self) -> Result<String> {
+        to_json(&self.settings.config)
+    }
+
+    fn state(&self) -> String {
+        let last_event_ms_ago = self.last_event.map_or("null".to_string(), |at| {
+            at.elapsed().as_millis().to_string()
+        });
+        // `null` while the reports show no frame counter to count drops by.
+        let dropped_reports = if self.sequence.is_active() {
+            self.sequence.dropped().to_string()
+        } else {
+            "null".to_string()
+        };
+        format!(
+            r#"{{"enabled":{},"locked":{},"profile":{},"output":{},"last_event_ms_ago":{last_event_ms_ago},"dropped_reports":{dropped_reports}}}"#,
+            self.enabled,
+            self.locked,
+            quote(&self.settings.profiles.active().name),
+            quote(self.settings.outputs.current().0),
+        )
+    }
+}
+
+/// A driver for `config` on a 0x7FFF square, writing into the returned
+/// sink, for tests.
+#[cfg(test)]
+pub fn recording_driver(config: &Config) -> (Driver, crate::emitter::RecordingSink) {
+    let settings = Settings::from_config(config).unwrap();
+    let state =
+        crate::emitter::EmitterState::new(config.button_map.clone(), true, (0x7FFF, 0x7FFF));
+    recording_driver_with(settings, state)
+}
+
+/// Like `recording_driver`, with the settings and emitter state set up by
+/// the test.
+#[cfg(test)]
+pub fn recording_driver_with(
+    settings: Settings,
+    state: crate::emitter::EmitterState,
+) -> (Driver, crate::emitter::RecordingSink) {
+    let sink = crate::emitter::RecordingSink::default();
+    let commands = CommandRunner::new(crate::command::CommandPolicy::default());
+    let driver = Driver::new(settings, Emitter::with_sink(state, sink.clone()), commands);
+
+    (driver, sink)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use evdev_rs::enums::{EV_ABS, EV_KEY, EV_REL, EventCode};
+
+    use super::*;
+    use crate::{
+        control::{self, ControlCommand},
+        dispatch::UnknownReports,
+        emitter::{EmitterState, RecordingSink},
+    };
+
+    #[test]
+    fn events_carry_the_time_their_report_was_read() {
+        let config = Config::default();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let record = seen.clone();
+        let (driver, _) = recording_driver(&config);
+        let mut driver = driver.with_observer(Box::new(move |stamped| {
+            record.borrow_mut().push(stamped.at)
+        }));
+
+        let start = Instant::now();
+        let reads: Vec<Instant> = (0..3)
+            .map(|i| start + Duration::from_millis(5 * i))
+            .collect();
+        let pen = [0x09, 0x40, 0x00, 0x10, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00];
+        driver.handle_report(2, &pen, reads[0]).unwrap();
+        driver
+            .handle_report(1, &[0x01, 0, 0, 0, 0, 0x04, 0, 0], reads[1])
+            .unwrap();
+        driver.handle_report(2, &pen, reads[2]).unwrap();
+
+        let seen = seen.borrow();
+        assert_eq!(*seen, reads);
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn panic_stays_off_until_explicitly_unlocked() {
+        let mut config = Config::default();
+        config.button_map[0] = Action::Panic;
+        config.button_map[1] = Action::ToggleOutput;
+        config.button_map[2] = Action::Unlock;
+        let (mut driver, sink) = recording_driver(&config);
+        let press = |driver: &mut Driver, mask: u8| {
+            driver
+                .handle_report(1, &[0x01, 0, 0, 0, mask, 0, 0, 0], Instant::now())
+                .unwrap();
+        };
+        let ctrl = |value| (EventCode::EV_KEY(EV_KEY::KEY_LEFTCTRL), value);
+
+        press(&mut driver, 0x08);
+        assert!(sink.take().contains(&ctrl(1)));
+        press(&mut driver, 0x88);
+        assert!(sink.take().contains(&ctrl(0)));
+
+        press(&mut driver, 0x00);
+        press(&mut driver, 0x40);
+        press(&mut driver, 0x00);
+        press(&mut driver, 0x08);
+        assert_eq!(sink.take(), vec![]);
+        assert!(driver.status().starts_with("locked"));
+
+        press(&mut driver, 0x00);
+        press(&mut driver, 0x20);
+        press(&mut driver, 0x00);
+        press(&mut driver, 0x08);
+        assert!(sink.take().contains(&ctrl(1)));
+        assert!(driver.status().starts_with("enabled"));
+    }
+
+    #[test]
+    fn the_pen_moves_the_selected_device() {
+        let mut config = Config::default();
+        config.mouse.enabled = true;
+        config.button_map[1] = Action::ToggleMouse;
+        let mouse = RecordingSink::default();
+        let (driver, tablet) = recording_driver(&config);
+        let mut driver =
+            driver.with_mouse(Some(MouseEmitter::with_sink(&config.mouse, mouse.clone())));
+        let pen = |driver: &mut Driver, x: u8| {
+            let report = [0x09, 0x40, x, 0x10, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00];
+            driver.handle_report(2, &report, Instant::now()).unwrap();
+        };
+        let toggle = |driver: &mut Driver| {
+            for mask in [0x40, 0x00] {
+                driver
+                    .handle_report(1, &[0x01, 0, 0, 0, mask, 0, 0, 0], Instant::now())
+                    .unwrap();
+            }
+        };
+        let abs_x = |events: &[(EventCode, i32)]| {
+            events
+                .iter()
+                .any(|(code, _)| *code == EventCode::EV_ABS(EV_ABS::ABS_X))
+        };
+
+        pen(&mut driver, 0x00);
+        assert!(abs_x(&tablet.take()));
+        toggle(&mut driver);
+        assert!(
+            tablet
+                .take()
+                .contains(&(EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 0))
+        );
+
+        pen(&mut driver, 0x00);
+        pen(&mut driver, 0x20);
+        assert!(!abs_x(&tablet.take()));
+        assert!(
+            mouse
+                .take()
+                .contains(&(EventCode::EV_REL(EV_REL::REL_X), 0x20))
+        );
+
+        toggle(&mut driver);
+        pen(&mut driver, 0x40);
+        assert!(abs_x(&tablet.take()));
+        assert_eq!(mouse.take(), vec![]);
+    }
+
+    #[test]
+    fn turning_the_output_off_pauses_the_readers_until_it_is_back_on() {
+        let config = Config::default();
+        let pause = Arc::new(Pause::default());
+        let (driver, sink) = recording_driver(&config);
+        let mut driver = driver.with_pause(pause.clone());
+        let pen = [0x09, 0x41, 0x00, 0x10, 0x00, 0x20, 0x00, 0x08, 0x00, 0x00];
+
+        driver.handle_report(2, &pen, Instant::now()).unwrap();
+        driver.set_enabled(false).unwrap();
+        assert!(pause.is_paused());
+        assert!(
+            sink.take()
+                .contains(&(EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 0))
+        );
+
+        driver.set_enabled(true).unwrap();
+        assert!(!pause.is_paused());
+        assert!(!driver.take_reinit_request());
+        driver.handle_report(2, &pen, Instant::now()).unwrap();
+        let events = sink.take();
+        assert!(events.contains(&(EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 1)));
+        assert!(events.contains(&(EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 1)));
+    }
+
+    #[test]
+    fn the_tip_can_be_ignored_out_of_range() {
+        let mut config = Config::default();
+        config.device.tip_needs_in_range = true;
+        let tips = Rc::new(RefCell::new(vec![]));
+        let record = tips.clone();
+        let (driver, _) = recording_driver(&config);
+        let mut driver = driver.with_observer(Box::new(move |stamped| {
+            if let Event::Pen(pen) = stamped.event {
+                record.borrow_mut().push(pen.tip);
+            }
+        }));
+
+        // Tip bit set, out of range, then in range.
+        for status in [0x01, 0x41] {
+            let pen = [0x09, status, 0x00, 0x10, 0x00, 0x20, 0x00, 0x01, 0x00, 0x00];
+            driver.handle_report(2, &pen, Instant::now()).unwrap();
+        }
+
+        assert_eq!(*tips.borrow(), vec![false, true]);
+    }
+
+    #[test]
+    fn holding_the_scroll_button_turns_pressure_into_the_wheel() {
+        let mut config = Config::default();
+        config.pressure_scroll.button = Some(0);
+        let (mut driver, sink) = recording_driver(&config);
+        let pen = |driver: &mut Driver, pressure: u16| {
+            let [low, high] = pressure.to_le_bytes();
+            let report = [0x09, 0x41, 0x00, 0x10, 0x00, 0x20, low, high, 0x00, 0x00];
+            driver.handle_report(2, &report, Instant::now()).unwrap();
+        };
+        let buttons = |driver: &mut Driver, mask: u8| {
+            driver
+                .handle_report(1, &[0x01, 0, 0, 0, mask, 0, 0, 0], Instant::now())
+                .unwrap();
+        };
+        let wheel = |events: &[(EventCode, i32)]| -> i32 {
+            events
+                .iter()
+                .filter(|(code, _)| *code == EventCode::EV_REL(EV_REL::REL_WHEEL))
+                .map(|(_, value)| value)
+                .sum()
+        };
+        let touch = (EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 1);
+
+        pen(&mut driver, 0x0400);
+        assert!(sink.take().contains(&touch));
+
+        buttons(&mut driver, 0x80);
+        sink.take();
+        pen(&mut driver, 0x0400);
+        pen(&mut driver, 0x0C00);
+        let harder = sink.take();
+        assert!(wheel(&harder) > 0);
+        assert!(!harder.contains(&touch));
+        assert!(
+            !harder
+                .iter()
+                .any(|(code, _)| *code == EventCode::EV_ABS(EV_ABS::ABS_X))
+        );
+        pen(&mut driver, 0x0400);
+        assert!(wheel(&sink.take()) < 0);
+
+        buttons(&mut driver, 0x00);
+        pen(&mut driver, 0x0400);
+        let drawing = sink.take();
+        assert!(drawing.contains(&touch));
+        assert_eq!(wheel(&drawing), 0);
+    }
+
+    #[test]
+    fn events_are_dropped_while_warming_up() {
+        let mut config = Config::default();
+        config.init.warm_up_ms = 200;
+        let seen = Rc::new(RefCell::new(vec![]));
+        let record = seen.clone();
+        let (driver, _) = recording_driver(&config);
+        let mut driver = driver.with_observer(Box::new(move |stamped| {
+            if let Event::Pen(pen) = stamped.event {
+                record.borrow_mut().push(pen.x);
+            }
+        }));
+
+        let initialized = Instant::now();
+        driver.warm_up(initialized);
+        for (x, after_ms) in [(0x10, 0), (0x20, 199), (0x30, 200), (0x40, 250)] {
+            let pen = [0x09, 0x40, x, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00];
+            let read_at = initialized + Duration::from_millis(after_ms);
+            driver.handle_report(2, &pen, read_at).unwrap();
+        }
+
+        assert_eq!(seen.borrow().len(), 2);
+    }
+
+    #[test]
+    fn unknown_reports_reach_the_observers_only_with_passthrough() {
+        for (unknown, passed) in [
+            (UnknownReports::Log, false),
+            (UnknownReports::Drop, false),
+            (UnknownReports::PassthroughRaw, true),
+        ] {
+            let mut config = Config::default();
+            config.device.unknown_reports = unknown;
+            let seen = Rc::new(RefCell::new(vec![]));
+            let record = seen.clone();
+            let (driver, sink) = recording_driver(&config);
+            let mut driver = driver.with_observer(Box::new(move |stamped| {
+                record.borrow_mut().push(stamped.event.clone())
+            }));
+
+            driver
+                .handle_report(2, &[0x42, 0x01, 0x02], Instant::now())
+                .unwrap();
+
+            let raw = Event::Raw(RawReport {
+                interface: 2,
+                bytes: vec![0x42, 0x01, 0x02],
+            });
+            assert_eq!(*seen.borrow() == vec![raw], passed, "{unknown:?}");
+            assert!(sink.take().is_empty());
+        }
+    }
+
+    #[test]
+    fn routes_only_reach_the_interfaces_that_are_read() {
+        let config: Config = toml::from_str(
+            "[device]\ntablet_interface = 3\nreports = [{ interface = 3, kind = \"pen\" }]\n",
+        )
+        .unwrap();
+        let (mut driver, sink) = recording_driver(&config);
+
+        let pen = [0x09, 0x40, 0x00, 0x10, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00];
+        let [buttons, tablet] = config.device.read_interfaces();
+        driver.handle_report(buttons, &pen, Instant::now()).unwrap();
+        assert!(sink.take().is_empty());
+        driver.handle_report(tablet, &pen, Instant::now()).unwrap();
+        assert!(
+            sink.take()
+                .contains(&(EventCode::EV_ABS(EV_ABS::ABS_X), 0x1000))
+        );
+
+        let unread: Config =
+            toml::from_str("[device]\nreports = [{ interface = 3, kind = \"pen\" }]\n").unwrap();
+        let error = Settings::from_config(&unread).err().unwrap().to_string();
+        assert!(error.contains("interface 3, which is not read"), "{error}");
+    }
+
+    #[test]
+    fn get_state_and_get_config_describe_the_driver() {
+        let mut config = Config::default();
+        config.mapping.screen = Some((1920, 1080));
+        let (driver, _) = recording_driver(&config);
+        let mut driver = driver;
+        control::dispatch(&ControlCommand::Disable, &mut driver);
+
+        assert_eq!(
+            control::dispatch(&ControlCommand::GetState, &mut driver),
+            r#"ok {"enabled":false,"locked":false,"profile":"default","output":"primary","last_event_ms_ago":null,"dropped_reports":null}"#
+        );
+
+        let reply = control::dispatch(&ControlCommand::GetConfig, &mut driver);
+        let json = reply.strip_prefix("ok ").unwrap();
+        assert_eq!(
+            crate::json::from_json(json),
+            Some(toml::Value::try_from(&config).unwrap())
+        );
+    }
+
+    #[test]
+    fn status_and_state_report_the_dropped_reports() {
+        let config = Config::default();
+        let (driver, _) = recording_driver(&config);
+        let mut driver = driver;
+        let mut pen = [0x09, 0x40, 0x00, 0x10, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00];
+        for counter in (0..8).chain([10]) {
+            pen[PEN_SEQUENCE_OFFSET] = counter;
+            driver.handle_report(2, &pen, Instant::now()).unwrap();
+        }
+
+        assert_eq!(driver.status(), "enabled profile=default dropped=2");
+        let state = control::dispatch(&ControlCommand::GetState, &mut driver);
+        assert!(state.ends_with(r#","dropped_reports":2}"#), "{state}");
+    }
+
+    #[test]
+    fn a_tap_shorter_than_the_tip_delay_does_not_click() {
+        let mut config = Config::default();
+        config.proximity.tip_delay_ms = 50;
+        let (mut driver, sink) = recording_driver(&config);
+        let start = Instant::now();
+        let mut presses = |reports: &[(u8, u64)]| {
+            for &(flags, ms) in reports {
+                let pen = [0x09, flags, 0x00, 0x10, 0x00, 0x20, 0x00, 0x08, 0x00, 0x00];
+                driver
+                    .handle_report(2, &pen, start + Duration::from_millis(ms))
+                    .unwrap();
+            }
+            sink.take()
+                .into_iter()
+                .filter(|event| *event == (EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 1))
+                .count()
+        };
+
+        assert_eq!(presses(&[(0x41, 0), (0x41, 30), (0x40, 40)]), 0);
+        assert_eq!(presses(&[(0x41, 100), (0x41, 130)]), 0);
+        assert_eq!(presses(&[(0x41, 150), (0x41, 160)]), 1);
+    }
+
+    #[test]
+    fn cycle_output_moves_the_mapping_to_the_next_output() {
+        let mut config = Config::default();
+        config.button_map[0] = Action::CycleOutput;
+        let mut settings = Settings::from_config(&config).unwrap();
+        let (left, right) = (Rect::new(0, 0, 1000, 1000), Rect::new(1000, 0, 1000, 1000));
+        settings.mapper.desktop = left.union(&right);
+        settings.mapper.target = left;
+        settings.outputs =
+            OutputCycle::new(vec![("left".into(), left), ("right".into(), right)], left);
+        let state = EmitterState::new(config.button_map.clone(), true, (1999, 999));
+        let seen = Rc::new(RefCell::new(vec![]));
+        let record = seen.clone();
+        let (driver, _) = recording_driver_with(settings, state);
+        let mut driver = driver.with_observer(Box::new(move |stamped| {
+            if let Event::Pen(pen) = stamped.event {
+                record.borrow_mut().push(pen.x);
+            }
+        }));
+
+        let pen = [0x09, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        for _ in 0..3 {
+            driver.handle_report(2, &pen, Instant::now()).unwrap();
+            for mask in [0x80, 0x00] {
+                driver
+                    .handle_report(1, &[0x01, 0, 0, 0, mask, 0, 0, 0], Instant::now())
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(*seen.borrow(), vec![0, 1000, 0]);
+    }
+
+    #[test]
+    fn switching_profiles_changes_the_curve_and_the_area_together() {
+        let config: Config = toml::from_str(
+            "[[profiles]]\nname = \"sketch\"\npressure_curve = 0.5\n\n\
+             [[profiles]]\nname = \"precision\"\npressure_curve = 2.0\n\
+             area = { x_min = 8000, y_min = 0, x_max = 24000, y_max = 32767 }\n",
+        )
+        .unwrap();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let record = seen.clone();
+        let (driver, _) = recording_driver(&config);
+        let mut driver = driver.with_observer(Box::new(move |stamped| {
+            if let Event::Pen(pen) = stamped.event {
+                record.borrow_mut().push((pen.x, pen.pressure));
+            }
+        }));
+
+        // x 8000, pressure a quarter of the way.
+        let pen = [0x09, 0x41, 0x40, 0x1F, 0x00, 0x20, 0x00, 0x08, 0x00, 0x00];
+        for profile in ["default", "sketch", "precision"] {
+            driver.set_profile(profile).unwrap();
+            driver.handle_report(2, &pen, Instant::now()).unwrap();
+        }
+
+        let seen = seen.borrow();
+        let (default, sketch, precision) = (seen[0], seen[1], seen[2]);
+        assert_eq!(default.0, 8000);
+        assert_eq!(sketch.0, 8000);
+        assert_eq!(precision.0, 0);
+        assert!(sketch.1 > default.1 && default.1 > precision.1);
+        assert!(driver.set_profile("gimp").is_err());
+    }
+}
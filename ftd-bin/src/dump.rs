@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{logger, replay};
+
+/// The interfaces whose packets the readers write to the log as they come
+/// in, whatever its level. Switched at runtime with `dump on <interface>`,
+/// to catch the bytes of a misbehaving interface without a restart.
+pub struct RawDump {
+    interfaces: [AtomicBool; 256],
+}
+
+impl Default for RawDump {
+    fn default() -> Self {
+        RawDump {
+            interfaces: [const { AtomicBool::new(false) }; 256],
+        }
+    }
+}
+
+impl RawDump {
+    pub fn set(&self, interface: u8, on: bool) {
+        self.interfaces[interface as usize].store(on, Ordering::Relaxed);
+    }
+
+    /// The line logged for `packet`, `None` while `interface` is not dumped.
+    /// It is the `replay` format, so a dump can be replayed.
+    pub fn line(&self, interface: u8, packet: &[u8]) -> Option<String> {
+        self.interfaces[interface as usize]
+            .load(Ordering::Relaxed)
+            .then(|| replay::format_line(interface, packet))
+    }
+
+    pub fn packet(&self, interface: u8, packet: &[u8]) {
+        if let Some(line) = self.line(interface, packet) {
+            logger::dump(&line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_dumped_interface_is_logged() {
+        let dump = RawDump::default();
+        assert_eq!(dump.line(2, &[0x09, 0x40]), None);
+
+        dump.set(2, true);
+        assert_eq!(
+            dump.line(2, &[0x09, 0x40]).as_deref(),
+            Some("Interface: 2 || Bytes: [09, 40]")
+        );
+        assert_eq!(dump.line(1, &[0x01]), None);
+
+        dump.set(2, false);
+        assert_eq!(dump.line(2, &[0x09, 0x40]), None);
+    }
+}
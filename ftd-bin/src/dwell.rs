@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use evdev_rs::enums::EV_KEY;
+use serde::{Deserialize, Serialize};
+
+use crate::action::Action;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DwellSettings {
+    /// Click by holding the pen still above the tablet, for when pressing
+    /// the tip or a button is hard.
+    pub enabled: bool,
+    /// How long the pen hovers within `radius` before it clicks.
+    pub time_ms: u64,
+    /// Largest distance in device units the pen may drift and still dwell.
+    pub radius: u16,
+    /// After a click, how long before the next dwell starts counting.
+    pub cooldown_ms: u64,
+    pub action: Action,
+}
+
+impl Default for DwellSettings {
+    fn default() -> Self {
+        DwellSettings {
+            enabled: false,
+            time_ms: 1000,
+            radius: 60,
+            cooldown_ms: 1000,
+            action: Action::Keys(vec![EV_KEY::BTN_LEFT]),
+        }
+    }
+}
+
+impl DwellSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.enabled && self.time_ms == 0 {
+            bail!("dwell.time_ms must be at least 1");
+        }
+
+        Ok(())
+    }
+}
+
+/// Fires `action` once the hovering pen stayed within `radius` of where it
+/// stopped for `time_ms`. The tip touching or the pen leaving starts over.
+///
+/// With `proximity.mode = "repeat"` a still pen counts as gone after
+/// `freeze_ms`, which has to be longer than `time_ms` for a dwell to fire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DwellClick {
+    settings: DwellSettings,
+    /// Where the pen stopped and since when.
+    anchor: Option<(u16, u16, Instant)>,
+    /// No dwell counts before this, set after a click.
+    cooldown_until: Option<Instant>,
+}
+
+impl DwellClick {
+    pub fn new(settings: DwellSettings) -> DwellClick {
+        DwellClick {
+            settings,
+            anchor: None,
+            cooldown_until: None,
+        }
+    }
+
+    /// Returns the action when the pen at `x`, `y` completes a dwell.
+    pub fn feed(&mut self, x: u16, y: u16, hovering: bool, now: Instant) -> Option<&Action> {
+        if !self.settings.enabled {
+            return None;
+        }
+        if !hovering || self.cooldown_until.is_some_and(|until| now < until) {
+            self.anchor = None;
+            return None;
+        }
+
+        match self.anchor {
+            Some((ax, ay, _)) if ax.abs_diff(x).max(ay.abs_diff(y)) <= self.settings.radius => {}
+            _ => self.anchor = Some((x, y, now)),
+        }
+        let (_, _, since) = self.anchor?;
+        if now.duration_since(since) < Duration::from_millis(self.settings.time_ms) {
+            return None;
+        }
+
+        self.anchor = None;
+        self.cooldown_until = Some(now + Duration::from_millis(self.settings.cooldown_ms));
+        Some(&self.settings.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dwell() -> DwellClick {
+        DwellClick::new(DwellSettings {
+            enabled: true,
+            ..DwellSettings::default()
+        })
+    }
+
+    #[test]
+    fn holding_still_clicks_once() {
+        let mut dwell = dwell();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert!(dwell.feed(1000, 1000, true, at(0)).is_none());
+        assert!(dwell.feed(1030, 980, true, at(500)).is_none());
+        assert_eq!(
+            dwell.feed(1020, 1010, true, at(1000)),
+            Some(&Action::Keys(vec![EV_KEY::BTN_LEFT]))
+        );
+        assert!(dwell.feed(1020, 1010, true, at(1100)).is_none());
+    }
+
+    #[test]
+    fn moving_or_touching_starts_over() {
+        let mut dwell = dwell();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        dwell.feed(1000, 1000, true, at(0));
+        dwell.feed(1200, 1000, true, at(600));
+        assert!(dwell.feed(1200, 1000, true, at(1200)).is_none());
+        dwell.feed(1200, 1000, false, at(1500));
+        assert!(dwell.feed(1200, 1000, true, at(1600)).is_none());
+        assert!(dwell.feed(1200, 1000, true, at(2599)).is_none());
+        assert!(dwell.feed(1200, 1000, true, at(2600)).is_some());
+    }
+
+    #[test]
+    fn the_cooldown_passes_before_the_next_dwell_counts() {
+        let mut dwell = dwell();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        dwell.feed(1000, 1000, true, at(0));
+        assert!(dwell.feed(1000, 1000, true, at(1000)).is_some());
+        assert!(dwell.feed(1000, 1000, true, at(1999)).is_none());
+        assert!(dwell.feed(1000, 1000, true, at(2000)).is_none());
+        assert!(dwell.feed(1000, 1000, true, at(2999)).is_none());
+        assert!(dwell.feed(1000, 1000, true, at(3000)).is_some());
+    }
+}
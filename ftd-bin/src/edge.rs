@@ -0,0 +1,127 @@
+use ftd_device::report::DEVICE_MAX_X;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeSettings {
+    /// Width in device units of the scroll strip along the right edge, 0 disables it.
+    pub width: u16,
+    /// Vertical travel in device units per wheel click.
+    pub units_per_click: u16,
+}
+
+impl Default for EdgeSettings {
+    fn default() -> Self {
+        EdgeSettings {
+            width: 0,
+            units_per_click: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeOutput {
+    /// Not an edge stroke, position the pointer as usual.
+    Pass,
+    /// Edge stroke, send these wheel clicks instead of motion.
+    Scroll(i32),
+}
+
+enum Stroke {
+    Drawing,
+    Scrolling { last_y: u16, remainder: i32 },
+}
+
+/// Turns strokes that start inside the edge strip into wheel scrolling.
+pub struct EdgeScroll {
+    settings: EdgeSettings,
+    stroke: Option<Stroke>,
+}
+
+impl EdgeScroll {
+    pub fn new(settings: EdgeSettings) -> EdgeScroll {
+        EdgeScroll {
+            settings,
+            stroke: None,
+        }
+    }
+
+    pub fn feed(&mut self, x: u16, y: u16, down: bool) -> EdgeOutput {
+        if !down {
+            let was_scrolling = matches!(self.stroke, Some(Stroke::Scrolling { .. }));
+            self.stroke = None;
+            return if was_scrolling {
+                EdgeOutput::Scroll(0)
+            } else {
+                EdgeOutput::Pass
+            };
+        }
+
+        let in_edge =
+            self.settings.width > 0 && x as i32 >= DEVICE_MAX_X - self.settings.width as i32;
+        let stroke = self.stroke.get_or_insert(if in_edge {
+            Stroke::Scrolling {
+                last_y: y,
+                remainder: 0,
+            }
+        } else {
+            Stroke::Drawing
+        });
+
+        match stroke {
+            Stroke::Drawing => EdgeOutput::Pass,
+            Stroke::Scrolling { last_y, remainder } => {
+                *remainder += *last_y as i32 - y as i32;
+                *last_y = y;
+
+                let per_click = self.settings.units_per_click.max(1) as i32;
+                let clicks = *remainder / per_click;
+                *remainder %= per_click;
+
+                EdgeOutput::Scroll(clicks)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge() -> EdgeScroll {
+        EdgeScroll::new(EdgeSettings {
+            width: 1000,
+            units_per_click: 100,
+        })
+    }
+
+    #[test]
+    fn motion_inside_the_edge_scrolls() {
+        let mut edge = edge();
+        let x = DEVICE_MAX_X as u16 - 500;
+
+        assert_eq!(edge.feed(x, 5000, true), EdgeOutput::Scroll(0));
+        assert_eq!(edge.feed(x, 4750, true), EdgeOutput::Scroll(2));
+        assert_eq!(edge.feed(x, 4700, true), EdgeOutput::Scroll(1));
+        assert_eq!(edge.feed(x, 4900, true), EdgeOutput::Scroll(-2));
+        assert_eq!(edge.feed(x, 4900, false), EdgeOutput::Scroll(0));
+    }
+
+    #[test]
+    fn central_motion_positions_normally() {
+        let mut edge = edge();
+
+        assert_eq!(edge.feed(10000, 5000, true), EdgeOutput::Pass);
+        assert_eq!(edge.feed(10000, 4000, true), EdgeOutput::Pass);
+    }
+
+    #[test]
+    fn stroke_crossing_into_the_edge_keeps_drawing() {
+        let mut edge = edge();
+
+        assert_eq!(edge.feed(10000, 5000, true), EdgeOutput::Pass);
+        assert_eq!(
+            edge.feed(DEVICE_MAX_X as u16 - 10, 4000, true),
+            EdgeOutput::Pass
+        );
+        assert_eq!(edge.feed(10000, 5000, false), EdgeOutput::Pass);
+    }
+}
@@ -0,0 +1,1136 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, ErrorKind},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Sender, TrySendError};
+
+use evdev_rs::{
+    AbsInfo, DeviceWrapper, EnableCodeData, InputEvent, TimeVal, UInputDevice, UninitDevice,
+    enums::{EV_ABS, EV_KEY, EV_REL, EV_SYN, EventCode},
+};
+
+use log::{info, warn};
+
+use ftd_device::report::{
+    ButtonEvent, DEVICE_MAX_PRESSURE, DEVICE_MAX_X, DEVICE_MAX_Y, Event, MAX_CONTACTS, PenReport,
+    TouchReport,
+};
+
+use crate::{
+    action::{Action, default_tip},
+    merge::{SharedSink, TabletSinks},
+};
+
+pub const DEVICE_NAME: &str = "FreeTomate Tablet";
+
+/// Where the emitter writes its frames, one event at a time.
+pub trait EventSink {
+    fn write(&mut self, event: &InputEvent) -> io::Result<()>;
+}
+
+impl EventSink for UInputDevice {
+    fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+        self.write_event(event)
+    }
+}
+
+/// Logs each event instead of sending it anywhere.
+pub struct LogSink;
+
+impl EventSink for LogSink {
+    fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+        info!("{:?} {}", event.event_code, event.value);
+        Ok(())
+    }
+}
+
+/// Drops every event, for `watch`.
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn write(&mut self, _event: &InputEvent) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hands the events to another sink on a thread of its own, so a slow one
+/// never holds up the others. Events are dropped while its queue is full.
+pub struct BackgroundSink {
+    events: Sender<InputEvent>,
+    dropped: bool,
+}
+
+impl BackgroundSink {
+    const QUEUE: usize = 1024;
+
+    pub fn spawn(mut sink: impl EventSink + Send + 'static) -> BackgroundSink {
+        let (events, queue) = crossbeam_channel::bounded::<InputEvent>(Self::QUEUE);
+        thread::spawn(move || {
+            for event in queue {
+                if let Err(e) = sink.write(&event) {
+                    warn!("Background sink stopped: {e}");
+                    return;
+                }
+            }
+        });
+
+        BackgroundSink {
+            events,
+            dropped: false,
+        }
+    }
+}
+
+impl EventSink for BackgroundSink {
+    fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+        match self.events.try_send(event.clone()) {
+            Ok(()) => self.dropped = false,
+            Err(TrySendError::Full(_)) => {
+                if !self.dropped {
+                    warn!("Background sink is falling behind, dropping events");
+                }
+                self.dropped = true;
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps every event in memory. Clones share the same list, so a test can
+/// hand one to the emitter and read what it wrote through another.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingSink(std::sync::Arc<std::sync::Mutex<Vec<(EventCode, i32)>>>);
+
+#[cfg(test)]
+impl RecordingSink {
+    pub fn take(&self) -> Vec<(EventCode, i32)> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+impl EventSink for RecordingSink {
+    fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+        self.0.lock().unwrap().push((event.event_code, event.value));
+        Ok(())
+    }
+}
+
+/// What `set_up_device` declares the device with, before it is created.
+pub trait DeviceSetup {
+    fn set_name(&self, name: &str);
+    fn enable_event_code(&self, code: &EventCode, data: Option<EnableCodeData>) -> io::Result<()>;
+}
+
+impl DeviceSetup for UninitDevice {
+    fn set_name(&self, name: &str) {
+        DeviceWrapper::set_name(self, name);
+    }
+
+    fn enable_event_code(&self, code: &EventCode, data: Option<EnableCodeData>) -> io::Result<()> {
+        DeviceWrapper::enable_event_code(self, code, data)
+    }
+}
+
+/// The uinput device for `state`, see `set_up_device`. Fails when
+/// `/dev/uinput` can't be opened, see `uinput_hint`.
+pub fn create_device(state: &EmitterState) -> io::Result<UInputDevice> {
+    let uninit = UninitDevice::new()
+        .ok_or_else(|| io::Error::other("Failed to allocate the uinput device"))?;
+    set_up_device(state, &uninit)?;
+
+    UInputDevice::create_from_device(&uninit)
+}
+
+/// Gives `device` the name, axes and keys of `state`.
+pub fn set_up_device(state: &EmitterState, device: &impl DeviceSetup) -> io::Result<()> {
+    device.set_name(state.name());
+
+    for (axis, maximum, resolution) in state.abs_axes() {
+        let info = AbsInfo {
+            value: 0,
+            minimum: 0,
+            maximum,
+            fuzz: 0,
+            flat: 0,
+            resolution,
+        };
+        device.enable_event_code(
+            &EventCode::EV_ABS(axis),
+            Some(EnableCodeData::AbsInfo(info)),
+        )?;
+    }
+
+    for key in state.capabilities() {
+        device.enable_event_code(&EventCode::EV_KEY(key), None)?;
+    }
+    device.enable_event_code(&EventCode::EV_REL(EV_REL::REL_WHEEL), None)
+}
+
+/// Virtual uinput tablet that mirrors the decoded reports.
+pub struct Emitter {
+    /// Every frame goes to each of these, in order.
+    sinks: Vec<Box<dyn EventSink>>,
+    state: EmitterState,
+    /// The keys the device was created with, the kernel drops the others.
+    declared: BTreeSet<EV_KEY>,
+    /// Merge window of `coalesce`, `None` writes every frame right away.
+    coalesce: Option<Duration>,
+    pending: Option<PendingFrame>,
+}
+
+/// A frame `coalesce` is still adding to, without its `SYN_REPORT`.
+struct PendingFrame {
+    events: Vec<InputEvent>,
+    since: Instant,
+    sources: Vec<u8>,
+}
+
+impl Emitter {
+    /// Fails when `/dev/uinput` can't be opened, see `uinput_hint`.
+    /// With `TabletSinks::Merged` the device is written through a
+    /// `MergedSink`, which the emitters of other tablets can share.
+    pub fn new(state: EmitterState) -> io::Result<Emitter> {
+        let device = create_device(&state)?;
+        if state.sinks == TabletSinks::Merged {
+            return Ok(Emitter::with_sink(state, SharedSink::new(device).handle()));
+        }
+
+        Ok(Emitter::with_sink(state, device))
+    }
+
+    /// Logs the frames instead of writing them, for `--dry-run`.
+    pub fn log_only(state: EmitterState) -> Emitter {
+        Emitter::with_sink(state, LogSink)
+    }
+
+    /// Sends the centered position of `EmitterState::with_center` first.
+    pub fn with_sink(state: EmitterState, sink: impl EventSink + 'static) -> Emitter {
+        let mut emitter = Emitter {
+            sinks: vec![Box::new(sink)],
+            declared: state.capabilities(),
+            state,
+            coalesce: None,
+            pending: None,
+        };
+        if emitter.state.center_on_start {
+            let frames = emitter.state.center();
+            if let Err(e) = emitter.write(&frames) {
+                warn!("Failed to send the starting position: {e}");
+            }
+        }
+
+        emitter
+    }
+
+    /// Merges events read within `window` into one frame, see `coalesce`.
+    pub fn with_coalescing(mut self, window: Option<Duration>) -> Emitter {
+        self.coalesce = window;
+        self
+    }
+
+    /// Changes the window of `coalesce`, writing out what it holds.
+    pub fn set_coalescing(&mut self, window: Option<Duration>) -> io::Result<()> {
+        self.flush()?;
+        self.coalesce = window;
+        Ok(())
+    }
+
+    /// Also writes every frame to `sink`, after the ones already there.
+    pub fn add_sink(&mut self, sink: impl EventSink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    pub fn emit(&mut self, event: &Event) -> io::Result<()> {
+        self.flush()?;
+        let frame = self.state.frame(event);
+        self.write(&frame)
+    }
+
+    /// Like `emit`, but with a window set the event joins the frame of
+    /// those read from other interfaces within it, so they reach clients
+    /// under one `SYN_REPORT`. A second event from the same `source`
+    /// starts a new frame. The frame is written by the event that does not
+    /// fit anymore, `flush_due` or anything else that writes.
+    pub fn coalesce(&mut self, source: u8, read_at: Instant, event: &Event) -> io::Result<()> {
+        let Some(window) = self.coalesce else {
+            return self.emit(event);
+        };
+        let mut frame = self.state.frame(event);
+        if frame.pop().is_none() {
+            return Ok(());
+        }
+
+        if let Some(pending) = &self.pending
+            && (pending.sources.contains(&source)
+                || read_at.saturating_duration_since(pending.since) > window)
+        {
+            self.flush()?;
+        }
+        let pending = self.pending.get_or_insert_with(|| PendingFrame {
+            events: vec![],
+            since: read_at,
+            sources: vec![],
+        });
+        pending.events.append(&mut frame);
+        pending.sources.push(source);
+
+        Ok(())
+    }
+
+    /// When the frame `coalesce` holds is due, if it holds one.
+    pub fn deadline(&self) -> Option<Instant> {
+        Some(self.pending.as_ref()?.since + self.coalesce?)
+    }
+
+    /// Writes the frame `coalesce` holds once its window passed at `now`.
+    pub fn flush_due(&mut self, now: Instant) -> io::Result<()> {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => self.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the pending frame now, due or not.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let Some(mut pending) = self.pending.take() else {
+            return Ok(());
+        };
+        pending
+            .events
+            .push(input_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0));
+        self.write(&pending.events)
+    }
+
+    pub fn trigger(&mut self, action: &Action) -> io::Result<()> {
+        self.flush()?;
+        let frames = self.state.trigger(action);
+        self.write(&frames)
+    }
+
+    pub fn set_hover(&mut self, hover: bool) {
+        self.state.hover_moves_cursor = hover;
+    }
+
+    /// Releases what the old tip action holds, then switches to `tip`.
+    pub fn set_tip(&mut self, tip: Action) -> io::Result<()> {
+        if tip != self.state.tip {
+            self.teardown()?;
+            self.state.tip = tip;
+        }
+        Ok(())
+    }
+
+    /// The keys of `actions` the device was not created with, e.g. a media
+    /// key first bound on `reload`.
+    pub fn undeclared_keys(&self, actions: &[Action]) -> Vec<EV_KEY> {
+        let mut keys = vec![];
+        for action in actions {
+            if let Action::Keys(codes) = action {
+                keys.extend(codes.iter().filter(|key| !self.declared.contains(key)));
+            }
+        }
+        keys.dedup();
+        keys
+    }
+
+    /// Releases what the old bindings hold, then switches to `button_map`.
+    pub fn set_button_map(&mut self, button_map: Vec<Action>) -> io::Result<()> {
+        let undeclared = self.undeclared_keys(&button_map);
+        if !undeclared.is_empty() {
+            warn!(
+                "The device was created without {undeclared:?}, restart the driver for \
+                 their bindings to work"
+            );
+        }
+        self.teardown()?;
+        self.state.button_map = button_map;
+        Ok(())
+    }
+
+    /// Releases everything still held so nothing stays stuck in the compositor.
+    pub fn teardown(&mut self) -> io::Result<()> {
+        self.flush()?;
+        let frame = self.state.release_all();
+        self.write(&frame)
+    }
+
+    /// Writes the frame to every sink, even after one fails, and returns
+    /// the first error.
+    fn write(&mut self, frame: &[InputEvent]) -> io::Result<()> {
+        let mut result = Ok(());
+        for sink in &mut self.sinks {
+            for event in frame {
+                if let Err(e) = sink.write(event) {
+                    result = result.and(Err(e));
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// What to do about the usual reasons the virtual device can't be created.
+pub fn uinput_hint(error: &io::Error) -> Option<&'static str> {
+    match error.kind() {
+        ErrorKind::NotFound => Some(
+            "/dev/uinput does not exist, load the kernel module with `sudo modprobe uinput` \
+             (add `uinput` to /etc/modules-load.d/ to keep it across reboots)",
+        ),
+        ErrorKind::PermissionDenied => Some(
+            "/dev/uinput is not writable, run as root or add a udev rule giving your user \
+             access to it, e.g. KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\"",
+        ),
+        _ => None,
+    }
+}
+
+impl Drop for Emitter {
+    fn drop(&mut self) {
+        let _ = self.teardown();
+    }
+}
+
+/// Translates events into uinput frames while tracking what is asserted.
+pub struct EmitterState {
+    /// What the virtual device is called, `DEVICE_NAME` unless set.
+    name: String,
+    sinks: TabletSinks,
+    button_map: Vec<Action>,
+    tip: Action,
+    tip_down: bool,
+    touch: bool,
+    pen_bounds: (i32, i32),
+    pen_resolution: (i32, i32),
+    touch_resolution: (i32, i32),
+    extra_keys: BTreeSet<EV_KEY>,
+    asserted: BTreeSet<EV_KEY>,
+    in_proximity: bool,
+    hover_moves_cursor: bool,
+    center_on_start: bool,
+}
+
+impl EmitterState {
+    /// With `touch` off, touch reports produce no output at all. `pen_bounds`
+    /// is the largest X/Y a pen report can carry.
+    pub fn new(button_map: Vec<Action>, touch: bool, pen_bounds: (i32, i32)) -> EmitterState {
+        EmitterState {
+            name: DEVICE_NAME.to_string(),
+            sinks: TabletSinks::default(),
+            button_map,
+            tip: default_tip(),
+            tip_down: false,
+            touch,
+            pen_bounds,
+            pen_resolution: (0, 0),
+            touch_resolution: (0, 0),
+            extra_keys: BTreeSet::new(),
+            asserted: BTreeSet::new(),
+            in_proximity: false,
+            hover_moves_cursor: true,
+            center_on_start: false,
+        }
+    }
+
+    /// Names the virtual device, which some applications key their tablet
+    /// settings on.
+    pub fn with_name(mut self, name: &str) -> EmitterState {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Whether the device is `TabletSinks::Merged` with other tablets'.
+    pub fn with_sinks(mut self, sinks: TabletSinks) -> EmitterState {
+        self.sinks = sinks;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// With `hover` off the pen position is only sent while the tip is
+    /// down, a hovering pen leaves the cursor where it is.
+    pub fn with_hover(mut self, hover: bool) -> EmitterState {
+        self.hover_moves_cursor = hover;
+        self
+    }
+
+    /// With `center` on the device reports the pen in the middle of
+    /// `pen_bounds` as soon as it is created, then out of range again.
+    pub fn with_center(mut self, center: bool) -> EmitterState {
+        self.center_on_start = center;
+        self
+    }
+
+    /// What the tip does instead of a touch, see `Config::tip`.
+    pub fn with_tip(mut self, tip: Action) -> EmitterState {
+        self.tip = tip;
+        self
+    }
+
+    /// Units per mm reported on the pen axes (in `pen_bounds` units) and on
+    /// the touch axes (in device units). 0 means unknown.
+    pub fn with_resolution(mut self, pen: (i32, i32), touch: (i32, i32)) -> EmitterState {
+        self.pen_resolution = pen;
+        self.touch_resolution = touch;
+        self
+    }
+
+    /// Also advertises the keys of bindings that may be switched to later,
+    /// the kernel drops keys the device was not created with.
+    pub fn with_keys(mut self, button_maps: &[&[Action]]) -> EmitterState {
+        for action in button_maps.iter().flat_map(|map| map.iter()) {
+            if let Action::Keys(codes) = action {
+                self.extra_keys.extend(codes.iter().copied());
+            }
+        }
+        self
+    }
+
+    /// `(axis, maximum, resolution)` of every absolute axis.
+    pub fn abs_axes(&self) -> Vec<(EV_ABS, i32, i32)> {
+        let mut axes = vec![
+            (EV_ABS::ABS_X, self.pen_bounds.0, self.pen_resolution.0),
+            (EV_ABS::ABS_Y, self.pen_bounds.1, self.pen_resolution.1),
+            (EV_ABS::ABS_PRESSURE, DEVICE_MAX_PRESSURE, 0),
+        ];
+
+        if self.touch {
+            axes.extend([
+                (EV_ABS::ABS_MT_SLOT, MAX_CONTACTS as i32 - 1, 0),
+                (EV_ABS::ABS_MT_TRACKING_ID, u16::MAX as i32, 0),
+                (
+                    EV_ABS::ABS_MT_POSITION_X,
+                    DEVICE_MAX_X,
+                    self.touch_resolution.0,
+                ),
+                (
+                    EV_ABS::ABS_MT_POSITION_Y,
+                    DEVICE_MAX_Y,
+                    self.touch_resolution.1,
+                ),
+            ]);
+        }
+
+        axes
+    }
+
+    pub fn capabilities(&self) -> BTreeSet<EV_KEY> {
+        let mut keys = BTreeSet::from([
+            EV_KEY::BTN_TOOL_PEN,
+            EV_KEY::BTN_TOUCH,
+            EV_KEY::BTN_LEFT,
+            EV_KEY::BTN_RIGHT,
+            EV_KEY::BTN_MIDDLE,
+        ]);
+
+        for action in self.button_map.iter().chain([&self.tip]) {
+            if let Action::Keys(codes) = action {
+                keys.extend(codes.iter().copied());
+            }
+        }
+        keys.extend(self.extra_keys.iter().copied());
+
+        keys
+    }
+
+    /// One report becomes one frame: every axis and key change it carries,
+    /// then a single `SYN_REPORT`, so clients never see half a report.
+    pub fn frame(&mut self, event: &Event) -> Vec<InputEvent> {
+        let mut frame = vec![];
+
+        match event {
+            Event::Pen(report) => self.pen(report, &mut frame),
+            Event::Button(button) => self.button(button, &mut frame),
+            Event::Touch(report) => self.touch(report, &mut frame),
+            // uinput has nothing to carry them, only the observers see them.
+            Event::Raw(_) => {}
+        }
+
+        if !frame.is_empty() {
+            frame.push(input_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0));
+        }
+
+        frame
+    }
+
+    /// The pen entering range in the middle of `pen_bounds` and leaving
+    /// again, as two synced frames. What is asserted stays untouched.
+    pub fn center(&self) -> Vec<InputEvent> {
+        let syn = || input_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+        vec![
+            key_event(EV_KEY::BTN_TOOL_PEN, 1),
+            abs_event(EV_ABS::ABS_X, self.pen_bounds.0 / 2),
+            abs_event(EV_ABS::ABS_Y, self.pen_bounds.1 / 2),
+            syn(),
+            key_event(EV_KEY::BTN_TOOL_PEN, 0),
+            syn(),
+        ]
+    }
+
+    /// Presses and releases `action` as two synced frames.
+    pub fn trigger(&mut self, action: &Action) -> Vec<InputEvent> {
+        let mut frames = vec![];
+
+        for pressed in [true, false] {
+            let mut frame = vec![];
+            self.action(action, pressed, &mut frame);
+            if !frame.is_empty() {
+                frame.push(input_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0));
+            }
+            frames.extend(frame);
+        }
+
+        frames
+    }
+
+    /// Releases the tip, every held key and finally the tool.
+    pub fn release_all(&mut self) -> Vec<InputEvent> {
+        let mut frame = vec![];
+
+        if self.asserted.remove(&EV_KEY::BTN_TOUCH) {
+            frame.push(key_event(EV_KEY::BTN_TOUCH, 0));
+        }
+        self.tip_down = false;
+
+        let tool = self.asserted.remove(&EV_KEY::BTN_TOOL_PEN);
+        for key in std::mem::take(&mut self.asserted) {
+            frame.push(key_event(key, 0));
+        }
+
+        if self.in_proximity {
+            self.in_proximity = false;
+            frame.push(input_event(EventCode::EV_ABS(EV_ABS::ABS_PRESSURE), 0));
+        }
+
+        if tool {
+            frame.push(key_event(EV_KEY::BTN_TOOL_PEN, 0));
+        }
+
+        if !frame.is_empty() {
+            frame.push(input_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0));
+        }
+
+        frame
+    }
+
+    fn pen(&mut self, report: &PenReport, frame: &mut Vec<InputEvent>) {
+        if !report.in_range {
+            if self.in_proximity {
+                self.in_proximity = false;
+                self.set_tip(false, frame);
+                frame.push(input_event(EventCode::EV_ABS(EV_ABS::ABS_PRESSURE), 0));
+                self.set_key(EV_KEY::BTN_TOOL_PEN, false, frame);
+            }
+            return;
+        }
+
+        if !self.in_proximity {
+            self.in_proximity = true;
+            self.set_key(EV_KEY::BTN_TOOL_PEN, true, frame);
+        }
+
+        // Clients take pressure for a touch, whatever the tip is bound to.
+        let pressure = if report.tip && self.tip == default_tip() {
+            report.pressure
+        } else {
+            0
+        };
+        if report.tip || self.hover_moves_cursor {
+            frame.push(input_event(
+                EventCode::EV_ABS(EV_ABS::ABS_X),
+                report.x as i32,
+            ));
+            frame.push(input_event(
+                EventCode::EV_ABS(EV_ABS::ABS_Y),
+                report.y as i32,
+            ));
+        }
+        frame.push(input_event(
+            EventCode::EV_ABS(EV_ABS::ABS_PRESSURE),
+            pressure as i32,
+        ));
+        self.set_tip(report.tip, frame);
+    }
+
+    fn set_tip(&mut self, down: bool, frame: &mut Vec<InputEvent>) {
+        if std::mem::replace(&mut self.tip_down, down) != down {
+            let tip = self.tip.clone();
+            self.action(&tip, down, frame);
+        }
+    }
+
+    fn button(&mut self, button: &ButtonEvent, frame: &mut Vec<InputEvent>) {
+        if let Some(action) = self.button_map.get(button.index as usize).cloned() {
+            self.action(&action, button.pressed, frame);
+        }
+    }
+
+    fn action(&mut self, action: &Action, pressed: bool, frame: &mut Vec<InputEvent>) {
+        match action.clone() {
+            Action::Keys(keys) if pressed => {
+                for key in keys {
+                    self.set_key(key, true, frame);
+                }
+            }
+            Action::Keys(keys) => {
+                for key in keys.into_iter().rev() {
+                    self.set_key(key, false, frame);
+                }
+            }
+            Action::Scroll(clicks) if pressed => {
+                frame.push(input_event(EventCode::EV_REL(EV_REL::REL_WHEEL), clicks));
+            }
+            Action::Scroll(_)
+            | Action::Command { .. }
+            | Action::CycleArea
+            | Action::CycleOutput
+            | Action::ToggleOutput
+            | Action::Panic
+            | Action::Unlock
+            | Action::ToggleMouse => {}
+        }
+    }
+
+    fn touch(&mut self, report: &TouchReport, frame: &mut Vec<InputEvent>) {
+        if !self.touch {
+            return;
+        }
+
+        for contact in &report.contacts {
+            let slot = contact.id as usize % MAX_CONTACTS;
+            frame.push(abs_event(EV_ABS::ABS_MT_SLOT, slot as i32));
+
+            if contact.down {
+                frame.push(abs_event(EV_ABS::ABS_MT_TRACKING_ID, contact.id as i32));
+                frame.push(abs_event(EV_ABS::ABS_MT_POSITION_X, contact.x as i32));
+                frame.push(abs_event(EV_ABS::ABS_MT_POSITION_Y, contact.y as i32));
+            } else {
+                frame.push(abs_event(EV_ABS::ABS_MT_TRACKING_ID, -1));
+            }
+        }
+    }
+
+    fn set_key(&mut self, key: EV_KEY, down: bool, frame: &mut Vec<InputEvent>) {
+        let changed = if down {
+            self.asserted.insert(key)
+        } else {
+            self.asserted.remove(&key)
+        };
+
+        if changed {
+            frame.push(key_event(key, down as i32));
+        }
+    }
+}
+
+fn key_event(key: EV_KEY, value: i32) -> InputEvent {
+    input_event(EventCode::EV_KEY(key), value)
+}
+
+fn abs_event(axis: EV_ABS, value: i32) -> InputEvent {
+    input_event(EventCode::EV_ABS(axis), value)
+}
+
+fn input_event(code: EventCode, value: i32) -> InputEvent {
+    InputEvent::new(&TimeVal::new(0, 0), &code, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::default_button_map;
+    use ftd_device::report::Contact;
+
+    const DEVICE_BOUNDS: (i32, i32) = (DEVICE_MAX_X, DEVICE_MAX_Y);
+
+    fn press(index: u8, pressed: bool) -> Event {
+        Event::Button(ButtonEvent { index, pressed })
+    }
+
+    /// Keeps what the device was declared with.
+    #[derive(Default)]
+    struct RecordingSetup {
+        name: std::cell::RefCell<Option<String>>,
+        codes: std::cell::RefCell<Vec<EventCode>>,
+    }
+
+    impl DeviceSetup for RecordingSetup {
+        fn set_name(&self, name: &str) {
+            self.name.replace(Some(name.to_string()));
+        }
+
+        fn enable_event_code(
+            &self,
+            code: &EventCode,
+            _data: Option<EnableCodeData>,
+        ) -> io::Result<()> {
+            self.codes.borrow_mut().push(*code);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn the_device_is_set_up_with_the_configured_name() {
+        let state = EmitterState::new(default_button_map(), false, DEVICE_BOUNDS);
+        let device = RecordingSetup::default();
+        set_up_device(&state, &device).unwrap();
+        assert_eq!(device.name.take().as_deref(), Some(DEVICE_NAME));
+
+        let config: crate::config::Config =
+            toml::from_str("device_name = \"Studio Tablet\"\n").unwrap();
+        let device = RecordingSetup::default();
+        set_up_device(&state.with_name(&config.device_name), &device).unwrap();
+        assert_eq!(device.name.take().as_deref(), Some("Studio Tablet"));
+        assert!(
+            device
+                .codes
+                .borrow()
+                .contains(&EventCode::EV_ABS(EV_ABS::ABS_PRESSURE))
+        );
+    }
+
+    #[test]
+    fn the_cursor_is_centered_at_setup_only_when_asked() {
+        let sink = RecordingSink::default();
+        let state = EmitterState::new(default_button_map(), false, (1920, 1080));
+        Emitter::with_sink(state, sink.clone());
+        assert!(sink.take().is_empty());
+
+        let state = EmitterState::new(default_button_map(), false, (1920, 1080)).with_center(true);
+        let mut emitter = Emitter::with_sink(state, sink.clone());
+        let syn = (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+        assert_eq!(
+            sink.take(),
+            vec![
+                (EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 1),
+                (EventCode::EV_ABS(EV_ABS::ABS_X), 960),
+                (EventCode::EV_ABS(EV_ABS::ABS_Y), 540),
+                syn,
+                (EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 0),
+                syn,
+            ]
+        );
+        // Nothing is left held for teardown to release.
+        emitter.teardown().unwrap();
+        assert!(sink.take().is_empty());
+    }
+
+    #[test]
+    fn every_sink_receives_the_same_events() {
+        let state = EmitterState::new(default_button_map(), false, DEVICE_BOUNDS);
+        let (first, second) = (RecordingSink::default(), RecordingSink::default());
+        let mut emitter = Emitter::with_sink(state, first.clone());
+        emitter.add_sink(second.clone());
+
+        emitter
+            .emit(&Event::Pen(PenReport {
+                x: 10,
+                y: 20,
+                pressure: 300,
+                in_range: true,
+                tip: true,
+            }))
+            .unwrap();
+        emitter.emit(&press(4, true)).unwrap();
+        emitter.teardown().unwrap();
+
+        let events = first.take();
+        assert!(events.len() > 4);
+        assert_eq!(events, second.take());
+    }
+
+    #[test]
+    fn a_button_and_a_pen_move_within_the_window_are_one_frame() {
+        let state = EmitterState::new(default_button_map(), false, DEVICE_BOUNDS);
+        let sink = RecordingSink::default();
+        let mut emitter = Emitter::with_sink(state, sink.clone());
+        emitter
+            .set_coalescing(Some(Duration::from_millis(4)))
+            .unwrap();
+        let start = Instant::now();
+        let pen = |x| {
+            Event::Pen(PenReport {
+                x,
+                y: 20,
+                pressure: 0,
+                in_range: true,
+                tip: false,
+            })
+        };
+        let syncs = |events: &[(EventCode, i32)]| {
+            events
+                .iter()
+                .filter(|(code, _)| *code == EventCode::EV_SYN(EV_SYN::SYN_REPORT))
+                .count()
+        };
+
+        emitter.coalesce(1, start, &pen(10)).unwrap();
+        emitter
+            .coalesce(0, start + Duration::from_millis(2), &press(4, true))
+            .unwrap();
+        assert_eq!(sink.take(), vec![]);
+        assert_eq!(emitter.deadline(), Some(start + Duration::from_millis(4)));
+
+        emitter.flush_due(start + Duration::from_millis(4)).unwrap();
+        let frame = sink.take();
+        assert_eq!(syncs(&frame), 1);
+        assert_eq!(
+            frame.last(),
+            Some(&(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0))
+        );
+        assert!(frame.contains(&(EventCode::EV_ABS(EV_ABS::ABS_X), 10)));
+        assert!(frame.contains(&(EventCode::EV_KEY(EV_KEY::KEY_LEFTCTRL), 1)));
+
+        // Two pen reports never share a frame, nor do events too far apart.
+        emitter.coalesce(1, start, &pen(30)).unwrap();
+        emitter.coalesce(1, start, &pen(40)).unwrap();
+        emitter
+            .coalesce(0, start + Duration::from_millis(5), &press(4, false))
+            .unwrap();
+        emitter.flush_due(start + Duration::from_secs(1)).unwrap();
+        assert_eq!(syncs(&sink.take()), 3);
+    }
+
+    #[test]
+    fn teardown_releases_exactly_the_asserted_keys() {
+        let mut state = EmitterState::new(default_button_map(), false, DEVICE_BOUNDS);
+
+        state.frame(&Event::Pen(PenReport {
+            x: 10,
+            y: 20,
+            pressure: 300,
+            in_range: true,
+            tip: true,
+        }));
+        state.frame(&press(0, true));
+        state.frame(&press(5, true));
+        state.frame(&press(5, false));
+        state.frame(&press(11, true));
+
+        let released: Vec<(EventCode, i32)> = state
+            .release_all()
+            .into_iter()
+            .map(|event| (event.event_code, event.value))
+            .collect();
+
+        assert_eq!(
+            released,
+            vec![
+                (EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 0),
+                (EventCode::EV_KEY(EV_KEY::KEY_MINUS), 0),
+                (EventCode::EV_KEY(EV_KEY::KEY_LEFTCTRL), 0),
+                (EventCode::EV_KEY(EV_KEY::KEY_B), 0),
+                (EventCode::EV_ABS(EV_ABS::ABS_PRESSURE), 0),
+                (EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 0),
+                (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0),
+            ]
+        );
+        assert!(state.release_all().is_empty());
+    }
+
+    #[test]
+    fn pen_report_is_one_frame_with_a_single_sync() {
+        let mut state = EmitterState::new(default_button_map(), false, DEVICE_BOUNDS);
+        let mut pen = |x, y, pressure| {
+            state
+                .frame(&Event::Pen(PenReport {
+                    x,
+                    y,
+                    pressure,
+                    in_range: true,
+                    tip: true,
+                }))
+                .into_iter()
+                .map(|event| (event.event_code, event.value))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            pen(10, 20, 300),
+            vec![
+                (EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 1),
+                (EventCode::EV_ABS(EV_ABS::ABS_X), 10),
+                (EventCode::EV_ABS(EV_ABS::ABS_Y), 20),
+                (EventCode::EV_ABS(EV_ABS::ABS_PRESSURE), 300),
+                (EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 1),
+                (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0),
+            ]
+        );
+        assert_eq!(
+            pen(11, 21, 310),
+            vec![
+                (EventCode::EV_ABS(EV_ABS::ABS_X), 11),
+                (EventCode::EV_ABS(EV_ABS::ABS_Y), 21),
+                (EventCode::EV_ABS(EV_ABS::ABS_PRESSURE), 310),
+                (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignored_touch_produces_nothing() {
+        let report = Event::Touch(TouchReport {
+            contacts: vec![Contact {
+                id: 1,
+                down: true,
+                x: 100,
+                y: 200,
+            }],
+        });
+
+        let mut ignoring = EmitterState::new(default_button_map(), false, DEVICE_BOUNDS);
+        assert!(ignoring.frame(&report).is_empty());
+        assert!(
+            !ignoring
+                .abs_axes()
+                .iter()
+                .any(|(axis, _, _)| *axis == EV_ABS::ABS_MT_SLOT)
+        );
+
+        let mut touching = EmitterState::new(default_button_map(), true, DEVICE_BOUNDS);
+        assert_eq!(touching.frame(&report).len(), 5);
+    }
+
+    #[test]
+    fn hover_without_cursor_motion_waits_for_the_tip() {
+        let mut state =
+            EmitterState::new(default_button_map(), false, DEVICE_BOUNDS).with_hover(false);
+        let pen = |tip| {
+            Event::Pen(PenReport {
+                x: 100,
+                y: 200,
+                pressure: 300,
+                in_range: true,
+                tip,
+            })
+        };
+        let moves = |frame: Vec<InputEvent>| {
+            frame
+                .iter()
+                .any(|event| event.event_code == EventCode::EV_ABS(EV_ABS::ABS_X))
+        };
+
+        let hover = state.frame(&pen(false));
+        assert!(
+            hover
+                .iter()
+                .any(|event| event.event_code == EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN))
+        );
+        assert!(!moves(hover));
+        assert!(moves(state.frame(&pen(true))));
+        assert!(!moves(state.frame(&pen(false))));
+    }
+
+    #[test]
+    fn a_bound_tip_sends_its_action_instead_of_the_touch() {
+        let mut state = EmitterState::new(default_button_map(), false, DEVICE_BOUNDS)
+            .with_tip(Action::Keys(vec![EV_KEY::BTN_RIGHT]));
+        let pen = |in_range, tip| {
+            Event::Pen(PenReport {
+                x: 100,
+                y: 200,
+                pressure: 300,
+                in_range,
+                tip,
+            })
+        };
+        let keys = |frame: Vec<InputEvent>| {
+            frame
+                .iter()
+                .filter_map(|event| match event.event_code {
+                    EventCode::EV_KEY(key) if key != EV_KEY::BTN_TOOL_PEN => {
+                        Some((key, event.value))
+                    }
+                    EventCode::EV_ABS(EV_ABS::ABS_PRESSURE) => {
+                        assert_eq!(event.value, 0);
+                        None
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(state.capabilities().contains(&EV_KEY::BTN_RIGHT));
+        assert_eq!(keys(state.frame(&pen(true, false))), vec![]);
+        assert_eq!(
+            keys(state.frame(&pen(true, true))),
+            vec![(EV_KEY::BTN_RIGHT, 1)]
+        );
+        assert_eq!(keys(state.frame(&pen(true, true))), vec![]);
+        assert_eq!(
+            keys(state.frame(&pen(false, false))),
+            vec![(EV_KEY::BTN_RIGHT, 0)]
+        );
+    }
+
+    #[test]
+    fn media_keys_are_declared_and_sent() {
+        let mut button_map = default_button_map();
+        button_map[0] = Action::Keys(vec![EV_KEY::KEY_PLAYPAUSE]);
+        let volume = Action::Keys(vec![EV_KEY::KEY_VOLUMEUP]);
+        let state = EmitterState::new(button_map, false, DEVICE_BOUNDS)
+            .with_keys(&[std::slice::from_ref(&volume)]);
+        assert!(state.capabilities().contains(&EV_KEY::KEY_PLAYPAUSE));
+        assert!(state.capabilities().contains(&EV_KEY::KEY_VOLUMEUP));
+
+        let sink = RecordingSink::default();
+        let mut emitter = Emitter::with_sink(state, sink.clone());
+        emitter.emit(&press(0, true)).unwrap();
+        emitter.emit(&press(0, false)).unwrap();
+        let keys: Vec<_> = sink
+            .take()
+            .into_iter()
+            .filter(|(code, _)| matches!(code, EventCode::EV_KEY(_)))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (EventCode::EV_KEY(EV_KEY::KEY_PLAYPAUSE), 1),
+                (EventCode::EV_KEY(EV_KEY::KEY_PLAYPAUSE), 0),
+            ]
+        );
+
+        let mute = Action::Keys(vec![EV_KEY::KEY_MUTE]);
+        assert_eq!(emitter.undeclared_keys(&[volume]), vec![]);
+        assert_eq!(emitter.undeclared_keys(&[mute]), vec![EV_KEY::KEY_MUTE]);
+    }
+
+    #[test]
+    fn abs_axes_carry_the_configured_resolution() {
+        let state = EmitterState::new(default_button_map(), true, (1919, 1079))
+            .with_resolution((7, 8), (129, 215));
+        let resolution = |wanted: EV_ABS| {
+            state
+                .abs_axes()
+                .into_iter()
+                .find(|(axis, _, _)| *axis == wanted)
+                .map(|(_, _, resolution)| resolution)
+        };
+
+        assert_eq!(resolution(EV_ABS::ABS_X), Some(7));
+        assert_eq!(resolution(EV_ABS::ABS_Y), Some(8));
+        assert_eq!(resolution(EV_ABS::ABS_PRESSURE), Some(0));
+        assert_eq!(resolution(EV_ABS::ABS_MT_POSITION_X), Some(129));
+        assert_eq!(resolution(EV_ABS::ABS_MT_POSITION_Y), Some(215));
+    }
+
+    #[test]
+    fn missing_or_locked_uinput_gets_a_hint() {
+        let missing = io::Error::from_raw_os_error(libc::ENOENT);
+        let locked = io::Error::from_raw_os_error(libc::EACCES);
+
+        assert!(uinput_hint(&missing).unwrap().contains("modprobe uinput"));
+        assert!(uinput_hint(&locked).unwrap().contains("udev rule"));
+        assert_eq!(uinput_hint(&io::Error::other("busy")), None);
+    }
+}
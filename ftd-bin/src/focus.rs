@@ -0,0 +1,209 @@
+use std::{fs, process::Command, thread, time::Duration};
+
+use crossbeam_channel::Receiver;
+
+use crate::profile::ProfileConfig;
+
+/// What could be read about the focused window, for the `window_class` and
+/// `process_name` rules of the profiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Focused {
+    /// The instance and the class name of its `WM_CLASS`.
+    pub classes: Vec<String>,
+    /// `/proc/<pid>/comm` of its `_NET_WM_PID`.
+    pub process: Option<String>,
+}
+
+impl Focused {
+    /// Neither could be read, e.g. without X11 or for a window that sets
+    /// none of the properties.
+    pub fn is_unknown(&self) -> bool {
+        self.classes.is_empty() && self.process.is_none()
+    }
+
+    /// Asks X11 through `xprop`, anything it cannot tell is left out.
+    pub fn query() -> Focused {
+        let Some(window) = xprop(&["-root", "_NET_ACTIVE_WINDOW"])
+            .as_deref()
+            .and_then(parse_active_window)
+        else {
+            return Focused::default();
+        };
+        let Some(properties) = xprop(&["-id", &window, "WM_CLASS", "_NET_WM_PID"]) else {
+            return Focused::default();
+        };
+        let (classes, pid) = parse_window(&properties);
+        let process = pid
+            .and_then(|pid| fs::read_to_string(format!("/proc/{pid}/comm")).ok())
+            .map(|comm| comm.trim_end().to_string());
+
+        Focused { classes, process }
+    }
+}
+
+fn xprop(args: &[&str]) -> Option<String> {
+    let output = Command::new("xprop").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `0x3a00007` of `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007`,
+/// `None` for `0x0` when nothing has the focus.
+fn parse_active_window(output: &str) -> Option<String> {
+    let id = output
+        .split('#')
+        .nth(1)?
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .find(|id| !id.is_empty())?;
+    (u32::from_str_radix(id.trim_start_matches("0x"), 16).ok()? != 0).then(|| id.to_string())
+}
+
+/// The `WM_CLASS` strings and the `_NET_WM_PID` of `xprop -id` output.
+fn parse_window(output: &str) -> (Vec<String>, Option<u32>) {
+    let mut classes = vec![];
+    let mut pid = None;
+    for line in output.lines() {
+        let Some((name, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        if name.starts_with("WM_CLASS") {
+            classes = value
+                .split(", ")
+                .map(|class| class.trim().trim_matches('"').to_string())
+                .filter(|class| !class.is_empty())
+                .collect();
+        } else if name.starts_with("_NET_WM_PID") {
+            pid = value.trim().parse().ok();
+        }
+    }
+
+    (classes, pid)
+}
+
+/// The profile to switch to for `focused`. A profile matches when every
+/// rule it sets does, ignoring case. One setting both rules goes first,
+/// then one with only `window_class`, then one with only `process_name`,
+/// and the first in the config within each.
+pub fn matching<'a>(profiles: &'a [ProfileConfig], focused: &Focused) -> Option<&'a str> {
+    let class_matches = |wanted: &String| {
+        focused
+            .classes
+            .iter()
+            .any(|class| class.eq_ignore_ascii_case(wanted))
+    };
+    let process_matches = |wanted: &String| {
+        focused
+            .process
+            .as_ref()
+            .is_some_and(|process| process.eq_ignore_ascii_case(wanted))
+    };
+    let rank = |profile: &ProfileConfig| match (&profile.window_class, &profile.process_name) {
+        (Some(class), Some(process)) => {
+            (class_matches(class) && process_matches(process)).then_some(0)
+        }
+        (Some(class), None) => class_matches(class).then_some(1),
+        (None, Some(process)) => process_matches(process).then_some(2),
+        (None, None) => None,
+    };
+
+    profiles
+        .iter()
+        .filter_map(|profile| Some((rank(profile)?, profile.name.as_str())))
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, name)| name)
+}
+
+/// Reads the focused window every `interval` on a background thread and
+/// hands on the changes, `xprop` runs there and not in the report loop.
+pub struct FocusWatcher {
+    changes: Receiver<Focused>,
+}
+
+impl FocusWatcher {
+    pub fn spawn(interval: Duration) -> FocusWatcher {
+        let (sender, changes) = crossbeam_channel::unbounded();
+        thread::spawn(move || {
+            let mut last = None;
+            loop {
+                let focused = Focused::query();
+                if last.as_ref() != Some(&focused) {
+                    if sender.send(focused.clone()).is_err() {
+                        return;
+                    }
+                    last = Some(focused);
+                }
+                thread::sleep(interval);
+            }
+        });
+
+        FocusWatcher { changes }
+    }
+
+    /// The latest focus change since the last call.
+    pub fn changed(&self) -> Option<Focused> {
+        self.changes.try_iter().last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, class: Option<&str>, process: Option<&str>) -> ProfileConfig {
+        ProfileConfig {
+            name: name.to_string(),
+            button_map: None,
+            pressure_curve: None,
+            area: None,
+            rotation: None,
+            pen: None,
+            window_class: class.map(str::to_string),
+            process_name: process.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn both_rules_beat_the_class_which_beats_the_process() {
+        let profiles = [
+            rule("by-process", None, Some("electron")),
+            rule("by-class", Some("Obsidian"), None),
+            rule("by-both", Some("obsidian"), Some("electron")),
+            rule("manual", None, None),
+        ];
+        let focused = |classes: &[&str], process: Option<&str>| Focused {
+            classes: classes.iter().map(|class| class.to_string()).collect(),
+            process: process.map(str::to_string),
+        };
+
+        let both = focused(&["obsidian", "Obsidian"], Some("electron"));
+        assert_eq!(matching(&profiles, &both), Some("by-both"));
+        let class = focused(&["obsidian", "Obsidian"], Some("obsidian-bin"));
+        assert_eq!(matching(&profiles, &class), Some("by-class"));
+        let process = focused(&[], Some("Electron"));
+        assert_eq!(matching(&profiles, &process), Some("by-process"));
+        assert_eq!(matching(&profiles, &focused(&["krita"], None)), None);
+        assert!(Focused::default().is_unknown());
+    }
+
+    #[test]
+    fn reads_the_window_and_its_process_from_xprop() {
+        assert_eq!(
+            parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007\n"),
+            Some("0x3a00007".to_string())
+        );
+        assert_eq!(
+            parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0\n"),
+            None
+        );
+        assert_eq!(
+            parse_window("WM_CLASS(STRING) = \"krita\", \"krita\"\n_NET_WM_PID(CARDINAL) = 4242\n"),
+            (vec!["krita".to_string(), "krita".to_string()], Some(4242))
+        );
+        assert_eq!(
+            parse_window("WM_CLASS:  not found.\n_NET_WM_PID:  not found.\n"),
+            (vec![], None)
+        );
+    }
+}
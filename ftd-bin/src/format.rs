@@ -0,0 +1,206 @@
+use anyhow::{Result, bail};
+
+use ftd_device::report::{DEVICE_MAX_PRESSURE, Event};
+
+/// How the read loop prints what comes off the tablet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The interface and bytes of every report.
+    #[default]
+    Raw,
+    /// One short line per decoded event, see `human`.
+    Human,
+    /// Fixed-size frames on stdout for other programs, see `binary`.
+    Binary,
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Result<OutputFormat> {
+        match name {
+            "raw" => Ok(OutputFormat::Raw),
+            "human" => Ok(OutputFormat::Human),
+            "binary" => Ok(OutputFormat::Binary),
+            _ => bail!("Unknown format {name}, expected raw, human or binary"),
+        }
+    }
+}
+
+/// A decoded event on one line, like `PEN x=1234 y=5678 p=0.42 tip=1` or
+/// `BTN 3 pressed`. Pressure is a fraction of the device range.
+pub fn human(event: &Event) -> String {
+    match event {
+        Event::Pen(pen) if pen.in_range => format!(
+            "PEN x={} y={} p={:.2} tip={}",
+            pen.x,
+            pen.y,
+            pen.pressure as f32 / DEVICE_MAX_PRESSURE as f32,
+            pen.tip as u8
+        ),
+        Event::Pen(_) => "PEN out of range".to_string(),
+        Event::Button(button) => format!(
+            "BTN {} {}",
+            button.index,
+            if button.pressed {
+                "pressed"
+            } else {
+                "released"
+            }
+        ),
+        Event::Touch(touch) if touch.contacts.is_empty() => "TOUCH none".to_string(),
+        Event::Touch(touch) => {
+            let contacts: Vec<String> = touch
+                .contacts
+                .iter()
+                .map(|contact| {
+                    format!(
+                        "{}:{} x={} y={}",
+                        contact.id,
+                        if contact.down { "down" } else { "up" },
+                        contact.x,
+                        contact.y
+                    )
+                })
+                .collect();
+            format!("TOUCH {}", contacts.join(", "))
+        }
+        Event::Raw(raw) => format!("RAW {} {:02X?}", raw.interface, raw.bytes),
+    }
+}
+
+pub const FRAME_SIZE: usize = 8;
+const PEN_FRAME: u8 = 1;
+const BUTTON_FRAME: u8 = 2;
+const CONTACT_FRAME: u8 = 3;
+const RAW_FRAME: u8 = 4;
+
+/// A decoded event as `FRAME_SIZE` byte frames, numbers little endian:
+///
+/// | tag | byte 1          | bytes 2..8                               |
+/// |-----|-----------------|------------------------------------------|
+/// | 1   | bit 0 in range, bit 1 tip | x u16, y u16, pressure u16     |
+/// | 2   | 1 pressed, 0 released     | button index u8, 5 zero bytes  |
+/// | 3   | 1 down, 0 up    | x u16, y u16, contact id u8, 1 zero byte |
+/// | 4   | interface       | up to 6 report bytes, zero padded        |
+///
+/// A pen or button event is one frame, a touch event one frame per contact
+/// and a raw report one frame per 6 of its bytes.
+pub fn binary(event: &Event) -> Vec<u8> {
+    let mut frames = vec![];
+    let mut frame = |tag: u8, flags: u8, fields: &[u8]| {
+        let mut bytes = [0; FRAME_SIZE];
+        bytes[0] = tag;
+        bytes[1] = flags;
+        bytes[2..2 + fields.len()].copy_from_slice(fields);
+        frames.extend_from_slice(&bytes);
+    };
+
+    match event {
+        Event::Pen(pen) => {
+            let flags = pen.in_range as u8 | (pen.tip as u8) << 1;
+            let [x, y, pressure] = [pen.x, pen.y, pen.pressure].map(u16::to_le_bytes);
+            frame(PEN_FRAME, flags, &[x, y, pressure].concat());
+        }
+        Event::Button(button) => frame(BUTTON_FRAME, button.pressed as u8, &[button.index]),
+        Event::Touch(touch) => {
+            for contact in &touch.contacts {
+                let [x, y] = [contact.x, contact.y].map(u16::to_le_bytes);
+                frame(
+                    CONTACT_FRAME,
+                    contact.down as u8,
+                    &[&x[..], &y[..], &[contact.id]].concat(),
+                );
+            }
+        }
+        Event::Raw(raw) => {
+            for chunk in raw.bytes.chunks(FRAME_SIZE - 2) {
+                frame(RAW_FRAME, raw.interface, chunk);
+            }
+        }
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftd_device::report::{ButtonEvent, Contact, PenReport, TouchReport};
+
+    #[test]
+    fn renders_each_event_on_one_line() {
+        let pen = PenReport {
+            x: 1234,
+            y: 5678,
+            pressure: (DEVICE_MAX_PRESSURE as f32 * 0.42).round() as u16,
+            in_range: true,
+            tip: true,
+        };
+        assert_eq!(human(&Event::Pen(pen)), "PEN x=1234 y=5678 p=0.42 tip=1");
+        assert_eq!(
+            human(&Event::Pen(PenReport {
+                in_range: false,
+                ..pen
+            })),
+            "PEN out of range"
+        );
+        assert_eq!(
+            human(&Event::Button(ButtonEvent {
+                index: 3,
+                pressed: true,
+            })),
+            "BTN 3 pressed"
+        );
+        assert_eq!(
+            human(&Event::Touch(TouchReport {
+                contacts: vec![Contact {
+                    id: 1,
+                    down: true,
+                    x: 10,
+                    y: 20,
+                }],
+            })),
+            "TOUCH 1:down x=10 y=20"
+        );
+        assert!(OutputFormat::parse("json").is_err());
+    }
+
+    #[test]
+    fn binary_frames_have_the_documented_layout() {
+        let pen = Event::Pen(PenReport {
+            x: 0x1234,
+            y: 0x5678,
+            pressure: 0x0100,
+            in_range: true,
+            tip: true,
+        });
+        assert_eq!(
+            binary(&pen),
+            [0x01, 0x03, 0x34, 0x12, 0x78, 0x56, 0x00, 0x01]
+        );
+
+        let button = Event::Button(ButtonEvent {
+            index: 11,
+            pressed: true,
+        });
+        assert_eq!(binary(&button), [0x02, 0x01, 0x0B, 0, 0, 0, 0, 0]);
+
+        let touch = Event::Touch(TouchReport {
+            contacts: vec![
+                Contact {
+                    id: 7,
+                    down: true,
+                    x: 0x0010,
+                    y: 0x0020,
+                },
+                Contact {
+                    id: 8,
+                    down: false,
+                    x: 0,
+                    y: 0,
+                },
+            ],
+        });
+        assert_eq!(binary(&touch).len(), 2 * FRAME_SIZE);
+        assert_eq!(binary(&touch)[..8], [0x03, 0x01, 0x10, 0, 0x20, 0, 0x07, 0]);
+    }
+}
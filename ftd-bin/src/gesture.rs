@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use evdev_rs::enums::EV_KEY;
+use ftd_device::report::TouchReport;
+
+use crate::action::Action;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapSettings {
+    pub max_duration: Duration,
+    /// Largest distance in device units a finger may travel and still tap.
+    pub max_distance: u16,
+    pub action: Action,
+}
+
+impl Default for TapSettings {
+    fn default() -> Self {
+        TapSettings {
+            max_duration: Duration::from_millis(180),
+            max_distance: 200,
+            action: Action::Keys(vec![EV_KEY::BTN_LEFT]),
+        }
+    }
+}
+
+struct Touch {
+    id: u8,
+    started: Instant,
+    x: u16,
+    y: u16,
+    cancelled: bool,
+}
+
+/// Recognizes a quick single-finger touch down/up as a tap.
+pub struct TapRecognizer {
+    settings: TapSettings,
+    touch: Option<Touch>,
+}
+
+impl TapRecognizer {
+    pub fn new(settings: TapSettings) -> TapRecognizer {
+        TapRecognizer {
+            settings,
+            touch: None,
+        }
+    }
+
+    /// Returns the tap action when `report` completes a tap.
+    pub fn feed(&mut self, report: &TouchReport, now: Instant) -> Option<&Action> {
+        let down: Vec<_> = report.contacts.iter().filter(|c| c.down).collect();
+
+        if let Some(touch) = &mut self.touch {
+            if down.len() > 1 {
+                touch.cancelled = true;
+            }
+
+            match report.contacts.iter().find(|c| c.id == touch.id) {
+                Some(contact) if contact.down => {
+                    let dx = contact.x.abs_diff(touch.x);
+                    let dy = contact.y.abs_diff(touch.y);
+                    if dx.max(dy) > self.settings.max_distance {
+                        touch.cancelled = true;
+                    }
+                }
+                _ => {
+                    let tapped = !touch.cancelled
+                        && now.duration_since(touch.started) <= self.settings.max_duration;
+                    self.touch = None;
+
+                    if tapped {
+                        return Some(&self.settings.action);
+                    }
+                }
+            }
+        } else if let [contact] = down[..] {
+            self.touch = Some(Touch {
+                id: contact.id,
+                started: now,
+                x: contact.x,
+                y: contact.y,
+                cancelled: false,
+            });
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftd_device::report::Contact;
+
+    fn touch(down: bool, x: u16, y: u16) -> TouchReport {
+        TouchReport {
+            contacts: vec![Contact { id: 0, down, x, y }],
+        }
+    }
+
+    #[test]
+    fn quick_touch_is_a_tap() {
+        let mut taps = TapRecognizer::new(TapSettings::default());
+        let start = Instant::now();
+
+        assert!(taps.feed(&touch(true, 1000, 1000), start).is_none());
+        assert!(taps.feed(&touch(true, 1050, 1020), start).is_none());
+        let action = taps.feed(&touch(false, 1050, 1020), start + Duration::from_millis(90));
+
+        assert_eq!(action, Some(&Action::Keys(vec![EV_KEY::BTN_LEFT])));
+    }
+
+    #[test]
+    fn slow_press_is_not_a_tap() {
+        let mut taps = TapRecognizer::new(TapSettings::default());
+        let start = Instant::now();
+
+        taps.feed(&touch(true, 1000, 1000), start);
+        let action = taps.feed(
+            &touch(false, 1000, 1000),
+            start + Duration::from_millis(600),
+        );
+
+        assert!(action.is_none());
+    }
+
+    #[test]
+    fn drag_is_not_a_tap() {
+        let mut taps = TapRecognizer::new(TapSettings::default());
+        let start = Instant::now();
+
+        taps.feed(&touch(true, 1000, 1000), start);
+        taps.feed(&touch(true, 1600, 1000), start + Duration::from_millis(40));
+        let action = taps.feed(&touch(false, 1600, 1000), start + Duration::from_millis(80));
+
+        assert!(action.is_none());
+    }
+}
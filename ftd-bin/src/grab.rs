@@ -0,0 +1,172 @@
+use std::{fs, io, path::PathBuf};
+
+use evdev_rs::{Device, DeviceWrapper, GrabMode};
+use log::{info, warn};
+
+/// `BUS_USB` from `linux/input.h`. The virtual device is on the virtual
+/// bus, so it is never grabbed.
+const BUS_USB: u16 = 0x03;
+
+/// An input node that can be grabbed, the tablet's own or a stand-in in
+/// the tests.
+pub trait Grabbable {
+    fn describe(&self) -> String;
+    /// `EVIOCGRAB` with `grab`, on or off.
+    fn set_grab(&mut self, grab: bool) -> io::Result<()>;
+}
+
+/// An `/dev/input/event*` node of the tablet that the kernel still
+/// handles, e.g. of an interface that was not claimed.
+pub struct EventNode {
+    path: PathBuf,
+    device: Device,
+}
+
+impl Grabbable for EventNode {
+    fn describe(&self) -> String {
+        format!(
+            "{} ({})",
+            self.path.display(),
+            self.device.name().unwrap_or("unnamed")
+        )
+    }
+
+    fn set_grab(&mut self, grab: bool) -> io::Result<()> {
+        self.device.grab(if grab {
+            GrabMode::Grab
+        } else {
+            GrabMode::Ungrab
+        })
+    }
+}
+
+/// The USB event nodes of `vendor_id:product_id`. Nodes that cannot be
+/// opened are passed over, they are usually someone else's.
+pub fn event_nodes(vendor_id: u16, product_id: u16) -> Vec<EventNode> {
+    let Ok(entries) = fs::read_dir("/dev/input") else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let device = Device::new_from_path(&path).ok()?;
+            (device.bustype() == BUS_USB
+                && device.vendor_id() == vendor_id
+                && device.product_id() == product_id)
+                .then_some(EventNode { path, device })
+        })
+        .collect()
+}
+
+/// Grabs every event node `event_nodes` finds.
+pub fn grab_tablet(vendor_id: u16, product_id: u16) -> Grabs {
+    let nodes = event_nodes(vendor_id, product_id);
+    if nodes.is_empty() {
+        info!("The kernel has no input nodes of the tablet to grab");
+    }
+
+    Grabs::grab(
+        nodes
+            .into_iter()
+            .map(|node| Box::new(node) as Box<dyn Grabbable>)
+            .collect(),
+    )
+}
+
+/// Holds the grab of the tablet's own input nodes so only the virtual
+/// device reaches the desktop, and lets go of them in reverse order when
+/// dropped. The kernel lets go too when the process dies.
+pub struct Grabs {
+    held: Vec<Box<dyn Grabbable>>,
+}
+
+impl Grabs {
+    /// A node that cannot be grabbed is logged and left alone.
+    pub fn grab(nodes: Vec<Box<dyn Grabbable>>) -> Grabs {
+        let mut held = vec![];
+        for mut node in nodes {
+            match node.set_grab(true) {
+                Ok(()) => {
+                    info!("Grabbed {}", node.describe());
+                    held.push(node);
+                }
+                Err(e) => warn!("Failed to grab {}: {e}", node.describe()),
+            }
+        }
+
+        Grabs { held }
+    }
+}
+
+impl Drop for Grabs {
+    fn drop(&mut self) {
+        while let Some(mut node) = self.held.pop() {
+            if let Err(e) = node.set_grab(false) {
+                warn!("Failed to release {}: {e}", node.describe());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    struct FakeNode {
+        name: &'static str,
+        fails: bool,
+        calls: Rc<RefCell<Vec<(&'static str, bool)>>>,
+    }
+
+    impl Grabbable for FakeNode {
+        fn describe(&self) -> String {
+            self.name.to_string()
+        }
+
+        fn set_grab(&mut self, grab: bool) -> io::Result<()> {
+            self.calls.borrow_mut().push((self.name, grab));
+            if self.fails {
+                return Err(io::Error::from_raw_os_error(libc::EBUSY));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn grabs_in_order_and_releases_in_reverse_on_drop() {
+        let calls = Rc::new(RefCell::new(vec![]));
+        let node = |name, fails| -> Box<dyn Grabbable> {
+            Box::new(FakeNode {
+                name,
+                fails,
+                calls: calls.clone(),
+            })
+        };
+
+        let grabs = Grabs::grab(vec![
+            node("pen", false),
+            node("busy", true),
+            node("keys", false),
+        ]);
+        assert_eq!(
+            *calls.borrow(),
+            vec![("pen", true), ("busy", true), ("keys", true)]
+        );
+
+        calls.borrow_mut().clear();
+        drop(grabs);
+        assert_eq!(*calls.borrow(), vec![("keys", false), ("pen", false)]);
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Result, bail};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::{CommandRunner, Spawner},
+    profile::Profile,
+};
+
+/// What happens when the tablet connects or disconnects. Either or both
+/// can be set, the command starts first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hook {
+    /// Program started without waiting for it, under the same
+    /// `--allow-commands` policy as `Action::Command`.
+    pub program: Option<String>,
+    pub args: Vec<String>,
+    /// Profile switched to.
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// At startup once the tablet is initialized, and after each reconnect.
+    pub on_connect: Hook,
+    /// When the tablet goes away, before waiting for it to come back.
+    pub on_disconnect: Hook,
+}
+
+impl HooksConfig {
+    pub fn validate(&self, profiles: &[Profile]) -> Result<()> {
+        for (what, hook) in [
+            ("on_connect", &self.on_connect),
+            ("on_disconnect", &self.on_disconnect),
+        ] {
+            if let Some(name) = &hook.profile
+                && !profiles.iter().any(|profile| &profile.name == name)
+            {
+                bail!("hooks.{what} switches to the unknown profile `{name}`");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the hooks on the transitions between connected and not, telling
+/// them apart from being told the same state again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionHooks {
+    connected: bool,
+}
+
+impl ConnectionHooks {
+    /// Starts the command of the hook for a change to `connected` and
+    /// returns the profile it switches to. Nothing runs when `connected`
+    /// did not change.
+    pub fn transition<'a, S: Spawner>(
+        &mut self,
+        config: &'a HooksConfig,
+        connected: bool,
+        commands: &CommandRunner<S>,
+    ) -> Option<&'a str> {
+        if std::mem::replace(&mut self.connected, connected) == connected {
+            return None;
+        }
+        let (what, hook) = if connected {
+            ("on_connect", &config.on_connect)
+        } else {
+            ("on_disconnect", &config.on_disconnect)
+        };
+        if let Some(program) = &hook.program {
+            info!("Running the {what} hook");
+            commands.run(program, &hook.args);
+        }
+
+        hook.profile.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandPolicy, RecordingSpawner};
+
+    #[test]
+    fn hooks_run_once_per_connect_and_disconnect() {
+        let spawner = RecordingSpawner::default();
+        let commands = CommandRunner::with_spawner(
+            &spawner,
+            CommandPolicy {
+                allow: true,
+                allow_root: false,
+            },
+            false,
+        );
+        let config = HooksConfig {
+            on_connect: Hook {
+                program: Some("openrgb".to_string()),
+                args: vec!["--profile".to_string(), "drawing".to_string()],
+                profile: Some("krita".to_string()),
+            },
+            on_disconnect: Hook {
+                program: Some("openrgb".to_string()),
+                args: vec!["--profile".to_string(), "off".to_string()],
+                profile: None,
+            },
+        };
+        let mut hooks = ConnectionHooks::default();
+
+        let profiles: Vec<Option<&str>> = [true, true, false, false, true]
+            .into_iter()
+            .map(|connected| hooks.transition(&config, connected, &commands))
+            .collect();
+        assert_eq!(
+            profiles,
+            vec![Some("krita"), None, None, None, Some("krita")]
+        );
+        let started: Vec<String> = spawner
+            .0
+            .borrow()
+            .iter()
+            .map(|(_, args)| args[1].clone())
+            .collect();
+        assert_eq!(started, vec!["drawing", "off", "drawing"]);
+    }
+}
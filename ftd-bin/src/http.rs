@@ -0,0 +1,338 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use log::warn;
+
+use crate::{
+    control::{ControlCommand, ControlTarget, Request, run_requests},
+    json::quote,
+};
+
+/// The command for `method` and `path`, or the status and message to
+/// reply with instead.
+///
+/// `GET /status`, `/config` and `/state`, and `POST /enable`, `/disable`,
+/// `/reload`, `/reinit`, `/profile/<name>` and `/save-profile/<name>`, the
+/// names percent-encoded.
+pub fn route(method: &str, path: &str) -> Result<ControlCommand, (u16, String)> {
+    let path = path.split('?').next().unwrap_or(path);
+    let (command, wanted) = match path.trim_end_matches('/') {
+        "/status" => (ControlCommand::Status, "GET"),
+        "/config" => (ControlCommand::GetConfig, "GET"),
+        "/state" => (ControlCommand::GetState, "GET"),
+        "/enable" => (ControlCommand::Enable, "POST"),
+        "/disable" => (ControlCommand::Disable, "POST"),
+        "/reload" => (ControlCommand::Reload, "POST"),
+        "/reinit" => (ControlCommand::Reinit, "POST"),
+        "/profile" => return Err((400, "usage: POST /profile/<name>".into())),
+        "/save-profile" => return Err((400, "usage: POST /save-profile/<name>".into())),
+        other if other.starts_with("/save-profile/") => {
+            match percent_decode(&other["/save-profile/".len()..]) {
+                Some(name) if !name.is_empty() => (ControlCommand::SaveProfile(name), "POST"),
+                _ => return Err((400, "usage: POST /save-profile/<name>".into())),
+            }
+        }
+        other => match other.strip_prefix("/profile/").map(percent_decode) {
+            Some(Some(name)) if !name.is_empty() => (ControlCommand::SetProfile(name), "POST"),
+            Some(_) => return Err((400, "usage: POST /profile/<name>".into())),
+            None => return Err((404, format!("no route {path}"))),
+        },
+    };
+    if method != wanted {
+        return Err((405, format!("{path} takes {wanted}")));
+    }
+
+    Ok(command)
+}
+
+/// The status and JSON body for a `dispatch` reply line. `get-config` and
+/// `get-state` are sent as they are, the rest as
+/// `{"ok":true,"status":...}` or `{"ok":false,"error":...}`.
+pub fn response(command: &ControlCommand, reply: &str) -> (u16, String) {
+    match reply.strip_prefix("ok ") {
+        Some(json)
+            if matches!(
+                command,
+                ControlCommand::GetConfig | ControlCommand::GetState
+            ) =>
+        {
+            (200, json.to_string())
+        }
+        Some(status) => (200, format!(r#"{{"ok":true,"status":{}}}"#, quote(status))),
+        None => {
+            let error = reply.strip_prefix("error: ").unwrap_or(reply);
+            (400, error_body(error))
+        }
+    }
+}
+
+/// How long a connection may take to send its request before it is closed.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Refuses a request a web page could have made: one with an `Origin`,
+/// which browsers add to cross-origin posts, or whose `Host` is not
+/// `bound`, as after a DNS rebinding. `headers` are lowercase names and
+/// trimmed values.
+pub fn check_headers(headers: &[(String, String)], bound: SocketAddr) -> Result<(), (u16, String)> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    };
+    if header("origin").is_some() {
+        return Err((403, "requests from web pages are refused".into()));
+    }
+    let localhost = format!("localhost:{}", bound.port());
+    match header("host") {
+        Some(host) if host == bound.to_string() => Ok(()),
+        Some(host) if bound.ip().is_loopback() && host == localhost => Ok(()),
+        Some(host) => Err((403, format!("Host {host} is not {bound}"))),
+        None => Err((400, "no Host header".into())),
+    }
+}
+
+fn error_body(message: &str) -> String {
+    format!(r#"{{"ok":false,"error":{}}}"#, quote(message))
+}
+
+/// `%XX` escapes and nothing else, `None` for a broken one.
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// The control commands over HTTP, e.g.
+/// `curl -X POST http://127.0.0.1:7878/profile/krita`.
+///
+/// There is no authentication: anyone who can reach the address can turn
+/// the tablet off or switch its profile. Requests from browsers are
+/// refused, see `check_headers`, so a web page cannot.
+pub struct HttpServer {
+    requests: Receiver<Request>,
+}
+
+impl HttpServer {
+    pub fn bind(address: SocketAddr) -> io::Result<HttpServer> {
+        let listener = TcpListener::bind(address)?;
+        let bound = listener.local_addr()?;
+
+        let (sender, requests) = crossbeam_channel::unbounded();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || serve(stream, sender, (bound, READ_TIMEOUT)));
+                    }
+                    Err(e) => warn!("HTTP control accept failed: {e}"),
+                }
+            }
+        });
+
+        Ok(HttpServer { requests })
+    }
+
+    /// Runs the pending commands against `target`.
+    pub fn poll(&self, target: &mut dyn ControlTarget) {
+        run_requests(&self.requests, target);
+    }
+}
+
+/// One request per connection to `bound`, any body is ignored. A client
+/// that sends nothing for `timeout` is dropped without a reply.
+fn serve(stream: TcpStream, requests: Sender<Request>, (bound, timeout): (SocketAddr, Duration)) {
+    if stream.set_read_timeout(Some(timeout)).is_err() {
+        return;
+    }
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut headers = vec![];
+    loop {
+        let mut header = String::new();
+        match reader.read_line(&mut header) {
+            Ok(read) if read > 0 && !header.trim().is_empty() => {
+                if let Some((name, value)) = header.split_once(':') {
+                    headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+                }
+            }
+            Ok(_) => break,
+            Err(_) => return,
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let routed = check_headers(&headers, bound)
+        .and_then(|()| route(parts.next().unwrap_or(""), parts.next().unwrap_or("")));
+    let (status, body) = match routed {
+        Ok(command) => {
+            let (reply, answer) = crossbeam_channel::bounded(1);
+            let sent = requests.send(Request {
+                command: command.clone(),
+                reply,
+            });
+            match sent.ok().and_then(|()| answer.recv().ok()) {
+                Some(reply) => response(&command, &reply),
+                None => (503, error_body("driver stopped")),
+            }
+        }
+        Err((status, message)) => (status, error_body(&message)),
+    };
+
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Service Unavailable",
+    };
+    let _ = write!(
+        writer,
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{FakeTarget, dispatch};
+
+    fn call(target: &mut FakeTarget, method: &str, path: &str) -> (u16, String) {
+        match route(method, path) {
+            Ok(command) => response(&command, &dispatch(&command, target)),
+            Err((status, message)) => (status, error_body(&message)),
+        }
+    }
+
+    #[test]
+    fn routes_map_onto_the_socket_commands() {
+        assert_eq!(route("GET", "/status"), Ok(ControlCommand::Status));
+        assert_eq!(route("POST", "/enable/"), Ok(ControlCommand::Enable));
+        assert_eq!(
+            route("POST", "/profile/My%20Sketch"),
+            Ok(ControlCommand::SetProfile("My Sketch".into()))
+        );
+        assert_eq!(
+            route("POST", "/save-profile/inking"),
+            Ok(ControlCommand::SaveProfile("inking".into()))
+        );
+        assert_eq!(route("GET", "/enable").unwrap_err().0, 405);
+        assert_eq!(route("POST", "/profile/").unwrap_err().0, 400);
+        assert_eq!(route("POST", "/profile/%zz").unwrap_err().0, 400);
+        assert_eq!(route("GET", "/explode").unwrap_err().0, 404);
+    }
+
+    #[test]
+    fn handlers_reply_with_json() {
+        let mut target = FakeTarget::default();
+
+        assert_eq!(
+            call(&mut target, "POST", "/enable"),
+            (
+                200,
+                r#"{"ok":true,"status":"enabled=true profile="}"#.to_string()
+            )
+        );
+        assert!(target.enabled);
+        assert_eq!(
+            call(&mut target, "POST", "/profile/gimp"),
+            (
+                400,
+                r#"{"ok":false,"error":"Unknown profile `gimp`"}"#.to_string()
+            )
+        );
+        assert_eq!(
+            call(&mut target, "GET", "/state"),
+            (200, r#"{"enabled":true,"profile":""}"#.to_string())
+        );
+        assert_eq!(call(&mut target, "POST", "/reload").0, 400);
+        assert_eq!(call(&mut target, "GET", "/reinit").0, 405);
+        assert_eq!(target.reinits, 0);
+    }
+
+    #[test]
+    fn requests_a_web_page_could_make_are_refused() {
+        let bound: SocketAddr = "127.0.0.1:7878".parse().unwrap();
+        let headers = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            check_headers(&headers(&[("host", "127.0.0.1:7878")]), bound),
+            Ok(())
+        );
+        assert_eq!(
+            check_headers(&headers(&[("host", "localhost:7878")]), bound),
+            Ok(())
+        );
+        let cross_origin = headers(&[
+            ("host", "127.0.0.1:7878"),
+            ("origin", "https://example.com"),
+        ]);
+        assert_eq!(check_headers(&cross_origin, bound).unwrap_err().0, 403);
+        let rebound = headers(&[("host", "evil.example:7878")]);
+        assert_eq!(check_headers(&rebound, bound).unwrap_err().0, 403);
+        assert_eq!(check_headers(&[], bound).unwrap_err().0, 400);
+    }
+
+    #[test]
+    fn serve_checks_the_headers_and_drops_an_idle_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let bound = listener.local_addr().unwrap();
+        let (sender, requests) = crossbeam_channel::unbounded();
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                serve(stream, sender.clone(), (bound, Duration::from_millis(50)));
+            }
+        });
+
+        let mut client = TcpStream::connect(bound).unwrap();
+        write!(
+            client,
+            "POST /disable HTTP/1.1\r\nHost: {bound}\r\nOrigin: https://example.com\r\n\r\n"
+        )
+        .unwrap();
+        let mut reply = String::new();
+        io::Read::read_to_string(&mut client, &mut reply).unwrap();
+        assert!(reply.starts_with("HTTP/1.1 403 Forbidden"), "{reply}");
+
+        let mut idle = TcpStream::connect(bound).unwrap();
+        let mut reply = String::new();
+        io::Read::read_to_string(&mut idle, &mut reply).unwrap();
+        assert_eq!(reply, "");
+        server.join().unwrap();
+        assert!(requests.try_recv().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Result, bail};
+use log::warn;
+use rusb::UsbContext;
+use serde::{Deserialize, Serialize};
+
+use ftd_device::device::USBDevice;
+
+/// What the tablet is recognized by before the init packets go out. They
+/// are vendor specific and could upset another device that answers to the
+/// same IDs, e.g. after a mistyped `--pid`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceIdentity {
+    /// Refuse to send the init packets to a device that does not match,
+    /// `--force` only warns instead.
+    pub check: bool,
+    /// Part of the USB product string, ignoring case. Unset skips it.
+    pub product: Option<String>,
+    /// Interfaces of the active configuration, three on the MTM-1106.
+    pub interface_count: Option<usize>,
+    /// IN endpoint addresses the device must have.
+    pub in_endpoints: Vec<u8>,
+}
+
+impl Default for DeviceIdentity {
+    fn default() -> Self {
+        DeviceIdentity {
+            check: true,
+            product: None,
+            interface_count: Some(3),
+            in_endpoints: vec![],
+        }
+    }
+}
+
+/// What an opened device shows of itself, for `DeviceIdentity::guard`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceTraits {
+    /// `None` when the device has none or it could not be read.
+    pub product: Option<String>,
+    pub interface_count: usize,
+    pub in_endpoints: Vec<u8>,
+}
+
+impl DeviceTraits {
+    pub fn read<T: UsbContext>(usb_device: &USBDevice<T>) -> DeviceTraits {
+        let product = usb_device
+            .device
+            .device_descriptor()
+            .and_then(|descriptor| usb_device.handle.read_product_string_ascii(&descriptor))
+            .ok();
+        let mut in_endpoints: Vec<u8> = usb_device
+            .interfaces
+            .values()
+            .flat_map(|info| info.endpoints_in.iter().copied())
+            .collect();
+        in_endpoints.sort_unstable();
+
+        DeviceTraits {
+            product,
+            interface_count: usb_device.interfaces.len(),
+            in_endpoints,
+        }
+    }
+}
+
+impl DeviceIdentity {
+    /// How `traits` differ from what is expected, one line each.
+    pub fn mismatches(&self, traits: &DeviceTraits) -> Vec<String> {
+        let mut mismatches = vec![];
+        if let Some(product) = &self.product {
+            let matches = traits.product.as_ref().is_some_and(|found| {
+                found
+                    .to_lowercase()
+                    .contains(product.to_lowercase().as_str())
+            });
+            if !matches {
+                mismatches.push(format!(
+                    "the product string is {:?}, not one containing {product:?}",
+                    traits.product.as_deref().unwrap_or("unreadable")
+                ));
+            }
+        }
+        if let Some(count) = self.interface_count
+            && count != traits.interface_count
+        {
+            mismatches.push(format!(
+                "it has {} interfaces, not {count}",
+                traits.interface_count
+            ));
+        }
+        let missing: Vec<String> = self
+            .in_endpoints
+            .iter()
+            .filter(|address| !traits.in_endpoints.contains(address))
+            .map(|address| format!("{address:#04x}"))
+            .collect();
+        if !missing.is_empty() {
+            mismatches.push(format!("it has no IN endpoint {}", missing.join(", ")));
+        }
+
+        mismatches
+    }
+
+    /// Fails when the device does not match, unless `check` is off.
+    pub fn guard(&self, traits: &DeviceTraits) -> Result<()> {
+        let mismatches = self.mismatches(traits);
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let reasons = mismatches.join("; ");
+        if !self.check {
+            warn!("The device does not look like the tablet ({reasons}), initializing anyway");
+            return Ok(());
+        }
+
+        bail!(
+            "Refusing to send the init packets, the device does not look like the tablet: \
+             {reasons}. Check --vid and --pid, or `device.identity` for this model, or pass \
+             --force to initialize it anyway"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_mismatched_device_is_only_initialized_with_force() {
+        let mut identity = DeviceIdentity {
+            product: Some("tablet".to_string()),
+            in_endpoints: vec![0x81, 0x82],
+            ..DeviceIdentity::default()
+        };
+        let tablet = DeviceTraits {
+            product: Some("Pen Tablet".to_string()),
+            interface_count: 3,
+            in_endpoints: vec![0x81, 0x82, 0x83],
+        };
+        assert!(identity.guard(&tablet).is_ok());
+
+        let keyboard = DeviceTraits {
+            product: Some("USB Keyboard".to_string()),
+            interface_count: 2,
+            in_endpoints: vec![0x81],
+        };
+        assert_eq!(identity.mismatches(&keyboard).len(), 3);
+        let error = identity.guard(&keyboard).unwrap_err().to_string();
+        assert!(error.contains("\"USB Keyboard\""), "{error}");
+        assert!(error.contains("no IN endpoint 0x82"), "{error}");
+
+        identity.check = false;
+        assert!(identity.guard(&keyboard).is_ok());
+    }
+}
@@ -0,0 +1,277 @@
+use anyhow::{Result, bail};
+use evdev_rs::enums::EV_KEY;
+use serde::Serialize;
+
+use ftd_device::report::BUTTON_COUNT;
+
+use crate::{
+    action::{Action, default_button_map},
+    keys,
+};
+
+/// Button bindings read from an `xsetwacom` script, for moving over from
+/// the X driver.
+///
+/// Lines of the form `xsetwacom set "<pad>" Button <n> <mapping>`, with or
+/// without the leading `xsetwacom set "<pad>"`, translate when the mapping
+/// is one of:
+///
+/// - `"key +ctrl +z -z -ctrl"`: the keys pressed, in order, held together.
+///   Both our key names and the X keysym names (`Control_L`, `minus`,
+///   `bracketleft`, ...) are understood, releases are dropped.
+/// - `"button +1"` to `+3`, or the bare `1` to `3`: a left, middle or
+///   right click. `4` and `5` scroll up and down.
+/// - `0`: the button does nothing.
+///
+/// X numbers pad buttons 1 to 3 and then from 8, skipping the four it keeps
+/// for the wheel, so `Button 8` is our fourth button. Anything else, e.g.
+/// `modetoggle`, other `xsetwacom` parameters or button numbers past the
+/// tablet's, is left out and listed in `untranslated`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Imported {
+    /// `default_button_map` with the translated buttons replaced.
+    pub button_map: Vec<Action>,
+    /// The lines that did not translate and why, numbered from 1.
+    pub untranslated: Vec<(usize, String)>,
+}
+
+impl Imported {
+    pub fn parse(text: &str) -> Imported {
+        let mut imported = Imported {
+            button_map: default_button_map(),
+            untranslated: vec![],
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_line(line) {
+                Ok((index, action)) => imported.button_map[index] = action,
+                Err(e) => imported.untranslated.push((number + 1, e.to_string())),
+            }
+        }
+
+        imported
+    }
+
+    /// A config holding the translated `button_map`, the lines that did
+    /// not translate listed as comments above it.
+    pub fn to_text(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Bindings<'a> {
+            button_map: &'a [Action],
+        }
+
+        let mut text = String::new();
+        for (number, reason) in &self.untranslated {
+            text.push_str(&format!("# line {number} not translated: {reason}\n"));
+        }
+        text.push_str(&toml::to_string(&Bindings {
+            button_map: &self.button_map,
+        })?);
+
+        Ok(text)
+    }
+}
+
+/// Our button index and its action for one `Button <n> <mapping>` line.
+pub fn parse_line(line: &str) -> Result<(usize, Action)> {
+    let words = split_words(line)?;
+    let mut rest = words.as_slice();
+    if rest.first().is_some_and(|word| word == "xsetwacom") {
+        match rest {
+            [_, set, _device, tail @ ..] if set == "set" || set == "--set" => rest = tail,
+            _ => bail!("only `xsetwacom set` lines translate"),
+        }
+    }
+    let [parameter, number, mapping] = rest else {
+        bail!("expected `Button <n> <mapping>`");
+    };
+    if !parameter.eq_ignore_ascii_case("button") {
+        bail!("`{parameter}` has no equivalent here");
+    }
+    let number: usize = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("`{number}` is not a button number"))?;
+    let index = match number {
+        1..=3 => number - 1,
+        4..=7 => bail!("X button {number} is the wheel, not a pad button"),
+        _ => number - 5,
+    };
+    if index >= BUTTON_COUNT {
+        bail!("the tablet has no X button {number}");
+    }
+
+    Ok((index, parse_mapping(mapping)?))
+}
+
+fn parse_mapping(mapping: &str) -> Result<Action> {
+    if let Ok(number) = mapping.trim().parse() {
+        return click(number);
+    }
+
+    let mut pressed: Vec<EV_KEY> = vec![];
+    let mut scroll = None;
+    let mut clicking = false;
+    for word in mapping.split_whitespace() {
+        match word.to_ascii_lowercase().as_str() {
+            "key" => clicking = false,
+            "button" => clicking = true,
+            _ if word.starts_with('-') => {}
+            _ => {
+                let name = word.strip_prefix('+').unwrap_or(word);
+                let key = if clicking {
+                    let number = name
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("`{name}` is not a mouse button"))?;
+                    match click(number)? {
+                        Action::Keys(keys) => keys[0],
+                        wheel => {
+                            scroll = Some(wheel);
+                            continue;
+                        }
+                    }
+                } else {
+                    key_by_keysym(name)?
+                };
+                if !pressed.contains(&key) {
+                    pressed.push(key);
+                }
+            }
+        }
+    }
+
+    match scroll {
+        Some(scroll) if pressed.is_empty() => Ok(scroll),
+        Some(_) => bail!("a wheel click only translates by itself"),
+        None if pressed.is_empty() => bail!("`{mapping}` presses nothing"),
+        None => Ok(Action::Keys(pressed)),
+    }
+}
+
+/// X mouse button `number`: 1 to 3 click, 4 and 5 scroll, 0 is nothing.
+fn click(number: u8) -> Result<Action> {
+    Ok(match number {
+        0 => Action::Keys(vec![]),
+        1 => Action::Keys(vec![EV_KEY::BTN_LEFT]),
+        2 => Action::Keys(vec![EV_KEY::BTN_MIDDLE]),
+        3 => Action::Keys(vec![EV_KEY::BTN_RIGHT]),
+        4 => Action::Scroll(1),
+        5 => Action::Scroll(-1),
+        _ => bail!("mouse button {number} has no equivalent here"),
+    })
+}
+
+/// The X keysym names that differ from ours.
+const KEYSYMS: &[(&str, &str)] = &[
+    ("control", "ctrl"),
+    ("control_l", "ctrl"),
+    ("control_r", "rctrl"),
+    ("shift_l", "shift"),
+    ("shift_r", "rshift"),
+    ("alt_l", "alt"),
+    ("alt_r", "altgr"),
+    ("meta", "alt"),
+    ("super_l", "super"),
+    ("super_r", "rsuper"),
+    ("return", "enter"),
+    ("escape", "esc"),
+    ("prior", "pageup"),
+    ("next", "pagedown"),
+    ("page_up", "pageup"),
+    ("page_down", "pagedown"),
+    ("minus", "-"),
+    ("equal", "="),
+    ("bracketleft", "["),
+    ("bracketright", "]"),
+    ("semicolon", ";"),
+    ("apostrophe", "'"),
+    ("grave", "`"),
+    ("backslash", "\\"),
+    ("comma", ","),
+    ("period", "."),
+    ("slash", "/"),
+    ("kp_add", "kp+"),
+    ("kp_subtract", "kp-"),
+];
+
+fn key_by_keysym(name: &str) -> Result<EV_KEY> {
+    let name = KEYSYMS
+        .iter()
+        .find(|(keysym, _)| keysym.eq_ignore_ascii_case(name))
+        .map_or(name, |(_, ours)| ours);
+
+    keys::key_by_name(name).map_err(anyhow::Error::msg)
+}
+
+/// Whitespace separated words, double quotes keeping one together.
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_default();
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    if quoted {
+        bail!("unclosed quote");
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use EV_KEY::*;
+
+    #[test]
+    fn translates_representative_xsetwacom_lines() {
+        let parse = |line| parse_line(line).unwrap();
+
+        assert_eq!(
+            parse(r#"xsetwacom set "HUION Pad pad" Button 1 "key +ctrl +z -z -ctrl""#),
+            (0, Action::Keys(vec![KEY_LEFTCTRL, KEY_Z]))
+        );
+        assert_eq!(
+            parse(r#"xsetwacom --set "pad" Button 9 "key Control_L bracketleft""#),
+            (4, Action::Keys(vec![KEY_LEFTCTRL, KEY_LEFTBRACE]))
+        );
+        assert_eq!(parse("Button 3 3"), (2, Action::Keys(vec![BTN_RIGHT])));
+        assert_eq!(parse(r#"Button 8 "button +5""#), (3, Action::Scroll(-1)));
+        assert_eq!(parse("Button 2 0"), (1, Action::Keys(vec![])));
+    }
+
+    #[test]
+    fn lists_what_does_not_translate() {
+        let imported = Imported::parse(
+            "# set up the pad\n\
+             xsetwacom set \"pad\" Button 1 \"key shift\"\n\
+             xsetwacom set \"pad\" Button 4 \"key a\"\n\
+             xsetwacom set \"pad\" Button 2 modetoggle\n\
+             xsetwacom set \"pad\" Rotate half\n\
+             xsetwacom set \"pad\" Button 30 \"key a\"\n",
+        );
+
+        let lines: Vec<usize> = imported
+            .untranslated
+            .iter()
+            .map(|(line, _)| *line)
+            .collect();
+        assert_eq!(lines, vec![3, 4, 5, 6]);
+        assert_eq!(imported.button_map[0], Action::Keys(vec![KEY_LEFTSHIFT]));
+        assert_eq!(imported.button_map[1..], default_button_map()[1..]);
+
+        let config = Config::parse(&imported.to_text().unwrap()).unwrap();
+        assert_eq!(config.button_map, imported.button_map);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Remembers when a device was last initialized so a quick restart can skip
+/// the magic packet and its two 500ms sleeps.
+///
+/// The tablet keeps desktop mode as long as it stays powered, so this only
+/// goes wrong if it was replugged within the window and came back on the same
+/// bus address. The window is kept short for that reason, callers fall back
+/// to a full init when the first read fails, and `--force-init` bypasses it.
+pub struct InitState {
+    path: PathBuf,
+}
+
+impl InitState {
+    pub fn new(path: PathBuf) -> InitState {
+        InitState { path }
+    }
+
+    pub fn default_path() -> PathBuf {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+
+        dir.join("freetomate-init")
+    }
+
+    /// Whether `device` was initialized less than `window` before `now`.
+    pub fn is_fresh(&self, device: &str, window: Duration, now: SystemTime) -> bool {
+        let Ok(contents) = fs::read_to_string(&self.path) else {
+            return false;
+        };
+        let Some((key, secs)) = contents.trim().split_once(' ') else {
+            return false;
+        };
+        let Ok(secs) = secs.parse::<u64>() else {
+            return false;
+        };
+
+        let initialized = UNIX_EPOCH + Duration::from_secs(secs);
+        key == device
+            && now
+                .duration_since(initialized)
+                .is_ok_and(|elapsed| elapsed < window)
+    }
+
+    pub fn record(&self, device: &str, now: SystemTime) -> io::Result<()> {
+        let secs = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?
+            .as_secs();
+
+        fs::write(&self.path, format!("{device} {secs}\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(name: &str) -> InitState {
+        let path = std::env::temp_dir().join(format!("ftd-init-{}-{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        InitState::new(path)
+    }
+
+    #[test]
+    fn fresh_only_within_the_window_and_for_the_same_device() {
+        let state = state("window");
+        let window = Duration::from_secs(30);
+        let now = SystemTime::now();
+
+        assert!(!state.is_fresh("1-4", window, now));
+
+        state.record("1-4", now).unwrap();
+        assert!(state.is_fresh("1-4", window, now + Duration::from_secs(10)));
+        assert!(!state.is_fresh("1-4", window, now + Duration::from_secs(31)));
+        assert!(!state.is_fresh("1-5", window, now));
+
+        let _ = fs::remove_file(&state.path);
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+/// Held for as long as this instance drives one tablet, so a second one
+/// started for the same device stops with a clear message instead of a
+/// failed claim. The lock is `flock` on the file and goes away with the
+/// process, even when it is killed.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// `$XDG_RUNTIME_DIR/freetomate-<bus>-<address>.lock`, the temporary
+    /// directory without `XDG_RUNTIME_DIR`.
+    pub fn default_path(bus: u8, address: u8) -> PathBuf {
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join(format!("freetomate-{bus}-{address}.lock"))
+    }
+
+    /// Takes the lock at `path` and writes this process's ID into it, or
+    /// fails naming the process that holds it.
+    pub fn acquire(path: &Path) -> Result<InstanceLock> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open the lock file {}", path.display()))?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                bail!(
+                    "Another instance (pid {}) is already running for this device, \
+                     stop it first",
+                    pid.trim()
+                );
+            }
+            return Err(error).with_context(|| format!("Failed to lock {}", path.display()));
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+
+        Ok(InstanceLock { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_second_lock_fails_until_the_first_is_dropped() {
+        let path = env::temp_dir().join(format!("ftd-lock-{}.lock", std::process::id()));
+
+        let first = InstanceLock::acquire(&path).unwrap();
+        let error = InstanceLock::acquire(&path).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!("pid {}", std::process::id())),
+            "{error}"
+        );
+
+        drop(first);
+        assert!(InstanceLock::acquire(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
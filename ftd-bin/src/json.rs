@@ -0,0 +1,197 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+
+/// `value` as one line of JSON, going through the same serialization as
+/// the config file.
+pub fn to_json(value: &impl Serialize) -> Result<String> {
+    let mut out = String::new();
+    write_value(&toml::Value::try_from(value)?, &mut out);
+
+    Ok(out)
+}
+
+/// `text` as a JSON string.
+pub fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+fn write_value(value: &toml::Value, out: &mut String) {
+    if let Some(text) = value.as_str() {
+        out.push_str(&quote(text));
+    } else if let Some(table) = value.as_table() {
+        out.push('{');
+        for (index, (key, value)) in table.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            out.push_str(&quote(key));
+            out.push(':');
+            write_value(value, out);
+        }
+        out.push('}');
+    } else if let Some(items) = value.as_array() {
+        out.push('[');
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            write_value(item, out);
+        }
+        out.push(']');
+    } else if matches!(value, toml::Value::Float(float) if !float.is_finite()) {
+        // TOML takes `inf` and `nan`, JSON has no such numbers.
+        out.push_str("null");
+    } else {
+        // Integers, floats and booleans are written the same in both.
+        out.push_str(&value.to_string());
+    }
+}
+
+/// The JSON `to_json` writes, read back, for the tests. `None` when it is
+/// not valid JSON or holds a `null`, which TOML has no value for.
+#[cfg(test)]
+pub fn from_json(text: &str) -> Option<toml::Value> {
+    let mut chars = text.trim().chars().peekable();
+    let value = read_value(&mut chars)?;
+
+    chars.next().is_none().then_some(value)
+}
+
+#[cfg(test)]
+fn read_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<toml::Value> {
+    match *chars.peek()? {
+        '"' => read_string(chars).map(toml::Value::String),
+        '{' => {
+            chars.next();
+            let mut table = toml::Table::new();
+            while chars.peek() != Some(&'}') {
+                let key = read_string(chars)?;
+                (chars.next()? == ':').then_some(())?;
+                table.insert(key, read_value(chars)?);
+                if chars.peek() == Some(&',') {
+                    chars.next();
+                }
+            }
+            chars.next();
+            Some(toml::Value::Table(table))
+        }
+        '[' => {
+            chars.next();
+            let mut items = vec![];
+            while chars.peek() != Some(&']') {
+                items.push(read_value(chars)?);
+                if chars.peek() == Some(&',') {
+                    chars.next();
+                }
+            }
+            chars.next();
+            Some(toml::Value::Array(items))
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| !",]}".contains(**c)) {
+                word.push(c);
+                chars.next();
+            }
+            match word.as_str() {
+                "true" => Some(toml::Value::Boolean(true)),
+                "false" => Some(toml::Value::Boolean(false)),
+                _ if word.contains(['.', 'e', 'E']) => word.parse().ok().map(toml::Value::Float),
+                _ => word.parse().ok().map(toml::Value::Integer),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn read_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    (chars.next()? == '"').then_some(())?;
+    let mut text = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(text),
+            '\\' => text.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let hex: String = chars.take(4).collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                c => c,
+            }),
+            c => text.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fields in alphabetical order, which is how tables may be kept.
+    #[derive(Serialize)]
+    struct Output {
+        modes: Vec<Mode>,
+        name: String,
+        primary: bool,
+        scale: f32,
+        size: (i32, i32),
+    }
+
+    #[derive(Serialize)]
+    struct Mode {
+        rate: u32,
+    }
+
+    #[test]
+    fn writes_nested_values_as_json() {
+        let output = Output {
+            modes: vec![Mode { rate: 60 }, Mode { rate: 144 }],
+            name: "HDMI-1 \"left\"\n".into(),
+            primary: true,
+            scale: 1.5,
+            size: (2560, 1440),
+        };
+
+        assert_eq!(
+            to_json(&output).unwrap(),
+            r#"{"modes":[{"rate":60},{"rate":144}],"name":"HDMI-1 \"left\"\n","primary":true,"scale":1.5,"size":[2560,1440]}"#
+        );
+        assert_eq!(quote("a\u{1}b"), r#""a\u0001b""#);
+    }
+
+    #[test]
+    fn non_finite_floats_are_written_as_null() {
+        #[derive(Serialize)]
+        struct Scales {
+            nan: f64,
+            up: f64,
+        }
+
+        let scales = Scales {
+            nan: f64::NAN,
+            up: f64::INFINITY,
+        };
+        assert_eq!(to_json(&scales).unwrap(), r#"{"nan":null,"up":null}"#);
+    }
+}
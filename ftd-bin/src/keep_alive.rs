@@ -0,0 +1,145 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use ftd_device::error::FtdError;
+
+use crate::config::{InitPacket, InitStep};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeepAliveConfig {
+    /// Sent every `interval_ms` while the tablet is read, for units that
+    /// stop sending pen data without it. Unset sends nothing.
+    pub packet: Option<InitPacket>,
+    pub interval_ms: u64,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        KeepAliveConfig {
+            packet: None,
+            interval_ms: 5000,
+        }
+    }
+}
+
+impl KeepAliveConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.packet.is_some() && self.interval_ms == 0 {
+            bail!("keep_alive.interval_ms must be at least 1");
+        }
+
+        Ok(())
+    }
+}
+
+/// When the next keep-alive is due. A send that comes late does not make
+/// the ones after it bunch up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeepAlive {
+    interval: Duration,
+    next: Instant,
+}
+
+impl KeepAlive {
+    /// The first one is due one `interval` after `now`.
+    pub fn new(interval: Duration, now: Instant) -> KeepAlive {
+        KeepAlive {
+            interval,
+            next: now + interval,
+        }
+    }
+
+    pub fn due(&mut self, now: Instant) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.next += self.interval;
+        if self.next <= now {
+            self.next = now + self.interval;
+        }
+
+        true
+    }
+
+    pub fn wait(&self, now: Instant) -> Duration {
+        self.next.saturating_duration_since(now)
+    }
+}
+
+/// Sends `packet` through `send` on schedule until `running` is cleared,
+/// sleeping at most `poll` at a time so it notices. A failed send leaves
+/// it to the readers to find out what is wrong.
+pub fn run(
+    packet: &InitPacket,
+    mut keep_alive: KeepAlive,
+    running: &AtomicBool,
+    poll: Duration,
+    now: impl Fn() -> Instant,
+    sleep: impl Fn(Duration),
+    mut send: impl FnMut(&InitStep) -> Result<(), FtdError>,
+) {
+    let step = packet.step();
+    while running.load(Ordering::SeqCst) {
+        if keep_alive.due(now())
+            && let Err(e) = send(&step)
+        {
+            warn!("Sending the keep-alive failed: {e}");
+        }
+        sleep(keep_alive.wait(now()).min(poll));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+
+    #[test]
+    fn sends_at_the_configured_cadence() {
+        let start = Instant::now();
+        let clock = Cell::new(start);
+        let running = AtomicBool::new(true);
+        let sent = RefCell::new(vec![]);
+
+        run(
+            &InitPacket::default(),
+            KeepAlive::new(Duration::from_millis(1000), start),
+            &running,
+            Duration::from_millis(300),
+            || clock.get(),
+            |duration| clock.set(clock.get() + duration),
+            |step| {
+                assert!(matches!(step, InitStep::Control(message) if message.value == 0x0202));
+                let at = clock.get() - start;
+                sent.borrow_mut().push(at.as_millis());
+                if sent.borrow().len() == 3 {
+                    running.store(false, Ordering::SeqCst);
+                }
+                Ok(())
+            },
+        );
+
+        assert_eq!(*sent.borrow(), vec![1000, 2000, 3000]);
+    }
+
+    #[test]
+    fn a_late_send_does_not_bunch_up_the_next_ones() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut keep_alive = KeepAlive::new(Duration::from_millis(1000), start);
+
+        assert!(!keep_alive.due(at(999)));
+        assert!(keep_alive.due(at(3500)));
+        assert!(!keep_alive.due(at(3500)));
+        assert_eq!(keep_alive.wait(at(3500)), Duration::from_millis(1000));
+        assert!(keep_alive.due(at(4500)));
+    }
+}
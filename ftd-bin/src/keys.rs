@@ -0,0 +1,203 @@
+use evdev_rs::enums::EV_KEY;
+use serde::{Deserialize, Deserializer, de::Error};
+
+use EV_KEY::*;
+
+/// Short key names accepted in the config, matched case-insensitively.
+/// The kernel's own `KEY_*` and `BTN_*` names work as well.
+pub const KEY_NAMES: &[(&str, EV_KEY)] = &[
+    ("ctrl", KEY_LEFTCTRL),
+    ("shift", KEY_LEFTSHIFT),
+    ("alt", KEY_LEFTALT),
+    ("super", KEY_LEFTMETA),
+    ("rctrl", KEY_RIGHTCTRL),
+    ("rshift", KEY_RIGHTSHIFT),
+    ("altgr", KEY_RIGHTALT),
+    ("rsuper", KEY_RIGHTMETA),
+    ("a", KEY_A),
+    ("b", KEY_B),
+    ("c", KEY_C),
+    ("d", KEY_D),
+    ("e", KEY_E),
+    ("f", KEY_F),
+    ("g", KEY_G),
+    ("h", KEY_H),
+    ("i", KEY_I),
+    ("j", KEY_J),
+    ("k", KEY_K),
+    ("l", KEY_L),
+    ("m", KEY_M),
+    ("n", KEY_N),
+    ("o", KEY_O),
+    ("p", KEY_P),
+    ("q", KEY_Q),
+    ("r", KEY_R),
+    ("s", KEY_S),
+    ("t", KEY_T),
+    ("u", KEY_U),
+    ("v", KEY_V),
+    ("w", KEY_W),
+    ("x", KEY_X),
+    ("y", KEY_Y),
+    ("z", KEY_Z),
+    ("0", KEY_0),
+    ("1", KEY_1),
+    ("2", KEY_2),
+    ("3", KEY_3),
+    ("4", KEY_4),
+    ("5", KEY_5),
+    ("6", KEY_6),
+    ("7", KEY_7),
+    ("8", KEY_8),
+    ("9", KEY_9),
+    ("f1", KEY_F1),
+    ("f2", KEY_F2),
+    ("f3", KEY_F3),
+    ("f4", KEY_F4),
+    ("f5", KEY_F5),
+    ("f6", KEY_F6),
+    ("f7", KEY_F7),
+    ("f8", KEY_F8),
+    ("f9", KEY_F9),
+    ("f10", KEY_F10),
+    ("f11", KEY_F11),
+    ("f12", KEY_F12),
+    ("f13", KEY_F13),
+    ("f14", KEY_F14),
+    ("f15", KEY_F15),
+    ("f16", KEY_F16),
+    ("f17", KEY_F17),
+    ("f18", KEY_F18),
+    ("f19", KEY_F19),
+    ("f20", KEY_F20),
+    ("f21", KEY_F21),
+    ("f22", KEY_F22),
+    ("f23", KEY_F23),
+    ("f24", KEY_F24),
+    ("esc", KEY_ESC),
+    ("tab", KEY_TAB),
+    ("space", KEY_SPACE),
+    ("enter", KEY_ENTER),
+    ("backspace", KEY_BACKSPACE),
+    ("delete", KEY_DELETE),
+    ("insert", KEY_INSERT),
+    ("home", KEY_HOME),
+    ("end", KEY_END),
+    ("pageup", KEY_PAGEUP),
+    ("pagedown", KEY_PAGEDOWN),
+    ("up", KEY_UP),
+    ("down", KEY_DOWN),
+    ("left", KEY_LEFT),
+    ("right", KEY_RIGHT),
+    ("capslock", KEY_CAPSLOCK),
+    ("print", KEY_SYSRQ),
+    ("pause", KEY_PAUSE),
+    ("menu", KEY_COMPOSE),
+    ("-", KEY_MINUS),
+    ("=", KEY_EQUAL),
+    ("[", KEY_LEFTBRACE),
+    ("]", KEY_RIGHTBRACE),
+    (";", KEY_SEMICOLON),
+    ("'", KEY_APOSTROPHE),
+    ("`", KEY_GRAVE),
+    ("\\", KEY_BACKSLASH),
+    (",", KEY_COMMA),
+    (".", KEY_DOT),
+    ("/", KEY_SLASH),
+    ("kp+", KEY_KPPLUS),
+    ("kp-", KEY_KPMINUS),
+    ("kp*", KEY_KPASTERISK),
+    ("kp/", KEY_KPSLASH),
+    ("volumeup", KEY_VOLUMEUP),
+    ("volumedown", KEY_VOLUMEDOWN),
+    ("mute", KEY_MUTE),
+    ("micmute", KEY_MICMUTE),
+    ("playpause", KEY_PLAYPAUSE),
+    ("stop", KEY_STOPCD),
+    ("nextsong", KEY_NEXTSONG),
+    ("previoussong", KEY_PREVIOUSSONG),
+    ("rewind", KEY_REWIND),
+    ("fastforward", KEY_FASTFORWARD),
+    ("record", KEY_RECORD),
+    ("eject", KEY_EJECTCD),
+    ("brightnessup", KEY_BRIGHTNESSUP),
+    ("brightnessdown", KEY_BRIGHTNESSDOWN),
+    ("screenlock", KEY_COFFEE),
+    ("sleep", KEY_SLEEP),
+    ("power", KEY_POWER),
+    ("calc", KEY_CALC),
+    ("mail", KEY_MAIL),
+    ("www", KEY_WWW),
+    ("search", KEY_SEARCH),
+    ("stylus", BTN_STYLUS),
+    ("stylus2", BTN_STYLUS2),
+    ("left_click", BTN_LEFT),
+    ("right_click", BTN_RIGHT),
+    ("middle_click", BTN_MIDDLE),
+    ("touch", BTN_TOUCH),
+];
+
+/// The first name of `key` in `KEY_NAMES`.
+pub fn key_name(key: EV_KEY) -> Option<&'static str> {
+    KEY_NAMES
+        .iter()
+        .find(|(_, named)| *named == key)
+        .map(|(name, _)| *name)
+}
+
+pub fn key_by_name(name: &str) -> Result<EV_KEY, String> {
+    KEY_NAMES
+        .iter()
+        .find(|(short, _)| short.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key)
+        .or_else(|| name.parse().ok())
+        .ok_or_else(|| format!("Unknown key `{name}`, `list-keys` prints the known names"))
+}
+
+/// `deserialize_with` for a list of key names.
+pub fn deserialize_keys<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<EV_KEY>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|name| key_by_name(name).map_err(D::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+
+    #[test]
+    fn resolves_short_and_kernel_names() {
+        assert_eq!(key_by_name("ctrl"), Ok(KEY_LEFTCTRL));
+        assert_eq!(key_by_name("Z"), Ok(KEY_Z));
+        assert_eq!(key_by_name("f5"), Ok(KEY_F5));
+        assert_eq!(key_by_name("["), Ok(KEY_LEFTBRACE));
+        assert_eq!(key_by_name("KEY_VOLUMEUP"), Ok(KEY_VOLUMEUP));
+        assert_eq!(key_by_name("playpause"), Ok(KEY_PLAYPAUSE));
+        assert!(
+            key_by_name("hyper")
+                .unwrap_err()
+                .contains("Unknown key `hyper`")
+        );
+    }
+
+    #[test]
+    fn key_actions_take_names_in_the_config() {
+        #[derive(Deserialize)]
+        struct Map {
+            button_map: Vec<Action>,
+        }
+
+        let map: Map = toml::from_str("button_map = [{ keys = [\"ctrl\", \"z\"] }]").unwrap();
+        assert_eq!(
+            map.button_map,
+            vec![Action::Keys(vec![KEY_LEFTCTRL, KEY_Z])]
+        );
+
+        let error = toml::from_str::<Map>("button_map = [{ keys = [\"ctrl\", \"zz\"] }]");
+        assert!(error.is_err());
+    }
+}
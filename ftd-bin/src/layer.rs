@@ -0,0 +1,212 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use ftd_device::report::{BUTTON_COUNT, ButtonEvent};
+
+use crate::action::Action;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayerConfig {
+    /// Button index, as in `report::BUTTON_BITS`, that switches to
+    /// `button_map` while held. It has no action of its own.
+    pub button: Option<u8>,
+    /// Bit of the pen report's status byte, a barrel button on pens that
+    /// have one, that also holds the layer while set. Letting go of it
+    /// with the tip down keeps the layer until the tip lifts, so a stroke
+    /// ends on the bindings it started on. The MTM-1106 pen has none.
+    pub pen_bit: Option<u8>,
+    /// Bindings while the layer is held, indexed like the main ones.
+    /// Buttons past its end keep their main binding.
+    pub button_map: Vec<Action>,
+}
+
+impl LayerConfig {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(button) = self.button
+            && button as usize >= BUTTON_COUNT
+        {
+            bail!("layer.button {button} is not a button index");
+        }
+        if let Some(bit) = self.pen_bit
+            && bit.count_ones() != 1
+        {
+            bail!("layer.pen_bit {bit:#04x} must be a single bit");
+        }
+        if self.button_map.len() > BUTTON_COUNT {
+            bail!(
+                "layer.button_map has {} entries, there are only {BUTTON_COUNT} buttons",
+                self.button_map.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// `main` padded to one binding per button, then the layer bindings,
+    /// which `Layer` addresses from `BUTTON_COUNT` on.
+    pub fn combined_map(&self, main: &[Action]) -> Vec<Action> {
+        if self.button.is_none() && self.pen_bit.is_none() {
+            return main.to_vec();
+        }
+
+        let mut combined = main.to_vec();
+        combined.resize(BUTTON_COUNT, Action::Keys(vec![]));
+        combined.extend(self.button_map.iter().cloned());
+        combined
+    }
+}
+
+/// Sends buttons pressed while the layer button is held to the layer
+/// bindings. A button is released on the layer it was pressed on, so
+/// letting go of the layer button first leaves nothing stuck.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    button: Option<u8>,
+    bindings: usize,
+    held: bool,
+    pen_held: bool,
+    pressed_on_layer: [bool; BUTTON_COUNT],
+}
+
+impl Layer {
+    pub fn new(config: &LayerConfig) -> Layer {
+        Layer {
+            button: config.button,
+            bindings: config.button_map.len(),
+            held: false,
+            pen_held: false,
+            pressed_on_layer: [false; BUTTON_COUNT],
+        }
+    }
+
+    /// Follows the pen's `pen_bit`, `set` when the pen is in range with it.
+    pub fn set_pen(&mut self, set: bool, tip: bool) {
+        if set {
+            self.pen_held = true;
+        } else if !tip {
+            self.pen_held = false;
+        }
+    }
+
+    /// The event to dispatch against `LayerConfig::combined_map`, `None`
+    /// for the layer button itself.
+    pub fn apply(&mut self, event: ButtonEvent) -> Option<ButtonEvent> {
+        if Some(event.index) == self.button {
+            self.held = event.pressed;
+            return None;
+        }
+        let Some(on_layer) = self.pressed_on_layer.get_mut(event.index as usize) else {
+            return Some(event);
+        };
+
+        let layered = if event.pressed {
+            *on_layer = (self.held || self.pen_held) && (event.index as usize) < self.bindings;
+            *on_layer
+        } else {
+            std::mem::take(on_layer)
+        };
+        Some(ButtonEvent {
+            index: if layered {
+                event.index + BUTTON_COUNT as u8
+            } else {
+                event.index
+            },
+            ..event
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev_rs::enums::EV_KEY;
+
+    const FN: u8 = 13;
+
+    fn config() -> LayerConfig {
+        LayerConfig {
+            button: Some(FN),
+            pen_bit: None,
+            button_map: vec![Action::Keys(vec![EV_KEY::KEY_Z]); 2],
+        }
+    }
+
+    fn press(index: u8, pressed: bool) -> ButtonEvent {
+        ButtonEvent { index, pressed }
+    }
+
+    #[test]
+    fn held_layer_button_switches_the_bindings() {
+        let config = config();
+        let mut layer = Layer::new(&config);
+        let layered = BUTTON_COUNT as u8;
+
+        assert_eq!(layer.apply(press(0, true)), Some(press(0, true)));
+        assert_eq!(layer.apply(press(0, false)), Some(press(0, false)));
+
+        assert_eq!(layer.apply(press(FN, true)), None);
+        assert_eq!(layer.apply(press(1, true)), Some(press(layered + 1, true)));
+        // Past the layer bindings the main one applies.
+        assert_eq!(layer.apply(press(5, true)), Some(press(5, true)));
+
+        let combined = config.combined_map(&[Action::Scroll(1)]);
+        assert_eq!(combined.len(), BUTTON_COUNT + 2);
+        assert_eq!(combined[0], Action::Scroll(1));
+        assert_eq!(
+            combined[BUTTON_COUNT + 1],
+            Action::Keys(vec![EV_KEY::KEY_Z])
+        );
+    }
+
+    #[test]
+    fn buttons_release_on_the_layer_they_were_pressed_on() {
+        let mut layer = Layer::new(&config());
+        let layered = BUTTON_COUNT as u8;
+
+        layer.apply(press(FN, true));
+        assert_eq!(layer.apply(press(0, true)), Some(press(layered, true)));
+        assert_eq!(layer.apply(press(FN, false)), None);
+        assert_eq!(layer.apply(press(0, false)), Some(press(layered, false)));
+
+        assert_eq!(layer.apply(press(1, true)), Some(press(1, true)));
+        layer.apply(press(FN, true));
+        assert_eq!(layer.apply(press(1, false)), Some(press(1, false)));
+        assert!(
+            LayerConfig {
+                button: Some(BUTTON_COUNT as u8),
+                ..config()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn the_pen_bit_holds_the_layer_until_the_tip_lifts() {
+        let mut layer = Layer::new(&LayerConfig {
+            button: None,
+            pen_bit: Some(0x02),
+            ..config()
+        });
+        let layered = BUTTON_COUNT as u8;
+
+        layer.set_pen(true, true);
+        assert_eq!(layer.apply(press(0, true)), Some(press(layered, true)));
+        assert_eq!(layer.apply(press(0, false)), Some(press(layered, false)));
+
+        // Let go of the barrel mid-stroke, then lift the tip.
+        layer.set_pen(false, true);
+        assert_eq!(layer.apply(press(1, true)), Some(press(layered + 1, true)));
+        layer.set_pen(false, false);
+        assert_eq!(layer.apply(press(0, true)), Some(press(0, true)));
+        assert!(
+            LayerConfig {
+                pen_bit: Some(0x03),
+                ..config()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Minimal stderr logger, the level is read from `FTD_LOG` (default `info`).
+/// With `log_to_file` every line also goes to a file.
+struct Logger {
+    file: Mutex<Option<RotatingFile>>,
+}
+
+static LOGGER: Logger = Logger {
+    file: Mutex::new(None),
+};
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let level = match record.level() {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN ",
+            Level::Info => "INFO ",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        self.write(&format!("[{level}] {}", record.args()));
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+impl Logger {
+    fn write(&self, line: &str) {
+        eprintln!("{line}");
+        if let Some(file) = self.file.lock().unwrap().as_mut()
+            && let Err(e) = file.write_line(line)
+        {
+            eprintln!("[WARN ] Failed to write the log file: {e}");
+        }
+    }
+}
+
+/// Logs `line` at any level, for the packets of `RawDump`.
+pub fn dump(line: &str) {
+    LOGGER.write(&format!("[DUMP ] {line}"));
+}
+
+pub fn init() {
+    let level = std::env::var("FTD_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Also appends the log to `path`, see `RotatingFile`.
+pub fn log_to_file(path: &Path, max_bytes: u64, keep: usize) -> io::Result<()> {
+    let file = RotatingFile::open(path, max_bytes, keep)?;
+    *LOGGER.file.lock().unwrap() = Some(file);
+
+    Ok(())
+}
+
+/// A log file that is moved to `<path>.1` once a line would take it past
+/// `max_bytes`, shifting the older ones up to `<path>.<keep>`.
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: BufWriter<File>,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            max_bytes,
+            keep,
+            file: BufWriter::new(file),
+            written,
+        })
+    }
+
+    fn rotated(&self, number: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{number}"));
+        path.into()
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+        if self.written > 0 && self.written + length > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.written += length;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep > 0 {
+            for number in (1..self.keep).rev() {
+                let from = self.rotated(number);
+                if from.exists() {
+                    fs::rename(from, self.rotated(number + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = BufWriter::new(File::create(&self.path)?);
+        self.written = 0;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_at_the_size_and_keeps_the_newest_files() {
+        let dir = std::env::temp_dir().join(format!("ftd-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ftd.log");
+
+        // Each line takes 10 bytes with its newline, two fit in 25.
+        let mut file = RotatingFile::open(&path, 25, 2).unwrap();
+        for number in 0..7 {
+            file.write_line(&format!("line {number:04}")).unwrap();
+        }
+        file.flush().unwrap();
+
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(read(path.clone()), "line 0006\n");
+        assert_eq!(read(file.rotated(1)), "line 0004\nline 0005\n");
+        assert_eq!(read(file.rotated(2)), "line 0002\nline 0003\n");
+        assert!(!file.rotated(3).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,218 +1,1385 @@
+mod action;
+mod activity;
+mod assemble;
+mod bands;
+mod bindings;
+mod calibrate;
+mod cli;
+mod command;
+mod config;
+mod control;
+mod diagnostics;
+mod dispatch;
+mod distance;
+mod driver;
+mod dump;
+mod dwell;
+mod edge;
+mod emitter;
+mod focus;
+mod format;
+mod gesture;
+mod grab;
+mod hooks;
+#[cfg(feature = "http")]
+mod http;
+mod identity;
+mod import;
+mod init_state;
+mod instance;
+mod json;
+mod keep_alive;
+mod keys;
+mod layer;
+mod logger;
+mod mapper;
+mod merge;
+#[cfg(feature = "monitor")]
+mod monitor;
+mod mouse;
+mod pause;
+mod precision;
+mod pressure;
+mod pressure_scroll;
+mod pressure_test;
+mod profile;
+mod proximity;
+mod queue;
+mod read_stats;
+mod reader;
+mod reconnect;
+mod recording;
+mod recovery;
+mod remap;
+mod replay;
+mod screen;
+mod session;
+mod shutdown;
+mod signal;
+mod simulate;
+mod smoothing;
+mod supervise;
+mod tilt;
+mod tip_delay;
+mod transitions;
+mod version;
+
 use std::{
-    collections::HashMap,
-    sync::{Arc, atomic::AtomicBool},
-    time::Duration,
+    cell::RefCell,
+    fs,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context as AnyHowContext, Result};
-use rusb::{Context, Device, DeviceHandle, Direction, Result as RusbResult, UsbContext};
+use anyhow::{Context as AnyHowContext, Result, anyhow, bail};
+use crossbeam_channel::RecvTimeoutError;
+use log::{debug, error, info, warn};
+use rusb::{Context, DeviceHandle, UsbContext};
 
-const VENDOR_ID: u16 = 0x08f2;
-const PRODUCT_ID: u16 = 0x6811;
+use ftd_device::{
+    device::{
+        InterfaceInfo, MessageDevice, USBDevice, check_interfaces, claim_interfaces_except,
+        detect_interfaces, find_device, health_check, open_device_with_configuration,
+        query_capabilities, read_device, read_feature_report, send_to_device, write_to_endpoint,
+    },
+    error::FtdError,
+    report::{BUTTON_COUNT, DEVICE_MAX_PRESSURE, DEVICE_RESOLUTION, PenReport},
+    transfer::{AsyncReader, UsbTransfers},
+};
 
-const MASS_STORAGE: u8 = 0;
-const BUTTONS_INTERAFCE: u8 = 1;
-const TABLET_INTERFACE: u8 = 2;
+use crate::{
+    activity::ActivityTracker,
+    calibrate::Calibration,
+    cli::{Args, RawControl},
+    command::{CommandPolicy, CommandRunner},
+    config::{Config, DeviceConfig, InitStep, ReadMode},
+    control::ControlServer,
+    driver::{Driver, Settings},
+    dump::RawDump,
+    emitter::{BackgroundSink, Emitter, EmitterState, LogSink, NullSink, uinput_hint},
+    format::OutputFormat,
+    identity::DeviceTraits,
+    init_state::InitState,
+    instance::InstanceLock,
+    keep_alive::KeepAlive,
+    mouse::MouseEmitter,
+    pause::Pause,
+    profile::ProfileConfig,
+    read_stats::ReadStats,
+    reader::{BlockingReads, PacketSource, read_loop},
+    reconnect::Backoff,
+    recording::Recorder,
+    recovery::{Escalation, Recover, RecoveryStep, ReinitLimiter},
+    session::SessionStats,
+    shutdown::ShutdownReason,
+    signal::{Interrupt, Interrupts},
+    transitions::Transitions,
+};
 
-struct USBDevice<T: UsbContext> {
-    pub device: Device<T>,
-    pub handle: DeviceHandle<T>,
-    pub interfaces: HashMap<u8, InterfaceInfo>,
-}
+/// How long the report loop waits for a report before it checks on
+/// signals, the control socket and idleness.
+const POLL: Duration = Duration::from_millis(50);
 
-impl<T: UsbContext> Drop for USBDevice<T> {
-    fn drop(&mut self) {
-        let interfaces: Vec<u8> = self.interfaces.keys().into_iter().map(|v| *v).collect();
+/// Reads kept queued on each IN endpoint with `device.reader = "async"`.
+const QUEUED_READS: usize = 2;
 
-        for i in interfaces {
-            if let Ok(res) = self.handle.kernel_driver_active(i) {
-                if res {
-                    let _ = self.handle.attach_kernel_driver(i);
-                }
-            }
-        }
+fn main() -> ExitCode {
+    logger::init();
+    let reason = run().unwrap_or_else(ShutdownReason::Failed);
+    match reason {
+        ShutdownReason::Requested => info!("{reason}"),
+        _ => error!("{reason}"),
     }
-}
+    log::logger().flush();
 
-#[derive(Hash, Clone)]
-pub struct InterfaceInfo {
-    pub number: u8,
-    pub endpoints_in: Vec<u8>,
-    pub endpoints_out: Vec<u8>,
+    ExitCode::from(reason.exit_code())
 }
 
-struct MessageDevice {
-    pub request_type: u8,
-    pub request: u8,
-    pub value: u16,
-    pub interface: u16,
-    pub payload: Vec<u8>,
-    pub timeout: Duration,
-}
+fn run() -> Result<ShutdownReason> {
+    let args = Args::parse()?;
+    if args.version {
+        println!("{}", version::about());
+        return Ok(ShutdownReason::Requested);
+    }
+    if let Some(path) = &args.log_file {
+        let max_bytes = args.log_file_max_kb.unwrap_or(1024) * 1024;
+        logger::log_to_file(path, max_bytes, args.log_file_keep.unwrap_or(3))
+            .with_context(|| format!("Failed to open the log file {}", path.display()))?;
+    }
+    if let Some(path) = &args.import_bindings {
+        return import_bindings(path).map(|()| ShutdownReason::Requested);
+    }
+    if args.list_keys {
+        for (name, key) in keys::KEY_NAMES {
+            println!("{name:<12} {key:?}");
+        }
+        return Ok(ShutdownReason::Requested);
+    }
+    if args.monitor {
+        if !cfg!(feature = "monitor") {
+            bail!("`monitor` is not part of this build, rebuild with `--features monitor`");
+        }
+        // Anything below a warning would scroll the view away.
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+    let mut config = args.load_config()?;
+    if args.dump_config {
+        print!("{}", config.to_text()?);
+        return Ok(ShutdownReason::Requested);
+    }
+    if let Some(raw) = &args.raw_send {
+        return raw_send(&config.device, raw).map(|()| ShutdownReason::Requested);
+    }
+    if let Some(path) = &args.diagnostics {
+        return diagnostics(&args, &config, path).map(|()| ShutdownReason::Requested);
+    }
+    if args.calibrate {
+        return calibrate(&args, &config).map(|()| ShutdownReason::Requested);
+    }
+    if args.pressure_test {
+        return pressure_test(&config).map(|()| ShutdownReason::Requested);
+    }
+    let mut settings = Settings::from_config(&config)?;
+
+    let commands = CommandRunner::new(CommandPolicy {
+        allow: args.allow_commands && !args.watch,
+        allow_root: args.allow_commands_as_root,
+    });
+    if args.test_bindings {
+        return test_bindings(&args, &config, settings, commands)
+            .map(|()| ShutdownReason::Requested);
+    }
+    if let Some(pattern) = args.simulate {
+        return simulate(&args, &config, settings, commands, pattern)
+            .map(|()| ShutdownReason::Requested);
+    }
+    if let Some(path) = std::env::var_os("FTD_REPLAY") {
+        return replay_recording(&args, &config, settings, commands, Path::new(&path))
+            .map(|()| ShutdownReason::Requested);
+    }
+
+    let control = if args.no_control_socket {
+        None
+    } else {
+        let path = args
+            .control_socket
+            .clone()
+            .unwrap_or_else(ControlServer::default_path);
+        let server = ControlServer::bind(&path)
+            .with_context(|| format!("Failed to bind the control socket {}", path.display()))?;
+        info!("Listening for control commands on {}", path.display());
+        Some(server)
+    };
+    if args.http_bind.is_some() && args.http_port.is_none() {
+        bail!("--http-bind needs --http-port");
+    }
+    if args.http_port.is_some() && !cfg!(feature = "http") {
+        bail!("`--http-port` is not part of this build, rebuild with `--features http`");
+    }
+    #[cfg(feature = "http")]
+    let http = match args.http_port {
+        Some(port) => {
+            let address = std::net::SocketAddr::new(
+                args.http_bind
+                    .unwrap_or(std::net::Ipv4Addr::LOCALHOST.into()),
+                port,
+            );
+            let server = http::HttpServer::bind(address)
+                .with_context(|| format!("Failed to bind the HTTP control endpoint {address}"))?;
+            info!("Listening for control commands on http://{address}");
+            if !address.ip().is_loopback() {
+                warn!(
+                    "The HTTP control endpoint has no authentication, anyone who can reach \
+                     {address} can control the tablet"
+                );
+            }
+            Some(server)
+        }
+        None => None,
+    };
+    let focus = config
+        .profiles
+        .iter()
+        .any(ProfileConfig::follows_focus)
+        .then(|| focus::FocusWatcher::spawn(Duration::from_millis(config.focus_poll_ms)));
 
-fn main() -> Result<()> {
     let running = Arc::new(AtomicBool::new(true));
 
     let r = running.clone();
-    ctrlc::set_handler(move || {
-        r.store(false, std::sync::atomic::Ordering::SeqCst);
+    let mut interrupts = Interrupts::new(Duration::from_millis(config.force_quit_ms));
+    ctrlc::set_handler(move || match interrupts.press(Instant::now()) {
+        Interrupt::Graceful => {
+            info!("Stopping, press Ctrl+C again to quit at once");
+            r.store(false, Ordering::SeqCst);
+        }
+        Interrupt::Force => {
+            // Closing the USB device releases the claimed interfaces, only
+            // the kernel drivers are not reattached.
+            warn!("Quitting without releasing the tablet");
+            log::logger().flush();
+            std::process::exit(130);
+        }
     })
     .expect("Unlonw handle error");
+    signal::install_reinit_handler();
 
     let mut context = Context::new()?;
 
-    let mut usb_device =
-        open_device(&mut context, VENDOR_ID, PRODUCT_ID)?.context("Tablet Not Found")?;
-
-    claim_interfaces(
-        &mut usb_device.handle,
-        &[MASS_STORAGE, BUTTONS_INTERAFCE, TABLET_INTERFACE],
-    )?;
-
-    std::thread::sleep(Duration::from_millis(500));
+    // Taken before claiming, and released when `run` returns.
+    let device = &config.device;
+    let _instance = match find_device(&mut context, device.vendor_id, device.product_id)? {
+        Some(found) => Some(InstanceLock::acquire(&InstanceLock::default_path(
+            found.bus_number(),
+            found.address(),
+        ))?),
+        None => None,
+    };
+    let (mut usb_device, skipped) = open_tablet(&mut context, &config.device)?;
+    let revision = usb_device.revision;
+    info!("Firmware revision {revision:#06x}");
+    if let Some(expected) = config.device.revision
+        && expected != revision
+    {
+        warn!(
+            "The device reports firmware revision {revision:#06x} but the settings are for \
+             {expected:#06x}, add `device.revision_quirks` if it misbehaves"
+        );
+    }
+    if config.device.use_revision(revision) {
+        info!("Using the quirks of revision {revision:#06x}");
+        settings = Settings::from_config(&config)?;
+    }
+    let grab_nodes = |device: &DeviceConfig| {
+        device
+            .grab_event_nodes
+            .then(|| grab::grab_tablet(device.vendor_id, device.product_id))
+    };
+    let mut grabs = grab_nodes(&config.device);
+    let detected = detect(&usb_device, &config.device, &skipped);
+    if let Some((buttons, tablet)) = detected {
+        config.device.use_interfaces(buttons, tablet);
+        settings = Settings::from_config(&config)?;
+    }
+    let device = &config.device;
+    let mut interfaces = read_interfaces(&usb_device, device, &skipped)?;
+    config
+        .init
+        .check_interfaces(|number| usb_device.interface(number).is_some())?;
 
-    let magic_packet = MessageDevice {
-        request_type: 0x21,
-        request: 0x09,
-        value: 0x0202,
-        interface: 2,
-        payload: vec![0x02, 0x01],
-        timeout: Duration::from_secs(1),
+    let capabilities = if device.probe_capabilities {
+        query_capabilities(&usb_device.handle, device.tablet_interface as u16).unwrap_or_else(|e| {
+            warn!("Failed to read the capabilities report: {e}");
+            None
+        })
+    } else {
+        None
     };
-    let _ = send_to_device(&mut usb_device.handle, &magic_packet);
-
-    std::thread::sleep(Duration::from_millis(500));
-
-    while running.load(std::sync::atomic::Ordering::SeqCst) {
-        match read_device(
-            &mut usb_device.handle,
-            usb_device.interfaces.get(&BUTTONS_INTERAFCE).unwrap(),
-            8,
-            10,
-        ) {
-            Ok((id, bytes)) => println!("Interface: {id} || Bytes: {bytes:02X?}"),
-            Err(rusb::Error::Timeout) => {
-                //print!(".");
-                //io::stdout().flush().unwrap();
+    match capabilities {
+        Some(capabilities) => {
+            info!("Tablet reports {capabilities:?}");
+            if capabilities.max_pressure != DEVICE_MAX_PRESSURE
+                || capabilities.buttons as usize != BUTTON_COUNT
+            {
+                warn!("The reported pressure range or button count differs from the MTM-1106");
             }
-            Err(e) => {
-                println!("Erro fatal na leitura: {:?}", e);
-                break;
+            config.seed(&capabilities);
+        }
+        None => info!("No capabilities report, using the built-in defaults"),
+    }
+
+    let init_state = InitState::new(InitState::default_path());
+    let device_key = format!(
+        "{}-{}",
+        usb_device.device.bus_number(),
+        usb_device.device.address()
+    );
+    let init_window = Duration::from_secs(config.init.window_secs);
+
+    let skip_init =
+        !args.force_init && init_state.is_fresh(&device_key, init_window, SystemTime::now());
+    if skip_init {
+        info!("Device was initialized recently, skipping init (--force-init to redo it)");
+    } else {
+        config
+            .device
+            .identity
+            .guard(&DeviceTraits::read(&usb_device))?;
+        initialize(&usb_device.handle, &config)
+            .context("Failed to initialize the tablet, it would send no reports")?;
+    }
+    if let Err(e) = init_state.record(&device_key, SystemTime::now()) {
+        warn!("Failed to record the init state: {e}");
+    }
+    let mut verified = !skip_init;
+
+    let pen_bounds = pen_bounds(&settings);
+    let emitter = create_emitter(&args, emitter_state(&config, &settings, pen_bounds))?;
+
+    let read_stats = Arc::new(ReadStats::default());
+    let pause = Arc::new(Pause::default());
+    let raw_dump = Arc::new(RawDump::default());
+    let session_stats = Rc::new(RefCell::new(SessionStats::new(Instant::now())));
+    let observed = session_stats.clone();
+    let reload_args = args.clone();
+    let mut driver = Driver::new(settings, emitter, commands)
+        .with_mouse(create_mouse(&args, &config)?)
+        .with_read_stats(read_stats.clone())
+        .with_pause(pause.clone())
+        .with_raw_dump(raw_dump.clone())
+        .with_observer(Box::new(move |stamped| {
+            observed.borrow_mut().observe(stamped)
+        }));
+    if let Some(path) = config::locate(args.config.as_deref(), &config::search_paths()) {
+        driver = driver.with_config_path(path);
+    }
+    let mut driver = driver.with_reload(Box::new(move || {
+        let mut config = reload_args.load_config()?;
+        if let Some(capabilities) = &capabilities {
+            config.seed(capabilities);
+        }
+        config.device.use_revision(revision);
+        if let Some((buttons, tablet)) = detected {
+            config.device.use_interfaces(buttons, tablet);
+        }
+        Settings::from_config(&config)
+    }));
+    if !skip_init {
+        driver.warm_up(Instant::now());
+    }
+    if args.log_transitions {
+        let mut transitions = Transitions::default();
+        driver = driver.with_observer(Box::new(move |stamped| {
+            if let Some(state) = transitions.observe(&stamped.event) {
+                info!("{state}");
             }
+        }));
+    }
+    match args.output_format() {
+        _ if args.monitor => {}
+        OutputFormat::Raw => {}
+        OutputFormat::Human => {
+            driver = driver.with_observer(Box::new(|stamped| {
+                println!("{}", format::human(&stamped.event));
+            }));
+        }
+        OutputFormat::Binary => {
+            let mut failed = false;
+            driver = driver.with_observer(Box::new(move |stamped| {
+                let mut stdout = io::stdout().lock();
+                let written = stdout
+                    .write_all(&format::binary(&stamped.event))
+                    .and_then(|()| stdout.flush());
+                if let Err(e) = written
+                    && !std::mem::replace(&mut failed, true)
+                {
+                    warn!("Failed to write the frames to stdout: {e}");
+                }
+            }));
         }
+    }
+    #[cfg(feature = "monitor")]
+    if args.monitor {
+        let mut view = monitor::Monitor::new(pen_bounds);
+        monitor::Monitor::enter()?;
+        view.draw()?;
+        driver = driver.with_observer(Box::new(move |stamped| {
+            view.update(&stamped.event);
+            let _ = view.draw();
+        }));
+    }
 
-        match read_device(
-            &mut usb_device.handle,
-            usb_device.interfaces.get(&TABLET_INTERFACE).unwrap(),
-            8,
-            10,
-        ) {
-            Ok((id, bytes)) => println!("Interface: {id} || Bytes: {bytes:02X?}"),
-            Err(rusb::Error::Timeout) => {
-                //print!(".");
-                //io::stdout().flush().unwrap();
+    let mut recorder = args.record.as_deref().map(Recorder::create).transpose()?;
+    if let Some(path) = &args.record {
+        info!("Recording the reports to {}", path.display());
+    }
+    let mut backoff = Backoff::new(config.reconnect);
+    let mut activity = ActivityTracker::new(Duration::from_millis(config.idle.grace_ms));
+    let mut escalation = Escalation::new(&config.recovery);
+    let mut reinits = ReinitLimiter::new(&config.recovery);
+    let queue_dropped = AtomicU64::new(0);
+    driver.set_connected(true)?;
+    let result = loop {
+        let handle = &usb_device.handle;
+        // Stops the readers of this connection, `running` stops everything.
+        let connected = AtomicBool::new(true);
+        let session = thread::scope(|scope| {
+            let (sender, reports) = queue::channel(&config.queue, &queue_dropped);
+            let mut readers = interfaces
+                .iter()
+                .map(|read| {
+                    let sender = sender.clone();
+                    // The buttons carry the binding that turns the output back on.
+                    let pause = (read.0.number != config.device.buttons_interface)
+                        .then_some(pause.as_ref());
+                    let (running, connected) = (&running, &connected);
+                    let taps = (read_stats.as_ref(), raw_dump.as_ref());
+                    let device = &config.device;
+                    scope.spawn(move || {
+                        let what = format!("The reader of interface {}", read.0.number);
+                        supervise::fatal_on_panic(running, &what, || {
+                            let mut source = packet_source(handle, &read.0, device.reader);
+                            read_loop(&mut *source, read, device, connected, pause, taps, sender);
+                            Ok(())
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            drop(sender);
+            if let Some(packet) = &config.keep_alive.packet {
+                let interval = Duration::from_millis(config.keep_alive.interval_ms);
+                let (running, connected) = (&running, &connected);
+                readers.push(scope.spawn(move || {
+                    supervise::fatal_on_panic(running, "The keep-alive", || {
+                        keep_alive::run(
+                            packet,
+                            KeepAlive::new(interval, Instant::now()),
+                            connected,
+                            POLL,
+                            Instant::now,
+                            thread::sleep,
+                            |step| send_step(handle, step),
+                        );
+                        Ok(())
+                    })
+                }));
             }
-            Err(e) => {
-                println!("Erro fatal na leitura: {:?}", e);
-                break;
+
+            let mut end = SessionEnd::Stopped;
+            let result = supervise::fatal_on_panic(&running, "The report loop", || {
+                while running.load(Ordering::SeqCst) {
+                    if let Some(control) = &control {
+                        control.poll(&mut driver);
+                    }
+                    #[cfg(feature = "http")]
+                    if let Some(http) = &http {
+                        http.poll(&mut driver);
+                    }
+                    if let Some(focused) = focus.as_ref().and_then(|focus| focus.changed())
+                        && let Err(e) = driver.follow_focus(&focused)
+                    {
+                        warn!("Failed to follow the focused window: {e:#}");
+                    }
+
+                    if signal::take_reinit_request() || driver.take_reinit_request() {
+                        match reinits.admit(Instant::now()) {
+                            Err(wait) => {
+                                warn!("Not re-initializing on request, re-inits resume in {wait:?}")
+                            }
+                            Ok(()) => {
+                                info!("Re-sending the init packets on request");
+                                match initialize(handle, &config) {
+                                    Ok(()) => {
+                                        info!("Re-init done");
+                                        session_stats.borrow_mut().reinit();
+                                        driver.warm_up(Instant::now());
+                                    }
+                                    Err(e) => warn!("Re-init failed: {e}"),
+                                }
+                            }
+                        }
+                    }
+
+                    // Wakes up for a coalesced frame that is due.
+                    let wait = driver.frame_deadline().map_or(POLL, |deadline| {
+                        deadline.saturating_duration_since(Instant::now()).min(POLL)
+                    });
+                    match reports.recv_timeout(wait) {
+                        Ok((id, Ok((read_at, bytes)))) => {
+                            verified = true;
+                            escalation.reset();
+                            if activity.read(read_at).is_some() {
+                                debug!("The tablet is active");
+                            }
+                            if args.output_format() == OutputFormat::Raw && !args.monitor {
+                                println!("{}", replay::format_line(id, &bytes));
+                            }
+                            if let Some(writing) = &mut recorder
+                                && let Err(e) = writing.record(id, &bytes)
+                            {
+                                warn!("Failed to write the recording, no longer recording: {e}");
+                                recorder = None;
+                            }
+                            driver.handle_report(id, &bytes, read_at)?;
+                        }
+                        Ok((_, Err(e))) if !verified => {
+                            warn!("First read failed after skipping init ({e}), initializing");
+                            initialize(handle, &config)
+                                .context("Failed to initialize the tablet")?;
+                            session_stats.borrow_mut().reinit();
+                            driver.warm_up(Instant::now());
+                            verified = true;
+                        }
+                        Ok((_, Err(rusb::Error::NoDevice))) if config.reconnect.enabled => {
+                            warn!("The tablet was disconnected");
+                            end = SessionEnd::Disconnected;
+                            break;
+                        }
+                        Ok((_, Err(e))) => {
+                            warn!("Reading the tablet failed: {e}");
+                            end = SessionEnd::ReadFailed;
+                            break;
+                        }
+                        Err(RecvTimeoutError::Timeout) => {
+                            driver.flush_due(Instant::now())?;
+                            if activity.tick(Instant::now()).is_some() {
+                                debug!("The tablet is idle");
+                                if config.idle.release_pen {
+                                    driver.release_pen()?;
+                                }
+                            }
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+
+                Ok(())
+            });
+
+            // The readers only notice this after their current read times out,
+            // or at once when they wait for room in the queue.
+            connected.store(false, Ordering::SeqCst);
+            drop(reports);
+            readers
+                .into_iter()
+                .fold(result, |result, reader| {
+                    let reader = reader
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow!("A reader thread panicked")));
+                    result.and(reader)
+                })
+                .map(|()| end)
+        });
+
+        match session {
+            Ok(SessionEnd::Stopped) => break Ok(ShutdownReason::Requested),
+            Ok(SessionEnd::Disconnected) => {}
+            Ok(SessionEnd::ReadFailed) => {
+                driver.teardown()?;
+                if !wait_for_reinit(&mut reinits, &running) {
+                    break Ok(ShutdownReason::Requested);
+                }
+                let mut target = Recovery {
+                    context: &mut context,
+                    config: &config,
+                    usb_device: &mut usb_device,
+                    interfaces: &mut interfaces,
+                };
+                match escalation.recover(&mut target) {
+                    Some(step) => {
+                        info!("Reading again after {step:?}");
+                        session_stats.borrow_mut().reinit();
+                    }
+                    None => break Ok(ShutdownReason::ReadsFailed),
+                }
+                driver.warm_up(Instant::now());
+                verified = true;
+                continue;
             }
+            Err(e) => break Err(e),
         }
+        // Nothing stays held while the tablet is gone.
+        driver.teardown()?;
+        driver.set_connected(false)?;
+        if !wait_for_reinit(&mut reinits, &running) {
+            break Ok(ShutdownReason::Requested);
+        }
+        match reconnect(&mut context, &config, &running, &mut backoff) {
+            Ok(Some((device, read))) => {
+                info!("Reconnected to the tablet");
+                session_stats.borrow_mut().reinit();
+                (usb_device, interfaces) = (device, read);
+                // The old nodes went with the old connection.
+                drop(grabs.take());
+                grabs = grab_nodes(&config.device);
+                driver.warm_up(Instant::now());
+                driver.set_connected(true)?;
+                verified = true;
+            }
+            Ok(None) if running.load(Ordering::SeqCst) => {
+                break Ok(ShutdownReason::GaveUpReconnecting(backoff.attempts()));
+            }
+            Ok(None) => break Ok(ShutdownReason::Requested),
+            Err(e) => break Err(e),
+        }
+    };
+
+    #[cfg(feature = "monitor")]
+    if args.monitor {
+        monitor::Monitor::leave()?;
+    }
+    driver.teardown()?;
+    drop(grabs);
+    if let Some(recorder) = recorder
+        && let Err(e) = recorder.finish()
+    {
+        warn!("Failed to finish the recording: {e}");
+    }
+    let reason = result?;
+
+    if driver.sequence().is_active() {
+        info!("Dropped reports: {}", driver.sequence().dropped());
+    }
+    let reads = read_stats.summary();
+    if !reads.is_empty() {
+        info!("Reads: {reads}");
+    }
+    let dropped = queue_dropped.load(Ordering::Relaxed);
+    if dropped > 0 {
+        warn!("Dropped {dropped} reports the report loop could not keep up with");
+    }
+    for line in session_stats
+        .borrow()
+        .summary(Instant::now(), &read_stats.counts())
+    {
+        info!("{line}");
+    }
+
+    Ok(reason)
+}
+
+/// Largest X/Y of the pen events, the desktop the pen is mapped onto.
+fn pen_bounds(settings: &Settings) -> (i32, i32) {
+    settings.mapper.bounds()
+}
+
+fn emitter_state(config: &Config, settings: &Settings, pen_bounds: (i32, i32)) -> EmitterState {
+    let resolution = config.device.resolution.unwrap_or(DEVICE_RESOLUTION);
+    let band_actions: Vec<_> = settings.bands.actions().cloned().collect();
+    let mut button_maps: Vec<&[_]> = settings
+        .profiles
+        .profiles()
+        .iter()
+        .map(|p| p.button_map.as_slice())
+        .collect();
+    button_maps.push(&band_actions);
+    let tilt_actions: Vec<_> = settings.tilt.actions().cloned().collect();
+    button_maps.push(&tilt_actions);
+    let dwell_action = [settings.dwell.action.clone()];
+    button_maps.push(&dwell_action);
+    EmitterState::new(
+        settings.profiles.active().button_map.clone(),
+        !settings.ignore_touch,
+        pen_bounds,
+    )
+    .with_resolution(settings.mapper.resolution(resolution), resolution)
+    .with_keys(&button_maps)
+    .with_hover(settings.hover_moves_cursor)
+    .with_center(config.mapping.center_on_start)
+    .with_tip(settings.tip.clone())
+    .with_name(&config.device_name)
+    .with_sinks(config.tablet_sinks)
+}
+
+/// The uinput device, or the log for `monitor` and `--dry-run`, or
+/// nothing at all for `watch`.
+fn create_emitter(args: &Args, state: EmitterState) -> Result<Emitter> {
+    if args.monitor {
+        return Ok(Emitter::log_only(state));
+    }
+    if args.watch {
+        info!("Watching, events are printed and not sent to uinput");
+        return Ok(Emitter::with_sink(state, NullSink));
+    }
+    if args.dry_run {
+        info!("Dry run, events are logged instead of sent to uinput");
+        return Ok(Emitter::log_only(state));
+    }
+
+    let mut emitter = Emitter::new(state).map_err(|e| match uinput_hint(&e) {
+        Some(hint) => anyhow!("Failed to create the uinput device: {hint} ({e})"),
+        None => anyhow!("Failed to create the uinput device: {e}"),
+    })?;
+    if args.log_events {
+        emitter.add_sink(BackgroundSink::spawn(LogSink));
+    }
+
+    Ok(emitter)
+}
+
+/// The `[mouse]` device when it is enabled, logged instead with
+/// `--dry-run` and left out for `monitor` and `watch`.
+fn create_mouse(args: &Args, config: &Config) -> Result<Option<MouseEmitter>> {
+    if !config.mouse.enabled || args.monitor || args.watch {
+        return Ok(None);
+    }
+    if args.dry_run {
+        return Ok(Some(MouseEmitter::with_sink(&config.mouse, LogSink)));
+    }
+
+    let mouse = MouseEmitter::new(&config.mouse).map_err(|e| match uinput_hint(&e) {
+        Some(hint) => anyhow!("Failed to create the uinput mouse: {hint} ({e})"),
+        None => anyhow!("Failed to create the uinput mouse: {e}"),
+    })?;
+
+    Ok(Some(mouse))
+}
+
+/// Runs the reports printed by an earlier run, the `Interface: ..` lines,
+/// through the driver instead of reading the tablet. Set `FTD_REPLAY` to
+/// the recording to check the whole pipeline without the hardware.
+fn replay_recording(
+    args: &Args,
+    config: &Config,
+    settings: Settings,
+    commands: CommandRunner,
+    path: &Path,
+) -> Result<()> {
+    let recording = recording::read(path)?;
+    let pen_bounds = pen_bounds(&settings);
+    let emitter = create_emitter(args, emitter_state(config, &settings, pen_bounds))?;
+    let mut driver =
+        Driver::new(settings, emitter, commands).with_mouse(create_mouse(args, config)?);
+
+    let count = replay::replay(&mut driver, &recording)?;
+    driver.teardown()?;
+    info!("Replayed {count} reports from {}", path.display());
+
+    Ok(())
+}
+
+/// Moves a synthetic pen along `pattern` through the driver until Ctrl+C,
+/// through uinput or with `--dry-run` the log, without the tablet.
+fn simulate(
+    args: &Args,
+    config: &Config,
+    settings: Settings,
+    commands: CommandRunner,
+    pattern: simulate::Pattern,
+) -> Result<()> {
+    let rate = args.rate.unwrap_or(100);
+    if rate == 0 {
+        bail!("--rate must be at least 1 report a second");
+    }
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))
+        .context("Failed to install the Ctrl+C handler")?;
+
+    let pen_bounds = pen_bounds(&settings);
+    let emitter = create_emitter(args, emitter_state(config, &settings, pen_bounds))?;
+    let mut driver =
+        Driver::new(settings, emitter, commands).with_mouse(create_mouse(args, config)?);
+    let simulation = simulate::Simulation {
+        interface: config.device.tablet_interface,
+        pattern,
+        period: Duration::from_secs(4),
+    };
+    info!("Simulating a {pattern:?} at {rate} reports a second, Ctrl+C to stop");
+
+    let interval = Duration::from_secs(1) / rate;
+    let start = Instant::now();
+    let mut next = start;
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        let report = simulation.report(now - start);
+        driver.handle_report(simulation.interface, &report, now)?;
+        next += interval;
+        thread::sleep(next.saturating_duration_since(Instant::now()));
     }
+    driver.teardown()?;
 
     Ok(())
 }
 
-fn open_device<T: UsbContext>(context: &mut T, vid: u16, pid: u16) -> Result<Option<USBDevice<T>>> {
-    let devices = context.devices()?;
+/// Prints the bindings of the active profile and with `--emit` fires each
+/// one in turn, a second apart, through uinput or with `--dry-run` the log.
+fn test_bindings(
+    args: &Args,
+    config: &Config,
+    settings: Settings,
+    commands: CommandRunner,
+) -> Result<()> {
+    let bindings = bindings::list(&settings);
+    print!("{}", bindings::table(&bindings));
+    if !args.emit {
+        return Ok(());
+    }
 
-    for device in devices.iter() {
-        let desc = device.device_descriptor()?;
+    let pen_bounds = pen_bounds(&settings);
+    let emitter = create_emitter(args, emitter_state(config, &settings, pen_bounds))?;
+    let mut driver =
+        Driver::new(settings, emitter, commands).with_mouse(create_mouse(args, config)?);
+    let mut first = true;
+    bindings::fire_all(&mut driver, &bindings, |binding| {
+        if !std::mem::take(&mut first) {
+            thread::sleep(Duration::from_secs(1));
+        }
+        match binding.skipped() {
+            Some(reason) => println!("Skipping {} ({reason})", binding.button),
+            None => println!(
+                "Firing {}: {}",
+                binding.button,
+                bindings::describe(&binding.action)
+            ),
+        }
+    })?;
+    driver.teardown()?;
 
-        if desc.vendor_id() == vid && desc.product_id() == pid {
-            let handle = device.open()?;
-            let mut interfaces = HashMap::new();
+    Ok(())
+}
 
-            let config_descriptor = device.active_config_descriptor()?;
-            for int in config_descriptor.interfaces() {
-                let number = int.number();
-                for desc in int.descriptors() {
-                    let mut endpoints_in = vec![];
-                    let mut endpoints_out = vec![];
-                    for endpoint in desc.endpoint_descriptors() {
-                        if endpoint.direction() == Direction::In {
-                            endpoints_in.push(endpoint.address());
-                        }
+/// Asks for a tap on each corner and saves their extents as the mapped
+/// area, or with `--affine` the matrix from three taps, in the `--config`
+/// file or the per-user one. Ctrl+C leaves the config untouched.
+fn calibrate(args: &Args, config: &Config) -> Result<()> {
+    let path = match &args.config {
+        Some(path) => path.clone(),
+        None => config::search_paths()
+            .into_iter()
+            .next()
+            .context("No config path to save the calibration to, pass --config")?,
+    };
 
-                        if endpoint.direction() == Direction::Out {
-                            endpoints_out.push(endpoint.address());
-                        }
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))
+        .context("Failed to install the Ctrl+C handler")?;
+
+    let device = &config.device;
+    let mut context = Context::new()?;
+    let (usb_device, skipped) = open_tablet(&mut context, device)?;
+    let (tablet, _) = read_interfaces(&usb_device, device, &skipped)?
+        .into_iter()
+        .find(|(interface, _)| interface.number == device.tablet_interface)
+        .context("Calibrating needs the tablet interface")?;
+    config
+        .init
+        .check_interfaces(|number| usb_device.interface(number).is_some())?;
+    device.identity.guard(&DeviceTraits::read(&usb_device))?;
+    initialize(&usb_device.handle, config).context("Failed to initialize the tablet")?;
+
+    let mut calibration = if args.affine {
+        Calibration::affine()
+    } else {
+        Calibration::corners()
+    };
+    while let Some(corner) = calibration.next_corner() {
+        println!("Tap the {corner} corner of the active area with the pen");
+        loop {
+            if !running.load(Ordering::SeqCst) {
+                println!("Calibration aborted, {} is unchanged", path.display());
+                return Ok(());
+            }
+            match read_device(&usb_device.handle, &tablet, 100) {
+                Ok((_, bytes)) => {
+                    if let Some(report) = PenReport::parse(&bytes)
+                        && calibration.feed(&report)
+                    {
+                        break;
                     }
-                    interfaces.insert(
-                        number,
-                        InterfaceInfo {
-                            number,
-                            endpoints_in,
-                            endpoints_out,
-                        },
-                    );
                 }
+                Err(rusb::Error::Timeout) => {}
+                Err(e) => return Err(e.into()),
             }
+        }
+    }
 
-            return Ok(Some(USBDevice {
-                device: device,
-                handle: handle,
-                interfaces,
-            }));
+    if args.affine {
+        let matrix = calibration.matrix()?;
+        config::save_mapping(&path, "matrix", &matrix)?;
+        println!("Saved the matrix {:?} to {}", matrix.0, path.display());
+    } else {
+        let area = calibration
+            .area()
+            .context("The taps do not span an area, run calibrate again")?;
+        config::save_mapping(&path, "area", &area)?;
+        println!("Saved {area:?} to {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Shows the raw and the curved pressure of each pen report until Ctrl+C,
+/// then the extremes seen, without creating the uinput device.
+fn pressure_test(config: &Config) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))
+        .context("Failed to install the Ctrl+C handler")?;
+
+    let device = &config.device;
+    let mut context = Context::new()?;
+    let (usb_device, skipped) = open_tablet(&mut context, device)?;
+    let (tablet, _) = read_interfaces(&usb_device, device, &skipped)?
+        .into_iter()
+        .find(|(interface, _)| interface.number == device.tablet_interface)
+        .context("The pressure test needs the tablet interface")?;
+    config
+        .init
+        .check_interfaces(|number| usb_device.interface(number).is_some())?;
+    device.identity.guard(&DeviceTraits::read(&usb_device))?;
+    initialize(&usb_device.handle, config).context("Failed to initialize the tablet")?;
+
+    let mut test = pressure_test::PressureTest::new(config.pressure.pipeline()?);
+    println!("Press the pen on the tablet, Ctrl+C to stop");
+    while running.load(Ordering::SeqCst) {
+        match read_device(&usb_device.handle, &tablet, 100) {
+            Ok((_, bytes)) => {
+                if let Some(report) = PenReport::parse(&bytes) {
+                    print!("\r{}", test.line(&report));
+                    io::stdout().flush()?;
+                }
+            }
+            Err(rusb::Error::Timeout) => {}
+            Err(e) => return Err(e.into()),
         }
     }
+    println!("\n{}", test.extremes.summary());
+
+    Ok(())
+}
+
+/// With `device.detect_interfaces`, the buttons and tablet interfaces among
+/// the claimed ones when their descriptors tell them apart and they differ
+/// from the configured ones.
+fn detect(
+    usb_device: &USBDevice<Context>,
+    device: &DeviceConfig,
+    skipped: &[u8],
+) -> Option<(u8, u8)> {
+    if !device.detect_interfaces {
+        return None;
+    }
 
-    Ok(None)
+    let claimed: Vec<u8> = device
+        .claimed_interfaces()
+        .into_iter()
+        .filter(|number| !skipped.contains(number))
+        .collect();
+    let configured = (device.buttons_interface, device.tablet_interface);
+    match detect_interfaces(&usb_device.handle, &claimed) {
+        Some(found) if found != configured => {
+            info!(
+                "Reading the buttons from interface {} and the pen from {}, going by their descriptors",
+                found.0, found.1
+            );
+            Some(found)
+        }
+        Some(_) => None,
+        None => {
+            info!(
+                "The descriptors do not tell the interfaces apart, using buttons {} and tablet {}",
+                configured.0, configured.1
+            );
+            None
+        }
+    }
 }
 
-fn claim_interfaces<T: UsbContext>(handle: &mut DeviceHandle<T>, interfaces: &[u8]) -> Result<()> {
-    for num in interfaces {
-        if handle.kernel_driver_active(*num)? {
-            handle.detach_kernel_driver(*num)?;
+/// The interfaces read, each with its read timeout in ms.
+type ReadInterfaces = Vec<(InterfaceInfo, u64)>;
+
+/// The buttons and tablet interfaces that were not `skipped` when claiming,
+/// checked to be readable, see `health_check`, and with the endpoint and
+/// read size from the config applied. Read timeouts are raised to the
+/// polling interval.
+fn read_interfaces(
+    usb_device: &USBDevice<Context>,
+    device: &DeviceConfig,
+    skipped: &[u8],
+) -> Result<ReadInterfaces> {
+    let configure = |what, number, endpoint: Option<u8>, read_size| -> Result<InterfaceInfo> {
+        let mut interface = usb_device
+            .interface(number)
+            .cloned()
+            .with_context(|| format!("Interface {number} was not found"))?;
+        interface.ensure_readable()?;
+        if let Some(address) = endpoint {
+            interface.prefer_in(address)?;
+        }
+        for &address in &device.skip_endpoints {
+            if interface.endpoints_in.contains(&address) && !interface.skip_in(address) {
+                warn!("Not skipping IN endpoint {address:#04x}, interface {number} has no other");
+            }
+        }
+        interface.check_transfer_types()?;
+        for (address, declared) in interface.mismatched_endpoints() {
+            warn!(
+                "IN endpoint {address:#04x} of interface {number} is declared {declared:?}, \
+                 its interrupt reads fail: set `device.{what}_endpoint` to an interrupt one"
+            );
+        }
+        interface.read_size = read_size;
+        if device.health_check_ms > 0 {
+            health_check(
+                &usb_device.handle,
+                &interface,
+                Duration::from_millis(device.health_check_ms),
+            )?;
         }
-        handle.claim_interface(*num)?;
+        Ok(interface)
+    };
+
+    let mut read = vec![];
+    let [buttons, tablet] = device.read_interfaces();
+    let [buttons_timeout, tablet_timeout] = device.read_timeouts_ms();
+    for (what, number, endpoint, read_size, timeout) in [
+        (
+            "buttons",
+            buttons,
+            device.buttons_endpoint,
+            device.buttons_read_size,
+            buttons_timeout,
+        ),
+        (
+            "tablet",
+            tablet,
+            device.tablet_endpoint,
+            device.tablet_read_size,
+            tablet_timeout,
+        ),
+    ] {
+        if skipped.contains(&number) {
+            warn!("The {what} interface {number} could not be claimed, it is not read");
+            continue;
+        }
+        let interface = configure(what, number, endpoint, read_size)?;
+        if let Some(rate) = interface.report_rate() {
+            info!("The {what} interface {number} is polled up to {rate:.0} times a second");
+        }
+        let timeout = interface.read_timeout(timeout);
+        read.push((interface, timeout));
+    }
+    if read.is_empty() {
+        bail!("Neither the buttons nor the tablet interface could be claimed");
+    }
+
+    Ok(read)
+}
+
+/// Prints the bindings of the `xsetwacom` script at `path` as a config.
+fn import_bindings(path: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let imported = import::Imported::parse(&text);
+    for (line, reason) in &imported.untranslated {
+        warn!("{}:{line} not translated: {reason}", path.display());
     }
+    print!("{}", imported.to_text()?);
 
     Ok(())
 }
 
-fn send_to_device<T: UsbContext>(
-    handle: &mut DeviceHandle<T>,
-    message: &MessageDevice,
-) -> Result<()> {
-    handle.write_control(
-        message.request_type,
-        message.request,
-        message.value,
-        message.interface,
-        &message.payload,
-        message.timeout,
+/// Writes the descriptors, the effective config, the environment and a few
+/// seconds of reports to `path` for a bug report. Whatever fails is noted
+/// in the file instead, a tablet that does not open still gets described.
+fn diagnostics(args: &Args, config: &Config, path: &Path) -> Result<()> {
+    let device = &config.device;
+    let mut bundle = diagnostics::Bundle::default();
+    bundle.section("Environment", &diagnostics::environment());
+    bundle.section(
+        "Config",
+        &config
+            .to_text()
+            .unwrap_or_else(|e| format!("Failed to serialize: {e}")),
+    );
+
+    let mut context = Context::new()?;
+    match find_device(&mut context, device.vendor_id, device.product_id)? {
+        Some(found) => bundle.section(
+            "Device",
+            &diagnostics::describe_device(&found, args.include_serial),
+        ),
+        None => bundle.section(
+            "Device",
+            &format!(
+                "No device {:04x}:{:04x}",
+                device.vendor_id, device.product_id
+            ),
+        ),
+    }
+
+    let reports = open_and_initialize(&mut context, config)
+        .map(|(usb_device, read)| {
+            println!(
+                "Capturing reports for {} seconds, use the pen and the buttons",
+                diagnostics::CAPTURE.as_secs()
+            );
+            diagnostics::capture(
+                &usb_device.handle,
+                &read
+                    .into_iter()
+                    .map(|(interface, _)| interface)
+                    .collect::<Vec<_>>(),
+                diagnostics::CAPTURE,
+            )
+        })
+        .unwrap_or_else(|e| format!("No capture: {e:#}"));
+    bundle.section("Reports", &reports);
+
+    fs::write(path, bundle.into_text())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote {}, attach it to the issue", path.display());
+
+    Ok(())
+}
+
+/// The device and the interfaces that could not be claimed.
+type Opened = (USBDevice<Context>, Vec<u8>);
+
+/// Opens the configured device, on `device.configuration` if set, and
+/// claims its interfaces, see `device.essential_interfaces`.
+fn open_tablet(context: &mut Context, device: &DeviceConfig) -> Result<Opened> {
+    let claimed = device.claimed_interfaces();
+    let mut usb_device = open_device_with_configuration(
+        context,
+        device.vendor_id,
+        device.product_id,
+        (device.configuration, &claimed),
+    )?
+    .context("Tablet Not Found")?;
+    check_interfaces(&usb_device.interfaces, &device.expected_interfaces())?;
+    let skipped = claim_interfaces_except(
+        &mut usb_device.handle,
+        &claimed,
+        &device.essential_interfaces(),
     )?;
 
+    Ok((usb_device, skipped))
+}
+
+/// Claims the interfaces like a normal run, sends `raw` and logs the answer.
+fn raw_send(device: &DeviceConfig, raw: &RawControl) -> Result<()> {
+    let mut context = Context::new()?;
+    let (usb_device, _) = open_tablet(&mut context, device)?;
+
+    let message = MessageDevice {
+        request_type: raw.request_type,
+        request: raw.request,
+        value: raw.value,
+        interface: raw.index,
+        payload: raw.payload.clone(),
+        timeout: Duration::from_secs(1),
+    };
+    info!("Sending {raw:02X?}");
+    if raw.is_read() {
+        let bytes = usb_device.read_raw_control(&message, raw.length)?;
+        info!("Response ({} bytes): {bytes:02X?}", bytes.len());
+    } else {
+        usb_device.send_raw_control(&message)?;
+        info!("Sent {} bytes", message.payload.len());
+    }
+
     Ok(())
 }
 
-fn read_device<T: UsbContext>(
-    handle: &mut DeviceHandle<T>,
-    interface: &InterfaceInfo,
-    bytes: usize,
-    timeout: u64,
-) -> RusbResult<(u8, Vec<u8>)> {
-    let mut buffer = vec![0; bytes];
-    let mut res = Ok(0);
+/// Why a session of reading the tablet ended.
+enum SessionEnd {
+    /// `running` was cleared or the readers are gone.
+    Stopped,
+    Disconnected,
+    /// A read failed for another reason than a disconnect.
+    ReadFailed,
+}
 
-    for endpoint in &interface.endpoints_in {
-        res = handle.read_interrupt(*endpoint, &mut buffer, Duration::from_millis(timeout));
+/// The open tablet, for the steps of `recovery`.
+struct Recovery<'a> {
+    context: &'a mut Context,
+    config: &'a Config,
+    usb_device: &'a mut USBDevice<Context>,
+    interfaces: &'a mut ReadInterfaces,
+}
+
+impl Recovery<'_> {
+    fn claim_and_initialize(&mut self) -> Result<()> {
+        let device = &self.config.device;
+        claim_interfaces_except(
+            &mut self.usb_device.handle,
+            &device.claimed_interfaces(),
+            &device.essential_interfaces(),
+        )?;
+        initialize(&self.usb_device.handle, self.config)
+            .context("Failed to initialize the tablet")?;
+
+        Ok(())
+    }
 
-        if let Ok(bytes_read) = &res {
-            return Ok((interface.number, buffer[..(*bytes_read)].to_vec()));
+    /// Gives up the interfaces, also the ones that were not claimed.
+    fn release(&self) {
+        for number in self.config.device.claimed_interfaces() {
+            let _ = self.usb_device.handle.release_interface(number);
         }
     }
+}
+
+impl Recover for Recovery<'_> {
+    fn run(&mut self, step: RecoveryStep) -> Result<()> {
+        info!("Trying to recover the tablet by {step:?}");
+        match step {
+            RecoveryStep::SoftReset => {
+                self.release();
+                self.claim_and_initialize()
+            }
+            RecoveryStep::HardReset => {
+                self.usb_device.handle.reset()?;
+                self.claim_and_initialize()
+            }
+            RecoveryStep::Reopen => {
+                // The interfaces stay claimed until the old handle is dropped.
+                self.release();
+                let (usb_device, interfaces) = open_and_initialize(self.context, self.config)?;
+                (*self.usb_device, *self.interfaces) = (usb_device, interfaces);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Waits until `limiter` allows another re-init. False when `running` was
+/// cleared meanwhile.
+fn wait_for_reinit(limiter: &mut ReinitLimiter, running: &AtomicBool) -> bool {
+    while let Err(wait) = limiter.admit(Instant::now()) {
+        if !reconnect::sleep_while_running(wait, running) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Opens, claims, checks and initializes the tablet.
+fn open_and_initialize(
+    context: &mut Context,
+    config: &Config,
+) -> Result<(USBDevice<Context>, ReadInterfaces)> {
+    let device = &config.device;
+    let (usb_device, skipped) = open_tablet(context, device)?;
+    let read = read_interfaces(&usb_device, device, &skipped)?;
+    config
+        .init
+        .check_interfaces(|number| usb_device.interface(number).is_some())?;
+    device.identity.guard(&DeviceTraits::read(&usb_device))?;
+    initialize(&usb_device.handle, config).context("Failed to initialize the tablet")?;
+
+    Ok((usb_device, read))
+}
+
+/// Opens, claims and initializes the tablet again after it disconnected,
+/// waiting out `backoff` and then for the tablet to be connected before
+/// each attempt. `None` when `running` was cleared meanwhile or once
+/// `backoff` gives up.
+fn reconnect(
+    context: &mut Context,
+    config: &Config,
+    running: &AtomicBool,
+    backoff: &mut Backoff,
+) -> Result<Option<(USBDevice<Context>, ReadInterfaces)>> {
+    let device = &config.device;
+    loop {
+        let Some(delay) = backoff.next_delay() else {
+            return Ok(None);
+        };
+        info!("Reconnecting in {delay:?}");
+        if !reconnect::sleep_while_running(delay, running)
+            || !reconnect::wait_for_device(
+                context,
+                device.vendor_id,
+                device.product_id,
+                config.reconnect,
+                running,
+            )?
+        {
+            return Ok(None);
+        }
+
+        match open_and_initialize(context, config) {
+            Ok(connected) => {
+                backoff.reset();
+                return Ok(Some(connected));
+            }
+            Err(e) => warn!("Reconnect attempt {} failed: {e:#}", backoff.attempts()),
+        }
+    }
+}
+
+/// The reads of `interface` for `mode`, the blocking ones when no reads
+/// could be queued.
+fn packet_source<'a, T: UsbContext>(
+    handle: &'a DeviceHandle<T>,
+    interface: &InterfaceInfo,
+    mode: ReadMode,
+) -> Box<dyn PacketSource + 'a> {
+    if mode == ReadMode::Async {
+        match AsyncReader::new(UsbTransfers::new(handle), interface, QUEUED_READS) {
+            Ok(reader) => return Box::new(reader),
+            Err(e) => warn!(
+                "Failed to queue reads on interface {} ({e}), reading it synchronously",
+                interface.number
+            ),
+        }
+    }
+
+    Box::new(BlockingReads(handle))
+}
+
+fn send_step<T: UsbContext>(handle: &DeviceHandle<T>, step: &InitStep) -> Result<(), FtdError> {
+    match step {
+        InitStep::Control(message) => send_to_device(handle, message),
+        InitStep::Interrupt {
+            endpoint,
+            payload,
+            timeout,
+        } => write_to_endpoint(handle, *endpoint, payload, *timeout),
+    }
+}
+
+fn initialize<T: UsbContext>(handle: &DeviceHandle<T>, config: &Config) -> Result<(), FtdError> {
+    let init = &config.init;
+    std::thread::sleep(Duration::from_millis(init.delay_before_ms));
 
-    let bytes_read = res?;
-    Ok((interface.number, buffer[..bytes_read].to_vec()))
+    init.send_set_idle(&config.device.report_interfaces(), |step| {
+        send_step(handle, step)
+    });
+    init.send_confirmed(
+        |step| send_step(handle, step),
+        |verify| read_feature_report(handle, verify.interface, verify.report_id, verify.length),
+    )
 }
@@ -0,0 +1,694 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use ftd_device::report::{DEVICE_MAX_X, DEVICE_MAX_Y};
+
+use crate::screen::{Rect, ScreenProvider};
+
+/// How the tablet is turned relative to the screen, clockwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Half,
+    Ccw90,
+}
+
+/// Part of the tablet surface that is mapped, in device units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Area {
+    pub x_min: u16,
+    pub y_min: u16,
+    pub x_max: u16,
+    pub y_max: u16,
+}
+
+impl Default for Area {
+    fn default() -> Self {
+        Area {
+            x_min: 0,
+            y_min: 0,
+            x_max: DEVICE_MAX_X as u16,
+            y_max: DEVICE_MAX_Y as u16,
+        }
+    }
+}
+
+impl Area {
+    pub fn validate(&self) -> Result<()> {
+        if self.x_min >= self.x_max || self.y_min >= self.y_max {
+            bail!("The active area must have x_min < x_max and y_min < y_max");
+        }
+
+        Ok(())
+    }
+}
+
+/// Device units taken off each side of the mapped area before it is
+/// stretched onto the output, unlike cropping to an `Area` the change is
+/// the same whichever area is in use. For units whose pen cannot reach the
+/// edge of the surface, the last position it reaches then lands on the
+/// screen edge. Negative values extend the range past the area instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Padding {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Padding {
+    /// The `(x_min, x_max)` and `(y_min, y_max)` mapped of `area`.
+    pub fn apply(&self, area: &Area) -> ((i64, i64), (i64, i64)) {
+        (
+            (
+                area.x_min as i64 + self.left as i64,
+                area.x_max as i64 - self.right as i64,
+            ),
+            (
+                area.y_min as i64 + self.top as i64,
+                area.y_max as i64 - self.bottom as i64,
+            ),
+        )
+    }
+
+    pub fn validate(&self, area: &Area) -> Result<()> {
+        let ((x_min, x_max), (y_min, y_max)) = self.apply(area);
+        if x_min >= x_max || y_min >= y_max {
+            bail!("mapping.padding leaves nothing of the area {area:?}");
+        }
+
+        Ok(())
+    }
+}
+
+/// The areas `Action::CycleArea` steps through, starting on the first and
+/// wrapping around after the last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AreaCycle {
+    areas: Vec<Area>,
+    index: usize,
+}
+
+impl AreaCycle {
+    /// `first` is the area in use until the first step.
+    pub fn new(first: Area, rest: &[Area]) -> Result<AreaCycle> {
+        let areas: Vec<Area> = [first].into_iter().chain(rest.iter().copied()).collect();
+        for area in &areas {
+            area.validate()?;
+        }
+
+        Ok(AreaCycle { areas, index: 0 })
+    }
+
+    /// Moves on to the next area and returns its position in the list.
+    pub fn advance(&mut self) -> (usize, Area) {
+        self.index = (self.index + 1) % self.areas.len();
+        (self.index, self.areas[self.index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+}
+
+/// The outputs `Action::CycleOutput` steps through, starting on the one
+/// the mapper targets and wrapping around after the last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputCycle {
+    outputs: Vec<(String, Rect)>,
+    index: usize,
+}
+
+impl OutputCycle {
+    /// `current` is added as `configured` when it is none of `outputs`.
+    pub fn new(mut outputs: Vec<(String, Rect)>, current: Rect) -> OutputCycle {
+        let index = match outputs.iter().position(|(_, rect)| *rect == current) {
+            Some(index) => index,
+            None => {
+                outputs.insert(0, ("configured".to_string(), current));
+                0
+            }
+        };
+
+        OutputCycle { outputs, index }
+    }
+
+    /// Moves on to the next output and returns its name and geometry.
+    pub fn advance(&mut self) -> (&str, Rect) {
+        self.index = (self.index + 1) % self.outputs.len();
+        let (name, rect) = &self.outputs[self.index];
+        (name, *rect)
+    }
+
+    /// The name and geometry of the output mapped onto.
+    pub fn current(&self) -> (&str, Rect) {
+        let (name, rect) = &self.outputs[self.index];
+        (name, *rect)
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+}
+
+/// `[[a, b, c], [d, e, f]]`, taking raw `(x, y)` to
+/// `(a x + b y + c, d x + e y + f)` before the area and rotation apply.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Affine(pub [[f64; 3]; 2]);
+
+impl Default for Affine {
+    fn default() -> Self {
+        Affine([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]])
+    }
+}
+
+impl Affine {
+    /// The matrix taking each point of `from` onto the matching one of `to`.
+    pub fn from_points(from: [(f64, f64); 3], to: [(f64, f64); 3]) -> Result<Affine> {
+        let [(x0, y0), (x1, y1), (x2, y2)] = from;
+        let det = x0 * (y1 - y2) - y0 * (x1 - x2) + (x1 * y2 - x2 * y1);
+        if det.abs() < 1e-6 {
+            bail!("The calibration points are on one line");
+        }
+
+        // Cramer's rule on [x y 1] [a b c]^T = t, once per output axis.
+        let solve = |t0: f64, t1: f64, t2: f64| {
+            [
+                (t0 * (y1 - y2) - y0 * (t1 - t2) + (t1 * y2 - t2 * y1)) / det,
+                (x0 * (t1 - t2) - t0 * (x1 - x2) + (x1 * t2 - x2 * t1)) / det,
+                (x0 * (y1 * t2 - y2 * t1) - y0 * (x1 * t2 - x2 * t1) + t0 * (x1 * y2 - x2 * y1))
+                    / det,
+            ]
+        };
+
+        Ok(Affine([
+            solve(to[0].0, to[1].0, to[2].0),
+            solve(to[0].1, to[1].1, to[2].1),
+        ]))
+    }
+
+    pub fn apply(&self, x: u16, y: u16) -> (u16, u16) {
+        let [[a, b, c], [d, e, f]] = self.0;
+        let (x, y) = (x as f64, y as f64);
+        let clamp = |v: f64| v.round().clamp(0.0, u16::MAX as f64) as u16;
+
+        (clamp(a * x + b * y + c), clamp(d * x + e * y + f))
+    }
+}
+
+/// The rotation and area that map the tablet onto `output` without
+/// stretching either axis: turned a quarter when the tablet, at
+/// `resolution` device units per mm, and the output are held in different
+/// orientations, then cropped to the largest centered part with the
+/// output's aspect ratio.
+pub fn fit(output: Rect, resolution: (i32, i32)) -> (Rotation, Area) {
+    let width_mm = DEVICE_MAX_X as f64 / resolution.0 as f64;
+    let height_mm = DEVICE_MAX_Y as f64 / resolution.1 as f64;
+    let rotation = if (width_mm >= height_mm) == (output.width >= output.height) {
+        Rotation::None
+    } else {
+        Rotation::Cw90
+    };
+
+    // Millimetres of the tablet along the screen axes, cropped.
+    let (across, down) = match rotation {
+        Rotation::None => (width_mm, height_mm),
+        _ => (height_mm, width_mm),
+    };
+    let aspect = output.width as f64 / output.height as f64;
+    let (across, down) = if across / down > aspect {
+        (down * aspect, down)
+    } else {
+        (across, across / aspect)
+    };
+    let (x_mm, y_mm) = match rotation {
+        Rotation::None => (across, down),
+        _ => (down, across),
+    };
+
+    let span = |mm: f64, resolution: i32, max: i32| {
+        let span = ((mm * resolution as f64).round() as i32).min(max);
+        let min = (max - span) / 2;
+        (min as u16, (min + span) as u16)
+    };
+    let (x_min, x_max) = span(x_mm, resolution.0, DEVICE_MAX_X);
+    let (y_min, y_max) = span(y_mm, resolution.1, DEVICE_MAX_Y);
+
+    (
+        rotation,
+        Area {
+            x_min,
+            y_min,
+            x_max,
+            y_max,
+        },
+    )
+}
+
+/// Maps tablet coordinates onto a rectangle of the desktop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateMapper {
+    pub target: Rect,
+    pub desktop: Rect,
+    pub matrix: Affine,
+    pub area: Area,
+    pub padding: Padding,
+    pub rotation: Rotation,
+    /// Positions in `0..=DEVICE_MAX_X/Y` spanning the desktop instead of in
+    /// its pixels, see `bounds`.
+    pub full_resolution: bool,
+    /// Where `clamp` keeps mapped positions, in desktop pixels.
+    pub clamp: Option<Rect>,
+}
+
+impl CoordinateMapper {
+    /// Targets `output` when given and known, the primary output otherwise.
+    pub fn new(screen: &dyn ScreenProvider, output: Option<&str>) -> CoordinateMapper {
+        let target = output
+            .and_then(|name| screen.output_by_name(name))
+            .unwrap_or_else(|| screen.primary_geometry());
+
+        CoordinateMapper {
+            target,
+            desktop: screen.desktop_geometry(),
+            matrix: Affine::default(),
+            area: Area::default(),
+            padding: Padding::default(),
+            rotation: Rotation::default(),
+            full_resolution: false,
+            clamp: None,
+        }
+    }
+
+    /// Identity mapping in device units, for when no screen is configured.
+    pub fn device() -> CoordinateMapper {
+        let rect = Rect::new(0, 0, DEVICE_MAX_X + 1, DEVICE_MAX_Y + 1);
+        CoordinateMapper {
+            target: rect,
+            desktop: rect,
+            matrix: Affine::default(),
+            area: Area::default(),
+            padding: Padding::default(),
+            rotation: Rotation::default(),
+            full_resolution: false,
+            clamp: None,
+        }
+    }
+
+    /// Converts the tablet `resolution` (device units per mm) into output
+    /// units per mm along each screen axis.
+    pub fn resolution(&self, resolution: (i32, i32)) -> (i32, i32) {
+        let width_mm = (self.area.x_max - self.area.x_min) as f64 / resolution.0 as f64;
+        let height_mm = (self.area.y_max - self.area.y_min) as f64 / resolution.1 as f64;
+        let (across, down) = match self.rotation {
+            Rotation::None | Rotation::Half => (width_mm, height_mm),
+            Rotation::Cw90 | Rotation::Ccw90 => (height_mm, width_mm),
+        };
+
+        let (scale_x, scale_y) = self.scale();
+        (
+            ((self.target.width - 1) as f64 * scale_x / across).round() as i32,
+            ((self.target.height - 1) as f64 * scale_y / down).round() as i32,
+        )
+    }
+
+    /// The largest X and Y `map` returns, which the pen axes go up to:
+    /// the desktop in pixels, or with `full_resolution` the tablet's own
+    /// range, for the compositor to scale onto the desktop without
+    /// dropping what falls between two pixels.
+    pub fn bounds(&self) -> (i32, i32) {
+        if self.full_resolution {
+            (DEVICE_MAX_X, DEVICE_MAX_Y)
+        } else {
+            (self.desktop.width - 1, self.desktop.height - 1)
+        }
+    }
+
+    /// Output units per desktop pixel.
+    fn scale(&self) -> (f64, f64) {
+        let (max_x, max_y) = self.bounds();
+        (
+            max_x as f64 / (self.desktop.width - 1).max(1) as f64,
+            max_y as f64 / (self.desktop.height - 1).max(1) as f64,
+        )
+    }
+
+    /// Moves a position `map` returned into the `clamp` region, the last
+    /// step before it is sent.
+    pub fn clamp(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        let Some(region) = self.clamp else {
+            return (x, y);
+        };
+        let (scale_x, scale_y) = self.scale();
+        let left = (region.x - self.desktop.x) as f64;
+        let top = (region.y - self.desktop.y) as f64;
+        let (max_x, max_y) = self.bounds();
+        let span = |from: f64, length: i32, scale: f64, max: i32| {
+            let low = (from * scale).round() as i32;
+            let high = ((from + (length - 1) as f64) * scale).round() as i32;
+            (low.clamp(0, max), high.clamp(0, max))
+        };
+        let (x_min, x_max) = span(left, region.width, scale_x, max_x);
+        let (y_min, y_max) = span(top, region.height, scale_y, max_y);
+
+        (x.clamp(x_min, x_max), y.clamp(y_min, y_max))
+    }
+
+    /// Returns the position relative to the desktop origin.
+    pub fn map(&self, x: u16, y: u16) -> (i32, i32) {
+        let (x, y) = self.matrix.apply(x, y);
+        let ((x_min, x_max), (y_min, y_max)) = self.padding.apply(&self.area);
+        let span_x = (x_max - x_min).max(1);
+        let span_y = (y_max - y_min).max(1);
+        let u = (x as i64).min(x_max).max(x_min) - x_min;
+        let v = (y as i64).min(y_max).max(y_min) - y_min;
+
+        // Position along each screen axis as a fraction of the matching tablet span.
+        let ((x, width), (y, height)) = match self.rotation {
+            Rotation::None => ((u, span_x), (v, span_y)),
+            Rotation::Cw90 => ((span_y - v, span_y), (u, span_x)),
+            Rotation::Half => ((span_x - u, span_x), (span_y - v, span_y)),
+            Rotation::Ccw90 => ((v, span_y), (span_x - u, span_x)),
+        };
+        // Pixels times `width` or `height`, divided last to keep the
+        // fraction of a pixel for `full_resolution`.
+        let x =
+            (self.target.x - self.desktop.x) as i64 * width + x * (self.target.width - 1) as i64;
+        let y =
+            (self.target.y - self.desktop.y) as i64 * height + y * (self.target.height - 1) as i64;
+        if !self.full_resolution {
+            return ((x / width) as i32, (y / height) as i32);
+        }
+
+        let (max_x, max_y) = self.bounds();
+        (
+            (x * max_x as i64 / (width * (self.desktop.width - 1).max(1) as i64)) as i32,
+            (y * max_y as i64 / (height * (self.desktop.height - 1).max(1) as i64)) as i32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::FixedScreen;
+    use evdev_rs::enums::EV_ABS;
+    use ftd_device::report::DEVICE_RESOLUTION;
+
+    #[test]
+    fn full_resolution_keeps_the_tablet_range_on_the_axes() {
+        let screen = FixedScreen {
+            rect: Rect::new(0, 0, 1920, 1080),
+        };
+        let mapper = CoordinateMapper {
+            full_resolution: true,
+            ..CoordinateMapper::new(&screen, None)
+        };
+        let state = crate::emitter::EmitterState::new(vec![], false, mapper.bounds());
+        let max = |wanted: EV_ABS| {
+            state
+                .abs_axes()
+                .into_iter()
+                .find(|(axis, _, _)| *axis == wanted)
+                .map(|(_, max, _)| max)
+        };
+
+        assert_eq!(max(EV_ABS::ABS_X), Some(DEVICE_MAX_X));
+        assert_eq!(max(EV_ABS::ABS_Y), Some(DEVICE_MAX_Y));
+        assert_eq!(
+            mapper.map(DEVICE_MAX_X as u16, DEVICE_MAX_Y as u16),
+            (DEVICE_MAX_X, DEVICE_MAX_Y)
+        );
+        // Two reports within one pixel still land apart.
+        assert_ne!(mapper.map(16000, 9000), mapper.map(16005, 9000));
+        let pixels = CoordinateMapper::new(&screen, None);
+        assert_eq!(pixels.map(16000, 9000), pixels.map(16005, 9000));
+    }
+
+    #[test]
+    fn the_clamp_region_holds_the_cursor_whatever_the_input() {
+        let screen = FixedScreen {
+            rect: Rect::new(0, 0, 1920, 1080),
+        };
+        let region = Rect::new(200, 100, 800, 600);
+        let inputs = [
+            (0, 0),
+            (DEVICE_MAX_X as u16, 0),
+            (DEVICE_MAX_X as u16, DEVICE_MAX_Y as u16),
+            (16000, 9000),
+        ];
+        for full_resolution in [false, true] {
+            let mapper = CoordinateMapper {
+                full_resolution,
+                clamp: Some(region),
+                ..CoordinateMapper::new(&screen, None)
+            };
+            let (max_x, max_y) = mapper.bounds();
+            let pixel = |(x, y): (i32, i32)| {
+                (
+                    (x as f64 * 1919.0 / max_x as f64).round() as i32,
+                    (y as f64 * 1079.0 / max_y as f64).round() as i32,
+                )
+            };
+
+            let clamped: Vec<_> = inputs
+                .iter()
+                .map(|&(x, y)| pixel(mapper.clamp(mapper.map(x, y))))
+                .collect();
+            // The last one is inside already and stays where it is.
+            assert_eq!(
+                clamped,
+                vec![(200, 100), (999, 100), (999, 699), (937, 296)]
+            );
+        }
+
+        // Past the end of a smaller area maps to the edge of the screen,
+        // the clamp still holds it inside the region.
+        let mapper = CoordinateMapper {
+            area: Area {
+                x_min: 0,
+                y_min: 0,
+                x_max: DEVICE_MAX_X as u16 / 2,
+                y_max: DEVICE_MAX_Y as u16 / 2,
+            },
+            clamp: Some(region),
+            ..CoordinateMapper::new(&screen, None)
+        };
+        assert_eq!(mapper.clamp(mapper.map(20000, 20000)), (999, 699));
+    }
+
+    #[test]
+    fn maps_the_tablet_onto_a_fixed_screen() {
+        let screen = FixedScreen {
+            rect: Rect::new(0, 0, 1920, 1080),
+        };
+        let mapper = CoordinateMapper::new(&screen, None);
+
+        assert_eq!(mapper.map(0, 0), (0, 0));
+        assert_eq!(
+            mapper.map(DEVICE_MAX_X as u16, DEVICE_MAX_Y as u16),
+            (1919, 1079)
+        );
+        assert_eq!(
+            mapper.map(DEVICE_MAX_X as u16 / 2 + 1, DEVICE_MAX_Y as u16 / 2 + 1),
+            (959, 539)
+        );
+    }
+
+    #[test]
+    fn crops_to_the_area_and_rotates() {
+        let screen = FixedScreen {
+            rect: Rect::new(0, 0, 1001, 501),
+        };
+        let mut mapper = CoordinateMapper::new(&screen, None);
+        mapper.area = Area {
+            x_min: 1000,
+            y_min: 2000,
+            x_max: 2000,
+            y_max: 3000,
+        };
+
+        assert_eq!(mapper.map(0, 0), (0, 0));
+        assert_eq!(mapper.map(1500, 2500), (500, 250));
+        assert_eq!(mapper.map(9000, 9000), (1000, 500));
+
+        mapper.rotation = Rotation::Cw90;
+        assert_eq!(mapper.map(1000, 2000), (1000, 0));
+        assert_eq!(mapper.map(2000, 2000), (1000, 500));
+
+        mapper.rotation = Rotation::Half;
+        assert_eq!(mapper.map(1000, 2000), (1000, 500));
+    }
+
+    #[test]
+    fn padding_lets_the_reachable_edge_reach_the_screen_edge() {
+        let screen = FixedScreen {
+            rect: Rect::new(0, 0, 1001, 501),
+        };
+        let mut mapper = CoordinateMapper::new(&screen, None);
+        mapper.area = Area {
+            x_min: 0,
+            y_min: 0,
+            x_max: 1200,
+            y_max: 700,
+        };
+        mapper.padding = Padding {
+            left: 100,
+            top: 100,
+            right: 100,
+            bottom: 100,
+        };
+
+        assert_eq!(mapper.map(100, 100), (0, 0));
+        assert_eq!(mapper.map(1100, 600), (1000, 500));
+        assert_eq!(mapper.map(20, 650), (0, 500));
+        mapper.rotation = Rotation::Half;
+        assert_eq!(mapper.map(100, 100), (1000, 500));
+
+        mapper.rotation = Rotation::None;
+        mapper.padding = Padding {
+            left: -100,
+            right: -100,
+            ..Padding::default()
+        };
+        assert_eq!(mapper.map(0, 0), (71, 0));
+        assert_eq!(mapper.map(1200, 700), (928, 500));
+        assert!(mapper.padding.validate(&mapper.area).is_ok());
+        mapper.padding.left = 1300;
+        assert!(mapper.padding.validate(&mapper.area).is_err());
+    }
+
+    #[test]
+    fn applies_the_matrix_before_mapping() {
+        let mut mapper = CoordinateMapper::device();
+        assert_eq!(mapper.map(1000, 2000), (1000, 2000));
+        assert_eq!(mapper.map(0, 32767), (0, 32767));
+
+        // A quarter turn at half scale, moved back onto the surface.
+        mapper.matrix = Affine([[0.0, -0.5, 20000.0], [0.5, 0.0, 0.0]]);
+        assert_eq!(mapper.map(1000, 2000), (19000, 500));
+        assert_eq!(mapper.map(0, 0), (20000, 0));
+        assert_eq!(mapper.map(0, 60000), (0, 0));
+    }
+
+    #[test]
+    fn three_points_give_back_their_matrix() {
+        let from = [(1000.0, 2000.0), (3000.0, 2000.0), (1000.0, 6000.0)];
+        let to = [(19000.0, 500.0), (19000.0, 1500.0), (17000.0, 500.0)];
+        let matrix = Affine::from_points(from, to).unwrap();
+
+        for (value, expected) in matrix
+            .0
+            .iter()
+            .flatten()
+            .zip([0.0, -0.5, 20000.0, 0.5, 0.0, 0.0])
+        {
+            assert!((value - expected).abs() < 1e-9, "{:?}", matrix);
+        }
+        assert!(Affine::from_points([(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)], to).is_err());
+    }
+
+    #[test]
+    fn cycles_through_the_areas_and_wraps() {
+        let crop = Area {
+            x_min: 8000,
+            y_min: 8000,
+            x_max: 24000,
+            y_max: 24000,
+        };
+        let left = Area {
+            x_max: 16000,
+            ..Area::default()
+        };
+        let mut cycle = AreaCycle::new(Area::default(), &[crop, left]).unwrap();
+
+        assert_eq!(cycle.advance(), (1, crop));
+        assert_eq!(cycle.advance(), (2, left));
+        assert_eq!(cycle.advance(), (0, Area::default()));
+        assert_eq!(cycle.len(), 3);
+        assert!(
+            AreaCycle::new(
+                Area::default(),
+                &[Area {
+                    x_min: 100,
+                    x_max: 100,
+                    ..Area::default()
+                }]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn cycles_through_the_outputs_from_the_current_one() {
+        let left = Rect::new(0, 360, 1920, 1080);
+        let right = Rect::new(1920, 0, 2560, 1440);
+        let outputs = vec![("eDP-1".to_string(), left), ("HDMI-1".to_string(), right)];
+
+        let mut cycle = OutputCycle::new(outputs.clone(), right);
+        assert_eq!(cycle.advance(), ("eDP-1", left));
+        assert_eq!(cycle.advance(), ("HDMI-1", right));
+
+        let custom = Rect::new(0, 0, 800, 600);
+        let mut cycle = OutputCycle::new(outputs, custom);
+        assert_eq!(cycle.len(), 3);
+        assert_eq!(cycle.advance(), ("eDP-1", left));
+        assert_eq!(cycle.advance().1, right);
+        assert_eq!(cycle.advance(), ("configured", custom));
+    }
+
+    #[test]
+    fn fits_without_stretching_and_turns_to_match_the_output() {
+        let landscape = Rect::new(0, 0, 1920, 1080);
+        let portrait = Rect::new(0, 0, 1080, 1920);
+        // Screen pixels per mm along each screen axis once fitted.
+        let scale = |output: Rect, resolution: (i32, i32)| {
+            let (rotation, area) = fit(output, resolution);
+            let width_mm = (area.x_max - area.x_min) as f64 / resolution.0 as f64;
+            let height_mm = (area.y_max - area.y_min) as f64 / resolution.1 as f64;
+            let (across, down) = match rotation {
+                Rotation::None => (width_mm, height_mm),
+                _ => (height_mm, width_mm),
+            };
+            (
+                rotation,
+                output.width as f64 / across,
+                output.height as f64 / down,
+            )
+        };
+
+        // A tablet twice as tall as wide, held upright next to a landscape screen.
+        let upright = (DEVICE_MAX_X / 100, DEVICE_MAX_Y / 200);
+        let (rotation, x, y) = scale(landscape, upright);
+        assert_eq!(rotation, Rotation::Cw90);
+        assert!((x / y - 1.0).abs() < 0.01, "{x} {y}");
+        assert_eq!(fit(landscape, upright).1.x_max, DEVICE_MAX_X as u16);
+
+        // The built-in landscape tablet on a portrait screen.
+        let (rotation, x, y) = scale(portrait, DEVICE_RESOLUTION);
+        assert_eq!(rotation, Rotation::Cw90);
+        assert!((x / y - 1.0).abs() < 0.01, "{x} {y}");
+
+        let (rotation, area) = fit(landscape, DEVICE_RESOLUTION);
+        assert_eq!(rotation, Rotation::None);
+        assert_eq!(area.x_min, DEVICE_MAX_X as u16 - area.x_max);
+    }
+
+    #[test]
+    fn unknown_output_falls_back_to_primary() {
+        let screen = FixedScreen {
+            rect: Rect::new(0, 0, 800, 600),
+        };
+
+        assert_eq!(
+            CoordinateMapper::new(&screen, Some("HDMI-1")).target,
+            Rect::new(0, 0, 800, 600)
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use evdev_rs::{
+    InputEvent,
+    enums::{EV_SYN, EventCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::emitter::EventSink;
+
+/// Whether several tablets drive one virtual device or one each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TabletSinks {
+    /// A virtual device per tablet, so each keeps its own cursor.
+    #[default]
+    PerDevice,
+    /// One virtual device all of them write to, one cursor for every pen.
+    Merged,
+}
+
+/// A sink written to by the emitters of several tablets, each on its own
+/// reader thread, through a `MergedSink` apiece.
+pub struct SharedSink<S: EventSink> {
+    sink: Arc<Mutex<S>>,
+}
+
+impl<S: EventSink> SharedSink<S> {
+    pub fn new(sink: S) -> SharedSink<S> {
+        SharedSink {
+            sink: Arc::new(Mutex::new(sink)),
+        }
+    }
+
+    /// The sink one more tablet writes through.
+    pub fn handle(&self) -> MergedSink<S> {
+        MergedSink {
+            sink: self.sink.clone(),
+            frame: vec![],
+        }
+    }
+}
+
+/// Holds the events of a frame back until its `SYN_REPORT`, then writes the
+/// whole frame to the shared sink under one lock, so the frames of two
+/// tablets never interleave.
+pub struct MergedSink<S: EventSink> {
+    sink: Arc<Mutex<S>>,
+    frame: Vec<InputEvent>,
+}
+
+impl<S: EventSink> EventSink for MergedSink<S> {
+    fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+        self.frame.push(event.clone());
+        if event.event_code != EventCode::EV_SYN(EV_SYN::SYN_REPORT) {
+            return Ok(());
+        }
+
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        for event in self.frame.drain(..) {
+            sink.write(&event)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Barrier, thread};
+
+    use evdev_rs::enums::EV_ABS;
+    use ftd_device::report::{Event, PenReport};
+
+    use super::*;
+    use crate::{
+        action::default_button_map,
+        emitter::{Emitter, EmitterState, RecordingSink},
+    };
+
+    /// Gives the other thread a chance between any two events.
+    struct Yielding(RecordingSink);
+
+    impl EventSink for Yielding {
+        fn write(&mut self, event: &InputEvent) -> io::Result<()> {
+            self.0.write(event)?;
+            thread::yield_now();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn two_tablets_reach_a_merged_sink_as_whole_frames() {
+        let recording = RecordingSink::default();
+        let shared = SharedSink::new(Yielding(recording.clone()));
+        let start = Barrier::new(2);
+        // Each tablet moves diagonally with Y a fixed step from X, so every
+        // frame carries both and tells which tablet it came from.
+        thread::scope(|scope| {
+            for step in [100, 200] {
+                let (sink, start) = (shared.handle(), &start);
+                scope.spawn(move || {
+                    let state = EmitterState::new(default_button_map(), true, (0x7FFF, 0x7FFF));
+                    let mut emitter = Emitter::with_sink(state, sink);
+                    start.wait();
+                    for x in 1..=1000 {
+                        let pen = PenReport {
+                            x,
+                            y: x + step,
+                            pressure: 0,
+                            in_range: true,
+                            tip: false,
+                        };
+                        emitter.emit(&Event::Pen(pen)).unwrap();
+                    }
+                });
+            }
+        });
+
+        let syn = EventCode::EV_SYN(EV_SYN::SYN_REPORT);
+        let events = recording.take();
+        assert_eq!(events.last().map(|(code, _)| *code), Some(syn));
+        let mut last_x = [0, 0];
+        for frame in events
+            .split(|(code, _)| *code == syn)
+            .filter(|frame| !frame.is_empty())
+        {
+            let values = |axis| {
+                frame
+                    .iter()
+                    .filter(|(code, _)| *code == EventCode::EV_ABS(axis))
+                    .map(|(_, value)| *value)
+                    .collect::<Vec<_>>()
+            };
+            let (x, y) = (values(EV_ABS::ABS_X), values(EV_ABS::ABS_Y));
+            if x.is_empty() && y.is_empty() {
+                // The pen leaving range as an emitter is dropped.
+                continue;
+            }
+            assert!(x.len() == 1 && y.len() == 1, "{frame:?}");
+            let tablet = match y[0] - x[0] {
+                100 => 0,
+                200 => 1,
+                _ => panic!("mixed frame {frame:?}"),
+            };
+            assert_eq!(x[0], last_x[tablet] + 1, "{frame:?}");
+            last_x[tablet] = x[0];
+        }
+        assert_eq!(last_x, [1000, 1000]);
+    }
+}
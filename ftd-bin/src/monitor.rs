@@ -0,0 +1,156 @@
+use std::io::{self, Write};
+
+use ftd_device::report::{BUTTON_COUNT, BUTTON_NAMES, DEVICE_MAX_PRESSURE, Event, PenReport};
+
+const SURFACE_WIDTH: usize = 48;
+const SURFACE_HEIGHT: usize = 16;
+const BAR_WIDTH: usize = 40;
+
+/// Live terminal view of the pen and buttons for `monitor`.
+pub struct Monitor {
+    pen_bounds: (i32, i32),
+    pen: Option<PenReport>,
+    pressed: [bool; BUTTON_COUNT],
+}
+
+impl Monitor {
+    /// `pen_bounds` is the largest X/Y the pen events carry.
+    pub fn new(pen_bounds: (i32, i32)) -> Monitor {
+        Monitor {
+            pen_bounds,
+            pen: None,
+            pressed: [false; BUTTON_COUNT],
+        }
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        match event {
+            Event::Pen(report) if report.in_range => self.pen = Some(*report),
+            Event::Pen(_) => self.pen = None,
+            Event::Button(button) => {
+                if let Some(pressed) = self.pressed.get_mut(button.index as usize) {
+                    *pressed = button.pressed;
+                }
+            }
+            Event::Touch(_) | Event::Raw(_) => {}
+        }
+    }
+
+    /// The whole view, one frame of plain text plus reverse-video buttons.
+    pub fn render(&self) -> String {
+        let dot = self.pen.map(|pen| {
+            let column = pen.x as usize * (SURFACE_WIDTH - 1) / self.pen_bounds.0.max(1) as usize;
+            let row = pen.y as usize * (SURFACE_HEIGHT - 1) / self.pen_bounds.1.max(1) as usize;
+            (
+                column.min(SURFACE_WIDTH - 1),
+                row.min(SURFACE_HEIGHT - 1),
+                if pen.tip { '@' } else { 'o' },
+            )
+        });
+
+        let mut out = String::new();
+        out.push_str(&format!("+{}+\n", "-".repeat(SURFACE_WIDTH)));
+        for row in 0..SURFACE_HEIGHT {
+            out.push('|');
+            for column in 0..SURFACE_WIDTH {
+                match dot {
+                    Some((x, y, mark)) if x == column && y == row => out.push(mark),
+                    _ => out.push(' '),
+                }
+            }
+            out.push_str("|\n");
+        }
+        out.push_str(&format!("+{}+\n", "-".repeat(SURFACE_WIDTH)));
+
+        let (position, pressure) = match self.pen {
+            Some(pen) => (format!("x {:5}  y {:5}", pen.x, pen.y), pen.pressure),
+            None => ("out of range".to_string(), 0),
+        };
+        let filled = pressure as usize * BAR_WIDTH / DEVICE_MAX_PRESSURE as usize;
+        out.push_str(&format!("{position}\n"));
+        out.push_str(&format!(
+            "pressure [{}{}] {pressure:5}\n",
+            "#".repeat(filled),
+            ".".repeat(BAR_WIDTH - filled)
+        ));
+
+        let buttons: Vec<String> = BUTTON_NAMES
+            .iter()
+            .zip(self.pressed)
+            .map(|(name, pressed)| {
+                if pressed {
+                    format!("\x1b[7m{name}\x1b[0m")
+                } else {
+                    name.to_string()
+                }
+            })
+            .collect();
+        out.push_str(&buttons.join(" "));
+        out.push('\n');
+
+        out
+    }
+
+    /// Redraws in place: cursor home, frame, clear what is below.
+    pub fn draw(&self) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "\x1b[H{}\x1b[J", self.render())?;
+        stdout.flush()
+    }
+
+    /// Switches to the alternate screen and hides the cursor.
+    pub fn enter() -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()
+    }
+
+    pub fn leave() -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "\x1b[?25h\x1b[?1049l")?;
+        stdout.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftd_device::report::ButtonEvent;
+
+    #[test]
+    fn draws_the_pen_pressure_and_pressed_buttons() {
+        let mut monitor = Monitor::new((1000, 1000));
+        monitor.update(&Event::Pen(PenReport {
+            x: 1000,
+            y: 0,
+            pressure: (DEVICE_MAX_PRESSURE as u16).div_ceil(2),
+            in_range: true,
+            tip: true,
+        }));
+        monitor.update(&Event::Button(ButtonEvent {
+            index: 11,
+            pressed: true,
+        }));
+
+        let view = monitor.render();
+        let lines: Vec<&str> = view.lines().collect();
+
+        assert!(lines[1].ends_with("@|"));
+        assert!(lines[SURFACE_HEIGHT + 3].contains(&format!(
+            "[{}{}]",
+            "#".repeat(20),
+            ".".repeat(20)
+        )));
+        assert!(view.contains("\x1b[7mB\x1b[0m"));
+        assert!(!view.contains("\x1b[7mE\x1b[0m"));
+
+        monitor.update(&Event::Pen(PenReport {
+            x: 0,
+            y: 0,
+            pressure: 0,
+            in_range: false,
+            tip: false,
+        }));
+        assert!(monitor.render().contains("out of range"));
+    }
+}
@@ -0,0 +1,257 @@
+use std::io;
+
+use anyhow::{Result, bail};
+use evdev_rs::{
+    DeviceWrapper, InputEvent, TimeVal, UInputDevice, UninitDevice,
+    enums::{EV_KEY, EV_REL, EV_SYN, EventCode},
+};
+use serde::{Deserialize, Serialize};
+
+use ftd_device::report::PenReport;
+
+use crate::emitter::EventSink;
+
+pub const MOUSE_NAME: &str = "FreeTomate Mouse";
+
+/// Which virtual device the pen moves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PenTarget {
+    /// The absolute tablet, for drawing.
+    #[default]
+    Tablet,
+    /// The relative mouse of `[mouse]`, for getting around the desktop.
+    Mouse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseConfig {
+    /// Create a relative mouse next to the tablet that the pen can be
+    /// switched to, with `toggle_mouse` or a profile's `pen`.
+    pub enabled: bool,
+    pub name: String,
+    /// Mouse units per pixel the mapped pen moves.
+    pub sensitivity: f32,
+    /// Where the pen goes at startup, profiles without `pen` use it too.
+    pub pen: PenTarget,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        MouseConfig {
+            enabled: false,
+            name: MOUSE_NAME.to_string(),
+            sensitivity: 1.0,
+            pen: PenTarget::Tablet,
+        }
+    }
+}
+
+impl MouseConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.sensitivity > 0.0 && self.sensitivity.is_finite()) {
+            bail!(
+                "mouse.sensitivity ({}) must be a positive number",
+                self.sensitivity
+            );
+        }
+        if !self.enabled && self.pen == PenTarget::Mouse {
+            bail!("mouse.pen is `mouse` but mouse.enabled is off");
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns the mapped pen into relative motion: the pointer follows the pen
+/// while it is in range, lifting it away moves nothing, like picking up a
+/// mouse. The tip clicks the left button.
+pub struct RelativeMouse {
+    sensitivity: f32,
+    last: Option<(i32, i32)>,
+    /// What rounding left over, so slow motion still adds up.
+    remainder: (f32, f32),
+    left: bool,
+}
+
+impl RelativeMouse {
+    pub fn new(sensitivity: f32) -> RelativeMouse {
+        RelativeMouse {
+            sensitivity,
+            last: None,
+            remainder: (0.0, 0.0),
+            left: false,
+        }
+    }
+
+    /// One synced frame for `report`, empty when nothing changed.
+    pub fn frame(&mut self, report: &PenReport) -> Vec<InputEvent> {
+        let mut frame = vec![];
+        let position = (report.x as i32, report.y as i32);
+        if !report.in_range {
+            self.release(&mut frame);
+        } else {
+            if let Some(last) = self.last.replace(position) {
+                let dx = self.step(position.0 - last.0, 0);
+                let dy = self.step(position.1 - last.1, 1);
+                if dx != 0 {
+                    frame.push(rel_event(EV_REL::REL_X, dx));
+                }
+                if dy != 0 {
+                    frame.push(rel_event(EV_REL::REL_Y, dy));
+                }
+            }
+            self.set_left(report.tip, &mut frame);
+        }
+
+        if !frame.is_empty() {
+            frame.push(input_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0));
+        }
+        frame
+    }
+
+    /// Releases the button and forgets the pen, for when it goes elsewhere.
+    pub fn release_all(&mut self) -> Vec<InputEvent> {
+        let mut frame = vec![];
+        self.release(&mut frame);
+        if !frame.is_empty() {
+            frame.push(input_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0));
+        }
+        frame
+    }
+
+    fn release(&mut self, frame: &mut Vec<InputEvent>) {
+        self.last = None;
+        self.remainder = (0.0, 0.0);
+        self.set_left(false, frame);
+    }
+
+    fn step(&mut self, delta: i32, axis: usize) -> i32 {
+        let remainder = if axis == 0 {
+            &mut self.remainder.0
+        } else {
+            &mut self.remainder.1
+        };
+        let exact = delta as f32 * self.sensitivity + *remainder;
+        let whole = exact.trunc();
+        *remainder = exact - whole;
+        whole as i32
+    }
+
+    fn set_left(&mut self, down: bool, frame: &mut Vec<InputEvent>) {
+        if std::mem::replace(&mut self.left, down) != down {
+            frame.push(input_event(
+                EventCode::EV_KEY(EV_KEY::BTN_LEFT),
+                down as i32,
+            ));
+        }
+    }
+}
+
+/// The virtual mouse of `[mouse]`, written to alongside the tablet.
+pub struct MouseEmitter {
+    sink: Box<dyn EventSink>,
+    state: RelativeMouse,
+}
+
+impl MouseEmitter {
+    /// Fails when `/dev/uinput` can't be opened, see `emitter::uinput_hint`.
+    pub fn new(config: &MouseConfig) -> io::Result<MouseEmitter> {
+        let uninit = UninitDevice::new()
+            .ok_or_else(|| io::Error::other("Failed to allocate the uinput mouse"))?;
+        uninit.set_name(&config.name);
+        for axis in [EV_REL::REL_X, EV_REL::REL_Y, EV_REL::REL_WHEEL] {
+            uninit.enable_event_code(&EventCode::EV_REL(axis), None)?;
+        }
+        for key in [EV_KEY::BTN_LEFT, EV_KEY::BTN_RIGHT, EV_KEY::BTN_MIDDLE] {
+            uninit.enable_event_code(&EventCode::EV_KEY(key), None)?;
+        }
+        let device = UInputDevice::create_from_device(&uninit)?;
+
+        Ok(MouseEmitter::with_sink(config, device))
+    }
+
+    pub fn with_sink(config: &MouseConfig, sink: impl EventSink + 'static) -> MouseEmitter {
+        MouseEmitter {
+            sink: Box::new(sink),
+            state: RelativeMouse::new(config.sensitivity),
+        }
+    }
+
+    pub fn emit(&mut self, report: &PenReport) -> io::Result<()> {
+        let frame = self.state.frame(report);
+        self.write(&frame)
+    }
+
+    pub fn teardown(&mut self) -> io::Result<()> {
+        let frame = self.state.release_all();
+        self.write(&frame)
+    }
+
+    fn write(&mut self, frame: &[InputEvent]) -> io::Result<()> {
+        for event in frame {
+            self.sink.write(event)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MouseEmitter {
+    fn drop(&mut self) {
+        let _ = self.teardown();
+    }
+}
+
+fn rel_event(axis: EV_REL, value: i32) -> InputEvent {
+    input_event(EventCode::EV_REL(axis), value)
+}
+
+fn input_event(code: EventCode, value: i32) -> InputEvent {
+    InputEvent::new(&TimeVal::new(0, 0), &code, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pen(x: u16, y: u16, in_range: bool, tip: bool) -> PenReport {
+        PenReport {
+            x,
+            y,
+            pressure: 0,
+            in_range,
+            tip,
+        }
+    }
+
+    #[test]
+    fn moves_by_the_scaled_pen_motion_and_lifting_moves_nothing() {
+        let mut mouse = RelativeMouse::new(0.5);
+        let codes = |frame: Vec<InputEvent>| -> Vec<(EventCode, i32)> {
+            frame.into_iter().map(|e| (e.event_code, e.value)).collect()
+        };
+        let syn = (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+
+        assert!(mouse.frame(&pen(100, 100, true, false)).is_empty());
+        assert_eq!(
+            codes(mouse.frame(&pen(103, 90, true, true))),
+            vec![
+                (EventCode::EV_REL(EV_REL::REL_X), 1),
+                (EventCode::EV_REL(EV_REL::REL_Y), -5),
+                (EventCode::EV_KEY(EV_KEY::BTN_LEFT), 1),
+                syn,
+            ]
+        );
+        // The half left over from the 3 adds up with the next 1.
+        assert_eq!(
+            codes(mouse.frame(&pen(104, 90, true, true))),
+            vec![(EventCode::EV_REL(EV_REL::REL_X), 1), syn]
+        );
+        assert_eq!(
+            codes(mouse.frame(&pen(0, 0, false, false))),
+            vec![(EventCode::EV_KEY(EV_KEY::BTN_LEFT), 0), syn]
+        );
+        assert!(mouse.frame(&pen(5000, 5000, true, false)).is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    sync::{
+        Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+/// How often a paused reader checks whether the driver is stopping.
+const CHECK: Duration = Duration::from_millis(100);
+
+/// Set while the output is off, shared by the driver and the readers. The
+/// readers it applies to stop polling, keeping their interfaces claimed and
+/// the tablet initialized, and go on at once when it is cleared.
+#[derive(Debug, Default)]
+pub struct Pause {
+    paused: Mutex<bool>,
+    changed: Condvar,
+}
+
+impl Pause {
+    pub fn set(&self, paused: bool) {
+        *self.paused.lock().unwrap_or_else(|e| e.into_inner()) = paused;
+        self.changed.notify_all();
+    }
+
+    #[cfg(test)]
+    pub fn is_paused(&self) -> bool {
+        *self.paused.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Blocks while paused, calling `on_pause` first when it is. False once
+    /// `running` is cleared.
+    pub fn wait(&self, running: &AtomicBool, on_pause: impl FnOnce()) -> bool {
+        let mut paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        if *paused {
+            // Not under the lock, `set` is not kept waiting.
+            drop(paused);
+            on_pause();
+            paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        }
+        while *paused && running.load(Ordering::SeqCst) {
+            paused = self
+                .changed
+                .wait_timeout(paused, CHECK)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+
+        running.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, atomic::AtomicU64},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn a_paused_reader_stops_polling_until_resumed() {
+        let pause = Arc::new(Pause::default());
+        let running = Arc::new(AtomicBool::new(true));
+        let polls = Arc::new(AtomicU64::new(0));
+        let reader = {
+            let (pause, running, polls) = (pause.clone(), running.clone(), polls.clone());
+            thread::spawn(move || {
+                while pause.wait(&running, || {}) {
+                    polls.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+        let settle = || thread::sleep(Duration::from_millis(20));
+
+        settle();
+        pause.set(true);
+        settle();
+        let paused_at = polls.load(Ordering::SeqCst);
+        settle();
+        assert_eq!(polls.load(Ordering::SeqCst), paused_at);
+
+        pause.set(false);
+        settle();
+        assert!(polls.load(Ordering::SeqCst) > paused_at);
+
+        pause.set(true);
+        running.store(false, Ordering::SeqCst);
+        reader.join().unwrap();
+    }
+}
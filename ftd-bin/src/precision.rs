@@ -0,0 +1,134 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrecisionSettings {
+    /// Button index, as in `report::BUTTON_BITS`, held for precision mode.
+    /// It is taken over and no longer emitted.
+    pub button: Option<u8>,
+    /// Fraction of the pen's motion the cursor follows while held.
+    pub sensitivity: f32,
+}
+
+impl Default for PrecisionSettings {
+    fn default() -> Self {
+        PrecisionSettings {
+            button: None,
+            sensitivity: 0.25,
+        }
+    }
+}
+
+impl PrecisionSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.sensitivity > 0.0 && self.sensitivity.is_finite()) {
+            bail!(
+                "precision.sensitivity ({}) must be a positive number",
+                self.sensitivity
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Absolute positioning that turns relative while the precision button is
+/// held: the cursor starts where it was and moves by the scaled pen motion.
+/// Releasing the button goes back to the pen's absolute position.
+pub struct PrecisionMode {
+    settings: PrecisionSettings,
+    held: bool,
+    /// `(pen position, cursor position)` when the button went down.
+    anchor: Option<((i32, i32), (i32, i32))>,
+    last: Option<((i32, i32), (i32, i32))>,
+}
+
+impl PrecisionMode {
+    pub fn new(settings: PrecisionSettings) -> PrecisionMode {
+        PrecisionMode {
+            settings,
+            held: false,
+            anchor: None,
+            last: None,
+        }
+    }
+
+    pub fn button(&self) -> Option<u8> {
+        self.settings.button
+    }
+
+    pub fn set_held(&mut self, held: bool) {
+        self.held = held;
+        self.anchor = if held { self.last } else { None };
+    }
+
+    /// Takes the absolute mapped position and returns where the cursor
+    /// goes, kept within `0..bounds`.
+    pub fn apply(&mut self, mapped: (i32, i32), bounds: (i32, i32)) -> (i32, i32) {
+        let cursor = if self.held {
+            let (pen, cursor) = *self.anchor.get_or_insert((mapped, mapped));
+            let scale = |delta: i32| (delta as f32 * self.settings.sensitivity).round() as i32;
+            (
+                (cursor.0 + scale(mapped.0 - pen.0)).clamp(0, bounds.0 - 1),
+                (cursor.1 + scale(mapped.1 - pen.1)).clamp(0, bounds.1 - 1),
+            )
+        } else {
+            mapped
+        };
+        self.last = Some((mapped, cursor));
+
+        cursor
+    }
+
+    /// Forgets the last position, for when the pen leaves proximity.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: (i32, i32) = (1920, 1080);
+
+    fn mode() -> PrecisionMode {
+        PrecisionMode::new(PrecisionSettings {
+            button: Some(8),
+            sensitivity: 0.25,
+        })
+    }
+
+    #[test]
+    fn holding_the_button_seeds_deltas_from_the_last_position() {
+        let mut mode = mode();
+        assert_eq!(mode.apply((1000, 500), BOUNDS), (1000, 500));
+
+        mode.set_held(true);
+        // The pen moved a bit between the last report and the first held one.
+        assert_eq!(mode.apply((1040, 500), BOUNDS), (1010, 500));
+        assert_eq!(mode.apply((1080, 420), BOUNDS), (1020, 480));
+
+        mode.set_held(false);
+        assert_eq!(mode.apply((1080, 420), BOUNDS), (1080, 420));
+    }
+
+    #[test]
+    fn held_before_any_position_starts_in_place_and_stays_on_screen() {
+        let mut mode = mode();
+        mode.set_held(true);
+
+        assert_eq!(mode.apply((10, 10), BOUNDS), (10, 10));
+        assert_eq!(mode.apply((-400, 10), BOUNDS), (0, 10));
+        assert!(
+            PrecisionSettings {
+                sensitivity: 0.0,
+                ..PrecisionSettings::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
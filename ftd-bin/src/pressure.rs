@@ -0,0 +1,195 @@
+use anyhow::{Result, bail};
+
+use ftd_device::report::DEVICE_MAX_PRESSURE;
+
+/// Usable window of raw pressure, stretched to the full output range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PressureRange {
+    pub in_min: u16,
+    pub in_max: u16,
+}
+
+impl Default for PressureRange {
+    fn default() -> Self {
+        PressureRange {
+            in_min: 0,
+            in_max: DEVICE_MAX_PRESSURE as u16,
+        }
+    }
+}
+
+impl PressureRange {
+    pub fn new(in_min: u16, in_max: u16) -> Result<PressureRange> {
+        if in_min >= in_max {
+            bail!("pressure_in_min ({in_min}) must be lower than pressure_in_max ({in_max})");
+        }
+
+        Ok(PressureRange { in_min, in_max })
+    }
+
+    pub fn apply(&self, raw: u16) -> u16 {
+        let raw = raw.clamp(self.in_min, self.in_max);
+        let span = (self.in_max - self.in_min) as u32;
+        let scaled = (raw - self.in_min) as u32 * DEVICE_MAX_PRESSURE as u32 / span;
+
+        scaled as u16
+    }
+}
+
+/// Power curve over the normalized pressure, below 1.0 favours light strokes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PressureCurve {
+    gamma: f32,
+}
+
+impl Default for PressureCurve {
+    fn default() -> Self {
+        PressureCurve { gamma: 1.0 }
+    }
+}
+
+impl PressureCurve {
+    pub fn new(gamma: f32) -> Result<PressureCurve> {
+        if !(gamma > 0.0 && gamma.is_finite()) {
+            bail!("pressure_curve ({gamma}) must be a positive number");
+        }
+
+        Ok(PressureCurve { gamma })
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    pub fn apply(&self, pressure: u16) -> u16 {
+        if self.gamma == 1.0 {
+            return pressure;
+        }
+        let max = DEVICE_MAX_PRESSURE as f32;
+        let normalized = (pressure as f32 / max).clamp(0.0, 1.0);
+
+        (normalized.powf(self.gamma) * max).round() as u16
+    }
+}
+
+/// Exponential smoothing of pressure, independent of position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PressureSmoother {
+    alpha: f32,
+    value: Option<f32>,
+}
+
+impl PressureSmoother {
+    /// `alpha` is the weight of each new sample, 1.0 disables smoothing.
+    pub fn new(alpha: f32) -> Result<PressureSmoother> {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            bail!("pressure_smoothing ({alpha}) must be in (0, 1]");
+        }
+
+        Ok(PressureSmoother { alpha, value: None })
+    }
+
+    pub fn apply(&mut self, pressure: u16) -> u16 {
+        let value = match self.value {
+            Some(previous) => previous + self.alpha * (pressure as f32 - previous),
+            None => pressure as f32,
+        };
+        self.value = Some(value);
+
+        value.round() as u16
+    }
+
+    pub fn reset(&mut self) {
+        self.value = None;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PressurePipeline {
+    pub range: PressureRange,
+    pub curve: PressureCurve,
+    pub smoother: Option<PressureSmoother>,
+}
+
+impl PressurePipeline {
+    pub fn new(
+        range: PressureRange,
+        curve: PressureCurve,
+        smoothing: Option<f32>,
+    ) -> Result<PressurePipeline> {
+        Ok(PressurePipeline {
+            range,
+            curve,
+            smoother: smoothing.map(PressureSmoother::new).transpose()?,
+        })
+    }
+
+    pub fn process(&mut self, raw: u16, in_range: bool) -> u16 {
+        let pressure = self.curve.apply(self.range.apply(raw));
+
+        match &mut self.smoother {
+            Some(smoother) if in_range => smoother.apply(pressure),
+            Some(smoother) => {
+                smoother.reset();
+                pressure
+            }
+            None => pressure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_edges_map_to_full_output() {
+        let range = PressureRange::new(35, 6000).unwrap();
+
+        assert_eq!(range.apply(35), 0);
+        assert_eq!(range.apply(6000), DEVICE_MAX_PRESSURE as u16);
+        assert_eq!(range.apply(10), 0);
+        assert_eq!(range.apply(8000), DEVICE_MAX_PRESSURE as u16);
+    }
+
+    #[test]
+    fn smooths_a_ramp_and_resets_on_lift() {
+        let mut pipeline = PressurePipeline::new(
+            PressureRange::default(),
+            PressureCurve::default(),
+            Some(0.5),
+        )
+        .unwrap();
+
+        assert_eq!(pipeline.process(0, true), 0);
+        assert_eq!(pipeline.process(1000, true), 500);
+        assert_eq!(pipeline.process(2000, true), 1250);
+
+        pipeline.process(0, false);
+        assert_eq!(pipeline.process(2000, true), 2000);
+    }
+
+    #[test]
+    fn curve_keeps_the_ends_and_bends_the_middle() {
+        let max = DEVICE_MAX_PRESSURE as u16;
+        let soft = PressureCurve::new(0.5).unwrap();
+
+        assert_eq!(soft.apply(0), 0);
+        assert_eq!(soft.apply(max), max);
+        assert!(soft.apply(max / 4) > max / 4);
+        assert!(PressureCurve::new(2.0).unwrap().apply(max / 4) < max / 4);
+        assert!(PressureCurve::new(0.0).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_smoothing() {
+        assert!(PressureSmoother::new(0.0).is_err());
+        assert!(PressureSmoother::new(1.5).is_err());
+    }
+
+    #[test]
+    fn rejects_inverted_window() {
+        assert!(PressureRange::new(100, 100).is_err());
+        assert!(PressureRange::new(200, 100).is_err());
+    }
+}
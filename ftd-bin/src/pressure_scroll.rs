@@ -0,0 +1,122 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PressureScrollSettings {
+    /// Button index, as in `report::BUTTON_BITS`, held to scroll with the
+    /// pen pressure. It is taken over and no longer emitted.
+    pub button: Option<u8>,
+    /// Pressure change per wheel click, in the units after the curve.
+    pub units_per_click: u16,
+    /// Pressure on either side of where the tip touched that scrolls
+    /// nothing, so resting the pen does not drift.
+    pub deadzone: u16,
+}
+
+impl Default for PressureScrollSettings {
+    fn default() -> Self {
+        PressureScrollSettings {
+            button: None,
+            units_per_click: 400,
+            deadzone: 300,
+        }
+    }
+}
+
+impl PressureScrollSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.units_per_click == 0 {
+            bail!("pressure_scroll.units_per_click must be at least 1");
+        }
+
+        Ok(())
+    }
+}
+
+/// Scrolls by pressing harder or softer while the button is held: the
+/// pressure the tip touched with is the center, and each
+/// `units_per_click` the pressure moves past the deadzone is a wheel click,
+/// up when harder and back down when softer.
+pub struct PressureScroll {
+    settings: PressureScrollSettings,
+    held: bool,
+    /// The pressure the tip touched with, then how far past the deadzone
+    /// it was last and what did not make a whole click yet.
+    stroke: Option<(u16, i32, i32)>,
+}
+
+impl PressureScroll {
+    pub fn new(settings: PressureScrollSettings) -> PressureScroll {
+        PressureScroll {
+            settings,
+            held: false,
+            stroke: None,
+        }
+    }
+
+    pub fn button(&self) -> Option<u8> {
+        self.settings.button
+    }
+
+    pub fn held(&self) -> bool {
+        self.held
+    }
+
+    pub fn set_held(&mut self, held: bool) {
+        self.held = held;
+        self.stroke = None;
+    }
+
+    /// The wheel clicks for this pressure, `None` while the button is up
+    /// and the pen draws as usual.
+    pub fn feed(&mut self, pressure: u16, tip: bool) -> Option<i32> {
+        if !self.held {
+            return None;
+        }
+        if !tip {
+            self.stroke = None;
+            return Some(0);
+        }
+
+        let (center, last, remainder) = self.stroke.get_or_insert((pressure, 0, 0));
+        let offset = pressure as i32 - *center as i32;
+        let past = offset.signum() * (offset.abs() - self.settings.deadzone as i32).max(0);
+        *remainder += past - *last;
+        *last = past;
+
+        let per_click = self.settings.units_per_click.max(1) as i32;
+        let clicks = *remainder / per_click;
+        *remainder %= per_click;
+
+        Some(clicks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressure_past_the_deadzone_scrolls_both_ways() {
+        let mut scroll = PressureScroll::new(PressureScrollSettings {
+            button: Some(3),
+            units_per_click: 100,
+            deadzone: 50,
+        });
+
+        assert_eq!(scroll.feed(2000, true), None);
+        scroll.set_held(true);
+        assert_eq!(scroll.feed(2000, true), Some(0));
+        assert_eq!(scroll.feed(2040, true), Some(0));
+        assert_eq!(scroll.feed(2260, true), Some(2));
+        assert_eq!(scroll.feed(2000, true), Some(-2));
+        assert_eq!(scroll.feed(1750, true), Some(-2));
+        assert_eq!(scroll.feed(0, false), Some(0));
+
+        // A new touch is a new center.
+        assert_eq!(scroll.feed(500, true), Some(0));
+        scroll.set_held(false);
+        assert_eq!(scroll.feed(3000, true), None);
+    }
+}
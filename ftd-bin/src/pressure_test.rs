@@ -0,0 +1,139 @@
+use ftd_device::report::{DEVICE_MAX_PRESSURE, PenReport};
+
+use crate::pressure::PressurePipeline;
+
+const BAR_WIDTH: usize = 30;
+
+/// The lowest and highest raw pressure seen with the tip down, what
+/// `pressure.in_min` and `in_max` are set from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PressureExtremes {
+    pub min: Option<u16>,
+    pub max: Option<u16>,
+}
+
+impl PressureExtremes {
+    pub fn observe(&mut self, report: &PenReport) {
+        if !(report.in_range && report.tip) {
+            return;
+        }
+        self.min = Some(
+            self.min
+                .map_or(report.pressure, |min| min.min(report.pressure)),
+        );
+        self.max = Some(
+            self.max
+                .map_or(report.pressure, |max| max.max(report.pressure)),
+        );
+    }
+
+    /// Printed when `pressure-test` ends.
+    pub fn summary(&self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!(
+                "Raw pressure with the tip down: min {min}, max {max}, \
+                 try --pressure-in-min {min} --pressure-in-max {max}"
+            ),
+            _ => "The tip never touched, no pressure was seen".to_string(),
+        }
+    }
+}
+
+/// One line per pen report for `pressure-test`: the raw pressure and what
+/// the pipeline makes of it, each as a bar. Only reads, nothing is emitted.
+pub struct PressureTest {
+    pipeline: PressurePipeline,
+    pub extremes: PressureExtremes,
+}
+
+impl PressureTest {
+    pub fn new(pipeline: PressurePipeline) -> PressureTest {
+        PressureTest {
+            pipeline,
+            extremes: PressureExtremes::default(),
+        }
+    }
+
+    pub fn line(&mut self, report: &PenReport) -> String {
+        self.extremes.observe(report);
+        let pressure = self.pipeline.process(report.pressure, report.in_range);
+
+        format!(
+            "raw {} {:5}  curve {} {pressure:5}",
+            bar(report.pressure),
+            report.pressure,
+            bar(pressure)
+        )
+    }
+}
+
+fn bar(pressure: u16) -> String {
+    let filled = (pressure as usize * BAR_WIDTH / DEVICE_MAX_PRESSURE as usize).min(BAR_WIDTH);
+    format!("[{}{}]", "#".repeat(filled), ".".repeat(BAR_WIDTH - filled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pressure::{PressureCurve, PressureRange};
+
+    fn pen(pressure: u16, tip: bool) -> PenReport {
+        PenReport {
+            x: 0,
+            y: 0,
+            pressure,
+            in_range: true,
+            tip,
+        }
+    }
+
+    #[test]
+    fn tracks_the_extremes_while_the_tip_is_down() {
+        let mut extremes = PressureExtremes::default();
+        assert!(extremes.summary().contains("never touched"));
+
+        for report in [
+            pen(40, false),
+            pen(300, true),
+            pen(7100, true),
+            pen(150, true),
+        ] {
+            extremes.observe(&report);
+        }
+        extremes.observe(&PenReport {
+            in_range: false,
+            ..pen(8000, true)
+        });
+
+        assert_eq!(
+            extremes,
+            PressureExtremes {
+                min: Some(150),
+                max: Some(7100),
+            }
+        );
+        assert!(
+            extremes
+                .summary()
+                .ends_with("--pressure-in-min 150 --pressure-in-max 7100")
+        );
+    }
+
+    #[test]
+    fn shows_the_raw_and_the_curved_pressure() {
+        let half = (DEVICE_MAX_PRESSURE as u16).div_ceil(2);
+        let pipeline = PressurePipeline::new(
+            PressureRange::new(0, half).unwrap(),
+            PressureCurve::new(1.0).unwrap(),
+            None,
+        )
+        .unwrap();
+        let mut test = PressureTest::new(pipeline);
+
+        let line = test.line(&pen(half, true));
+        let half = format!("[{}{}]", "#".repeat(15), ".".repeat(15));
+        let full = format!("[{}]", "#".repeat(BAR_WIDTH));
+        assert!(line.starts_with(&format!("raw {half}")), "{line}");
+        assert!(line.contains(&format!("curve {full}")), "{line}");
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{Action, default_button_map},
+    mapper::{Area, Rotation},
+    mouse::PenTarget,
+    pressure::PressureCurve,
+};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// A `[[profiles]]` entry, what is not given is taken from the default
+/// profile: `button_map`, `pressure.curve`, `mapping.area`,
+/// `mapping.rotation` and `mouse.pen`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    pub name: String,
+    pub button_map: Option<Vec<Action>>,
+    pub pressure_curve: Option<f32>,
+    pub area: Option<Area>,
+    pub rotation: Option<Rotation>,
+    /// Which device the pen moves while the profile is active.
+    pub pen: Option<PenTarget>,
+    /// Switched to while the focused window has this X11 `WM_CLASS`,
+    /// instance or class name, see `focus::matching`.
+    pub window_class: Option<String>,
+    /// Switched to while the focused window belongs to a process of this
+    /// name, as in `/proc/<pid>/comm`.
+    pub process_name: Option<String>,
+}
+
+impl ProfileConfig {
+    /// Whether the focused window selects this profile.
+    pub fn follows_focus(&self) -> bool {
+        self.window_class.is_some() || self.process_name.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub button_map: Vec<Action>,
+    pub curve: PressureCurve,
+    pub area: Area,
+    pub rotation: Rotation,
+    pub pen: PenTarget,
+}
+
+impl Profile {
+    /// `config` on top of `default`.
+    pub fn new(config: &ProfileConfig, default: &Profile) -> Result<Profile> {
+        let area = config.area.unwrap_or(default.area);
+        area.validate()?;
+
+        Ok(Profile {
+            name: config.name.clone(),
+            button_map: config
+                .button_map
+                .clone()
+                .unwrap_or_else(|| default.button_map.clone()),
+            curve: match config.pressure_curve {
+                Some(gamma) => PressureCurve::new(gamma)?,
+                None => default.curve,
+            },
+            area,
+            rotation: config.rotation.unwrap_or(default.rotation),
+            pen: config.pen.unwrap_or(default.pen),
+        })
+    }
+}
+
+/// The known profiles and which one is currently active.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileSet {
+    profiles: Vec<Profile>,
+    active: usize,
+}
+
+impl Default for ProfileSet {
+    fn default() -> Self {
+        ProfileSet::new(vec![Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            button_map: default_button_map(),
+            curve: PressureCurve::default(),
+            area: Area::default(),
+            rotation: Rotation::None,
+            pen: PenTarget::Tablet,
+        }])
+    }
+}
+
+impl ProfileSet {
+    /// The first profile starts out active.
+    pub fn new(profiles: Vec<Profile>) -> ProfileSet {
+        ProfileSet {
+            profiles,
+            active: 0,
+        }
+    }
+
+    /// `default` followed by `configs`, whose names must all differ.
+    pub fn with_configs(default: Profile, configs: &[ProfileConfig]) -> Result<ProfileSet> {
+        let mut profiles = vec![];
+        for config in configs {
+            if config.name == default.name
+                || profiles.iter().any(|p: &Profile| p.name == config.name)
+            {
+                bail!("There are two profiles named `{}`", config.name);
+            }
+            profiles.push(Profile::new(config, &default)?);
+        }
+        profiles.insert(0, default);
+
+        Ok(ProfileSet::new(profiles))
+    }
+
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    pub fn active(&self) -> &Profile {
+        &self.profiles[self.active]
+    }
+
+    pub fn select(&mut self, name: &str) -> Result<()> {
+        let Some(index) = self.profiles.iter().position(|p| p.name == name) else {
+            let known: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+            bail!("Unknown profile `{name}`, available: {}", known.join(", "));
+        };
+
+        self.active = index;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str) -> Profile {
+        Profile {
+            name: name.to_string(),
+            button_map: vec![],
+            curve: PressureCurve::default(),
+            area: Area::default(),
+            rotation: Rotation::None,
+            pen: PenTarget::Tablet,
+        }
+    }
+
+    #[test]
+    fn selects_a_known_profile() {
+        let mut profiles = ProfileSet::new(vec![profile("default"), profile("krita")]);
+
+        profiles.select("krita").unwrap();
+        assert_eq!(profiles.active().name, "krita");
+    }
+
+    #[test]
+    fn unknown_profile_lists_the_available_ones() {
+        let mut profiles = ProfileSet::new(vec![profile("default"), profile("krita")]);
+
+        let error = profiles.select("gimp").unwrap_err().to_string();
+        assert_eq!(error, "Unknown profile `gimp`, available: default, krita");
+        assert_eq!(profiles.active().name, "default");
+    }
+}
@@ -0,0 +1,278 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use ftd_device::report::PenReport;
+
+/// How the driver tells that the pen left proximity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProximityMode {
+    /// The in range bit of each report.
+    #[default]
+    Bit,
+    /// The bit, and also a report repeated unchanged for `freeze_ms`, for
+    /// firmware that keeps sending the last position after the pen left.
+    Repeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProximitySettings {
+    pub mode: ProximityMode,
+    /// With `repeat`, how long the same report may come in before the pen
+    /// counts as gone. A pen held perfectly still for this long does too.
+    pub freeze_ms: u64,
+    /// Send the first report after the pen comes into range without the
+    /// tip, so a pen that lands far from the cursor moves it there before
+    /// drawing instead of starting the stroke at the old position.
+    pub lift_first_tip: bool,
+    /// How long the tip has to stay down before it presses, for taps that
+    /// land where the cursor settled rather than on the way there. Lifting
+    /// sooner cancels the press. 0 presses at once.
+    pub tip_delay_ms: u64,
+    /// How long the pen has to stay in or out of range before that counts,
+    /// for the bit flickering at the edge of the sensing range. The reports
+    /// in between are dropped. 0 takes every change at once.
+    pub debounce_ms: u64,
+}
+
+impl Default for ProximitySettings {
+    fn default() -> Self {
+        ProximitySettings {
+            mode: ProximityMode::Bit,
+            freeze_ms: 500,
+            lift_first_tip: false,
+            tip_delay_ms: 0,
+            debounce_ms: 0,
+        }
+    }
+}
+
+/// Lets one out of range report through when the pen leaves and drops the
+/// ones after it, so nothing moves while the pen is away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProximityFilter {
+    settings: ProximitySettings,
+    /// The last report as read and since when it keeps coming.
+    last: Option<(PenReport, Instant)>,
+    out: bool,
+    /// Whether the last report let through had the pen in range.
+    in_range: bool,
+    /// The first report of a change still within `debounce_ms`, and when
+    /// it came.
+    pending: Option<(PenReport, Instant)>,
+}
+
+impl ProximityFilter {
+    pub fn new(settings: ProximitySettings) -> ProximityFilter {
+        ProximityFilter {
+            settings,
+            last: None,
+            out: false,
+            in_range: false,
+            pending: None,
+        }
+    }
+
+    /// The report to emit for `report`, read at `at`, or `None` to drop it.
+    pub fn feed(&mut self, report: PenReport, at: Instant) -> Option<PenReport> {
+        let since = match self.last {
+            Some((last, since)) if last == report => since,
+            _ => at,
+        };
+        self.last = Some((report, since));
+
+        let frozen = self.settings.mode == ProximityMode::Repeat
+            && at.saturating_duration_since(since)
+                >= Duration::from_millis(self.settings.freeze_ms);
+        let report = if frozen {
+            PenReport {
+                pressure: 0,
+                in_range: false,
+                tip: false,
+                ..report
+            }
+        } else {
+            report
+        };
+        // A frozen pen already waited out `freeze_ms`.
+        if !frozen && !self.settled(&report, at) {
+            return None;
+        }
+
+        self.pass(report)
+    }
+
+    /// The out of range report of a pen that left and sent nothing since,
+    /// once `debounce_ms` passed at `now`.
+    pub fn expire(&mut self, now: Instant) -> Option<PenReport> {
+        let (report, since) = self.pending?;
+        if report.in_range || now.saturating_duration_since(since) < self.debounce() {
+            return None;
+        }
+        self.pending = None;
+
+        self.pass(report)
+    }
+
+    fn debounce(&self) -> Duration {
+        Duration::from_millis(self.settings.debounce_ms)
+    }
+
+    /// Whether `report` keeps the pen where it was, or changed it at
+    /// least `debounce_ms` ago.
+    fn settled(&mut self, report: &PenReport, at: Instant) -> bool {
+        if report.in_range == self.in_range || self.debounce().is_zero() {
+            self.pending = None;
+            return true;
+        }
+        let since = self.pending.map_or(at, |(_, since)| since);
+        if at.saturating_duration_since(since) >= self.debounce() {
+            self.pending = None;
+            return true;
+        }
+        self.pending = Some((*report, since));
+
+        false
+    }
+
+    fn pass(&mut self, report: PenReport) -> Option<PenReport> {
+        let was_in_range = std::mem::replace(&mut self.in_range, report.in_range);
+        if report.in_range {
+            self.out = false;
+            if !was_in_range && self.settings.lift_first_tip {
+                return Some(PenReport {
+                    pressure: 0,
+                    tip: false,
+                    ..report
+                });
+            }
+            return Some(report);
+        }
+        (!std::mem::replace(&mut self.out, true)).then_some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pen(x: u16, in_range: bool) -> PenReport {
+        PenReport {
+            x,
+            y: 2000,
+            pressure: 0,
+            in_range,
+            tip: false,
+        }
+    }
+
+    #[test]
+    fn only_the_first_out_of_range_report_passes() {
+        let mut filter = ProximityFilter::new(ProximitySettings::default());
+        let at = Instant::now();
+
+        assert_eq!(filter.feed(pen(100, true), at), Some(pen(100, true)));
+        assert_eq!(filter.feed(pen(100, false), at), Some(pen(100, false)));
+        assert_eq!(filter.feed(pen(100, false), at), None);
+        assert_eq!(filter.feed(pen(300, false), at), None);
+        // The bit alone is trusted, a still pen stays in range.
+        let later = at + Duration::from_secs(5);
+        assert_eq!(filter.feed(pen(300, true), later), Some(pen(300, true)));
+        assert_eq!(filter.feed(pen(300, true), later), Some(pen(300, true)));
+    }
+
+    #[test]
+    fn a_report_repeated_past_freeze_ms_counts_as_out_of_range() {
+        let mut filter = ProximityFilter::new(ProximitySettings {
+            mode: ProximityMode::Repeat,
+            freeze_ms: 200,
+            ..ProximitySettings::default()
+        });
+        let start = Instant::now();
+        let after = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(filter.feed(pen(100, true), start), Some(pen(100, true)));
+        assert_eq!(
+            filter.feed(pen(100, true), after(150)),
+            Some(pen(100, true))
+        );
+        assert_eq!(
+            filter.feed(pen(100, true), after(200)),
+            Some(pen(100, false))
+        );
+        assert_eq!(filter.feed(pen(100, true), after(400)), None);
+        assert_eq!(
+            filter.feed(pen(101, true), after(410)),
+            Some(pen(101, true))
+        );
+        assert_eq!(
+            filter.feed(pen(101, false), after(420)),
+            Some(pen(101, false))
+        );
+    }
+
+    #[test]
+    fn the_first_report_in_range_can_come_without_the_tip() {
+        let mut filter = ProximityFilter::new(ProximitySettings {
+            lift_first_tip: true,
+            ..ProximitySettings::default()
+        });
+        let at = Instant::now();
+        let down = |x| PenReport {
+            pressure: 3000,
+            tip: true,
+            ..pen(x, true)
+        };
+
+        assert_eq!(filter.feed(down(9000), at), Some(pen(9000, true)));
+        assert_eq!(filter.feed(down(9010), at), Some(down(9010)));
+        assert_eq!(filter.feed(pen(9010, false), at), Some(pen(9010, false)));
+        assert_eq!(filter.feed(down(100), at), Some(pen(100, true)));
+        assert_eq!(filter.feed(down(100), at), Some(down(100)));
+    }
+
+    #[test]
+    fn flicker_shorter_than_the_debounce_is_dropped() {
+        let mut filter = ProximityFilter::new(ProximitySettings {
+            debounce_ms: 30,
+            ..ProximitySettings::default()
+        });
+        let start = Instant::now();
+        let after = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(filter.feed(pen(100, true), after(0)), None);
+        assert_eq!(filter.feed(pen(101, true), after(30)), Some(pen(101, true)));
+        // Out for 20 ms, then back.
+        assert_eq!(filter.feed(pen(102, false), after(40)), None);
+        assert_eq!(filter.feed(pen(103, false), after(50)), None);
+        assert_eq!(filter.feed(pen(104, true), after(60)), Some(pen(104, true)));
+        assert_eq!(filter.expire(after(200)), None);
+
+        // Leaving for real, with no report after the first one out.
+        assert_eq!(filter.feed(pen(105, false), after(100)), None);
+        assert_eq!(filter.expire(after(129)), None);
+        assert_eq!(filter.expire(after(130)), Some(pen(105, false)));
+        assert_eq!(filter.feed(pen(105, false), after(140)), None);
+    }
+
+    #[test]
+    fn a_frozen_pen_leaves_without_the_debounce() {
+        let mut filter = ProximityFilter::new(ProximitySettings {
+            mode: ProximityMode::Repeat,
+            freeze_ms: 200,
+            debounce_ms: 50,
+            ..ProximitySettings::default()
+        });
+        let start = Instant::now();
+        let after = |ms| start + Duration::from_millis(ms);
+
+        filter.feed(pen(100, true), after(0));
+        assert_eq!(filter.feed(pen(100, true), after(60)), Some(pen(100, true)));
+        assert_eq!(
+            filter.feed(pen(100, true), after(260)),
+            Some(pen(100, false))
+        );
+    }
+}
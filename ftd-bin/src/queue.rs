@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Result, bail};
+use crossbeam_channel::{Receiver, SendError, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
+
+/// What a reader does with a report while the queue to the report loop is
+/// full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropPolicy {
+    /// Makes room by dropping the oldest queued report, the loop catches
+    /// up on the newest pen position.
+    #[default]
+    DropOldest,
+    /// Drops the report that did not fit.
+    DropNewest,
+    /// Waits for room, which holds up the USB reads of that interface.
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// Reports read but not yet handled, per session.
+    pub capacity: usize,
+    pub policy: DropPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            capacity: 256,
+            policy: DropPolicy::DropOldest,
+        }
+    }
+}
+
+impl QueueConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.capacity == 0 {
+            bail!("queue.capacity must be at least 1");
+        }
+
+        Ok(())
+    }
+}
+
+/// The sending side of `channel`, applies the policy and counts what it
+/// drops into `dropped`.
+pub struct QueueSender<'a, T> {
+    sender: Sender<T>,
+    /// For `DropOldest`, which takes from the front of the queue. Keeps the
+    /// queue connected, but that policy never waits on the receiver.
+    oldest: Option<Receiver<T>>,
+    policy: DropPolicy,
+    dropped: &'a AtomicU64,
+}
+
+impl<T> Clone for QueueSender<'_, T> {
+    fn clone(&self) -> Self {
+        QueueSender {
+            sender: self.sender.clone(),
+            oldest: self.oldest.clone(),
+            policy: self.policy,
+            dropped: self.dropped,
+        }
+    }
+}
+
+/// A queue of `config.capacity`, counting the items it drops into `dropped`.
+pub fn channel<'a, T>(
+    config: &QueueConfig,
+    dropped: &'a AtomicU64,
+) -> (QueueSender<'a, T>, Receiver<T>) {
+    let (sender, receiver) = crossbeam_channel::bounded(config.capacity);
+    let sender = QueueSender {
+        sender,
+        oldest: (config.policy == DropPolicy::DropOldest).then(|| receiver.clone()),
+        policy: config.policy,
+        dropped,
+    };
+
+    (sender, receiver)
+}
+
+impl<T> QueueSender<'_, T> {
+    /// Fails once the receiver is gone, except with `DropOldest`.
+    pub fn send(&self, mut item: T) -> Result<(), SendError<T>> {
+        if self.policy == DropPolicy::Block {
+            return self.sender.send(item);
+        }
+
+        loop {
+            match self.sender.try_send(item) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(item)) => return Err(SendError(item)),
+                Err(TrySendError::Full(rejected)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    let Some(oldest) = &self.oldest else {
+                        return Ok(());
+                    };
+                    // The loop may have taken one meanwhile, then nothing
+                    // is dropped but it was counted, which is close enough.
+                    let _ = oldest.try_recv();
+                    item = rejected;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends 1 to 6 into a queue of 2 while the consumer takes one item
+    /// after every third send.
+    fn slow_consumer(policy: DropPolicy) -> (Vec<u32>, u64) {
+        let dropped = AtomicU64::new(0);
+        let config = QueueConfig {
+            capacity: 2,
+            policy,
+        };
+        let (sender, receiver) = channel(&config, &dropped);
+
+        let mut received = vec![];
+        for item in 1..=6 {
+            sender.send(item).unwrap();
+            if item % 3 == 0 {
+                received.push(receiver.recv().unwrap());
+            }
+        }
+        drop(sender);
+        received.extend(receiver.iter());
+
+        (received, dropped.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn a_full_queue_drops_by_policy() {
+        assert_eq!(slow_consumer(DropPolicy::DropOldest), (vec![2, 5, 6], 3));
+        assert_eq!(slow_consumer(DropPolicy::DropNewest), (vec![1, 2, 4], 3));
+        assert!(
+            QueueConfig {
+                capacity: 0,
+                ..QueueConfig::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+/// What the reads of one interface came back with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadCounts {
+    pub reports: u64,
+    pub timeouts: u64,
+    /// A stalled endpoint, often a bad cable or port.
+    pub pipe_errors: u64,
+    pub other_errors: u64,
+}
+
+impl fmt::Display for ReadCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ok:{},timeout:{},pipe:{},other:{}",
+            self.reports, self.timeouts, self.pipe_errors, self.other_errors
+        )
+    }
+}
+
+/// Counts of the read outcomes per interface, shared by the reader threads
+/// and whoever reports them, so a flaky endpoint shows up by itself.
+#[derive(Debug, Default)]
+pub struct ReadStats {
+    counts: Mutex<BTreeMap<u8, ReadCounts>>,
+}
+
+impl ReadStats {
+    pub fn record<T>(&self, interface: u8, result: &rusb::Result<T>) {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let counts = counts.entry(interface).or_default();
+        match result {
+            Ok(_) => counts.reports += 1,
+            Err(rusb::Error::Timeout) => counts.timeouts += 1,
+            Err(rusb::Error::Pipe) => counts.pipe_errors += 1,
+            Err(_) => counts.other_errors += 1,
+        }
+    }
+
+    pub fn counts(&self) -> Vec<(u8, ReadCounts)> {
+        let counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts
+            .iter()
+            .map(|(&number, &counts)| (number, counts))
+            .collect()
+    }
+
+    /// `interface1=ok:..,timeout:..,pipe:..,other:..`, space separated.
+    pub fn summary(&self) -> String {
+        self.counts()
+            .iter()
+            .map(|(number, counts)| format!("interface{number}={counts}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_each_outcome_per_interface() {
+        let stats = ReadStats::default();
+        let results: [(u8, rusb::Result<()>); 6] = [
+            (1, Ok(())),
+            (2, Ok(())),
+            (2, Err(rusb::Error::Timeout)),
+            (2, Err(rusb::Error::Pipe)),
+            (1, Err(rusb::Error::Io)),
+            (2, Ok(())),
+        ];
+        for (interface, result) in &results {
+            stats.record(*interface, result);
+        }
+
+        assert_eq!(
+            stats.counts(),
+            vec![
+                (
+                    1,
+                    ReadCounts {
+                        reports: 1,
+                        other_errors: 1,
+                        ..ReadCounts::default()
+                    }
+                ),
+                (
+                    2,
+                    ReadCounts {
+                        reports: 2,
+                        timeouts: 1,
+                        pipe_errors: 1,
+                        other_errors: 0,
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            stats.summary(),
+            "interface1=ok:1,timeout:0,pipe:0,other:1 interface2=ok:2,timeout:1,pipe:1,other:0"
+        );
+    }
+}
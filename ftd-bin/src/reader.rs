@@ -0,0 +1,287 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use rusb::{DeviceHandle, UsbContext};
+
+use ftd_device::{
+    device::{EndpointStrikes, InterfaceInfo, read_device_observed},
+    transfer::{AsyncReader, Transfers},
+};
+
+use crate::{
+    assemble::ReportAssembler, config::DeviceConfig, dump::RawDump, pause::Pause,
+    queue::QueueSender, read_stats::ReadStats,
+};
+
+/// The bytes of a report and when its read returned them.
+pub type RawReport = (Instant, Vec<u8>);
+
+/// Where a reader thread gets the packets of its interface.
+pub trait PacketSource {
+    /// The next packet on `interface`, `Err(Timeout)` when none came within
+    /// `timeout`. `observe` sees the outcome on each endpoint tried.
+    fn read(
+        &mut self,
+        interface: &InterfaceInfo,
+        timeout: Duration,
+        observe: &mut dyn FnMut(u8, rusb::Result<usize>),
+    ) -> rusb::Result<(u8, Vec<u8>)>;
+
+    /// Called as the reads stop for a pause, the blocking reads have
+    /// nothing to let go of.
+    fn pause(&mut self) {}
+}
+
+/// One blocking read after the other, see `ReadMode::Sync`.
+pub struct BlockingReads<'a, T: UsbContext>(pub &'a DeviceHandle<T>);
+
+impl<T: UsbContext> PacketSource for BlockingReads<'_, T> {
+    fn read(
+        &mut self,
+        interface: &InterfaceInfo,
+        timeout: Duration,
+        observe: &mut dyn FnMut(u8, rusb::Result<usize>),
+    ) -> rusb::Result<(u8, Vec<u8>)> {
+        read_device_observed(self.0, interface, timeout.as_millis() as u64, observe)
+    }
+}
+
+impl<X: Transfers> PacketSource for AsyncReader<X> {
+    fn read(
+        &mut self,
+        interface: &InterfaceInfo,
+        timeout: Duration,
+        observe: &mut dyn FnMut(u8, rusb::Result<usize>),
+    ) -> rusb::Result<(u8, Vec<u8>)> {
+        self.read_observed(interface, timeout, observe)
+    }
+
+    fn pause(&mut self) {
+        if let Err(e) = AsyncReader::pause(self) {
+            warn!("Queued reads did not come back for the pause ({e}), stale reports may follow");
+        }
+    }
+}
+
+/// Forwards every report `source` reads from `interface` until `running` is
+/// cleared. After an error it backs off briefly, the main loop decides what
+/// is fatal. An endpoint that failed `skip_after` times in a row is no
+/// longer read. With a `report_length` the packets are joined into reports
+/// first. While `pause` is set nothing is read, the interface stays claimed
+/// and `source` lets go of the reads it has queued.
+///
+/// Each interface has a thread of its own, so a long timeout on one never
+/// holds up the reports of another.
+pub fn read_loop(
+    source: &mut dyn PacketSource,
+    (interface, timeout_ms): &(InterfaceInfo, u64),
+    device: &DeviceConfig,
+    running: &AtomicBool,
+    pause: Option<&Pause>,
+    (stats, dump): (&ReadStats, &RawDump),
+    reports: QueueSender<(u8, rusb::Result<RawReport>)>,
+) {
+    // Skipped endpoints are tried again on the next connection.
+    let mut interface = interface.clone();
+    let skip_after = device.skip_failing_endpoint_after;
+    let report_length = device.report_length(interface.number);
+    let mut strikes = EndpointStrikes::new(skip_after);
+    let mut assembler = ReportAssembler::new(interface.number, report_length);
+    while running.load(Ordering::SeqCst) {
+        if let Some(pause) = pause
+            && !pause.wait(running, || source.pause())
+        {
+            return;
+        }
+        let mut reads = vec![];
+        let read = source.read(
+            &interface,
+            Duration::from_millis(*timeout_ms),
+            &mut |endpoint, result| reads.push((endpoint, result)),
+        );
+        for (endpoint, result) in reads {
+            if strikes.observe(&mut interface, endpoint, result) {
+                warn!(
+                    "IN endpoint {endpoint:#04x} of interface {} failed {skip_after} times \
+                     in a row, no longer reading it",
+                    interface.number
+                );
+            }
+        }
+        stats.record(interface.number, &read);
+        let read_at = Instant::now();
+        match read {
+            Err(rusb::Error::Timeout) => {}
+            Ok((id, packet)) => {
+                dump.packet(interface.number, &packet);
+                let Some(bytes) = assembler.push(packet, read_at) else {
+                    continue;
+                };
+                if reports.send((id, Ok((read_at, bytes)))).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                if reports.send((interface.number, Err(e))).is_err() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::atomic::AtomicU64};
+
+    use super::*;
+    use crate::queue::{self, QueueConfig};
+
+    /// Hands out `packets` after their delay, then times out every read.
+    struct Scripted {
+        packets: VecDeque<(Duration, Vec<u8>)>,
+    }
+
+    impl PacketSource for Scripted {
+        fn read(
+            &mut self,
+            interface: &InterfaceInfo,
+            _timeout: Duration,
+            _observe: &mut dyn FnMut(u8, rusb::Result<usize>),
+        ) -> rusb::Result<(u8, Vec<u8>)> {
+            let Some((delay, packet)) = self.packets.pop_front() else {
+                thread::sleep(Duration::from_millis(1));
+                return Err(rusb::Error::Timeout);
+            };
+            thread::sleep(delay);
+            Ok((interface.number, packet))
+        }
+    }
+
+    /// Never has a report, each read blocks for its whole timeout like an
+    /// idle tablet's.
+    struct Idle;
+
+    impl PacketSource for Idle {
+        fn read(
+            &mut self,
+            _interface: &InterfaceInfo,
+            timeout: Duration,
+            _observe: &mut dyn FnMut(u8, rusb::Result<usize>),
+        ) -> rusb::Result<(u8, Vec<u8>)> {
+            thread::sleep(timeout);
+            Err(rusb::Error::Timeout)
+        }
+    }
+
+    /// The CPU time the calling thread used so far.
+    fn thread_cpu_time() -> Duration {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `now` is a valid timespec to write to.
+        assert_eq!(
+            unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut now) },
+            0
+        );
+        Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+    }
+
+    fn interface(number: u8) -> InterfaceInfo {
+        InterfaceInfo::new(number, vec![0x80 | number], 10)
+    }
+
+    #[test]
+    fn a_slow_interface_does_not_hold_up_the_others() {
+        let device = DeviceConfig::default();
+        let running = AtomicBool::new(true);
+        let (stats, dump) = (ReadStats::default(), RawDump::default());
+        let dropped = AtomicU64::new(0);
+        let (sender, reports) = queue::channel(&QueueConfig::default(), &dropped);
+        // A buttons read that only returns after half a second, as with a
+        // long `buttons_timeout_ms`, while the pen reports keep coming.
+        let mut buttons = Scripted {
+            packets: VecDeque::from([(Duration::from_millis(500), vec![0x01])]),
+        };
+        let mut pen = Scripted {
+            packets: (0..5)
+                .map(|i| (Duration::from_millis(1), vec![0x09, i]))
+                .collect(),
+        };
+
+        let received: Vec<(u8, Vec<u8>)> = thread::scope(|scope| {
+            for (source, read) in [
+                (&mut buttons, (interface(1), 1000)),
+                (&mut pen, (interface(2), 1)),
+            ] {
+                let sender = sender.clone();
+                let (device, running, taps) = (&device, &running, (&stats, &dump));
+                scope.spawn(move || read_loop(source, &read, device, running, None, taps, sender));
+            }
+            drop(sender);
+            let received = (0..6)
+                .map(|_| {
+                    let (id, report) = reports.recv().unwrap();
+                    (id, report.unwrap().1)
+                })
+                .collect();
+            running.store(false, Ordering::SeqCst);
+            received
+        });
+
+        let pen_reports: Vec<_> = (0..5).map(|i| (2, vec![0x09, i])).collect();
+        assert_eq!(received[..5], pen_reports);
+        assert_eq!(received[5], (1, vec![0x01]));
+    }
+
+    /// The CPU an idle reader costs with the short and the long read
+    /// timeouts. Only the loop's own share: the libusb and kernel work of
+    /// each timed out read comes on top of it, and scales the same way.
+    #[test]
+    #[ignore = "a benchmark, takes 6 seconds"]
+    fn long_read_timeouts_cost_an_idle_reader_less_cpu() {
+        let device = DeviceConfig::default();
+        let (stats, dump) = (ReadStats::default(), RawDump::default());
+        let dropped = AtomicU64::new(0);
+        let run = Duration::from_secs(3);
+        let used = |timeout_ms| {
+            let running = AtomicBool::new(true);
+            let (sender, _reports) = queue::channel(&QueueConfig::default(), &dropped);
+            thread::scope(|scope| {
+                let reader = scope.spawn(|| {
+                    let start = thread_cpu_time();
+                    let read = (interface(2), timeout_ms);
+                    read_loop(
+                        &mut Idle,
+                        &read,
+                        &device,
+                        &running,
+                        None,
+                        (&stats, &dump),
+                        sender,
+                    );
+                    thread_cpu_time() - start
+                });
+                thread::sleep(run);
+                running.store(false, Ordering::SeqCst);
+                reader.join().unwrap()
+            })
+        };
+
+        let short = used(10);
+        let long = used(crate::config::LONG_READ_TIMEOUT_MS);
+        let per_second = |cpu: Duration| cpu.as_secs_f64() / run.as_secs_f64() * 1e6;
+        eprintln!(
+            "idle reader: {:.0} us of CPU a second with 10 ms reads, {:.0} us with 1000 ms",
+            per_second(short),
+            per_second(long)
+        );
+        assert!(long * 10 < short, "{long:?} against {short:?}");
+    }
+}
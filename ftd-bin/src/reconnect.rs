@@ -0,0 +1,279 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use rusb::{Device, Hotplug, HotplugBuilder, UsbContext};
+use serde::{Deserialize, Serialize};
+
+use ftd_device::{device::find_device, error::FtdError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    /// Wait for the tablet to come back when it disconnects, instead of exiting.
+    pub enabled: bool,
+    /// Wait before the first attempt, doubled after each failed one.
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Failed attempts in a row after which the driver gives up and exits.
+    /// Only the attempts made while the tablet is connected count, the
+    /// wait for it to be plugged back in has no limit.
+    pub max_attempts: u32,
+    /// How often to look for the tablet while it is unplugged, when libusb
+    /// cannot report it coming back.
+    pub poll_interval_ms: u64,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            enabled: true,
+            initial_delay_ms: 500,
+            max_delay_ms: 10_000,
+            max_attempts: 10,
+            poll_interval_ms: 1000,
+        }
+    }
+}
+
+/// Spaces out reconnect attempts so a device that keeps failing to open
+/// costs a few wakeups instead of a spinning core.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backoff {
+    config: ReconnectConfig,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(config: ReconnectConfig) -> Backoff {
+        Backoff {
+            config,
+            attempts: 0,
+        }
+    }
+
+    /// The wait before the next attempt, or `None` once `max_attempts`
+    /// attempts have been made since the last `reset`.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempts >= self.config.max_attempts {
+            return None;
+        }
+
+        let factor = 1u64.checked_shl(self.attempts).unwrap_or(u64::MAX);
+        let delay = self
+            .config
+            .initial_delay_ms
+            .saturating_mul(factor)
+            .min(self.config.max_delay_ms);
+        self.attempts += 1;
+
+        Some(Duration::from_millis(delay))
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// After a successful reconnect, the next disconnect starts over.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// Where the tablet is looked for: the libusb context, or a script in the
+/// tests.
+pub trait DeviceScan {
+    /// Whether a `vid:pid` device is connected.
+    fn has_device(&mut self, vid: u16, pid: u16) -> Result<bool, FtdError>;
+}
+
+impl<T: UsbContext> DeviceScan for T {
+    fn has_device(&mut self, vid: u16, pid: u16) -> Result<bool, FtdError> {
+        Ok(find_device(self, vid, pid)?.is_some())
+    }
+}
+
+/// Returns once a `vid:pid` device is connected, or `false` when `running`
+/// was cleared first. Waits on libusb hotplug events where libusb has
+/// them, and otherwise, or when registering for them fails, looks for the
+/// device every `poll_interval_ms`.
+pub fn wait_for_device<T: UsbContext + 'static>(
+    context: &mut T,
+    vid: u16,
+    pid: u16,
+    config: ReconnectConfig,
+    running: &AtomicBool,
+) -> Result<bool> {
+    if scan(context, vid, pid) {
+        return Ok(true);
+    }
+    info!("Waiting for the tablet to be plugged back in");
+
+    if rusb::has_hotplug() {
+        let arrived = Arc::new(AtomicBool::new(false));
+        let registration = HotplugBuilder::new()
+            .vendor_id(vid)
+            .product_id(pid)
+            .enumerate(true)
+            .register::<T, _>(&*context, Box::new(Arrival(arrived.clone())));
+        match registration {
+            Ok(_registration) => {
+                while running.load(Ordering::SeqCst) {
+                    if arrived.load(Ordering::SeqCst) {
+                        return Ok(true);
+                    }
+                    context.handle_events(Some(Duration::from_millis(50)))?;
+                }
+                return Ok(false);
+            }
+            Err(e) => warn!("Failed to register for hotplug events, polling instead: {e}"),
+        }
+    }
+
+    let interval = Duration::from_millis(config.poll_interval_ms);
+    poll_for_device(context, (vid, pid), interval, running)
+}
+
+/// Looks for a `vid:pid` device on `devices` every `interval` until it is
+/// there, `false` once `running` is cleared.
+pub fn poll_for_device(
+    devices: &mut impl DeviceScan,
+    (vid, pid): (u16, u16),
+    interval: Duration,
+    running: &AtomicBool,
+) -> Result<bool> {
+    let found = poll(interval, running, || {
+        Ok(scan(devices, vid, pid).then_some(()))
+    })?;
+
+    Ok(found.is_some())
+}
+
+/// Whether a `vid:pid` device is on `devices`. A failed scan is only
+/// logged, the device being away may well be why it failed.
+fn scan(devices: &mut impl DeviceScan, vid: u16, pid: u16) -> bool {
+    devices.has_device(vid, pid).unwrap_or_else(|e| {
+        warn!("Failed to look for the tablet, trying again: {e}");
+        false
+    })
+}
+
+/// Sets its flag when the device it was registered for arrives.
+struct Arrival(Arc<AtomicBool>);
+
+impl<T: UsbContext> Hotplug<T> for Arrival {
+    fn device_arrived(&mut self, _device: Device<T>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    fn device_left(&mut self, _device: Device<T>) {}
+}
+
+/// Calls `probe` every `interval` until it finds something, or returns
+/// `None` once `running` is cleared.
+pub fn poll<T>(
+    interval: Duration,
+    running: &AtomicBool,
+    mut probe: impl FnMut() -> Result<Option<T>>,
+) -> Result<Option<T>> {
+    loop {
+        if let Some(found) = probe()? {
+            return Ok(Some(found));
+        }
+        if !sleep_while_running(interval, running) {
+            return Ok(None);
+        }
+    }
+}
+
+/// Sleeps for `duration` in short steps, `false` as soon as `running` is
+/// cleared.
+pub fn sleep_while_running(duration: Duration, running: &AtomicBool) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if !running.load(Ordering::SeqCst) {
+            return false;
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(Duration::from_millis(50)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_failures_back_off_then_give_up() {
+        let mut backoff = Backoff::new(ReconnectConfig {
+            enabled: true,
+            initial_delay_ms: 500,
+            max_delay_ms: 3000,
+            max_attempts: 5,
+            poll_interval_ms: 1000,
+        });
+
+        let delays: Vec<u64> = std::iter::from_fn(|| backoff.next_delay())
+            .map(|delay| delay.as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 3000, 3000]);
+        assert_eq!(backoff.next_delay(), None);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn polling_finds_the_device_once_it_shows_up() {
+        let running = AtomicBool::new(true);
+        let mut scans = 0;
+        let found = poll(Duration::from_millis(1), &running, || {
+            scans += 1;
+            Ok((scans == 3).then_some("tablet"))
+        })
+        .unwrap();
+        assert_eq!((found, scans), (Some("tablet"), 3));
+
+        running.store(false, Ordering::SeqCst);
+        let found = poll(Duration::from_millis(1), &running, || Ok(None::<()>)).unwrap();
+        assert_eq!(found, None);
+    }
+
+    /// No device, then a failing scan, then the device.
+    struct Replugged {
+        scans: Vec<Result<bool, FtdError>>,
+    }
+
+    impl DeviceScan for Replugged {
+        fn has_device(&mut self, _vid: u16, _pid: u16) -> Result<bool, FtdError> {
+            self.scans.pop().unwrap_or(Ok(true))
+        }
+    }
+
+    #[test]
+    fn a_failed_scan_keeps_waiting_for_the_device() {
+        let running = AtomicBool::new(true);
+        let mut devices = Replugged {
+            scans: vec![Err(FtdError::Usb(rusb::Error::Io)), Ok(false)],
+        };
+
+        let found = poll_for_device(
+            &mut devices,
+            (0x08F2, 0x6811),
+            Duration::from_millis(1),
+            &running,
+        );
+        assert!(found.unwrap());
+        assert!(devices.scans.is_empty());
+    }
+}
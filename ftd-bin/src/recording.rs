@@ -0,0 +1,235 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::replay::format_line;
+
+/// How a recording is compressed, picked by the extension of its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// `.gz` is gzip and `.zst` zstd, anything else stays plain text to
+    /// read and grep as it is.
+    pub fn for_path(path: &Path) -> Compression {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// The program that does it, which has to be installed.
+    fn program(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gzip"),
+            Compression::Zstd => Some("zstd"),
+        }
+    }
+}
+
+/// The error for `program` failing to start, saying to install it when it
+/// is not there.
+fn not_started(program: &str, what: &str, e: io::Error) -> anyhow::Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        anyhow!(
+            "`{program}` is needed to {what} the recording but is not installed: \
+             install it, or record to a path without `.gz` or `.zst`"
+        )
+    } else {
+        anyhow!("Failed to start {program} to {what} the recording: {e}")
+    }
+}
+
+/// Writes the reports read to a file in the lines `replay` reads back,
+/// piped through `gzip` or `zstd` for `Compression::for_path`.
+pub struct Recorder {
+    writer: Option<BufWriter<Box<dyn Write>>>,
+    compressor: Option<(&'static str, Child)>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Recorder> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create the recording {}", path.display()))?;
+        let Some(program) = Compression::for_path(path).program() else {
+            return Ok(Recorder {
+                writer: Some(BufWriter::new(Box::new(file))),
+                compressor: None,
+            });
+        };
+
+        let mut child = match Command::new(program)
+            .args(["-c", "-q"])
+            .stdin(Stdio::piped())
+            .stdout(file)
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = fs::remove_file(path);
+                return Err(not_started(program, "compress", e));
+            }
+        };
+        let stdin = child.stdin.take().context("No stdin to the compressor")?;
+
+        Ok(Recorder {
+            writer: Some(BufWriter::new(Box::new(stdin))),
+            compressor: Some((program, child)),
+        })
+    }
+
+    pub fn record(&mut self, interface: u8, bytes: &[u8]) -> Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writeln!(writer, "{}", format_line(interface, bytes))?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes what is buffered and waits for the compressor to write its
+    /// end, without which the file does not decompress.
+    pub fn finish(mut self) -> Result<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        if let Some((program, mut child)) = self.compressor.take() {
+            let status = child.wait()?;
+            if !status.success() {
+                bail!("{program} exited with {status} compressing the recording");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// The text of the recording at `path`, decompressed by its extension.
+pub fn read(path: &Path) -> Result<String> {
+    let Some(program) = Compression::for_path(path).program() else {
+        return fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the recording {}", path.display()));
+    };
+
+    let output = Command::new(program)
+        .arg("-dc")
+        .arg(path)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| not_started(program, "decompress", e))?;
+    if !output.status.success() {
+        bail!(
+            "{program} exited with {} decompressing {}",
+            output.status,
+            path.display()
+        );
+    }
+
+    String::from_utf8(output.stdout)
+        .with_context(|| format!("The recording {} is not text", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        driver::recording_driver,
+        replay::{parse_line, replay},
+    };
+
+    fn replayed(recording: &str) -> Vec<(evdev_rs::enums::EventCode, i32)> {
+        let (mut driver, sink) = recording_driver(&Config::default());
+        replay(&mut driver, recording).unwrap();
+        driver.teardown().unwrap();
+
+        sink.take()
+    }
+
+    /// Records through `extension`'s compressor and checks that the file is
+    /// a valid, smaller, `magic` stream that replays like the plain text.
+    fn round_trip(extension: &str, magic: &[u8]) {
+        let reports: [(u8, &[u8]); 3] = [
+            (
+                2,
+                &[0x09, 0x41, 0x10, 0x10, 0x00, 0x20, 0xFF, 0x1F, 0x00, 0x00],
+            ),
+            (1, &[0x01, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00]),
+            (1, &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+        ];
+        let path =
+            std::env::temp_dir().join(format!("ftd-record-{}.{extension}", std::process::id()));
+
+        let mut recorder = Recorder::create(&path).unwrap();
+        for _ in 0..100 {
+            for (interface, bytes) in reports {
+                recorder.record(interface, bytes).unwrap();
+            }
+        }
+        recorder.finish().unwrap();
+
+        let program = Compression::for_path(&path).program().unwrap();
+        let tested = Command::new(program)
+            .arg("-t")
+            .arg(&path)
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        let compressed = fs::read(&path).unwrap();
+        let recording = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(tested.success(), "{program} -t failed");
+        assert!(compressed.starts_with(magic));
+
+        let plain: String = reports
+            .iter()
+            .map(|(interface, bytes)| format_line(*interface, bytes) + "\n")
+            .collect::<String>()
+            .repeat(100);
+        assert!(compressed.len() < plain.len() / 10);
+        assert_eq!(recording, plain);
+        let lines: Vec<_> = recording.lines().filter_map(parse_line).collect();
+        assert_eq!(lines.len(), 300);
+        let events = replayed(&recording);
+        assert!(!events.is_empty());
+        assert_eq!(events, replayed(&plain));
+    }
+
+    #[test]
+    #[ignore = "needs gzip installed"]
+    fn a_gzip_recording_replays_into_the_same_events() {
+        round_trip("gz", &[0x1F, 0x8B]);
+    }
+
+    #[test]
+    #[ignore = "needs zstd installed"]
+    fn a_zstd_recording_replays_into_the_same_events() {
+        round_trip("zst", &[0x28, 0xB5, 0x2F, 0xFD]);
+    }
+
+    #[test]
+    fn a_missing_compressor_says_to_install_it() {
+        let e = not_started("zstd", "compress", io::ErrorKind::NotFound.into());
+        assert!(e.to_string().contains("`zstd` is needed"), "{e}");
+    }
+}
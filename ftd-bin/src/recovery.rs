@@ -0,0 +1,238 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+/// One way of getting a tablet whose reads fail working again, from the
+/// lightest to the heaviest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryStep {
+    /// Release and claim the interfaces again and resend the init packets.
+    SoftReset,
+    /// A USB port reset, then claim and init. The device may re-enumerate.
+    HardReset,
+    /// Close the device and open, claim and init it again.
+    Reopen,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecoveryConfig {
+    /// Tried in order when reads fail, each one after the one before did
+    /// not help. Empty stops the driver at the first failed read.
+    pub steps: Vec<RecoveryStep>,
+    /// At most this many re-inits, by recovery, reconnecting or on request,
+    /// within `window_secs`, 0 allows any number.
+    pub max_reinits: u32,
+    pub window_secs: u64,
+    /// How long a tablet that used them all up is left alone.
+    pub cooldown_secs: u64,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        RecoveryConfig {
+            steps: vec![
+                RecoveryStep::SoftReset,
+                RecoveryStep::HardReset,
+                RecoveryStep::Reopen,
+            ],
+            max_reinits: 5,
+            window_secs: 60,
+            cooldown_secs: 300,
+        }
+    }
+}
+
+/// What a recovery step is run on.
+pub trait Recover {
+    fn run(&mut self, step: RecoveryStep) -> Result<()>;
+}
+
+/// Walks the steps one failure at a time until reads work again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escalation {
+    steps: Vec<RecoveryStep>,
+    next: usize,
+}
+
+impl Escalation {
+    pub fn new(config: &RecoveryConfig) -> Escalation {
+        Escalation {
+            steps: config.steps.clone(),
+            next: 0,
+        }
+    }
+
+    /// Runs the next step not tried since the last `reset`, moving on to the
+    /// one after right away when it fails. Returns the step that went
+    /// through, or `None` once every step was tried.
+    pub fn recover(&mut self, target: &mut dyn Recover) -> Option<RecoveryStep> {
+        while let Some(&step) = self.steps.get(self.next) {
+            self.next += 1;
+            match target.run(step) {
+                Ok(()) => return Some(step),
+                Err(e) => warn!("Recovery by {step:?} failed: {e:#}"),
+            }
+        }
+
+        None
+    }
+
+    /// Starts over from the lightest step, once a report was read again.
+    pub fn reset(&mut self) {
+        self.next = 0;
+    }
+}
+
+/// Keeps a broken tablet from being re-initialized over and over: past
+/// `max_reinits` within the window it waits out the cooldown instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReinitLimiter {
+    max: u32,
+    window: Duration,
+    cooldown: Duration,
+    recent: VecDeque<Instant>,
+    cooling_until: Option<Instant>,
+}
+
+impl ReinitLimiter {
+    pub fn new(config: &RecoveryConfig) -> ReinitLimiter {
+        ReinitLimiter {
+            max: config.max_reinits,
+            window: Duration::from_secs(config.window_secs),
+            cooldown: Duration::from_secs(config.cooldown_secs),
+            recent: VecDeque::new(),
+            cooling_until: None,
+        }
+    }
+
+    /// Counts a re-init at `now`, or returns how long to wait before the
+    /// next one is allowed.
+    pub fn admit(&mut self, now: Instant) -> Result<(), Duration> {
+        if self.max == 0 {
+            return Ok(());
+        }
+        if let Some(until) = self.cooling_until {
+            if now < until {
+                return Err(until - now);
+            }
+            self.cooling_until = None;
+            self.recent.clear();
+        }
+        while self
+            .recent
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) >= self.window)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= self.max as usize {
+            error!(
+                "The tablet was re-initialized {} times within {:?}, it looks broken: \
+                 leaving it alone for {:?}",
+                self.max, self.window, self.cooldown
+            );
+            self.cooling_until = Some(now + self.cooldown);
+            return Err(self.cooldown);
+        }
+        self.recent.push_back(now);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+
+    #[derive(Default)]
+    struct FakeDevice {
+        runs: Vec<RecoveryStep>,
+        broken: Vec<RecoveryStep>,
+    }
+
+    impl Recover for FakeDevice {
+        fn run(&mut self, step: RecoveryStep) -> Result<()> {
+            self.runs.push(step);
+            if self.broken.contains(&step) {
+                bail!("{step:?} did not work");
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn escalates_from_soft_reset_to_reopen() {
+        let mut escalation = Escalation::new(&RecoveryConfig::default());
+        let mut device = FakeDevice {
+            broken: vec![RecoveryStep::HardReset],
+            ..FakeDevice::default()
+        };
+
+        assert_eq!(
+            escalation.recover(&mut device),
+            Some(RecoveryStep::SoftReset)
+        );
+        // Reads still fail: the hard reset fails too, so on to reopening.
+        assert_eq!(escalation.recover(&mut device), Some(RecoveryStep::Reopen));
+        assert_eq!(escalation.recover(&mut device), None);
+        assert_eq!(
+            device.runs,
+            vec![
+                RecoveryStep::SoftReset,
+                RecoveryStep::HardReset,
+                RecoveryStep::Reopen
+            ]
+        );
+
+        escalation.reset();
+        assert_eq!(
+            escalation.recover(&mut device),
+            Some(RecoveryStep::SoftReset)
+        );
+        assert_eq!(
+            Escalation::new(&RecoveryConfig {
+                steps: vec![],
+                ..RecoveryConfig::default()
+            })
+            .recover(&mut device),
+            None
+        );
+    }
+
+    #[test]
+    fn allows_a_burst_of_reinits_then_cools_down() {
+        let mut limiter = ReinitLimiter::new(&RecoveryConfig {
+            max_reinits: 3,
+            window_secs: 10,
+            cooldown_secs: 60,
+            ..RecoveryConfig::default()
+        });
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        for secs in [0, 1, 2] {
+            assert_eq!(limiter.admit(at(secs)), Ok(()));
+        }
+        assert_eq!(limiter.admit(at(3)), Err(Duration::from_secs(60)));
+        assert_eq!(limiter.admit(at(33)), Err(Duration::from_secs(30)));
+        // Past the cooldown the tablet gets a fresh burst.
+        for secs in [63, 64, 65] {
+            assert_eq!(limiter.admit(at(secs)), Ok(()));
+        }
+        // Re-inits spread out further than the window never trip it.
+        let mut spread = ReinitLimiter::new(&RecoveryConfig {
+            max_reinits: 1,
+            window_secs: 10,
+            ..RecoveryConfig::default()
+        });
+        assert!((0..5).all(|step| spread.admit(at(step * 10)).is_ok()));
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::{Result, bail};
+
+use ftd_device::report::{BUTTON_COUNT, ButtonEvent};
+
+/// Corrects the button numbering of units whose bits do not match the
+/// labels, `table[decoded index]` being the logical index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ButtonRemap {
+    table: Vec<u8>,
+}
+
+impl ButtonRemap {
+    /// An empty `table` keeps the numbering of `report::BUTTON_BITS`.
+    pub fn new(table: Vec<u8>) -> Result<ButtonRemap> {
+        if table.is_empty() {
+            return Ok(ButtonRemap::default());
+        }
+        if table.len() != BUTTON_COUNT {
+            bail!(
+                "button_remap has {} entries, it needs one per button ({BUTTON_COUNT})",
+                table.len()
+            );
+        }
+
+        let mut seen = [false; BUTTON_COUNT];
+        for &index in &table {
+            match seen.get_mut(index as usize) {
+                None => bail!("button_remap entry {index} is not a button index"),
+                Some(true) => bail!("button_remap maps two buttons to {index}"),
+                Some(seen) => *seen = true,
+            }
+        }
+
+        Ok(ButtonRemap { table })
+    }
+
+    pub fn apply(&self, event: ButtonEvent) -> ButtonEvent {
+        ButtonEvent {
+            index: self
+                .table
+                .get(event.index as usize)
+                .copied()
+                .unwrap_or(event.index),
+            ..event
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(index: u8) -> ButtonEvent {
+        ButtonEvent {
+            index,
+            pressed: true,
+        }
+    }
+
+    #[test]
+    fn swaps_the_listed_buttons_only() {
+        let mut table: Vec<u8> = (0..BUTTON_COUNT as u8).collect();
+        table.swap(11, 12);
+        let remap = ButtonRemap::new(table).unwrap();
+
+        assert_eq!(remap.apply(press(11)), press(12));
+        assert_eq!(remap.apply(press(12)), press(11));
+        assert_eq!(remap.apply(press(3)), press(3));
+        assert_eq!(ButtonRemap::new(vec![]).unwrap().apply(press(5)), press(5));
+    }
+
+    #[test]
+    fn rejects_tables_that_are_not_a_permutation() {
+        let mut duplicate: Vec<u8> = (0..BUTTON_COUNT as u8).collect();
+        duplicate[0] = 1;
+        let mut out_of_range: Vec<u8> = (0..BUTTON_COUNT as u8).collect();
+        out_of_range[0] = BUTTON_COUNT as u8;
+
+        assert!(ButtonRemap::new(duplicate).is_err());
+        assert!(ButtonRemap::new(out_of_range).is_err());
+        assert!(ButtonRemap::new(vec![0, 1, 2]).is_err());
+    }
+}
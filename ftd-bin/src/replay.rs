@@ -0,0 +1,115 @@
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+
+use crate::driver::Driver;
+
+/// The line printed for a report read on `interface`, which `parse_line`
+/// reads back.
+pub fn format_line(interface: u8, bytes: &[u8]) -> String {
+    format!("Interface: {interface} || Bytes: {bytes:02X?}")
+}
+
+/// Parses one of the `Interface: 2 || Bytes: [09, C0, ..]` lines the driver
+/// prints for every report it reads.
+pub fn parse_line(line: &str) -> Option<(u8, Vec<u8>)> {
+    let (interface, bytes) = line.trim().split_once(" || Bytes: ")?;
+    let interface = interface.strip_prefix("Interface: ")?.parse().ok()?;
+    let bytes = bytes.strip_prefix('[')?.strip_suffix(']')?;
+    let bytes = bytes
+        .split(", ")
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<_>>()?;
+
+    Some((interface, bytes))
+}
+
+/// Feeds the reports of a recorded run through `driver` in order and
+/// returns how many there were. Other lines are skipped. The recording has
+/// no timing, each report is stamped as it is replayed.
+pub fn replay(driver: &mut Driver, recording: &str) -> Result<usize> {
+    let mut count = 0;
+    for (number, line) in recording.lines().enumerate() {
+        let Some((interface, bytes)) = parse_line(line) else {
+            continue;
+        };
+        driver
+            .handle_report(interface, &bytes, Instant::now())
+            .with_context(|| format!("Failed to emit the report on line {}", number + 1))?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        driver::{Settings, recording_driver_with},
+        emitter::EmitterState,
+    };
+    use evdev_rs::enums::{EV_ABS, EV_KEY, EV_SYN, EventCode};
+
+    const RECORDING: &str = "\
+Interface: 2 || Bytes: [09, 40, 00, 10, 00, 20, 00, 00, 00, 00]
+Interface: 2 || Bytes: [09, 41, 10, 10, 00, 20, FF, 1F, 00, 00]
+Interface: 1 || Bytes: [01, 00, 00, 00, 00, 04, 00, 00]
+some unrelated output
+Interface: 1 || Bytes: [01, 00, 00, 00, 00, 00, 00, 00]
+Interface: 2 || Bytes: [09, 00, 00, 00, 00, 00, 00, 00, 00, 00]
+";
+
+    #[test]
+    fn parses_the_printed_report_lines() {
+        assert_eq!(
+            parse_line("Interface: 1 || Bytes: [01, 0A, FF]"),
+            Some((1, vec![0x01, 0x0A, 0xFF]))
+        );
+        assert_eq!(parse_line("Interface: 1 || Bytes: []"), Some((1, vec![])));
+        assert_eq!(parse_line("Interface: 1 || Bytes: [0G]"), None);
+        assert_eq!(parse_line("Using config /etc/freetomate/config.toml"), None);
+    }
+
+    #[test]
+    fn replays_reports_into_the_exact_events() {
+        let config = Config::default();
+        let settings = Settings::from_config(&config).unwrap();
+        let mapper = &settings.mapper;
+        let pen_bounds = (mapper.desktop.width - 1, mapper.desktop.height - 1);
+        let state = EmitterState::new(config.button_map.clone(), true, pen_bounds);
+        let (mut driver, sink) = recording_driver_with(settings, state);
+
+        assert_eq!(replay(&mut driver, RECORDING).unwrap(), 5);
+        driver.teardown().unwrap();
+
+        let abs = |axis, value| (EventCode::EV_ABS(axis), value);
+        let key = |key, value| (EventCode::EV_KEY(key), value);
+        let syn = (EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+        assert_eq!(
+            sink.take(),
+            vec![
+                key(EV_KEY::BTN_TOOL_PEN, 1),
+                abs(EV_ABS::ABS_X, 0x1000),
+                abs(EV_ABS::ABS_Y, 0x2000),
+                abs(EV_ABS::ABS_PRESSURE, 0),
+                syn,
+                abs(EV_ABS::ABS_X, 0x1010),
+                abs(EV_ABS::ABS_Y, 0x2000),
+                abs(EV_ABS::ABS_PRESSURE, 0x1FFF),
+                key(EV_KEY::BTN_TOUCH, 1),
+                syn,
+                key(EV_KEY::KEY_B, 1),
+                syn,
+                key(EV_KEY::KEY_B, 0),
+                syn,
+                key(EV_KEY::BTN_TOUCH, 0),
+                abs(EV_ABS::ABS_PRESSURE, 0),
+                key(EV_KEY::BTN_TOOL_PEN, 0),
+                syn,
+            ]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Smallest rectangle containing both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
+
+/// Source of display geometry, in desktop pixels.
+pub trait ScreenProvider {
+    fn primary_geometry(&self) -> Rect;
+
+    fn output_by_name(&self, name: &str) -> Option<Rect>;
+
+    /// Every output with its name, in the order `Action::CycleOutput`
+    /// steps through them.
+    fn outputs(&self) -> Vec<(String, Rect)> {
+        vec![("primary".to_string(), self.primary_geometry())]
+    }
+
+    /// Bounds of the whole desktop, the space absolute events are reported in.
+    fn desktop_geometry(&self) -> Rect {
+        self.primary_geometry()
+    }
+}
+
+/// Dimensions given by the user instead of queried from the display server.
+pub struct FixedScreen {
+    pub rect: Rect,
+}
+
+impl ScreenProvider for FixedScreen {
+    fn primary_geometry(&self) -> Rect {
+        self.rect
+    }
+
+    fn output_by_name(&self, _name: &str) -> Option<Rect> {
+        None
+    }
+}
+
+/// X11 outputs as reported by `xrandr --query`.
+pub struct X11Screen {
+    outputs: Vec<(String, Rect, bool)>,
+}
+
+impl X11Screen {
+    pub fn query() -> std::io::Result<X11Screen> {
+        let output = Command::new("xrandr").arg("--query").output()?;
+        if !output.status.success() {
+            return Err(std::io::Error::other("xrandr --query failed"));
+        }
+
+        Ok(X11Screen::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    fn parse(query: &str) -> X11Screen {
+        let outputs = query
+            .lines()
+            .filter(|line| line.contains(" connected"))
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                let name = words.next()?.to_string();
+                let primary = line.contains(" primary ");
+                let geometry = words.find_map(parse_geometry)?;
+
+                Some((name, geometry, primary))
+            })
+            .collect();
+
+        X11Screen { outputs }
+    }
+}
+
+impl ScreenProvider for X11Screen {
+    fn primary_geometry(&self) -> Rect {
+        self.outputs
+            .iter()
+            .find(|(_, _, primary)| *primary)
+            .or(self.outputs.first())
+            .map(|(_, rect, _)| *rect)
+            .unwrap_or(Rect::new(0, 0, 1920, 1080))
+    }
+
+    fn output_by_name(&self, name: &str) -> Option<Rect> {
+        self.outputs
+            .iter()
+            .find(|(output, _, _)| output == name)
+            .map(|(_, rect, _)| *rect)
+    }
+
+    fn outputs(&self) -> Vec<(String, Rect)> {
+        self.outputs
+            .iter()
+            .map(|(name, rect, _)| (name.clone(), *rect))
+            .collect()
+    }
+
+    fn desktop_geometry(&self) -> Rect {
+        self.outputs
+            .iter()
+            .map(|(_, rect, _)| *rect)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_else(|| self.primary_geometry())
+    }
+}
+
+/// Parses `WxH+X+Y`.
+fn parse_geometry(word: &str) -> Option<Rect> {
+    let (size, position) = word.split_once('+')?;
+    let (width, height) = size.split_once('x')?;
+    let (x, y) = position.split_once('+')?;
+
+    Some(Rect::new(
+        x.parse().ok()?,
+        y.parse().ok()?,
+        width.parse().ok()?,
+        height.parse().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY: &str = "\
+Screen 0: minimum 8 x 8, current 4480 x 1440, maximum 32767 x 32767
+eDP-1 connected 1920x1080+0+360 (normal left inverted right x axis y axis) 344mm x 193mm
+   1920x1080     60.00*+
+HDMI-1 connected primary 2560x1440+1920+0 (normal left inverted right x axis y axis) 597mm x 336mm
+DP-1 disconnected (normal left inverted right x axis y axis)
+";
+
+    #[test]
+    fn parses_xrandr_outputs() {
+        let screen = X11Screen::parse(QUERY);
+
+        assert_eq!(screen.primary_geometry(), Rect::new(1920, 0, 2560, 1440));
+        assert_eq!(
+            screen.output_by_name("eDP-1"),
+            Some(Rect::new(0, 360, 1920, 1080))
+        );
+        assert_eq!(screen.output_by_name("DP-1"), None);
+        assert_eq!(screen.desktop_geometry(), Rect::new(0, 0, 4480, 1440));
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::{Duration, Instant};
+
+use ftd_device::report::{Event, Timestamped};
+
+use crate::read_stats::ReadCounts;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// What one run of the driver handled, summed up when it stops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionStats {
+    started: Instant,
+    pub pen_samples: u64,
+    pub button_presses: u64,
+    pub touch_reports: u64,
+    /// Init packets sent again after startup: on request, after a failed
+    /// first read, on recovery and on reconnecting.
+    pub reinits: u64,
+    /// Most events within one `RATE_WINDOW`.
+    pub peak_rate: u64,
+    /// The window being counted and the events in it so far.
+    window: Option<(Instant, u64)>,
+}
+
+impl SessionStats {
+    pub fn new(started: Instant) -> SessionStats {
+        SessionStats {
+            started,
+            pen_samples: 0,
+            button_presses: 0,
+            touch_reports: 0,
+            reinits: 0,
+            peak_rate: 0,
+            window: None,
+        }
+    }
+
+    pub fn observe(&mut self, stamped: &Timestamped) {
+        match &stamped.event {
+            Event::Pen(_) => self.pen_samples += 1,
+            Event::Button(button) if button.pressed => self.button_presses += 1,
+            Event::Button(_) => {}
+            Event::Touch(_) => self.touch_reports += 1,
+            Event::Raw(_) => {}
+        }
+
+        let (since, count) = match self.window {
+            Some((since, count)) if stamped.at.saturating_duration_since(since) < RATE_WINDOW => {
+                (since, count + 1)
+            }
+            _ => (stamped.at, 1),
+        };
+        self.window = Some((since, count));
+        self.peak_rate = self.peak_rate.max(count);
+    }
+
+    pub fn reinit(&mut self) {
+        self.reinits += 1;
+    }
+
+    /// The block logged on exit, with the share of reads of each interface
+    /// that timed out.
+    pub fn summary(&self, now: Instant, reads: &[(u8, ReadCounts)]) -> Vec<String> {
+        let runtime = now.saturating_duration_since(self.started).as_secs();
+        let mut lines = vec![
+            format!(
+                "Session: {}h {:02}m {:02}s",
+                runtime / 3600,
+                runtime / 60 % 60,
+                runtime % 60
+            ),
+            format!("  pen samples     {}", self.pen_samples),
+            format!("  button presses  {}", self.button_presses),
+            format!("  touch reports   {}", self.touch_reports),
+            format!("  re-inits        {}", self.reinits),
+            format!("  peak rate       {} events/s", self.peak_rate),
+        ];
+        for (interface, counts) in reads {
+            let total = counts.reports + counts.timeouts + counts.pipe_errors + counts.other_errors;
+            if total == 0 {
+                continue;
+            }
+            lines.push(format!(
+                "  interface {interface}     {:.1}% of {total} reads timed out",
+                counts.timeouts as f64 * 100.0 / total as f64
+            ));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftd_device::report::{ButtonEvent, PenReport};
+
+    #[test]
+    fn sums_up_a_known_sequence_of_events() {
+        let start = Instant::now();
+        let mut stats = SessionStats::new(start);
+        let pen = Event::Pen(PenReport {
+            x: 0,
+            y: 0,
+            pressure: 0,
+            in_range: true,
+            tip: false,
+        });
+        let button = |pressed| Event::Button(ButtonEvent { index: 0, pressed });
+        // Three events in the first second, two in the next.
+        let events = [
+            (0, pen.clone()),
+            (100, button(true)),
+            (200, button(false)),
+            (1500, pen.clone()),
+            (1600, pen),
+        ];
+        for (ms, event) in events {
+            stats.observe(&Timestamped {
+                at: start + Duration::from_millis(ms),
+                event,
+            });
+        }
+        stats.reinit();
+
+        assert_eq!(
+            (
+                stats.pen_samples,
+                stats.button_presses,
+                stats.reinits,
+                stats.peak_rate
+            ),
+            (3, 1, 1, 3)
+        );
+        let reads = [(
+            2,
+            ReadCounts {
+                reports: 3,
+                timeouts: 1,
+                ..ReadCounts::default()
+            },
+        )];
+        let summary = stats.summary(start + Duration::from_secs(3725), &reads);
+        assert_eq!(summary[0], "Session: 1h 02m 05s");
+        assert_eq!(
+            summary.last().unwrap(),
+            "  interface 2     25.0% of 4 reads timed out"
+        );
+    }
+}
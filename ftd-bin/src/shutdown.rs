@@ -0,0 +1,67 @@
+use std::fmt;
+
+/// Why the driver exited, logged last and turned into the exit status so a
+/// service manager can tell a stop from a failure.
+#[derive(Debug)]
+pub enum ShutdownReason {
+    /// Ctrl+C or SIGTERM, or a subcommand that ran to its end.
+    Requested,
+    /// The tablet disconnected and was still gone after this many
+    /// reconnect attempts.
+    GaveUpReconnecting(u32),
+    /// Reads kept failing after every `recovery` step.
+    ReadsFailed,
+    /// Anything else that stopped the driver, e.g. a config that does not
+    /// load, a tablet that cannot be opened or a thread that panicked.
+    Failed(anyhow::Error),
+}
+
+impl ShutdownReason {
+    /// 0 only for a requested stop, so `Restart=on-failure` restarts the
+    /// rest.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ShutdownReason::Requested => 0,
+            ShutdownReason::Failed(_) => 1,
+            ShutdownReason::GaveUpReconnecting(_) => 2,
+            ShutdownReason::ReadsFailed => 3,
+        }
+    }
+}
+
+impl fmt::Display for ShutdownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownReason::Requested => write!(f, "Stopped on request"),
+            ShutdownReason::GaveUpReconnecting(attempts) => write!(
+                f,
+                "The tablet did not come back after {attempts} attempts, check that it is \
+                 plugged in and that its USB node is accessible"
+            ),
+            ShutdownReason::ReadsFailed => {
+                write!(f, "Reads kept failing, out of recovery steps")
+            }
+            ShutdownReason::Failed(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_requested_stop_exits_with_zero() {
+        assert_eq!(ShutdownReason::Requested.exit_code(), 0);
+
+        let failures = [
+            ShutdownReason::Failed(anyhow::anyhow!("Failed to open the tablet")),
+            ShutdownReason::GaveUpReconnecting(10),
+            ShutdownReason::ReadsFailed,
+        ];
+        let mut codes: Vec<u8> = failures.iter().map(ShutdownReason::exit_code).collect();
+        assert!(codes.iter().all(|&code| code != 0));
+        codes.dedup();
+        assert_eq!(codes.len(), failures.len());
+    }
+}
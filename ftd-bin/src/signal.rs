@@ -0,0 +1,86 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+static REINIT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reinit(_: libc::c_int) {
+    REINIT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes `SIGUSR1` request a re-send of the init packets.
+pub fn install_reinit_handler() {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            request_reinit as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+/// Returns whether a re-init was requested since the last call.
+pub fn take_reinit_request() -> bool {
+    REINIT_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// Stop reading, release the tablet and exit.
+    Graceful,
+    /// Exit now, for when a stuck USB call keeps the graceful stop from
+    /// finishing.
+    Force,
+}
+
+/// Turns Ctrl+C presses into what to do: the first one stops gracefully,
+/// another one within `window` of it forces the exit. One after the
+/// window starts over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interrupts {
+    window: Duration,
+    first: Option<Instant>,
+}
+
+impl Interrupts {
+    /// A zero `window` never forces.
+    pub fn new(window: Duration) -> Interrupts {
+        Interrupts {
+            window,
+            first: None,
+        }
+    }
+
+    pub fn press(&mut self, now: Instant) -> Interrupt {
+        match self.first {
+            Some(first) if !self.window.is_zero() && now.duration_since(first) <= self.window => {
+                Interrupt::Force
+            }
+            _ => {
+                self.first = Some(now);
+                Interrupt::Graceful
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_second_press_within_the_window_forces() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut interrupts = Interrupts::new(Duration::from_millis(2000));
+
+        assert_eq!(interrupts.press(at(0)), Interrupt::Graceful);
+        assert_eq!(interrupts.press(at(2500)), Interrupt::Graceful);
+        assert_eq!(interrupts.press(at(4000)), Interrupt::Force);
+
+        let mut never = Interrupts::new(Duration::ZERO);
+        assert_eq!(never.press(at(0)), Interrupt::Graceful);
+        assert_eq!(never.press(at(1)), Interrupt::Graceful);
+    }
+}
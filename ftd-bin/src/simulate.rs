@@ -0,0 +1,121 @@
+use std::{f64::consts::TAU, time::Duration};
+
+use anyhow::{Result, bail};
+
+use ftd_device::report::{DEVICE_MAX_PRESSURE, DEVICE_MAX_X, DEVICE_MAX_Y, PEN_REPORT_ID};
+
+/// The path the simulated pen draws.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pattern {
+    /// Round and round the middle of the tablet.
+    #[default]
+    Circle,
+    /// Left to right and back along a wave.
+    Sine,
+}
+
+impl Pattern {
+    pub fn parse(name: &str) -> Result<Pattern> {
+        match name {
+            "circle" => Ok(Pattern::Circle),
+            "sine" => Ok(Pattern::Sine),
+            _ => bail!("Unknown pattern {name}, expected circle or sine"),
+        }
+    }
+}
+
+/// Pen reports for the `simulate` subcommand, with the tip down and the
+/// pressure rising and falling as the pen goes, for trying the uinput
+/// device or recording a demo without the tablet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Simulation {
+    /// The tablet interface the reports are handed in as read from.
+    pub interface: u8,
+    pub pattern: Pattern,
+    /// How long one lap of the pattern takes.
+    pub period: Duration,
+}
+
+impl Simulation {
+    /// The pen report at `elapsed` into the run.
+    pub fn report(&self, elapsed: Duration) -> Vec<u8> {
+        let phase = (elapsed.as_secs_f64() / self.period.as_secs_f64()).fract();
+        let (x, y) = self.position(phase);
+        let pressure = DEVICE_MAX_PRESSURE as f64 * (0.5 - 0.4 * (TAU * phase).cos());
+
+        let mut bytes = vec![PEN_REPORT_ID, 0x41];
+        bytes.extend((x.round() as u16).to_le_bytes());
+        bytes.extend((y.round() as u16).to_le_bytes());
+        bytes.extend((pressure.round() as u16).to_le_bytes());
+        bytes.extend([0, 0]);
+
+        bytes
+    }
+
+    /// Where the pen is `phase` (0 to 1) into a lap, kept off the edges.
+    fn position(&self, phase: f64) -> (f64, f64) {
+        let (mid_x, mid_y) = (DEVICE_MAX_X as f64 / 2.0, DEVICE_MAX_Y as f64 / 2.0);
+        let angle = TAU * phase;
+        match self.pattern {
+            Pattern::Circle => (
+                mid_x + 0.4 * mid_x * angle.cos(),
+                mid_y + 0.4 * mid_y * angle.sin(),
+            ),
+            Pattern::Sine => {
+                // Out and back, so a lap ends where it started.
+                let across = 1.0 - (2.0 * phase - 1.0).abs();
+                (
+                    mid_x * (0.2 + 1.6 * across),
+                    mid_y + 0.4 * mid_y * (2.0 * angle).sin(),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftd_device::{device::TABLET_INTERFACE, report::PenReport};
+
+    fn pen(simulation: &Simulation, ms: u64) -> PenReport {
+        PenReport::parse(&simulation.report(Duration::from_millis(ms))).unwrap()
+    }
+
+    #[test]
+    fn a_circle_stays_on_the_tablet_and_comes_round() {
+        let circle = Simulation {
+            interface: TABLET_INTERFACE,
+            pattern: Pattern::Circle,
+            period: Duration::from_secs(4),
+        };
+
+        let reports: Vec<PenReport> = (0..400).map(|i| pen(&circle, i * 10)).collect();
+        for report in &reports {
+            assert!(report.in_range && report.tip);
+            assert!((0..=DEVICE_MAX_X).contains(&(report.x as i32)));
+            assert!((0..=DEVICE_MAX_Y).contains(&(report.y as i32)));
+            assert!((1..=DEVICE_MAX_PRESSURE).contains(&(report.pressure as i32)));
+        }
+        let (start, quarter) = (pen(&circle, 0), pen(&circle, 1000));
+        assert!(start.x > quarter.x && start.y < quarter.y);
+        assert_eq!(pen(&circle, 4000), start);
+        assert!(pen(&circle, 2000).pressure > start.pressure);
+    }
+
+    #[test]
+    fn a_sine_sweeps_across_and_back() {
+        let sine = Simulation {
+            interface: TABLET_INTERFACE,
+            pattern: Pattern::Sine,
+            period: Duration::from_secs(2),
+        };
+
+        let (start, middle) = (pen(&sine, 0), pen(&sine, 1000));
+        assert!(middle.x > start.x);
+        assert!(middle.y.abs_diff(start.y) <= 1);
+        assert!(pen(&sine, 250).y > start.y);
+        assert_eq!(pen(&sine, 2000), start);
+        assert!(Pattern::parse("spiral").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::VecDeque;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmoothingMode {
+    #[default]
+    Off,
+    /// Mean of the last `samples` positions, rounds corners off.
+    Average,
+    /// Exponential smoothing that backs off as the pen speeds up, so slow
+    /// curves are steadied while fast turns keep their corner.
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmoothingSettings {
+    pub mode: SmoothingMode,
+    /// Window of `average`.
+    pub samples: usize,
+    /// Weight of each new sample in `adaptive` while the pen barely moves.
+    pub min_alpha: f32,
+    /// Weight of each new sample in `adaptive` from `fast_speed` on.
+    pub max_alpha: f32,
+    /// Speed in device units per report.
+    pub fast_speed: f32,
+    /// From this speed on, in device units per report, positions pass
+    /// through `mode` untouched so quick strokes do not lag, 0 disables it.
+    pub bypass_speed: f32,
+    /// Moves shorter than this many device units from the last emitted
+    /// position are dropped, 0 disables it. Applied after `mode`.
+    pub deadband: u16,
+    /// Positions are rounded to the nearest multiple of this many device
+    /// units before `mode`, dropping the low bits a noisy unit wiggles. 1
+    /// leaves them as they are.
+    pub grid: u16,
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        SmoothingSettings {
+            mode: SmoothingMode::Off,
+            samples: 4,
+            min_alpha: 0.2,
+            max_alpha: 1.0,
+            fast_speed: 200.0,
+            bypass_speed: 0.0,
+            deadband: 0,
+            grid: 1,
+        }
+    }
+}
+
+/// Smooths pen positions in device units, one stroke at a time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionSmoother {
+    settings: SmoothingSettings,
+    window: VecDeque<(f32, f32)>,
+    value: Option<(f32, f32)>,
+    last_raw: Option<(f32, f32)>,
+}
+
+impl PositionSmoother {
+    pub fn new(settings: SmoothingSettings) -> Result<PositionSmoother> {
+        if settings.samples == 0 {
+            bail!("smoothing.samples must be at least 1");
+        }
+        if !(settings.min_alpha > 0.0
+            && settings.min_alpha <= settings.max_alpha
+            && settings.max_alpha <= 1.0)
+        {
+            bail!(
+                "smoothing needs 0 < min_alpha ({}) <= max_alpha ({}) <= 1",
+                settings.min_alpha,
+                settings.max_alpha
+            );
+        }
+        if !(settings.fast_speed > 0.0 && settings.fast_speed.is_finite()) {
+            bail!(
+                "smoothing.fast_speed ({}) must be a positive number",
+                settings.fast_speed
+            );
+        }
+
+        if !(settings.bypass_speed >= 0.0 && settings.bypass_speed.is_finite()) {
+            bail!(
+                "smoothing.bypass_speed ({}) must be 0 or a positive number",
+                settings.bypass_speed
+            );
+        }
+
+        if settings.grid == 0 {
+            bail!("smoothing.grid must be at least 1");
+        }
+
+        Ok(PositionSmoother {
+            settings,
+            window: VecDeque::with_capacity(settings.samples),
+            value: None,
+            last_raw: None,
+        })
+    }
+
+    /// Rounds a raw position to `grid`, before `apply`.
+    pub fn quantize(&self, x: u16, y: u16) -> (u16, u16) {
+        let grid = self.settings.grid as u32;
+        let round =
+            |value: u16| ((value as u32 + grid / 2) / grid * grid).min(u16::MAX as u32) as u16;
+
+        (round(x), round(y))
+    }
+
+    pub fn apply(&mut self, x: u16, y: u16) -> (u16, u16) {
+        let raw = (x as f32, y as f32);
+        let last = self.last_raw.replace(raw);
+        let speed = last.map(|last| (raw.0 - last.0).hypot(raw.1 - last.1));
+        if self.settings.bypass_speed > 0.0
+            && speed.is_some_and(|speed| speed >= self.settings.bypass_speed)
+        {
+            // Slowing down again smooths on from here, not from the lag.
+            self.window.clear();
+            self.window.push_back(raw);
+            self.value = Some(raw);
+            return (x, y);
+        }
+
+        let (x, y) = match self.settings.mode {
+            SmoothingMode::Off => raw,
+            SmoothingMode::Average => {
+                if self.window.len() == self.settings.samples {
+                    self.window.pop_front();
+                }
+                self.window.push_back(raw);
+                let count = self.window.len() as f32;
+                let (sum_x, sum_y) = self
+                    .window
+                    .iter()
+                    .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+                (sum_x / count, sum_y / count)
+            }
+            SmoothingMode::Adaptive => {
+                let value = match (self.value, speed) {
+                    (Some(previous), Some(speed)) => {
+                        let t = (speed / self.settings.fast_speed).min(1.0);
+                        let alpha = self.settings.min_alpha
+                            + t * (self.settings.max_alpha - self.settings.min_alpha);
+                        (
+                            previous.0 + alpha * (raw.0 - previous.0),
+                            previous.1 + alpha * (raw.1 - previous.1),
+                        )
+                    }
+                    _ => raw,
+                };
+                self.value = Some(value);
+                value
+            }
+        };
+
+        (x.round() as u16, y.round() as u16)
+    }
+
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.value = None;
+        self.last_raw = None;
+    }
+}
+
+/// Holds the position still until the pen really moves, so a hovering pen
+/// does not wiggle the cursor. Unlike smoothing this never interpolates:
+/// each output is either the last one or the new input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deadband {
+    threshold: u16,
+    reference: Option<(u16, u16)>,
+}
+
+impl Deadband {
+    pub fn new(threshold: u16) -> Deadband {
+        Deadband {
+            threshold,
+            reference: None,
+        }
+    }
+
+    pub fn apply(&mut self, x: u16, y: u16) -> (u16, u16) {
+        if let Some(reference) = self.reference {
+            let distance = (x as f32 - reference.0 as f32).hypot(y as f32 - reference.1 as f32);
+            if distance < self.threshold as f32 {
+                return reference;
+            }
+        }
+        self.reference = Some((x, y));
+
+        (x, y)
+    }
+
+    /// Forgets the reference, for when the pen leaves proximity.
+    pub fn reset(&mut self) {
+        self.reference = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smoother(mode: SmoothingMode) -> PositionSmoother {
+        PositionSmoother::new(SmoothingSettings {
+            mode,
+            ..SmoothingSettings::default()
+        })
+        .unwrap()
+    }
+
+    /// Largest distance of an output point from the stroke it smooths.
+    fn worst_deviation(
+        smoother: &mut PositionSmoother,
+        points: &[(u16, u16)],
+        on_path: impl Fn(f32, f32) -> f32,
+    ) -> f32 {
+        points
+            .iter()
+            .map(|&(x, y)| {
+                let (x, y) = smoother.apply(x, y);
+                on_path(x as f32, y as f32)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn adaptive_keeps_a_fast_corner_sharp() {
+        // Right then straight down at 300 units per report.
+        let corner = (5000.0, 5000.0);
+        let stroke: Vec<(u16, u16)> = (0..10)
+            .map(|i| (2000 + i * 300, 5000))
+            .chain((1..10).map(|i| (5000, 5000 + i * 300)))
+            .collect();
+        let off_path = |x: f32, y: f32| {
+            let to_horizontal = if x <= corner.0 {
+                (y - corner.1).abs()
+            } else {
+                (x - corner.0).hypot(y - corner.1)
+            };
+            let to_vertical = if y >= corner.1 {
+                (x - corner.0).abs()
+            } else {
+                (x - corner.0).hypot(y - corner.1)
+            };
+            to_horizontal.min(to_vertical)
+        };
+
+        let average = worst_deviation(&mut smoother(SmoothingMode::Average), &stroke, off_path);
+        let adaptive = worst_deviation(&mut smoother(SmoothingMode::Adaptive), &stroke, off_path);
+
+        assert!(average > 50.0, "average cut the corner by {average}");
+        assert!(adaptive < 1.0, "adaptive cut the corner by {adaptive}");
+    }
+
+    #[test]
+    fn adaptive_steadies_a_slow_arc() {
+        // A slow arc of radius 2000 with +-30 units of jitter.
+        let center = (8000.0_f32, 8000.0_f32);
+        let arc: Vec<(u16, u16)> = (0..200)
+            .map(|i| {
+                let angle = i as f32 * 0.005;
+                let jitter = if i % 2 == 0 { 30.0 } else { -30.0 };
+                let radius = 2000.0 + jitter;
+                (
+                    (center.0 + radius * angle.cos()).round() as u16,
+                    (center.1 + radius * angle.sin()).round() as u16,
+                )
+            })
+            .collect();
+        let off_arc = |x: f32, y: f32| ((x - center.0).hypot(y - center.1) - 2000.0).abs();
+
+        let mut adaptive = smoother(SmoothingMode::Adaptive);
+        for &(x, y) in &arc[..20] {
+            adaptive.apply(x, y);
+        }
+        let settled = worst_deviation(&mut adaptive, &arc[20..], off_arc);
+
+        assert!(settled < 15.0, "adaptive left {settled} of the jitter");
+        assert!(worst_deviation(&mut smoother(SmoothingMode::Off), &arc, off_arc) >= 29.0);
+    }
+
+    #[test]
+    fn fast_motion_bypasses_the_smoothing() {
+        let mut smoother = PositionSmoother::new(SmoothingSettings {
+            mode: SmoothingMode::Average,
+            bypass_speed: 100.0,
+            ..SmoothingSettings::default()
+        })
+        .unwrap();
+
+        assert_eq!(smoother.apply(1000, 1000), (1000, 1000));
+        assert_eq!(smoother.apply(1020, 1000), (1010, 1000));
+        assert_eq!(smoother.apply(1520, 1000), (1520, 1000));
+        assert_eq!(smoother.apply(2020, 1000), (2020, 1000));
+        // Slow again, the average starts over from the last fast position.
+        assert_eq!(smoother.apply(2040, 1000), (2030, 1000));
+    }
+
+    #[test]
+    fn positions_round_to_the_nearest_grid_point() {
+        let grid = |grid| {
+            PositionSmoother::new(SmoothingSettings {
+                grid,
+                ..SmoothingSettings::default()
+            })
+            .unwrap()
+        };
+
+        assert_eq!(grid(8).quantize(1003, 1004), (1000, 1008));
+        assert_eq!(grid(8).quantize(0, 65535), (0, 65535));
+        assert_eq!(grid(1).quantize(1003, 1004), (1003, 1004));
+        assert!(
+            PositionSmoother::new(SmoothingSettings {
+                grid: 0,
+                ..SmoothingSettings::default()
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn deadband_drops_small_moves_until_they_add_up() {
+        let mut deadband = Deadband::new(10);
+
+        assert_eq!(deadband.apply(1000, 1000), (1000, 1000));
+        assert_eq!(deadband.apply(1003, 998), (1000, 1000));
+        assert_eq!(deadband.apply(1006, 1000), (1000, 1000));
+        assert_eq!(deadband.apply(1010, 1000), (1010, 1000));
+        assert_eq!(deadband.apply(1014, 1000), (1010, 1000));
+
+        deadband.reset();
+        assert_eq!(deadband.apply(1014, 1000), (1014, 1000));
+        assert_eq!(Deadband::new(0).apply(5, 5), (5, 5));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let settings = SmoothingSettings::default();
+
+        assert!(
+            PositionSmoother::new(SmoothingSettings {
+                samples: 0,
+                ..settings
+            })
+            .is_err()
+        );
+        assert!(
+            PositionSmoother::new(SmoothingSettings {
+                min_alpha: 0.8,
+                max_alpha: 0.5,
+                ..settings
+            })
+            .is_err()
+        );
+        assert!(
+            PositionSmoother::new(SmoothingSettings {
+                fast_speed: 0.0,
+                ..settings
+            })
+            .is_err()
+        );
+        assert!(
+            PositionSmoother::new(SmoothingSettings {
+                bypass_speed: -1.0,
+                ..settings
+            })
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{Result, anyhow};
+use log::error;
+
+/// Runs `work` and turns a panic in it into an error, clearing `running`
+/// first so the other threads wind down and the device is released instead
+/// of staying claimed with nothing reading it.
+pub fn fatal_on_panic<T>(
+    running: &AtomicBool,
+    what: &str,
+    work: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(work)).unwrap_or_else(|payload| {
+        running.store(false, Ordering::SeqCst);
+        let cause = panic_message(payload.as_ref());
+        error!("{what} panicked: {cause}, shutting down");
+        Err(anyhow!("{what} panicked: {cause}"))
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_panicking_parser_stops_everything() {
+        let running = AtomicBool::new(true);
+        let parse = |bytes: &[u8]| u16::from_le_bytes([bytes[8], bytes[9]]);
+
+        let result = fatal_on_panic(&running, "The report loop", || Ok(parse(&[0x09, 0x40])));
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("The report loop panicked: index out of bounds"));
+        assert!(!running.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn passes_results_through() {
+        let running = AtomicBool::new(true);
+
+        assert_eq!(fatal_on_panic(&running, "work", || Ok(7)).unwrap(), 7);
+        assert!(fatal_on_panic::<()>(&running, "work", || Err(anyhow!("failed"))).is_err());
+        assert!(running.load(Ordering::SeqCst));
+    }
+}
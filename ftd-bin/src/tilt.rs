@@ -0,0 +1,214 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::action::Action;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TiltAxis {
+    X,
+    Y,
+}
+
+/// Tilt from `min` to `max` degrees along `axis` that fires `action` once
+/// when the pen leans into it, e.g. a key that rotates the canvas in apps
+/// that do not read tilt themselves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TiltZone {
+    pub axis: TiltAxis,
+    pub min: i8,
+    pub max: i8,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TiltConfig {
+    /// Byte of the tilt along X in pen reports, followed by the one along
+    /// Y, each signed degrees. The MTM-1106 sends no tilt, this is for
+    /// models that do. Unset reads none.
+    pub offset: Option<usize>,
+    pub zones: Vec<TiltZone>,
+    /// How many degrees past a zone's edge the pen must lean to leave it.
+    pub hysteresis: u8,
+}
+
+impl Default for TiltConfig {
+    fn default() -> Self {
+        TiltConfig {
+            offset: None,
+            zones: vec![],
+            hysteresis: 3,
+        }
+    }
+}
+
+/// Tracks which zone the tilt is in on each axis and reports each zone
+/// entered, like `PressureBands` does for the pressure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TiltZones {
+    offset: Option<usize>,
+    zones: Vec<TiltZone>,
+    hysteresis: i16,
+    /// The zone the pen is in along X and along Y.
+    current: [Option<usize>; 2],
+}
+
+impl TiltZones {
+    pub fn new(config: &TiltConfig) -> Result<TiltZones> {
+        if config.offset.is_none() && !config.zones.is_empty() {
+            bail!("tilt.zones need tilt.offset, where the pen reports carry the tilt");
+        }
+        let mut zones = config.zones.clone();
+        zones.sort_by_key(|zone| (zone.axis == TiltAxis::Y, zone.min));
+        for zone in &zones {
+            if zone.min >= zone.max {
+                bail!("tilt zone {}..{} must have min < max", zone.min, zone.max);
+            }
+        }
+        if let Some(pair) = zones
+            .windows(2)
+            .find(|pair| pair[0].axis == pair[1].axis && pair[0].max >= pair[1].min)
+        {
+            bail!(
+                "tilt zones {}..{} and {}..{} overlap",
+                pair[0].min,
+                pair[0].max,
+                pair[1].min,
+                pair[1].max
+            );
+        }
+
+        Ok(TiltZones {
+            offset: config.offset,
+            zones,
+            hysteresis: config.hysteresis as i16,
+            current: [None; 2],
+        })
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        self.zones.iter().map(|zone| &zone.action)
+    }
+
+    /// The tilt along X and Y in a pen report, when `offset` is set and
+    /// the report is long enough.
+    pub fn read(&self, bytes: &[u8]) -> Option<(i8, i8)> {
+        let offset = self.offset?;
+        match bytes.get(offset..offset + 2)? {
+            &[x, y] => Some((x as i8, y as i8)),
+            _ => None,
+        }
+    }
+
+    /// Takes the tilt of every pen report and returns the actions of the
+    /// zones just entered. The pen leaving range leaves any zone.
+    pub fn feed(&mut self, tilt: Option<(i8, i8)>, in_range: bool) -> Vec<Action> {
+        let Some((x, y)) = tilt.filter(|_| in_range) else {
+            self.current = [None; 2];
+            return vec![];
+        };
+
+        let mut entered_zones = vec![];
+        for (slot, axis, degrees) in [(0, TiltAxis::X, x), (1, TiltAxis::Y, y)] {
+            let degrees = degrees as i16;
+            if let Some(current) = self.current[slot] {
+                let zone = &self.zones[current];
+                let low = zone.min as i16 - self.hysteresis;
+                let high = zone.max as i16 + self.hysteresis;
+                if (low..=high).contains(&degrees) {
+                    continue;
+                }
+            }
+
+            let entered = self.zones.iter().position(|zone| {
+                zone.axis == axis && (zone.min as i16..=zone.max as i16).contains(&degrees)
+            });
+            if let Some(index) = entered
+                && entered != self.current[slot]
+            {
+                entered_zones.push(self.zones[index].action.clone());
+            }
+            self.current[slot] = entered;
+        }
+
+        entered_zones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev_rs::enums::EV_KEY;
+
+    fn zones() -> TiltZones {
+        TiltZones::new(&TiltConfig {
+            offset: Some(10),
+            zones: vec![
+                TiltZone {
+                    axis: TiltAxis::X,
+                    min: 30,
+                    max: 127,
+                    action: Action::Keys(vec![EV_KEY::KEY_E]),
+                },
+                TiltZone {
+                    axis: TiltAxis::X,
+                    min: -128,
+                    max: -30,
+                    action: Action::Keys(vec![EV_KEY::KEY_Q]),
+                },
+                TiltZone {
+                    axis: TiltAxis::Y,
+                    min: 40,
+                    max: 127,
+                    action: Action::Scroll(1),
+                },
+            ],
+            hysteresis: 3,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn crossing_a_threshold_fires_once_without_chatter() {
+        let mut zones = zones();
+        let mut fired = |x: i8, y: i8| zones.feed(Some((x, y)), true);
+
+        assert!(fired(10, 0).is_empty());
+        assert_eq!(fired(31, 0), vec![Action::Keys(vec![EV_KEY::KEY_E])]);
+        // Wobbling on the edge stays within the hysteresis.
+        for x in [29, 31, 28, 30, 27] {
+            assert!(fired(x, 0).is_empty());
+        }
+        assert!(fired(26, 0).is_empty());
+        assert_eq!(fired(30, 0), vec![Action::Keys(vec![EV_KEY::KEY_E])]);
+        assert_eq!(
+            fired(-45, 50),
+            vec![Action::Keys(vec![EV_KEY::KEY_Q]), Action::Scroll(1)]
+        );
+
+        zones.feed(Some((-45, 50)), false);
+        assert_eq!(
+            zones.feed(Some((-45, 0)), true),
+            vec![Action::Keys(vec![EV_KEY::KEY_Q])]
+        );
+    }
+
+    #[test]
+    fn reads_signed_degrees_at_the_offset() {
+        let zones = zones();
+        let mut report = vec![0; 12];
+        report[10] = 0xE2;
+        report[11] = 0x28;
+
+        assert_eq!(zones.read(&report), Some((-30, 40)));
+        assert_eq!(zones.read(&report[..11]), None);
+        assert!(
+            TiltZones::new(&TiltConfig {
+                zones: zones.zones.clone(),
+                ..TiltConfig::default()
+            })
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+use crate::proximity::ProximitySettings;
+
+/// Holds back a tip press until the tip has been down for `delay`, so the
+/// cursor settles where a quick tap lands before it clicks. A tap lifted
+/// sooner never clicks, a held one clicks with the first report after the
+/// delay. The pen hovers meanwhile, see `ProximitySettings::tip_delay_ms`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TipDelay {
+    delay: Duration,
+    /// When the tip went down, while it stays down.
+    since: Option<Instant>,
+}
+
+impl TipDelay {
+    pub fn new(settings: &ProximitySettings) -> TipDelay {
+        TipDelay {
+            delay: Duration::from_millis(settings.tip_delay_ms),
+            since: None,
+        }
+    }
+
+    /// Whether the tip counts as down for a report read at `at` with the
+    /// tip `down`.
+    pub fn feed(&mut self, down: bool, at: Instant) -> bool {
+        if !down {
+            self.since = None;
+            return false;
+        }
+        let since = *self.since.get_or_insert(at);
+
+        at.saturating_duration_since(since) >= self.delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_tip_held_past_the_delay_presses() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut delay = TipDelay::new(&ProximitySettings {
+            tip_delay_ms: 40,
+            ..ProximitySettings::default()
+        });
+
+        assert!(!delay.feed(true, at(0)));
+        assert!(!delay.feed(true, at(30)));
+        assert!(!delay.feed(false, at(35)));
+        // The next tap starts over.
+        assert!(!delay.feed(true, at(50)));
+        assert!(delay.feed(true, at(90)));
+        assert!(!delay.feed(false, at(100)));
+
+        let mut none = TipDelay::new(&ProximitySettings::default());
+        assert!(none.feed(true, at(0)));
+    }
+}
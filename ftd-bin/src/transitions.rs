@@ -0,0 +1,102 @@
+use ftd_device::report::{BUTTON_COUNT, BUTTON_NAMES, Event};
+
+/// Follows the decoded events and describes the state only when proximity,
+/// the tip, the fingers down or a button changed, for `--log-transitions`.
+#[derive(Debug, Default)]
+pub struct Transitions {
+    in_range: bool,
+    tip: bool,
+    fingers: usize,
+    pressed: [bool; BUTTON_COUNT],
+}
+
+impl Transitions {
+    pub fn observe(&mut self, event: &Event) -> Option<String> {
+        let before = (self.in_range, self.tip, self.fingers, self.pressed);
+        match event {
+            Event::Pen(report) => {
+                self.in_range = report.in_range;
+                self.tip = report.in_range && report.tip;
+            }
+            Event::Button(button) => {
+                if let Some(pressed) = self.pressed.get_mut(button.index as usize) {
+                    *pressed = button.pressed;
+                }
+            }
+            Event::Touch(touch) => {
+                self.fingers = touch.contacts.iter().filter(|c| c.down).count();
+            }
+            Event::Raw(_) => {}
+        }
+
+        if before == (self.in_range, self.tip, self.fingers, self.pressed) {
+            return None;
+        }
+        Some(self.summary())
+    }
+
+    fn summary(&self) -> String {
+        let pen = match (self.in_range, self.tip) {
+            (false, _) => "out of range",
+            (true, false) => "hovering",
+            (true, true) => "tip down",
+        };
+        let buttons: Vec<&str> = BUTTON_NAMES
+            .iter()
+            .zip(self.pressed)
+            .filter(|(_, pressed)| *pressed)
+            .map(|(name, _)| *name)
+            .collect();
+
+        format!(
+            "pen {pen}, {} finger(s) down, buttons [{}]",
+            self.fingers,
+            buttons.join(" ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ftd_device::report::{ButtonEvent, PenReport};
+
+    fn pen(x: u16, in_range: bool, tip: bool) -> Event {
+        Event::Pen(PenReport {
+            x,
+            y: 100,
+            pressure: if tip { 300 } else { 0 },
+            in_range,
+            tip,
+        })
+    }
+
+    #[test]
+    fn reports_only_changes() {
+        let mut transitions = Transitions::default();
+
+        assert_eq!(
+            transitions.observe(&pen(10, true, false)).as_deref(),
+            Some("pen hovering, 0 finger(s) down, buttons []")
+        );
+        assert_eq!(transitions.observe(&pen(20, true, false)), None);
+        assert_eq!(
+            transitions.observe(&pen(30, true, true)).as_deref(),
+            Some("pen tip down, 0 finger(s) down, buttons []")
+        );
+        assert_eq!(transitions.observe(&pen(40, true, true)), None);
+        assert_eq!(
+            transitions
+                .observe(&Event::Button(ButtonEvent {
+                    index: 8,
+                    pressed: true,
+                }))
+                .as_deref(),
+            Some("pen tip down, 0 finger(s) down, buttons [PEN1]")
+        );
+        assert_eq!(
+            transitions.observe(&pen(40, false, false)).as_deref(),
+            Some("pen out of range, 0 finger(s) down, buttons [PEN1]")
+        );
+    }
+}
@@ -0,0 +1,56 @@
+/// The features this build was compiled with, as the build script saw them.
+pub fn features() -> Vec<&'static str> {
+    env!("FTD_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect()
+}
+
+/// The libusb linked at runtime, `1.0.27.11882` and any release candidate
+/// suffix.
+pub fn libusb() -> String {
+    let libusb = rusb::version();
+    format!(
+        "{}.{}.{}.{}{}",
+        libusb.major(),
+        libusb.minor(),
+        libusb.micro(),
+        libusb.nano(),
+        libusb.rc().unwrap_or("")
+    )
+}
+
+/// What `version` prints, for pasting into a bug report.
+pub fn about() -> String {
+    let features = features();
+    format!(
+        "freetomate {} (git {})\nlibusb {}\nfeatures: {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("FTD_GIT_HASH"),
+        libusb(),
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_compiled_features() {
+        let compiled: Vec<&str> = [
+            ("http", cfg!(feature = "http")),
+            ("monitor", cfg!(feature = "monitor")),
+        ]
+        .into_iter()
+        .filter_map(|(feature, on)| on.then_some(feature))
+        .collect();
+
+        assert_eq!(features(), compiled);
+        assert!(about().lines().any(|line| line.starts_with("libusb 1.")));
+    }
+}
@@ -0,0 +1,125 @@
+use log::debug;
+
+const USAGE_PAGE: u8 = 0x04;
+const USAGE: u8 = 0x08;
+const COLLECTION: u8 = 0xA0;
+const END_COLLECTION: u8 = 0xC0;
+const LONG_ITEM: u8 = 0xFE;
+const APPLICATION: u32 = 0x01;
+
+pub const GENERIC_DESKTOP: u16 = 0x01;
+pub const KEYBOARD: u16 = 0x06;
+pub const DIGITIZER: u16 = 0x0D;
+
+/// `(usage page, usage)` of each top-level application collection in a HID
+/// report descriptor, in order. Parsing stops at the first truncated item.
+pub fn applications(descriptor: &[u8]) -> Vec<(u16, u16)> {
+    let mut applications = vec![];
+    let (mut page, mut usage, mut depth) = (0u16, None, 0usize);
+
+    let mut rest = descriptor;
+    while let Some(&prefix) = rest.first() {
+        if prefix == LONG_ITEM {
+            let Some(&size) = rest.get(1) else {
+                break;
+            };
+            rest = rest.get(3 + size as usize..).unwrap_or_default();
+            continue;
+        }
+
+        let size = [0, 1, 2, 4][(prefix & 0x03) as usize];
+        let Some(data) = rest.get(1..1 + size) else {
+            break;
+        };
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u32, |value, &byte| value << 8 | byte as u32);
+        rest = &rest[1 + size..];
+
+        match prefix & 0xFC {
+            USAGE_PAGE => page = value as u16,
+            // A four byte usage carries its own page.
+            USAGE if size == 4 => usage = Some(((value >> 16) as u16, value as u16)),
+            USAGE => usage = Some((page, value as u16)),
+            COLLECTION => {
+                if depth == 0
+                    && value == APPLICATION
+                    && let Some(usage) = usage
+                {
+                    applications.push(usage);
+                }
+                depth += 1;
+                usage = None;
+            }
+            END_COLLECTION => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    applications
+}
+
+/// Picks `(buttons, tablet)` from the report descriptor of each interface:
+/// the buttons are the one keyboard, the tablet the one digitizer. `None`
+/// unless exactly one interface is each, so the configured numbers apply.
+pub fn classify(descriptors: &[(u8, Vec<u8>)]) -> Option<(u8, u8)> {
+    let find = |wanted: &dyn Fn(u16, u16) -> bool| -> Option<u8> {
+        let mut matching = descriptors.iter().filter(|(_, descriptor)| {
+            applications(descriptor)
+                .into_iter()
+                .any(|(page, usage)| wanted(page, usage))
+        });
+        let (number, _) = matching.next()?;
+        matching.next().is_none().then_some(*number)
+    };
+
+    let buttons = find(&|page, usage| page == GENERIC_DESKTOP && usage == KEYBOARD);
+    let tablet = find(&|page, _| page == DIGITIZER);
+    debug!("Interfaces by descriptor: buttons {buttons:?}, tablet {tablet:?}");
+    match (buttons, tablet) {
+        (Some(buttons), Some(tablet)) if buttons != tablet => Some((buttons, tablet)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Usage Page (Generic Desktop), Usage (Keyboard), Collection (Application).
+    const KEYBOARD_DESCRIPTOR: &[u8] = &[0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0x85, 0x01, 0xC0];
+    /// Usage Page (Digitizer), Usage (Pen), Collection (Application),
+    /// Collection (Physical) inside, whose usage is not top-level.
+    const PEN_DESCRIPTOR: &[u8] = &[
+        0x05, 0x0D, 0x09, 0x02, 0xA1, 0x01, 0x85, 0x09, 0x09, 0x20, 0xA1, 0x00, 0xC0, 0xC0,
+    ];
+    /// Usage Page (Consumer), Usage (Consumer Control), Collection (Application).
+    const MEDIA_DESCRIPTOR: &[u8] = &[0x05, 0x0C, 0x09, 0x01, 0xA1, 0x01, 0xC0];
+
+    #[test]
+    fn lists_the_top_level_applications() {
+        assert_eq!(applications(PEN_DESCRIPTOR), vec![(DIGITIZER, 0x02)]);
+        assert_eq!(
+            applications(&[KEYBOARD_DESCRIPTOR, MEDIA_DESCRIPTOR].concat()),
+            vec![(GENERIC_DESKTOP, KEYBOARD), (0x0C, 0x01)]
+        );
+        assert_eq!(applications(&[0x05, 0x0D, 0x09]), vec![]);
+    }
+
+    #[test]
+    fn finds_the_pen_on_interface_one() {
+        let descriptors = vec![
+            (1, PEN_DESCRIPTOR.to_vec()),
+            (2, KEYBOARD_DESCRIPTOR.to_vec()),
+            (3, MEDIA_DESCRIPTOR.to_vec()),
+        ];
+        assert_eq!(classify(&descriptors), Some((2, 1)));
+
+        // Two digitizers are inconclusive.
+        let mut twice = descriptors.clone();
+        twice.push((4, PEN_DESCRIPTOR.to_vec()));
+        assert_eq!(classify(&twice), None);
+        assert_eq!(classify(&descriptors[1..]), None);
+    }
+}
@@ -0,0 +1,1046 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use rusb::{
+    Device, DeviceHandle, Direction, Result as RusbResult, Speed, TransferType, UsbContext,
+};
+
+use crate::{
+    descriptor,
+    error::FtdError,
+    report::{
+        ButtonDecoder, CAPABILITIES_REPORT_ID, Capabilities, Event, PenReport, Timestamped,
+        TouchReport,
+    },
+};
+
+pub const VENDOR_ID: u16 = 0x08f2;
+pub const PRODUCT_ID: u16 = 0x6811;
+
+pub const MASS_STORAGE: u8 = 0;
+pub const BUTTONS_INTERAFCE: u8 = 1;
+pub const TABLET_INTERFACE: u8 = 2;
+
+pub struct USBDevice<T: UsbContext> {
+    pub device: Device<T>,
+    pub handle: DeviceHandle<T>,
+    pub interfaces: HashMap<u8, InterfaceInfo>,
+    /// `bcdDevice`, the firmware revision, e.g. `0x0102` for 1.02.
+    pub revision: u16,
+}
+
+impl<T: UsbContext> Drop for USBDevice<T> {
+    fn drop(&mut self) {
+        let interfaces: Vec<u8> = self.interfaces.keys().copied().collect();
+
+        for i in interfaces {
+            if let Ok(res) = self.handle.kernel_driver_active(i)
+                && res
+            {
+                let _ = self.handle.attach_kernel_driver(i);
+            }
+        }
+    }
+}
+
+#[derive(Hash, Clone)]
+pub struct InterfaceInfo {
+    pub number: u8,
+    pub endpoints_in: Vec<u8>,
+    pub endpoints_out: Vec<u8>,
+    /// The one IN endpoint `read_device` reads, instead of trying them all.
+    pub preferred_in: Option<u8>,
+    /// Largest `wMaxPacketSize` of the IN endpoints, as the descriptor says.
+    pub max_packet_size: u16,
+    /// Buffer size for `read_device` in place of `max_packet_size`, for
+    /// clones whose descriptor understates their reports.
+    pub read_size: Option<usize>,
+    /// Shortest polling interval of the IN endpoints, from their `bInterval`
+    /// and the bus speed, see `poll_interval`.
+    pub poll_interval: Option<Duration>,
+    /// What the IN endpoints are declared as, by address. One that is not
+    /// listed is taken to be an interrupt endpoint, which `read_device`
+    /// reads them as.
+    pub transfer_types: Vec<(u8, TransferType)>,
+    /// IN endpoints `read_device` leaves out, see `skip_in`.
+    pub skipped_in: Vec<u8>,
+}
+
+impl InterfaceInfo {
+    /// An interface with just the IN `endpoints_in`, read the way the
+    /// descriptor says, and nothing else known about it.
+    pub fn new(number: u8, endpoints_in: Vec<u8>, max_packet_size: u16) -> InterfaceInfo {
+        InterfaceInfo {
+            number,
+            endpoints_in,
+            endpoints_out: vec![],
+            preferred_in: None,
+            max_packet_size,
+            read_size: None,
+            poll_interval: None,
+            transfer_types: vec![],
+            skipped_in: vec![],
+        }
+    }
+
+    /// Fails for an interface `read_device` could never read from, which
+    /// would otherwise come back empty on every call.
+    pub fn ensure_readable(&self) -> Result<(), FtdError> {
+        if self.endpoints_in.is_empty() {
+            return Err(FtdError::NoInEndpoint(self.number));
+        }
+
+        Ok(())
+    }
+
+    /// Pins reads to `address`, which must be one of `endpoints_in`.
+    pub fn prefer_in(&mut self, address: u8) -> Result<(), FtdError> {
+        if !self.endpoints_in.contains(&address) {
+            return Err(FtdError::MissingEndpoint(self.number, address));
+        }
+        self.preferred_in = Some(address);
+
+        Ok(())
+    }
+
+    /// The endpoints `read_device` reads that are declared other than
+    /// interrupt, with what they are declared as. The kernel rejects
+    /// interrupt reads of those.
+    pub fn mismatched_endpoints(&self) -> Vec<(u8, TransferType)> {
+        self.read_endpoints()
+            .iter()
+            .filter_map(|&address| {
+                self.transfer_types
+                    .iter()
+                    .find(|(declared, _)| *declared == address)
+                    .filter(|(_, kind)| *kind != TransferType::Interrupt)
+                    .copied()
+            })
+            .collect()
+    }
+
+    /// Fails when none of the endpoints `read_device` reads can be read
+    /// with interrupt transfers, instead of every read failing with
+    /// `InvalidParam` or `Pipe` later.
+    pub fn check_transfer_types(&self) -> Result<(), FtdError> {
+        let mismatched = self.mismatched_endpoints();
+        match mismatched.first() {
+            Some(&(address, declared)) if mismatched.len() == self.read_endpoints().len() => {
+                Err(FtdError::WrongTransferType(self.number, address, declared))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// How many bytes `read_device` asks for at most.
+    pub fn buffer_size(&self) -> usize {
+        self.read_size.unwrap_or(self.max_packet_size as usize)
+    }
+
+    /// How many reports a second the endpoints are polled for at most.
+    pub fn report_rate(&self) -> Option<f64> {
+        self.poll_interval
+            .filter(|interval| !interval.is_zero())
+            .map(|interval| 1.0 / interval.as_secs_f64())
+    }
+
+    /// `timeout_ms`, but never shorter than the polling interval: a read
+    /// that gives up before the next poll can only time out.
+    pub fn read_timeout(&self, timeout_ms: u64) -> u64 {
+        let interval_ms = self
+            .poll_interval
+            .map_or(0, |interval| interval.as_micros().div_ceil(1000) as u64);
+        timeout_ms.max(interval_ms)
+    }
+
+    /// The IN endpoints `read_device` tries, in order.
+    pub fn read_endpoints(&self) -> Vec<u8> {
+        match self.preferred_in {
+            Some(address) => vec![address],
+            None => self
+                .endpoints_in
+                .iter()
+                .copied()
+                .filter(|address| !self.skipped_in.contains(address))
+                .collect(),
+        }
+    }
+
+    /// Leaves `address` out of the reads, for an endpoint that only ever
+    /// errors. Refused, returning false, when it is not read anyway or no
+    /// other endpoint would be left to read.
+    pub fn skip_in(&mut self, address: u8) -> bool {
+        let read = self.read_endpoints();
+        if !read.contains(&address) || read.len() == 1 {
+            return false;
+        }
+        self.skipped_in.push(address);
+
+        true
+    }
+}
+
+/// Skips an IN endpoint once its reads failed `limit` times in a row with
+/// something other than a timeout, so a broken endpoint of a clone stops
+/// costing a failed read, and a log line, on every report.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointStrikes {
+    limit: u32,
+    strikes: HashMap<u8, u32>,
+}
+
+impl EndpointStrikes {
+    /// A `limit` of 0 never skips.
+    pub fn new(limit: u32) -> EndpointStrikes {
+        EndpointStrikes {
+            limit,
+            strikes: HashMap::new(),
+        }
+    }
+
+    /// Counts what a read of `endpoint` returned and skips it in
+    /// `interface` at the limit. Returns whether it was skipped just now.
+    pub fn observe(
+        &mut self,
+        interface: &mut InterfaceInfo,
+        endpoint: u8,
+        result: RusbResult<usize>,
+    ) -> bool {
+        let strikes = self.strikes.entry(endpoint).or_default();
+        match result {
+            Ok(_) => *strikes = 0,
+            Err(rusb::Error::Timeout) => {}
+            Err(_) => *strikes += 1,
+        }
+
+        self.limit > 0 && *strikes == self.limit && interface.skip_in(endpoint)
+    }
+}
+
+pub struct MessageDevice {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub interface: u16,
+    pub payload: Vec<u8>,
+    pub timeout: Duration,
+}
+
+pub fn open_device<T: UsbContext>(
+    context: &mut T,
+    vid: u16,
+    pid: u16,
+) -> Result<Option<USBDevice<T>>, FtdError> {
+    open_device_with_configuration(context, vid, pid, (None, &[]))
+}
+
+/// Like `open_device`, but first switches the device to `configuration`
+/// when given, for models that power up on a configuration without the
+/// tablet interfaces. `None` keeps the active one. The switch needs every
+/// kernel driver detached, those of interfaces not in `claimed` are
+/// attached again after it.
+pub fn open_device_with_configuration<T: UsbContext>(
+    context: &mut T,
+    vid: u16,
+    pid: u16,
+    (configuration, claimed): (Option<u8>, &[u8]),
+) -> Result<Option<USBDevice<T>>, FtdError> {
+    let Some(mut device) = find_device(context, vid, pid)? else {
+        return Ok(None);
+    };
+    let mut handle = device.open()?;
+
+    if let Some(value) = configuration {
+        let active = handle.active_configuration()?;
+        if active == value {
+            info!("Device already on configuration {value}");
+        } else {
+            info!("Switching the device from configuration {active} to {value}");
+            let mut detached = vec![];
+            for int in device.active_config_descriptor()?.interfaces() {
+                if handle.kernel_driver_active(int.number())? {
+                    handle.detach_kernel_driver(int.number())?;
+                    detached.push(int.number());
+                }
+            }
+            handle.set_active_configuration(value)?;
+
+            // Some devices re-enumerate on a configuration change, the old
+            // handle is then gone and the device shows up again shortly.
+            if matches!(handle.active_configuration(), Err(rusb::Error::NoDevice)) {
+                let (found, reopened) = reopen(context, vid, pid)?;
+                (device, handle) = (found, reopened);
+            }
+            let active = handle.active_configuration()?;
+            if active != value {
+                warn!("The device stayed on configuration {active} instead of {value}");
+            }
+            // An interface the new configuration lacks is not found, and
+            // one the kernel already bound again is left as is.
+            for number in reattached_after_switch(&detached, claimed) {
+                if handle.kernel_driver_active(number) == Ok(false)
+                    && let Err(e) = handle.attach_kernel_driver(number)
+                {
+                    warn!("Could not give interface {number} back to the kernel: {e}");
+                }
+            }
+        }
+    }
+
+    let interfaces = describe_interfaces(&device)?;
+    let revision = revision_bcd(device.device_descriptor()?.device_version());
+    debug!("Firmware revision {revision:#06x}");
+    Ok(Some(USBDevice {
+        device,
+        handle,
+        interfaces,
+        revision,
+    }))
+}
+
+/// The interfaces whose kernel driver was detached for a configuration
+/// switch and goes back on after it, those that are not claimed.
+fn reattached_after_switch(detached: &[u8], claimed: &[u8]) -> Vec<u8> {
+    detached
+        .iter()
+        .copied()
+        .filter(|number| !claimed.contains(number))
+        .collect()
+}
+
+/// `bcdDevice` back in its BCD form from the version rusb decodes it into.
+pub fn revision_bcd(version: rusb::Version) -> u16 {
+    let major = version.major() as u16;
+    (major / 10) << 12
+        | (major % 10) << 8
+        | (version.minor() as u16) << 4
+        | version.sub_minor() as u16
+}
+
+/// The first device with `vid:pid`, without opening it.
+pub fn find_device<T: UsbContext>(
+    context: &mut T,
+    vid: u16,
+    pid: u16,
+) -> Result<Option<Device<T>>, FtdError> {
+    for device in context.devices()?.iter() {
+        let desc = device.device_descriptor()?;
+        if desc.vendor_id() == vid && desc.product_id() == pid {
+            return Ok(Some(device));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Waits up to 2 seconds for the device to come back after re-enumerating.
+fn reopen<T: UsbContext>(
+    context: &mut T,
+    vid: u16,
+    pid: u16,
+) -> Result<(Device<T>, DeviceHandle<T>), FtdError> {
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_millis(100));
+        if let Some(device) = find_device(context, vid, pid)?
+            && let Ok(handle) = device.open()
+        {
+            return Ok((device, handle));
+        }
+    }
+
+    Err(FtdError::Usb(rusb::Error::NoDevice))
+}
+
+/// The polling interval an interrupt endpoint's `bInterval` stands for:
+/// frames of 1 ms at low and full speed, `2^(bInterval - 1)` microframes of
+/// 125 us from high speed on.
+pub fn poll_interval(speed: Speed, b_interval: u8) -> Duration {
+    match speed {
+        Speed::High | Speed::Super | Speed::SuperPlus => {
+            let exponent = b_interval.clamp(1, 16) - 1;
+            Duration::from_micros(125 << exponent)
+        }
+        _ => Duration::from_millis(b_interval as u64),
+    }
+}
+
+fn describe_interfaces<T: UsbContext>(
+    device: &Device<T>,
+) -> Result<HashMap<u8, InterfaceInfo>, FtdError> {
+    let mut interfaces = HashMap::new();
+
+    let config_descriptor = device.active_config_descriptor()?;
+    info!("Using configuration {}", config_descriptor.number());
+    for int in config_descriptor.interfaces() {
+        let number = int.number();
+        for desc in int.descriptors() {
+            let mut endpoints_in = vec![];
+            let mut endpoints_out = vec![];
+            let mut max_packet_size = 0;
+            let mut interval: Option<Duration> = None;
+            let mut transfer_types = vec![];
+            for endpoint in desc.endpoint_descriptors() {
+                if endpoint.direction() == Direction::In {
+                    endpoints_in.push(endpoint.address());
+                    transfer_types.push((endpoint.address(), endpoint.transfer_type()));
+                    max_packet_size = max_packet_size.max(endpoint.max_packet_size());
+                    let polled = poll_interval(device.speed(), endpoint.interval());
+                    interval = Some(interval.map_or(polled, |shortest| shortest.min(polled)));
+                }
+
+                if endpoint.direction() == Direction::Out {
+                    endpoints_out.push(endpoint.address());
+                }
+            }
+            interfaces.insert(
+                number,
+                InterfaceInfo {
+                    number,
+                    endpoints_in,
+                    endpoints_out,
+                    preferred_in: None,
+                    max_packet_size,
+                    read_size: None,
+                    poll_interval: interval,
+                    transfer_types,
+                    skipped_in: vec![],
+                },
+            );
+        }
+    }
+
+    Ok(interfaces)
+}
+
+/// Fails with every interface that was found unless all of `expected` are
+/// among them, so a different layout stops the driver at startup.
+pub fn check_interfaces(
+    interfaces: &HashMap<u8, InterfaceInfo>,
+    expected: &[u8],
+) -> Result<(), FtdError> {
+    if expected
+        .iter()
+        .all(|number| interfaces.contains_key(number))
+    {
+        return Ok(());
+    }
+
+    let mut expected = expected.to_vec();
+    expected.sort_unstable();
+    expected.dedup();
+    let mut found: Vec<u8> = interfaces.keys().copied().collect();
+    found.sort_unstable();
+    Err(FtdError::MissingInterfaces(expected, found))
+}
+
+pub fn claim_interfaces<T: UsbContext>(
+    handle: &mut DeviceHandle<T>,
+    interfaces: &[u8],
+) -> Result<(), FtdError> {
+    for num in interfaces {
+        if handle.kernel_driver_active(*num)? {
+            handle.detach_kernel_driver(*num)?;
+        }
+        handle.claim_interface(*num)?;
+    }
+
+    Ok(())
+}
+
+/// Claims `interfaces` like `claim_interfaces`, except that only failing
+/// on one of `essential` is an error. The others are logged and skipped,
+/// and returned so they are not read.
+pub fn claim_interfaces_except<T: UsbContext>(
+    handle: &mut DeviceHandle<T>,
+    interfaces: &[u8],
+    essential: &[u8],
+) -> Result<Vec<u8>, FtdError> {
+    claim_each(interfaces, essential, |num| {
+        if handle.kernel_driver_active(num)? {
+            handle.detach_kernel_driver(num)?;
+        }
+        handle.claim_interface(num)?;
+        Ok(())
+    })
+}
+
+fn claim_each(
+    interfaces: &[u8],
+    essential: &[u8],
+    mut claim: impl FnMut(u8) -> Result<(), FtdError>,
+) -> Result<Vec<u8>, FtdError> {
+    let mut skipped = vec![];
+    for &num in interfaces {
+        match claim(num) {
+            Ok(()) => {}
+            Err(e) if !essential.contains(&num) => {
+                warn!("Skipping interface {num}, claiming it failed: {e}");
+                skipped.push(num);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(skipped)
+}
+
+pub fn send_to_device<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    message: &MessageDevice,
+) -> Result<(), FtdError> {
+    handle.write_control(
+        message.request_type,
+        message.request,
+        message.value,
+        message.interface,
+        &message.payload,
+        message.timeout,
+    )?;
+
+    Ok(())
+}
+
+/// HID class GET_REPORT for a feature report.
+pub fn read_feature_report<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interface: u16,
+    report_id: u8,
+    length: usize,
+) -> Result<Vec<u8>, FtdError> {
+    let mut buffer = vec![0; length];
+    let read = handle.read_control(
+        0xA1,
+        0x01,
+        0x0300 | report_id as u16,
+        interface,
+        &mut buffer,
+        Duration::from_secs(1),
+    )?;
+    buffer.truncate(read);
+
+    Ok(buffer)
+}
+
+/// Asks the tablet for its parameter block. Tablets that stall the request
+/// or answer with something else yield `Ok(None)`.
+pub fn query_capabilities<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interface: u16,
+) -> Result<Option<Capabilities>, FtdError> {
+    match read_feature_report(handle, interface, CAPABILITIES_REPORT_ID, 16) {
+        Ok(bytes) => Ok(Capabilities::parse(&bytes)),
+        Err(FtdError::Usb(
+            rusb::Error::Pipe | rusb::Error::NotSupported | rusb::Error::Timeout,
+        )) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads one report of up to `interface.buffer_size()` bytes.
+pub fn read_device<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interface: &InterfaceInfo,
+    timeout: u64,
+) -> RusbResult<(u8, Vec<u8>)> {
+    read_device_observed(handle, interface, timeout, |_, _| {})
+}
+
+/// `read_device`, passing what the read of each endpoint it tried
+/// returned to `observe`, e.g. `EndpointStrikes::observe`.
+pub fn read_device_observed<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interface: &InterfaceInfo,
+    timeout: u64,
+    observe: impl FnMut(u8, RusbResult<usize>),
+) -> RusbResult<(u8, Vec<u8>)> {
+    let timeout = Duration::from_millis(timeout);
+    read_endpoints(
+        interface,
+        |endpoint, buffer| handle.read_interrupt(endpoint, buffer, timeout),
+        observe,
+    )
+}
+
+fn read_endpoints(
+    interface: &InterfaceInfo,
+    mut read: impl FnMut(u8, &mut [u8]) -> RusbResult<usize>,
+    mut observe: impl FnMut(u8, RusbResult<usize>),
+) -> RusbResult<(u8, Vec<u8>)> {
+    let mut buffer = vec![0; interface.buffer_size()];
+    let mut res = Ok(0);
+
+    for endpoint in interface.read_endpoints() {
+        res = read(endpoint, &mut buffer);
+        observe(endpoint, res);
+
+        if let Ok(bytes_read) = &res {
+            return Ok((interface.number, buffer[..(*bytes_read)].to_vec()));
+        }
+    }
+
+    let bytes_read = res?;
+    Ok((interface.number, buffer[..bytes_read].to_vec()))
+}
+
+/// Writes `payload` to the interrupt OUT endpoint `endpoint`, for clones
+/// that take their init this way instead of as a control request.
+pub fn write_to_endpoint<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    endpoint: u8,
+    payload: &[u8],
+    timeout: Duration,
+) -> Result<(), FtdError> {
+    handle.write_interrupt(endpoint, payload, timeout)?;
+
+    Ok(())
+}
+
+/// Makes sure the read loop can rely on `interface`: it has an IN endpoint,
+/// and asking each one `read_device` would use for its status answers
+/// within `timeout` that it is not halted. Nothing is read, so no report
+/// is lost to the check. A stall or any other error fails right away.
+pub fn health_check<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interface: &InterfaceInfo,
+    timeout: Duration,
+) -> Result<(), FtdError> {
+    check_endpoints(interface, |endpoint| {
+        // A standard GET_STATUS of the endpoint, bit 0 is its halt feature.
+        let mut status = [0; 2];
+        handle.read_control(0x82, 0x00, 0, endpoint as u16, &mut status, timeout)?;
+        Ok(status[0] & 1 == 1)
+    })
+}
+
+fn check_endpoints(
+    interface: &InterfaceInfo,
+    mut halted: impl FnMut(u8) -> RusbResult<bool>,
+) -> Result<(), FtdError> {
+    interface.ensure_readable()?;
+
+    for endpoint in interface.read_endpoints() {
+        match halted(endpoint) {
+            Ok(false) | Err(rusb::Error::Timeout) => {}
+            Ok(true) => {
+                return Err(FtdError::UnusableEndpoint(
+                    interface.number,
+                    endpoint,
+                    rusb::Error::Pipe,
+                ));
+            }
+            Err(e) => return Err(FtdError::UnusableEndpoint(interface.number, endpoint, e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// The HID report descriptor of `interface`, a standard GET_DESCRIPTOR
+/// request to the interface.
+pub fn report_descriptor<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interface: u8,
+) -> Result<Vec<u8>, FtdError> {
+    let mut buffer = vec![0; 4096];
+    let read = handle.read_control(
+        0x81,
+        0x06,
+        0x2200,
+        interface as u16,
+        &mut buffer,
+        Duration::from_secs(1),
+    )?;
+    buffer.truncate(read);
+
+    Ok(buffer)
+}
+
+/// `(buttons, tablet)` interface numbers among `interfaces`, told apart by
+/// their report descriptors, see `descriptor::classify`. Interfaces whose
+/// descriptor can't be read are left out.
+pub fn detect_interfaces<T: UsbContext>(
+    handle: &DeviceHandle<T>,
+    interfaces: &[u8],
+) -> Option<(u8, u8)> {
+    let descriptors: Vec<(u8, Vec<u8>)> = interfaces
+        .iter()
+        .filter_map(|&number| match report_descriptor(handle, number) {
+            Ok(descriptor) => Some((number, descriptor)),
+            Err(e) => {
+                debug!("No report descriptor for interface {number}: {e}");
+                None
+            }
+        })
+        .collect();
+
+    descriptor::classify(&descriptors)
+}
+
+impl<T: UsbContext> USBDevice<T> {
+    /// Endpoints of `number` as found at `open_device`, the full map is
+    /// `interfaces`.
+    pub fn interface(&self, number: u8) -> Option<&InterfaceInfo> {
+        self.interfaces.get(&number)
+    }
+
+    /// Sends an arbitrary host-to-device control request. Nothing checks
+    /// that the tablet understands it, this is for experimentation.
+    pub fn send_raw_control(&self, message: &MessageDevice) -> Result<(), FtdError> {
+        send_to_device(&self.handle, message)
+    }
+
+    /// Sends an arbitrary device-to-host control request and returns the
+    /// up to `length` bytes answered; `message.payload` is ignored.
+    pub fn read_raw_control(
+        &self,
+        message: &MessageDevice,
+        length: usize,
+    ) -> Result<Vec<u8>, FtdError> {
+        let mut buffer = vec![0; length];
+        let read = self.handle.read_control(
+            message.request_type,
+            message.request,
+            message.value,
+            message.interface,
+            &mut buffer,
+            message.timeout,
+        )?;
+        buffer.truncate(read);
+
+        Ok(buffer)
+    }
+
+    /// Polls the `(buttons, tablet)` interfaces and yields decoded events.
+    ///
+    /// Both are checked once here, an interface that is missing or has no
+    /// IN endpoint is an error. Read timeouts are skipped transparently, so
+    /// `next` only returns once a meaningful report or an error arrives.
+    /// Each event is stamped as its read returns. After a read fails the
+    /// iterator ends, drop it to stop earlier.
+    pub fn events(&mut self, numbers: (u8, u8)) -> Result<Events<'_, T>, FtdError> {
+        let interfaces = event_interfaces(&self.interfaces, numbers)?;
+        Ok(Events {
+            device: self,
+            interfaces,
+            buttons: ButtonDecoder::default(),
+            pending: VecDeque::new(),
+            next_interface: 0,
+            failed: false,
+        })
+    }
+}
+
+/// The `(buttons, tablet)` interfaces `Events` reads, each readable.
+fn event_interfaces(
+    interfaces: &HashMap<u8, InterfaceInfo>,
+    (buttons, tablet): (u8, u8),
+) -> Result<[InterfaceInfo; 2], FtdError> {
+    let readable = |number| -> Result<InterfaceInfo, FtdError> {
+        let interface = interfaces
+            .get(&number)
+            .ok_or(FtdError::MissingInterface(number))?;
+        interface.ensure_readable()?;
+        Ok(interface.clone())
+    };
+
+    Ok([readable(buttons)?, readable(tablet)?])
+}
+
+pub struct Events<'a, T: UsbContext> {
+    device: &'a mut USBDevice<T>,
+    /// Buttons first, then the tablet.
+    interfaces: [InterfaceInfo; 2],
+    buttons: ButtonDecoder,
+    pending: VecDeque<Timestamped>,
+    next_interface: usize,
+    failed: bool,
+}
+
+impl<T: UsbContext> Iterator for Events<'_, T> {
+    type Item = Result<Timestamped, FtdError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.failed {
+                return None;
+            }
+
+            let index = self.next_interface;
+            self.next_interface = 1 - index;
+
+            let read = read_device(&self.device.handle, &self.interfaces[index], 10);
+            let at = Instant::now();
+            let stamp = |event| Timestamped { at, event };
+            match read {
+                Ok((_, bytes)) if index == 0 => self.pending.extend(
+                    self.buttons
+                        .decode(&bytes)
+                        .into_iter()
+                        .map(Event::Button)
+                        .map(stamp),
+                ),
+                Ok((_, bytes)) => self.pending.extend(
+                    PenReport::parse(&bytes)
+                        .map(Event::Pen)
+                        .or_else(|| TouchReport::parse(&bytes).map(Event::Touch))
+                        .map(stamp),
+                ),
+                Err(rusb::Error::Timeout) => {}
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interface_without_in_endpoints_is_not_readable() {
+        let interface = InterfaceInfo {
+            endpoints_out: vec![0x03],
+            ..InterfaceInfo::new(TABLET_INTERFACE, vec![], 8)
+        };
+
+        assert!(matches!(
+            interface.ensure_readable(),
+            Err(FtdError::NoInEndpoint(TABLET_INTERFACE))
+        ));
+        assert!(
+            InterfaceInfo {
+                endpoints_in: vec![0x82],
+                ..interface
+            }
+            .ensure_readable()
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn missing_tablet_interface_lists_what_was_found() {
+        let interfaces: HashMap<u8, InterfaceInfo> = [0, BUTTONS_INTERAFCE]
+            .into_iter()
+            .map(|number| (number, InterfaceInfo::new(number, vec![0x81], 8)))
+            .collect();
+
+        let error =
+            check_interfaces(&interfaces, &[TABLET_INTERFACE, BUTTONS_INTERAFCE]).unwrap_err();
+        assert!(matches!(
+            &error,
+            FtdError::MissingInterfaces(expected, found)
+                if *expected == [BUTTONS_INTERAFCE, TABLET_INTERFACE] && *found == [0, BUTTONS_INTERAFCE]
+        ));
+        assert!(error.to_string().contains("[0, 1]"));
+        assert!(check_interfaces(&interfaces, &[0, BUTTONS_INTERAFCE]).is_ok());
+    }
+
+    #[test]
+    fn a_configuration_switch_gives_kept_interfaces_back() {
+        let detached = [MASS_STORAGE, BUTTONS_INTERAFCE, TABLET_INTERFACE];
+
+        assert_eq!(
+            reattached_after_switch(&detached, &[BUTTONS_INTERAFCE, TABLET_INTERFACE]),
+            vec![MASS_STORAGE]
+        );
+        assert!(reattached_after_switch(&detached, &detached).is_empty());
+    }
+
+    #[test]
+    fn events_reads_the_interfaces_it_is_given() {
+        let interfaces: HashMap<u8, InterfaceInfo> =
+            [(0, vec![0x81]), (3, vec![]), (4, vec![0x84])]
+                .into_iter()
+                .map(|(number, endpoints_in)| (number, InterfaceInfo::new(number, endpoints_in, 8)))
+                .collect();
+
+        let [buttons, tablet] = event_interfaces(&interfaces, (4, 0)).unwrap();
+        assert_eq!((buttons.number, tablet.number), (4, 0));
+        assert!(matches!(
+            event_interfaces(&interfaces, (0, TABLET_INTERFACE)),
+            Err(FtdError::MissingInterface(TABLET_INTERFACE))
+        ));
+        assert!(matches!(
+            event_interfaces(&interfaces, (3, 0)),
+            Err(FtdError::NoInEndpoint(3))
+        ));
+    }
+
+    #[test]
+    fn read_size_overrides_the_descriptor() {
+        let mut interface = InterfaceInfo::new(TABLET_INTERFACE, vec![0x82], 8);
+        assert_eq!(interface.buffer_size(), 8);
+
+        interface.read_size = Some(12);
+        assert_eq!(interface.buffer_size(), 12);
+    }
+
+    #[test]
+    fn pinned_endpoint_is_the_only_one_read() {
+        let mut interface = InterfaceInfo::new(TABLET_INTERFACE, vec![0x81, 0x83], 8);
+        assert_eq!(interface.read_endpoints(), &[0x81, 0x83]);
+
+        interface.prefer_in(0x83).unwrap();
+        assert_eq!(interface.read_endpoints(), &[0x83]);
+
+        assert!(matches!(
+            interface.prefer_in(0x82),
+            Err(FtdError::MissingEndpoint(TABLET_INTERFACE, 0x82))
+        ));
+        assert_eq!(interface.read_endpoints(), &[0x83]);
+    }
+
+    #[test]
+    fn endpoints_not_declared_interrupt_are_caught() {
+        let interface = InterfaceInfo {
+            transfer_types: vec![(0x81, TransferType::Bulk), (0x82, TransferType::Interrupt)],
+            ..InterfaceInfo::new(TABLET_INTERFACE, vec![0x81, 0x82], 64)
+        };
+        assert_eq!(
+            interface.mismatched_endpoints(),
+            vec![(0x81, TransferType::Bulk)]
+        );
+        assert!(interface.check_transfer_types().is_ok());
+
+        let mut bulk = interface.clone();
+        bulk.prefer_in(0x81).unwrap();
+        let error = bulk.check_transfer_types().unwrap_err();
+        assert!(matches!(
+            error,
+            FtdError::WrongTransferType(TABLET_INTERFACE, 0x81, TransferType::Bulk)
+        ));
+        assert!(error.to_string().contains("bulk"), "{error}");
+
+        let mut interrupt = interface;
+        interrupt.prefer_in(0x82).unwrap();
+        assert_eq!(interrupt.mismatched_endpoints(), vec![]);
+    }
+
+    #[test]
+    fn a_failing_endpoint_is_skipped_after_its_strikes() {
+        let mut interface = InterfaceInfo::new(TABLET_INTERFACE, vec![0x81, 0x82], 8);
+        let mut strikes = EndpointStrikes::new(3);
+        let mut tried = vec![];
+        for _ in 0..5 {
+            let mut seen = vec![];
+            let read = read_endpoints(
+                &interface,
+                |endpoint, _| {
+                    tried.push(endpoint);
+                    match endpoint {
+                        0x81 => Err(rusb::Error::Pipe),
+                        _ => Ok(4),
+                    }
+                },
+                |endpoint, result| seen.push((endpoint, result)),
+            );
+            assert!(read.is_ok());
+            for (endpoint, result) in seen {
+                strikes.observe(&mut interface, endpoint, result);
+            }
+        }
+
+        assert_eq!(tried, [0x81, 0x82, 0x81, 0x82, 0x81, 0x82, 0x82, 0x82]);
+        assert_eq!(interface.skipped_in, [0x81]);
+        // The last endpoint left is read however it fails.
+        assert!(!interface.skip_in(0x82));
+        assert_eq!(interface.read_endpoints(), &[0x82]);
+    }
+
+    #[test]
+    fn only_essential_interfaces_must_be_claimed() {
+        let busy_buttons = |num| match num {
+            BUTTONS_INTERAFCE => Err(FtdError::Usb(rusb::Error::Busy)),
+            _ => Ok(()),
+        };
+        let all = [0, BUTTONS_INTERAFCE, TABLET_INTERFACE];
+
+        assert_eq!(
+            claim_each(&all, &[TABLET_INTERFACE], busy_buttons).unwrap(),
+            vec![BUTTONS_INTERAFCE]
+        );
+        assert!(claim_each(&all, &all, busy_buttons).is_err());
+    }
+
+    #[test]
+    fn health_check_accepts_endpoints_that_are_not_halted() {
+        let interface = InterfaceInfo::new(TABLET_INTERFACE, vec![0x81, 0x82], 64);
+
+        assert!(check_endpoints(&interface, |_| Ok(false)).is_ok());
+        assert!(
+            check_endpoints(&interface, |endpoint| match endpoint {
+                0x81 => Err(rusb::Error::Timeout),
+                _ => Ok(false),
+            })
+            .is_ok()
+        );
+        assert!(matches!(
+            check_endpoints(&interface, |endpoint| Ok(endpoint == 0x82)),
+            Err(FtdError::UnusableEndpoint(
+                TABLET_INTERFACE,
+                0x82,
+                rusb::Error::Pipe
+            ))
+        ));
+        assert!(matches!(
+            check_endpoints(&interface, |endpoint| match endpoint {
+                0x81 => Ok(false),
+                _ => Err(rusb::Error::NoDevice),
+            }),
+            Err(FtdError::UnusableEndpoint(
+                TABLET_INTERFACE,
+                0x82,
+                rusb::Error::NoDevice
+            ))
+        ));
+
+        let empty = InterfaceInfo {
+            endpoints_in: vec![],
+            ..interface
+        };
+        assert!(matches!(
+            check_endpoints(&empty, |_| Ok(false)),
+            Err(FtdError::NoInEndpoint(TABLET_INTERFACE))
+        ));
+    }
+
+    #[test]
+    fn the_revision_is_kept_in_bcd() {
+        assert_eq!(revision_bcd(rusb::Version::from_bcd(0x0102)), 0x0102);
+        assert_eq!(revision_bcd(rusb::Version::from_bcd(0x1234)), 0x1234);
+        assert_eq!(revision_bcd(rusb::Version(2, 1, 0)), 0x0210);
+    }
+
+    #[test]
+    fn the_polling_interval_sets_the_rate_and_the_shortest_read() {
+        assert_eq!(poll_interval(Speed::Full, 4), Duration::from_millis(4));
+        assert_eq!(poll_interval(Speed::High, 4), Duration::from_millis(1));
+
+        let interface = InterfaceInfo {
+            poll_interval: Some(poll_interval(Speed::Full, 8)),
+            ..InterfaceInfo::new(TABLET_INTERFACE, vec![0x82], 64)
+        };
+        assert_eq!(interface.report_rate(), Some(125.0));
+        assert_eq!(interface.read_timeout(5), 8);
+        assert_eq!(interface.read_timeout(1000), 1000);
+        assert_eq!(
+            InterfaceInfo {
+                poll_interval: None,
+                transfer_types: vec![],
+                skipped_in: vec![],
+                ..interface
+            }
+            .read_timeout(5),
+            5
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum FtdError {
+    Usb(rusb::Error),
+    MissingInterface(u8),
+    /// `(expected, found)` interface numbers, both sorted.
+    MissingInterfaces(Vec<u8>, Vec<u8>),
+    NoInEndpoint(u8),
+    /// `(interface, endpoint address)`
+    MissingEndpoint(u8, u8),
+    /// `(interface, endpoint address, why the health check failed it)`
+    UnusableEndpoint(u8, u8, rusb::Error),
+    /// `(interface, endpoint address, what it is declared as)` for an
+    /// endpoint that is read with interrupt transfers but is not one.
+    WrongTransferType(u8, u8, rusb::TransferType),
+    /// What the last `init.verify` read returned, for init packets that
+    /// never left the tablet in the expected state.
+    InitNotConfirmed(Vec<u8>),
+}
+
+impl fmt::Display for FtdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FtdError::Usb(e) => write!(f, "USB error: {e}"),
+            FtdError::MissingInterface(number) => {
+                write!(f, "Interface {number} is not present on the device")
+            }
+            FtdError::MissingInterfaces(expected, found) => {
+                write!(
+                    f,
+                    "The device has interfaces {found:?} but {expected:?} are expected, \
+                     check `device.interfaces` for this model"
+                )
+            }
+            FtdError::NoInEndpoint(number) => {
+                write!(
+                    f,
+                    "Interface {number} has no IN endpoint to read reports from"
+                )
+            }
+            FtdError::MissingEndpoint(number, address) => {
+                write!(f, "Interface {number} has no IN endpoint {address:#04x}")
+            }
+            FtdError::UnusableEndpoint(number, address, e) => {
+                write!(
+                    f,
+                    "IN endpoint {address:#04x} of interface {number} is unusable ({e}), \
+                     check the `device` endpoints and interfaces for this model"
+                )
+            }
+            FtdError::WrongTransferType(number, address, declared) => {
+                let kind = match declared {
+                    rusb::TransferType::Control => "control",
+                    rusb::TransferType::Isochronous => "isochronous",
+                    rusb::TransferType::Bulk => "bulk",
+                    rusb::TransferType::Interrupt => "interrupt",
+                };
+                write!(
+                    f,
+                    "IN endpoint {address:#04x} of interface {number} is a {kind} endpoint, \
+                     but reports are read with interrupt transfers, which it rejects: it \
+                     needs {kind} reads, or pick an interrupt endpoint in the `device` \
+                     endpoints of this model"
+                )
+            }
+            FtdError::InitNotConfirmed(report) => {
+                write!(
+                    f,
+                    "The tablet still answered {report:02x?} after the init packets, \
+                     check `init.verify` for this model"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FtdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FtdError::Usb(e) | FtdError::UnusableEndpoint(_, _, e) => Some(e),
+            FtdError::MissingInterface(_)
+            | FtdError::MissingInterfaces(..)
+            | FtdError::NoInEndpoint(_)
+            | FtdError::MissingEndpoint(..)
+            | FtdError::WrongTransferType(..)
+            | FtdError::InitNotConfirmed(_) => None,
+        }
+    }
+}
+
+impl From<rusb::Error> for FtdError {
+    fn from(e: rusb::Error) -> Self {
+        FtdError::Usb(e)
+    }
+}
@@ -0,0 +1,373 @@
+//! Decoding of the raw HID reports described in `MTM-1106.md`.
+
+use std::time::Instant;
+
+pub const PEN_REPORT_ID: u8 = 0x09;
+pub const BUTTON_REPORT_ID: u8 = 0x01;
+pub const TOUCH_REPORT_ID: u8 = 0x03;
+/// Feature report holding the parameter block, see `Capabilities`.
+pub const CAPABILITIES_REPORT_ID: u8 = 0x05;
+
+pub const DEVICE_MAX_X: i32 = 0x7FFF;
+pub const DEVICE_MAX_Y: i32 = 0x7FFF;
+pub const DEVICE_MAX_PRESSURE: i32 = 0x1FFF;
+/// Units per mm for a 10 x 6 inch active area, the usual size for this class of tablet.
+pub const DEVICE_RESOLUTION: (i32, i32) = (129, 215);
+
+/// Byte that would hold a frame counter; bytes 8-9 are padding on the MTM-1106.
+pub const PEN_SEQUENCE_OFFSET: usize = 8;
+
+const STATUS_IN_RANGE: u8 = 0x40;
+const STATUS_TIP: u8 = 0x01;
+
+/// Physical buttons in the order they are numbered by the driver, as
+/// `(byte offset, bit mask)` inside a buttons report.
+pub const BUTTON_BITS: [(usize, u8); BUTTON_COUNT] = [
+    (4, 0x80), // CTRL -
+    (4, 0x40), // [
+    (4, 0x20), // Mouse UP
+    (4, 0x10), // Mouse DOWN
+    (4, 0x08), // CTRL
+    (4, 0x04), // ALT
+    (4, 0x02), // SPACE
+    (4, 0x01), // TAB
+    (5, 0x20), // Pen button 1
+    (5, 0x10), // Pen button 2
+    (5, 0x08), // ]
+    (5, 0x04), // B
+    (5, 0x02), // E
+    (5, 0x01), // CTRL +
+];
+pub const BUTTON_COUNT: usize = 14;
+/// Labels of `BUTTON_BITS`, as printed on the tablet.
+pub const BUTTON_NAMES: [&str; BUTTON_COUNT] = [
+    "CTRL-", "[", "UP", "DOWN", "CTRL", "ALT", "SPACE", "TAB", "PEN1", "PEN2", "]", "B", "E",
+    "CTRL+",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PenReport {
+    pub x: u16,
+    pub y: u16,
+    pub pressure: u16,
+    pub in_range: bool,
+    pub tip: bool,
+}
+
+impl PenReport {
+    /// Parses a `[09, ST, XL, XH, YL, YH, PL, PH, ..]` report.
+    pub fn parse(bytes: &[u8]) -> Option<PenReport> {
+        if bytes.len() < 8 || bytes[0] != PEN_REPORT_ID {
+            return None;
+        }
+
+        let status = bytes[1];
+        Some(PenReport {
+            x: u16::from_le_bytes([bytes[2], bytes[3]]),
+            y: u16::from_le_bytes([bytes[4], bytes[5]]),
+            pressure: u16::from_le_bytes([bytes[6], bytes[7]]),
+            in_range: status & STATUS_IN_RANGE != 0,
+            tip: status & STATUS_TIP != 0,
+        })
+    }
+
+    /// The same report with X and Y exchanged, for units that send each
+    /// axis in the other's bytes.
+    pub fn swap_xy(self) -> PenReport {
+        PenReport {
+            x: self.y,
+            y: self.x,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonEvent {
+    pub index: u8,
+    pub pressed: bool,
+}
+
+/// Turns the bitmask buttons reports into press/release edges.
+#[derive(Default)]
+pub struct ButtonDecoder {
+    pressed: [bool; BUTTON_COUNT],
+    ignored: [bool; BUTTON_COUNT],
+}
+
+impl ButtonDecoder {
+    /// A decoder that never reports the buttons at `indices`, for bits that
+    /// duplicate a signal the pen report already carries. Indices past
+    /// `BUTTON_COUNT` are skipped.
+    pub fn ignoring(indices: &[u8]) -> ButtonDecoder {
+        let mut decoder = ButtonDecoder::default();
+        for &index in indices {
+            if let Some(ignored) = decoder.ignored.get_mut(index as usize) {
+                *ignored = true;
+            }
+        }
+        decoder
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<ButtonEvent> {
+        if bytes.len() < 6 || bytes[0] != BUTTON_REPORT_ID {
+            return vec![];
+        }
+
+        let mut events = vec![];
+        for (index, (byte, mask)) in BUTTON_BITS.iter().enumerate() {
+            if self.ignored[index] {
+                continue;
+            }
+            let pressed = bytes[*byte] & mask != 0;
+            if pressed != self.pressed[index] {
+                self.pressed[index] = pressed;
+                events.push(ButtonEvent {
+                    index: index as u8,
+                    pressed,
+                });
+            }
+        }
+
+        events
+    }
+}
+
+pub const MAX_CONTACTS: usize = 5;
+const CONTACT_SIZE: usize = 6;
+const CONTACT_DOWN: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    pub id: u8,
+    pub down: bool,
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TouchReport {
+    pub contacts: Vec<Contact>,
+}
+
+impl TouchReport {
+    /// Parses a capacitive touch report on models that have one:
+    /// `[03, N, (FL, ID, XL, XH, YL, YH) * N]`, bit 0 of `FL` set while the
+    /// finger is down.
+    pub fn parse(bytes: &[u8]) -> Option<TouchReport> {
+        if bytes.len() < 2 || bytes[0] != TOUCH_REPORT_ID {
+            return None;
+        }
+
+        let count = bytes[1] as usize;
+        if count > MAX_CONTACTS || bytes.len() < 2 + count * CONTACT_SIZE {
+            return None;
+        }
+
+        let contacts = bytes[2..2 + count * CONTACT_SIZE]
+            .chunks_exact(CONTACT_SIZE)
+            .map(|c| Contact {
+                id: c[1],
+                down: c[0] & CONTACT_DOWN != 0,
+                x: u16::from_le_bytes([c[2], c[3]]),
+                y: u16::from_le_bytes([c[4], c[5]]),
+            })
+            .collect();
+
+        Some(TouchReport { contacts })
+    }
+}
+
+/// A report no parser handles, passed on as it was read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawReport {
+    pub interface: u8,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Pen(PenReport),
+    Button(ButtonEvent),
+    Touch(TouchReport),
+    Raw(RawReport),
+}
+
+/// An event and when the report it came from was read, on the monotonic
+/// clock, for replay timing and latency measurements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timestamped {
+    pub at: Instant,
+    pub event: Event,
+}
+
+/// What the tablet says about itself, read once at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub max_x: i32,
+    pub max_y: i32,
+    pub max_pressure: i32,
+    pub buttons: u8,
+    /// Units per mm along X and Y.
+    pub resolution: (i32, i32),
+}
+
+impl Capabilities {
+    /// Parses a `[05, XL, XH, YL, YH, PL, PH, BN, RL, RH]` feature report,
+    /// the parameter block layout of UC-Logic based tablets, where `R` is
+    /// the resolution in lines per inch. Zeroed fields mean the block is
+    /// not filled in and yield `None`.
+    pub fn parse(bytes: &[u8]) -> Option<Capabilities> {
+        if bytes.len() < 10 || bytes[0] != CAPABILITIES_REPORT_ID {
+            return None;
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as i32;
+        let (max_x, max_y, max_pressure, lpi) = (word(1), word(3), word(5), word(8));
+        if max_x == 0 || max_y == 0 || max_pressure == 0 || lpi == 0 {
+            return None;
+        }
+
+        let per_mm = (lpi as f64 / 25.4).round() as i32;
+        Some(Capabilities {
+            max_x,
+            max_y,
+            max_pressure,
+            buttons: bytes[7],
+            resolution: (per_mm, per_mm),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pen_report() {
+        let report =
+            PenReport::parse(&[0x09, 0xC1, 0x34, 0x12, 0x78, 0x56, 0x00, 0x01, 0, 0]).unwrap();
+
+        assert_eq!(report.x, 0x1234);
+        assert_eq!(report.y, 0x5678);
+        assert_eq!(report.pressure, 0x0100);
+        assert!(report.in_range);
+        assert!(report.tip);
+    }
+
+    #[test]
+    fn swap_xy_exchanges_the_parsed_axes() {
+        let report = PenReport::parse(&[0x09, 0xC1, 0x34, 0x12, 0x78, 0x56, 0x00, 0x01, 0, 0])
+            .unwrap()
+            .swap_xy();
+
+        assert_eq!((report.x, report.y), (0x5678, 0x1234));
+        assert_eq!(report.pressure, 0x0100);
+    }
+
+    #[test]
+    fn parses_single_finger_touch() {
+        let report = TouchReport::parse(&[0x03, 1, 0x01, 7, 0x10, 0x00, 0x20, 0x00]).unwrap();
+
+        assert_eq!(
+            report.contacts,
+            vec![Contact {
+                id: 7,
+                down: true,
+                x: 0x10,
+                y: 0x20
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_two_finger_touch() {
+        let bytes = [
+            0x03, 2, 0x01, 0, 0x00, 0x01, 0x00, 0x02, 0x00, 1, 0x00, 0x03, 0x00, 0x04,
+        ];
+        let report = TouchReport::parse(&bytes).unwrap();
+
+        assert_eq!(report.contacts.len(), 2);
+        assert!(report.contacts[0].down);
+        assert_eq!((report.contacts[0].x, report.contacts[0].y), (0x100, 0x200));
+        assert!(!report.contacts[1].down);
+        assert_eq!((report.contacts[1].x, report.contacts[1].y), (0x300, 0x400));
+
+        assert!(TouchReport::parse(&bytes[..10]).is_none());
+    }
+
+    #[test]
+    fn decodes_button_edges() {
+        let mut decoder = ButtonDecoder::default();
+
+        let pressed = decoder.decode(&[0x01, 0x80, 0xFF, 0x00, 0x08, 0x20, 0, 0]);
+        assert_eq!(
+            pressed,
+            vec![
+                ButtonEvent {
+                    index: 4,
+                    pressed: true
+                },
+                ButtonEvent {
+                    index: 8,
+                    pressed: true
+                },
+            ]
+        );
+
+        let released = decoder.decode(&[0x01, 0x80, 0x00, 0x00, 0x00, 0x00, 0, 0]);
+        assert_eq!(
+            released,
+            vec![
+                ButtonEvent {
+                    index: 4,
+                    pressed: false
+                },
+                ButtonEvent {
+                    index: 8,
+                    pressed: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignored_buttons_produce_no_events() {
+        let mut decoder = ButtonDecoder::ignoring(&[8, 40]);
+
+        let pressed = decoder.decode(&[0x01, 0x80, 0xFF, 0x00, 0x08, 0x20, 0, 0]);
+        assert_eq!(
+            pressed,
+            vec![ButtonEvent {
+                index: 4,
+                pressed: true
+            }]
+        );
+        assert!(
+            decoder
+                .decode(&[0x01, 0x80, 0xFF, 0x00, 0x08, 0x00, 0, 0])
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn parses_the_capabilities_block() {
+        let bytes = [0x05, 0xFF, 0x7F, 0xFF, 0x7F, 0xFF, 0x1F, 0x0E, 0x88, 0x13];
+
+        assert_eq!(
+            Capabilities::parse(&bytes),
+            Some(Capabilities {
+                max_x: 0x7FFF,
+                max_y: 0x7FFF,
+                max_pressure: 0x1FFF,
+                buttons: 14,
+                resolution: (197, 197),
+            })
+        );
+        assert_eq!(
+            Capabilities::parse(&[0x05, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            None
+        );
+        assert_eq!(Capabilities::parse(&bytes[..6]), None);
+    }
+}
@@ -0,0 +1,110 @@
+use log::{debug, warn};
+
+const DETECTION_SAMPLES: usize = 8;
+
+enum State {
+    Detecting(Vec<u8>),
+    Tracking(u8),
+    Disabled,
+}
+
+/// Counts reports lost between reads, for models whose reports carry a
+/// rolling frame counter. The counter is looked for during the first reports
+/// after init; if that byte does not increment, tracking stays disabled.
+pub struct SequenceTracker {
+    offset: usize,
+    state: State,
+    dropped: u64,
+}
+
+impl SequenceTracker {
+    pub fn new(offset: usize) -> SequenceTracker {
+        SequenceTracker {
+            offset,
+            state: State::Detecting(Vec::with_capacity(DETECTION_SAMPLES)),
+            dropped: 0,
+        }
+    }
+
+    /// Returns how many reports were skipped right before this one.
+    pub fn observe(&mut self, report: &[u8]) -> u64 {
+        let Some(&counter) = report.get(self.offset) else {
+            return 0;
+        };
+
+        match &mut self.state {
+            State::Detecting(samples) => {
+                samples.push(counter);
+                if samples.len() == DETECTION_SAMPLES {
+                    if samples.windows(2).all(|w| w[1] == w[0].wrapping_add(1)) {
+                        debug!("Frame counter found at byte {}", self.offset);
+                        self.state = State::Tracking(counter);
+                    } else {
+                        debug!("No frame counter in reports, drop detection disabled");
+                        self.state = State::Disabled;
+                    }
+                }
+                0
+            }
+            State::Tracking(last) => {
+                let gap = if counter == *last {
+                    0
+                } else {
+                    counter.wrapping_sub(last.wrapping_add(1)) as u64
+                };
+                *last = counter;
+
+                if gap > 0 {
+                    self.dropped += gap;
+                    warn!("Dropped {gap} reports, the read loop is falling behind");
+                }
+                gap
+            }
+            State::Disabled => 0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, State::Tracking(_))
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(counter: u8) -> [u8; 2] {
+        [0x09, counter]
+    }
+
+    #[test]
+    fn counts_gaps_once_a_counter_is_detected() {
+        let mut tracker = SequenceTracker::new(1);
+
+        for counter in 250..=255 {
+            tracker.observe(&report(counter));
+        }
+        tracker.observe(&report(0));
+        tracker.observe(&report(1));
+        assert!(tracker.is_active());
+
+        assert_eq!(tracker.observe(&report(2)), 0);
+        assert_eq!(tracker.observe(&report(5)), 2);
+        assert_eq!(tracker.dropped(), 2);
+    }
+
+    #[test]
+    fn stays_disabled_without_a_counter() {
+        let mut tracker = SequenceTracker::new(1);
+
+        for _ in 0..DETECTION_SAMPLES * 2 {
+            assert_eq!(tracker.observe(&report(0)), 0);
+        }
+        assert!(!tracker.is_active());
+        assert_eq!(tracker.dropped(), 0);
+    }
+}
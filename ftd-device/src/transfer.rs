@@ -0,0 +1,475 @@
+use std::{
+    collections::VecDeque,
+    ffi::{c_int, c_void},
+    ptr::NonNull,
+    sync::{
+        Mutex,
+        atomic::{AtomicI32, Ordering},
+    },
+    time::Duration,
+};
+
+use libusb1_sys::{
+    constants::*, libusb_alloc_transfer, libusb_cancel_transfer, libusb_fill_interrupt_transfer,
+    libusb_free_transfer, libusb_handle_events_timeout_completed, libusb_submit_transfer,
+    libusb_transfer,
+};
+use log::warn;
+use rusb::{DeviceHandle, Result as RusbResult, UsbContext};
+
+use crate::device::InterfaceInfo;
+
+/// How many times `UsbTransfers` waits `CANCEL_WAIT` for its cancelled
+/// transfers to come back before it gives up on them.
+const CANCEL_ROUNDS: usize = 20;
+const CANCEL_WAIT: Duration = Duration::from_millis(50);
+
+/// A queued read that finished, and what it returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub endpoint: u8,
+    pub result: RusbResult<Vec<u8>>,
+}
+
+/// Where `AsyncReader` queues its reads: libusb in `UsbTransfers`, a script
+/// in the tests.
+pub trait Transfers {
+    /// Queues a read of up to `length` bytes from the IN endpoint `endpoint`.
+    fn submit(&mut self, endpoint: u8, length: usize) -> RusbResult<()>;
+
+    /// The reads that finished, waiting up to `timeout` for the first.
+    fn complete(&mut self, timeout: Duration) -> RusbResult<Vec<Completion>>;
+
+    /// Cancels every queued read and waits for them to come back, dropping
+    /// what they read.
+    fn cancel(&mut self) -> RusbResult<()>;
+}
+
+/// Reads an interface through queued transfers rather than one blocking
+/// read after the other: `in_flight` reads stay queued on each IN endpoint
+/// and a finished one is queued again right away, so a report never waits
+/// for the next read to start and no time goes to read timeouts.
+pub struct AsyncReader<X: Transfers> {
+    transfers: X,
+    length: usize,
+    in_flight: usize,
+    ready: VecDeque<Completion>,
+    /// Endpoints a read could not be queued again on, retried next time.
+    unqueued: Vec<u8>,
+    /// Set by `pause`, the reads are queued again on the next read.
+    paused: bool,
+}
+
+impl<X: Transfers> AsyncReader<X> {
+    pub fn new(
+        mut transfers: X,
+        interface: &InterfaceInfo,
+        in_flight: usize,
+    ) -> RusbResult<AsyncReader<X>> {
+        let length = interface.buffer_size();
+        let in_flight = in_flight.max(1);
+        for endpoint in interface.read_endpoints() {
+            for _ in 0..in_flight {
+                transfers.submit(endpoint, length)?;
+            }
+        }
+
+        Ok(AsyncReader {
+            transfers,
+            length,
+            in_flight,
+            ready: VecDeque::new(),
+            unqueued: vec![],
+            paused: false,
+        })
+    }
+
+    /// Cancels the queued reads and drops what they and the finished ones
+    /// read, for when the output is off: the next `read_observed` queues
+    /// them again, so no report from before is handed out late.
+    pub fn pause(&mut self) -> RusbResult<()> {
+        self.paused = true;
+        self.ready.clear();
+        self.unqueued.clear();
+        self.transfers.cancel()
+    }
+
+    /// `read_device_observed` for the next read to finish, `Err(Timeout)`
+    /// when none did within `timeout`. An endpoint `interface` no longer
+    /// reads, see `InterfaceInfo::skip_in`, gets no more reads queued.
+    pub fn read_observed(
+        &mut self,
+        interface: &InterfaceInfo,
+        timeout: Duration,
+        mut observe: impl FnMut(u8, RusbResult<usize>),
+    ) -> RusbResult<(u8, Vec<u8>)> {
+        let read = interface.read_endpoints();
+        if std::mem::take(&mut self.paused) {
+            for &endpoint in &read {
+                for _ in 0..self.in_flight {
+                    self.queue(endpoint);
+                }
+            }
+        }
+        for endpoint in std::mem::take(&mut self.unqueued) {
+            if read.contains(&endpoint) {
+                self.queue(endpoint);
+            }
+        }
+        if self.ready.is_empty() {
+            for completion in self.transfers.complete(timeout)? {
+                let endpoint = completion.endpoint;
+                let gone = completion.result == Err(rusb::Error::NoDevice);
+                self.ready.push_back(completion);
+                if read.contains(&endpoint) && !gone {
+                    self.queue(endpoint);
+                }
+            }
+        }
+
+        let completion = self.ready.pop_front().ok_or(rusb::Error::Timeout)?;
+        observe(
+            completion.endpoint,
+            completion.result.as_ref().map(Vec::len).map_err(|e| *e),
+        );
+        completion.result.map(|bytes| (interface.number, bytes))
+    }
+
+    fn queue(&mut self, endpoint: u8) {
+        if let Err(e) = self.transfers.submit(endpoint, self.length) {
+            self.ready.push_back(Completion {
+                endpoint,
+                result: Err(e),
+            });
+            self.unqueued.push(endpoint);
+        }
+    }
+}
+
+/// What the libusb callback leaves for `UsbTransfers`. Any thread handling
+/// events on the context may be the one running it, e.g. another reader.
+#[derive(Default)]
+struct Finished {
+    /// Addresses of the transfers that finished.
+    transfers: Mutex<Vec<usize>>,
+    /// Passed to libusb as `completed`, which wakes a thread waiting for
+    /// events once it is set.
+    completed: AtomicI32,
+}
+
+/// Interrupt transfers submitted to libusb, for `AsyncReader`.
+pub struct UsbTransfers<'a, T: UsbContext> {
+    handle: &'a DeviceHandle<T>,
+    finished: Box<Finished>,
+    /// Every transfer allocated so far, with the buffer it reads into.
+    owned: Vec<(NonNull<libusb_transfer>, Box<[u8]>)>,
+    /// Allocated transfers that are not submitted.
+    idle: Vec<NonNull<libusb_transfer>>,
+    in_flight: usize,
+}
+
+impl<'a, T: UsbContext> UsbTransfers<'a, T> {
+    pub fn new(handle: &'a DeviceHandle<T>) -> UsbTransfers<'a, T> {
+        UsbTransfers {
+            handle,
+            finished: Box::default(),
+            owned: vec![],
+            idle: vec![],
+            in_flight: 0,
+        }
+    }
+
+    fn allocate(&mut self, length: usize) -> RusbResult<NonNull<libusb_transfer>> {
+        // SAFETY: no isochronous packets, the transfer is freed in `drop`.
+        let transfer =
+            NonNull::new(unsafe { libusb_alloc_transfer(0) }).ok_or(rusb::Error::NoMem)?;
+        self.owned
+            .push((transfer, vec![0; length].into_boxed_slice()));
+
+        Ok(transfer)
+    }
+}
+
+impl<T: UsbContext> Transfers for UsbTransfers<'_, T> {
+    fn submit(&mut self, endpoint: u8, length: usize) -> RusbResult<()> {
+        let transfer = match self.idle.pop() {
+            Some(transfer) => transfer,
+            None => self.allocate(length)?,
+        };
+        let (buffer, capacity) = self
+            .owned
+            .iter_mut()
+            .find(|(owned, _)| *owned == transfer)
+            .map(|(_, buffer)| (buffer.as_mut_ptr(), buffer.len()))
+            .ok_or(rusb::Error::Other)?;
+        let finished = &*self.finished as *const Finished as *mut c_void;
+
+        // SAFETY: the transfer, its buffer and `finished` are only freed in
+        // `drop`, after the transfer came back. No timeout, `complete` is
+        // what waits.
+        let code = unsafe {
+            libusb_fill_interrupt_transfer(
+                transfer.as_ptr(),
+                self.handle.as_raw(),
+                endpoint,
+                buffer,
+                length.min(capacity) as c_int,
+                on_finished,
+                finished,
+                0,
+            );
+            libusb_submit_transfer(transfer.as_ptr())
+        };
+        if code != 0 {
+            self.idle.push(transfer);
+            return Err(usb_error(code));
+        }
+        self.in_flight += 1;
+
+        Ok(())
+    }
+
+    fn complete(&mut self, timeout: Duration) -> RusbResult<Vec<Completion>> {
+        self.finished.completed.store(0, Ordering::SeqCst);
+        let empty = lock(&self.finished.transfers).is_empty();
+        if empty && self.in_flight > 0 {
+            let timeout = libc::timeval {
+                tv_sec: timeout.as_secs() as _,
+                tv_usec: timeout.subsec_micros() as _,
+            };
+            // SAFETY: `timeout` and `completed` outlive the call.
+            let code = unsafe {
+                libusb_handle_events_timeout_completed(
+                    self.handle.context().as_raw(),
+                    &timeout,
+                    self.finished.completed.as_ptr(),
+                )
+            };
+            if code != 0 && code != LIBUSB_ERROR_INTERRUPTED {
+                return Err(usb_error(code));
+            }
+        }
+
+        let done = std::mem::take(&mut *lock(&self.finished.transfers));
+        let mut completions = vec![];
+        for address in done {
+            let Some(transfer) = NonNull::new(address as *mut libusb_transfer) else {
+                continue;
+            };
+            // SAFETY: libusb no longer touches a transfer once its callback
+            // ran, until it is submitted again.
+            let (endpoint, status, length) = unsafe {
+                let transfer = transfer.as_ref();
+                (transfer.endpoint, transfer.status, transfer.actual_length)
+            };
+            let result = match status {
+                LIBUSB_TRANSFER_COMPLETED => self
+                    .owned
+                    .iter()
+                    .find(|(owned, _)| *owned == transfer)
+                    .map(|(_, buffer)| buffer[..(length.max(0) as usize)].to_vec())
+                    .ok_or(rusb::Error::Other),
+                status => Err(status_error(status)),
+            };
+            self.idle.push(transfer);
+            self.in_flight -= 1;
+            completions.push(Completion { endpoint, result });
+        }
+
+        Ok(completions)
+    }
+
+    fn cancel(&mut self) -> RusbResult<()> {
+        for (transfer, _) in &self.owned {
+            if !self.idle.contains(transfer) {
+                // SAFETY: the transfer is submitted, cancelling only makes
+                // it come back sooner.
+                unsafe { libusb_cancel_transfer(transfer.as_ptr()) };
+            }
+        }
+        for _ in 0..CANCEL_ROUNDS {
+            if self.in_flight == 0 {
+                return Ok(());
+            }
+            let _ = self.complete(CANCEL_WAIT);
+        }
+
+        Err(rusb::Error::Busy)
+    }
+}
+
+impl<T: UsbContext> Drop for UsbTransfers<'_, T> {
+    fn drop(&mut self) {
+        if self.cancel().is_err() {
+            // libusb may still write to them, better leaked than freed.
+            warn!(
+                "{} USB transfers did not come back after cancelling",
+                self.in_flight
+            );
+            std::mem::forget(std::mem::take(&mut self.owned));
+            Box::leak(std::mem::take(&mut self.finished));
+            return;
+        }
+        for (transfer, _) in self.owned.drain(..) {
+            // SAFETY: every transfer came back and none is submitted again.
+            unsafe { libusb_free_transfer(transfer.as_ptr()) };
+        }
+    }
+}
+
+extern "system" fn on_finished(transfer: *mut libusb_transfer) {
+    // SAFETY: `user_data` is the `Finished` of the `UsbTransfers` that
+    // submitted the transfer, which outlives it.
+    let finished = unsafe { &*((*transfer).user_data as *const Finished) };
+    lock(&finished.transfers).push(transfer as usize);
+    finished.completed.store(1, Ordering::SeqCst);
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The `rusb::Error` of a libusb error code.
+fn usb_error(code: c_int) -> rusb::Error {
+    match code {
+        LIBUSB_ERROR_IO => rusb::Error::Io,
+        LIBUSB_ERROR_INVALID_PARAM => rusb::Error::InvalidParam,
+        LIBUSB_ERROR_ACCESS => rusb::Error::Access,
+        LIBUSB_ERROR_NO_DEVICE => rusb::Error::NoDevice,
+        LIBUSB_ERROR_NOT_FOUND => rusb::Error::NotFound,
+        LIBUSB_ERROR_BUSY => rusb::Error::Busy,
+        LIBUSB_ERROR_TIMEOUT => rusb::Error::Timeout,
+        LIBUSB_ERROR_OVERFLOW => rusb::Error::Overflow,
+        LIBUSB_ERROR_PIPE => rusb::Error::Pipe,
+        LIBUSB_ERROR_INTERRUPTED => rusb::Error::Interrupted,
+        LIBUSB_ERROR_NO_MEM => rusb::Error::NoMem,
+        LIBUSB_ERROR_NOT_SUPPORTED => rusb::Error::NotSupported,
+        _ => rusb::Error::Other,
+    }
+}
+
+/// The error a transfer that did not complete stands for, as a blocking
+/// read would have returned it.
+fn status_error(status: c_int) -> rusb::Error {
+    match status {
+        LIBUSB_TRANSFER_TIMED_OUT => rusb::Error::Timeout,
+        LIBUSB_TRANSFER_STALL => rusb::Error::Pipe,
+        LIBUSB_TRANSFER_NO_DEVICE => rusb::Error::NoDevice,
+        LIBUSB_TRANSFER_OVERFLOW => rusb::Error::Overflow,
+        LIBUSB_TRANSFER_CANCELLED => rusb::Error::Interrupted,
+        _ => rusb::Error::Io,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out the completions of `script`, one batch per `complete`.
+    #[derive(Default)]
+    struct Scripted {
+        script: VecDeque<Vec<Completion>>,
+        submitted: Vec<u8>,
+        cancels: usize,
+    }
+
+    impl Transfers for Scripted {
+        fn submit(&mut self, endpoint: u8, _length: usize) -> RusbResult<()> {
+            self.submitted.push(endpoint);
+            Ok(())
+        }
+
+        fn complete(&mut self, _timeout: Duration) -> RusbResult<Vec<Completion>> {
+            Ok(self.script.pop_front().unwrap_or_default())
+        }
+
+        fn cancel(&mut self) -> RusbResult<()> {
+            self.cancels += 1;
+            Ok(())
+        }
+    }
+
+    fn completion(endpoint: u8, result: RusbResult<&[u8]>) -> Completion {
+        Completion {
+            endpoint,
+            result: result.map(<[u8]>::to_vec),
+        }
+    }
+
+    #[test]
+    fn hands_out_finished_reads_and_queues_them_again() {
+        let mut interface = InterfaceInfo::new(2, vec![0x82, 0x83], 10);
+        let script = Scripted {
+            script: VecDeque::from([
+                vec![completion(0x82, Ok(&[0x09, 0x40]))],
+                vec![],
+                vec![
+                    completion(0x83, Ok(&[0x01])),
+                    completion(0x82, Err(rusb::Error::Pipe)),
+                ],
+                vec![completion(0x83, Ok(&[0x02]))],
+            ]),
+            submitted: vec![],
+            cancels: 0,
+        };
+        let mut reader = AsyncReader::new(script, &interface, 2).unwrap();
+        assert_eq!(reader.transfers.submitted, [0x82, 0x82, 0x83, 0x83]);
+
+        let mut observed = vec![];
+        let mut read = |reader: &mut AsyncReader<Scripted>, interface: &InterfaceInfo| {
+            reader.read_observed(interface, Duration::from_millis(10), |endpoint, result| {
+                observed.push((endpoint, result))
+            })
+        };
+        assert_eq!(read(&mut reader, &interface), Ok((2, vec![0x09, 0x40])));
+        assert_eq!(read(&mut reader, &interface), Err(rusb::Error::Timeout));
+        assert_eq!(read(&mut reader, &interface), Ok((2, vec![0x01])));
+        // Taken from what already finished, without waiting again.
+        assert_eq!(read(&mut reader, &interface), Err(rusb::Error::Pipe));
+        assert_eq!(reader.transfers.submitted[4..], [0x82, 0x83, 0x82]);
+
+        assert!(interface.skip_in(0x83));
+        assert_eq!(read(&mut reader, &interface), Ok((2, vec![0x02])));
+        assert_eq!(reader.transfers.submitted.len(), 7);
+        assert_eq!(
+            observed,
+            [
+                (0x82, Ok(2)),
+                (0x83, Ok(1)),
+                (0x82, Err(rusb::Error::Pipe)),
+                (0x83, Ok(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_pause_drops_what_was_read_and_queues_afresh() {
+        let interface = InterfaceInfo::new(2, vec![0x82], 10);
+        let script = Scripted {
+            script: VecDeque::from([
+                vec![completion(0x82, Ok(&[0x01])), completion(0x82, Ok(&[0x02]))],
+                vec![completion(0x82, Ok(&[0x03]))],
+            ]),
+            submitted: vec![],
+            cancels: 0,
+        };
+        let mut reader = AsyncReader::new(script, &interface, 2).unwrap();
+        let timeout = Duration::from_millis(10);
+
+        assert_eq!(
+            reader.read_observed(&interface, timeout, |_, _| {}),
+            Ok((2, vec![0x01]))
+        );
+        assert_eq!(reader.transfers.submitted.len(), 4);
+        reader.pause().unwrap();
+        assert_eq!(reader.transfers.cancels, 1);
+
+        // The report finished before the pause is not handed out, and both
+        // reads are queued again before waiting for the next.
+        assert_eq!(
+            reader.read_observed(&interface, timeout, |_, _| {}),
+            Ok((2, vec![0x03]))
+        );
+        assert_eq!(reader.transfers.submitted.len(), 7);
+    }
+}